@@ -1,6 +1,6 @@
 use convert_case::{Case, Casing};
 use std::io::Write;
-use std::{fs::File, io, path::Path};
+use std::{fs::File, io, path::Path, process::Command};
 
 use serde::Deserialize;
 
@@ -28,6 +28,17 @@ struct Permissions {
 }
 
 fn main() -> io::Result<()> {
+    // builds without the git directory (e.g. docker) could pass `GIT_SHA` explicitly
+    let git_sha = std::env::var("GIT_SHA").ok().unwrap_or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .unwrap_or_else(|| String::from("unknown"))
+    });
+    println!("cargo:rustc-env=GIT_SHA={git_sha}");
     let out = Path::new("./src").join("application_path_gen.rs");
     let mut out = File::create(out).unwrap();
     let permission_file = File::open("path_permission_cfg.json").unwrap();
@@ -44,6 +44,48 @@ fn default() -> Self {
             (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/preview",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/bulk",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/import/validate",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/batch",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/status_counts",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/customers",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id",
@@ -66,6 +108,13 @@ fn default() -> Self {
             (axum::http::Method::PATCH,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/operations",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/check_then_update",
@@ -99,6 +148,27 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/locate",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/invalid_rates",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/awaiting_shipment/counts",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id",
@@ -107,6 +177,13 @@ fn default() -> Self {
             (axum::http::Method::DELETE,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/lineage",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id/rate",
@@ -114,6 +191,20 @@ fn default() -> Self {
             (axum::http::Method::PATCH,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/hold",
+        std::collections::HashMap::from([
+            (axum::http::Method::PATCH,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/:id/reopen",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
 
         Self {
             route: String::from("/order_items"),
@@ -175,6 +266,13 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/stream",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/operations/:item_code_ext",
@@ -182,6 +280,41 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/ledger/:item_code_ext",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/supply_sources/:item_code_ext",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/operations/by_related/:related_id",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/operations/export",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/operation_types",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/quantity/:item_code_ext",
@@ -189,6 +322,62 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/changes",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/dead_stock",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/integrity/guarantee_mismatch",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/integrity/negatives",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/reorder_points",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/reorder_points/:item_code",
+        std::collections::HashMap::from([
+            (axum::http::Method::PUT,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/adjust_bulk",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/swap_location",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/export",
@@ -223,6 +412,13 @@ fn default() -> Self {
             (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/from_order",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id",
@@ -280,6 +476,20 @@ fn default() -> Self {
             (axum::http::Method::PUT,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/reopen",
+        std::collections::HashMap::from([
+            (axum::http::Method::PUT,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/:id/duplicate",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id/no",
@@ -308,6 +518,27 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/invoice",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/:id/transfer_diff",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/:id/picking_list",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/by_no/:no",
@@ -315,6 +546,34 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/by_customer",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/unexported",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/discount_breakdown",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/by_status/:status",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/export",
@@ -322,6 +581,20 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/export_preview",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/integrity/duplicate_items",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
 
         Self {
             route: String::from("/shipment"),
@@ -349,6 +622,13 @@ fn default() -> Self {
             (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/summary",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/:id",
@@ -371,6 +651,20 @@ fn default() -> Self {
             (axum::http::Method::PUT,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/:id/location",
+        std::collections::HashMap::from([
+            (axum::http::Method::PUT,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/:id/repair",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
    matcher
     .insert(
         "/shipment_no/:shipment_no",
@@ -395,6 +689,32 @@ fn default() -> Self {
 
 impl_application_path!(TransferPath);
     
+#[derive(Clone)]
+pub struct ActivityPath {
+    pub route: String,
+    matcher: matchit::Router<std::collections::HashMap<axum::http::Method, crate::db::auth::UserRole>> 
+}
+
+impl Default for ActivityPath {
+fn default() -> Self {
+    let mut matcher = matchit::Router::new();
+   matcher
+    .insert(
+        "/",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+
+        Self {
+            route: String::from("/activity"),
+            matcher
+        }
+    }
+}
+
+impl_application_path!(ActivityPath);
+    
 #[derive(Clone)]
 pub struct ControlPath {
     pub route: String,
@@ -437,6 +757,13 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Visitor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/detailed",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Visitor),
+        ]),
+        ).unwrap();
 
         Self {
             route: String::from("/health_check"),
@@ -463,6 +790,21 @@ fn default() -> Self {
             (axum::http::Method::GET,crate::db::auth::UserRole::Editor),
         ]),
         ).unwrap();
+   matcher
+    .insert(
+        "/presets",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+            (axum::http::Method::PUT,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/recent",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
 
         Self {
             route: String::from("/user_info"),
@@ -473,6 +815,112 @@ fn default() -> Self {
 
 impl_application_path!(UserInfoPath);
     
+#[derive(Clone)]
+pub struct InvitesPath {
+    pub route: String,
+    matcher: matchit::Router<std::collections::HashMap<axum::http::Method, crate::db::auth::UserRole>> 
+}
+
+impl Default for InvitesPath {
+fn default() -> Self {
+    let mut matcher = matchit::Router::new();
+   matcher
+    .insert(
+        "/",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+
+        Self {
+            route: String::from("/invites"),
+            matcher
+        }
+    }
+}
+
+impl_application_path!(InvitesPath);
+    
+#[derive(Clone)]
+pub struct AdminPath {
+    pub route: String,
+    matcher: matchit::Router<std::collections::HashMap<axum::http::Method, crate::db::auth::UserRole>> 
+}
+
+impl Default for AdminPath {
+fn default() -> Self {
+    let mut matcher = matchit::Router::new();
+   matcher
+    .insert(
+        "/cache/clear",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/cache/warm",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/inventory/location_audit",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/broadcast_refresh",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+   matcher
+    .insert(
+        "/orders/backfill_customer_id",
+        std::collections::HashMap::from([
+            (axum::http::Method::POST,crate::db::auth::UserRole::Editor),
+        ]),
+        ).unwrap();
+
+        Self {
+            route: String::from("/admin"),
+            matcher
+        }
+    }
+}
+
+impl_application_path!(AdminPath);
+    
+#[derive(Clone)]
+pub struct ItemsPath {
+    pub route: String,
+    matcher: matchit::Router<std::collections::HashMap<axum::http::Method, crate::db::auth::UserRole>> 
+}
+
+impl Default for ItemsPath {
+fn default() -> Self {
+    let mut matcher = matchit::Router::new();
+   matcher
+    .insert(
+        "/:code/price",
+        std::collections::HashMap::from([
+            (axum::http::Method::GET,crate::db::auth::UserRole::Viewer),
+        ]),
+        ).unwrap();
+
+        Self {
+            route: String::from("/items"),
+            matcher
+        }
+    }
+}
+
+impl_application_path!(ItemsPath);
+    
 
 #[derive(Default)]
 pub struct PrivatePath {
@@ -483,7 +931,11 @@ pub struct PrivatePath {
    pub return_path:ReturnPath,
    pub shipment_path:ShipmentPath,
    pub transfer_path:TransferPath,
+   pub activity_path:ActivityPath,
    pub control_path:ControlPath,
    pub health_check_path:HealthCheckPath,
    pub user_info_path:UserInfoPath,
+   pub invites_path:InvitesPath,
+   pub admin_path:AdminPath,
+   pub items_path:ItemsPath,
 }
@@ -14,7 +14,11 @@ pub trait OrderCache: Send + Sync + 'static {
 
     fn contains_orders(&self, message: &QueryOrdersMessage) -> bool;
 
-    fn clear_orders(&self);
+    /// returns the number of evicted entries.
+    fn clear_orders(&self) -> usize;
+
+    /// returns the number of evicted entries.
+    fn clear_ph_items(&self) -> usize;
 }
 
 #[derive(Clone, Debug)]
@@ -48,7 +52,15 @@ impl OrderCache for MapCache {
         self.orders_cache.contains_key(message)
     }
 
-    fn clear_orders(&self) {
+    fn clear_orders(&self) -> usize {
+        let evicted = self.orders_cache.len();
         self.orders_cache.clear();
+        evicted
+    }
+
+    fn clear_ph_items(&self) -> usize {
+        let evicted = self.ph_item_cache.len();
+        self.ph_item_cache.clear();
+        evicted
     }
 }
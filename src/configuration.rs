@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+
+use mongodb::options::{Acknowledgment, ReadConcern, TransactionOptions, WriteConcern};
 use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
+use crate::db::{auth::UserRole, shipment::ShipmentVendor};
+
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
@@ -12,6 +17,315 @@ pub struct Settings {
     pub refresh_token_secret: Secret<String>,
     pub utility: UtilitySetting,
     pub google_service: GoogleServiceSetting,
+    /// where notifications, e.g. of concealed shipped items, are sent.
+    #[serde(default)]
+    pub notifier: NotifierBackend,
+    #[serde(default)]
+    pub notifier_retry: NotifierRetrySetting,
+    #[serde(default)]
+    pub guarantee_expiry: GuaranteeExpirySetting,
+    #[serde(default)]
+    pub daily_shipment_export: DailyShipmentExportSetting,
+    #[serde(default)]
+    pub consistency_check: ConsistencyCheckSetting,
+    #[serde(default)]
+    pub auth: AuthSetting,
+    #[serde(default)]
+    pub shipment: ShipmentSetting,
+    #[serde(default)]
+    pub item_type_map: ItemTypeMapSetting,
+    #[serde(default)]
+    pub ws: WsSetting,
+    #[serde(default)]
+    pub cache_warm: CacheWarmSetting,
+    #[serde(default)]
+    pub delete_grace: DeleteGraceSetting,
+    #[serde(default)]
+    pub assets: AssetsSetting,
+    /// max request body size of import routes
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: usize,
+    /// the largest page accepted by paged queries.
+    /// paged queries skip `page * page size` documents, so deep pages are expensive.
+    #[serde(default = "default_max_page")]
+    pub max_page: u32,
+    /// how many recently fetched orders and shipments are kept per user.
+    #[serde(default = "default_recent_resources_limit")]
+    pub recent_resources_limit: usize,
+}
+
+fn default_max_upload_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_max_page() -> u32 {
+    100
+}
+
+fn default_recent_resources_limit() -> usize {
+    20
+}
+
+/// localized labels of item types used in exports.
+/// an item type is identified by the code prefix, `item_code_ext[5..8]`.
+#[derive(serde::Deserialize, Default)]
+pub struct ItemTypeMapSetting {
+    pub default_locale: String,
+    pub item_types: Vec<ItemTypeLabels>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ItemTypeLabels {
+    pub prefix: String,
+    /// label keyed by locale
+    pub labels: HashMap<String, String>,
+}
+
+impl ItemTypeMapSetting {
+    /// the default locale will be used when the locale is not provided or not supported.
+    pub fn get_label(&self, prefix: &str, locale: Option<&str>) -> Option<&str> {
+        let labels = &self.item_types.iter().find(|t| t.prefix == prefix)?.labels;
+        locale
+            .and_then(|l| labels.get(l))
+            .or_else(|| labels.get(&self.default_locale))
+            .map(|l| l.as_str())
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct ShipmentSetting {
+    /// regex patterns of shipment no keyed by vendor.
+    /// vendors without pattern accept any shipment no.
+    #[serde(default)]
+    pub no_patterns: HashMap<ShipmentVendor, String>,
+    /// rounding of discounted prices and export totals.
+    #[serde(default)]
+    pub default_rounding: RoundingStrategy,
+    /// rounding keyed by vendor, vendors without one use `default_rounding`.
+    #[serde(default)]
+    pub rounding: HashMap<ShipmentVendor, RoundingStrategy>,
+}
+
+impl ShipmentSetting {
+    pub fn rounding_for(&self, vendor: Option<&ShipmentVendor>) -> RoundingStrategy {
+        vendor
+            .and_then(|vendor| self.rounding.get(vendor))
+            .copied()
+            .unwrap_or(self.default_rounding)
+    }
+}
+
+/// how a fractional yen amount becomes an integer.
+/// `Legacy` keeps the rounding used before strategies were configurable,
+/// vendors opt in to the others.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingStrategy {
+    #[default]
+    Legacy,
+    Floor,
+    HalfUp,
+    Ceil,
+}
+
+impl RoundingStrategy {
+    /// rounding of a discounted unit price.
+    pub fn apply(self, value: f64) -> u32 {
+        if self == RoundingStrategy::Legacy {
+            return match (value - value.trunc()) < 0.5 {
+                true => value.round() as u32,
+                false => value.ceil() as u32,
+            };
+        }
+        // drop the noise of float multiplication, e.g. 1000 * 0.7 = 699.9999999999999
+        let value = (value * 1e6).round() / 1e6;
+        match self {
+            RoundingStrategy::Floor => value.floor() as u32,
+            RoundingStrategy::HalfUp | RoundingStrategy::Legacy => {
+                if (value - value.trunc()) < 0.5 {
+                    value.trunc() as u32
+                } else {
+                    value.ceil() as u32
+                }
+            }
+            RoundingStrategy::Ceil => value.ceil() as u32,
+        }
+    }
+
+    /// rounding of an export total, `Legacy` always rounds it up.
+    pub fn apply_total(self, value: f64) -> u32 {
+        match self {
+            RoundingStrategy::Legacy => value.ceil() as u32,
+            _ => self.apply(value),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct AuthSetting {
+    /// secrets used before rotating, tokens signed by them are still accepted.
+    /// new tokens are always signed by the current secrets.
+    #[serde(default)]
+    pub previous_secrets: Vec<PreviousSecret>,
+    /// signup secrets used before rotating, still accepted on sign up.
+    #[serde(default)]
+    pub previous_signup_secrets: Vec<Secret<String>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviousSecret {
+    pub access_token_secret: Secret<String>,
+    pub refresh_token_secret: Secret<String>,
+}
+
+/// Policy for reverting guaranteed order items which have not been touched for a long time
+/// back to backordering, so their inventory could be used by other orders.
+#[derive(serde::Deserialize)]
+pub struct GuaranteeExpirySetting {
+    pub enabled: bool,
+    pub guarantee_expiry_days: u32,
+    pub check_interval_secs: u64,
+}
+
+impl Default for GuaranteeExpirySetting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            guarantee_expiry_days: 30,
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+/// Startup check of shipments diverged from their order items
+/// and inventory items missing locations.
+/// found ones are only logged unless `repair` is set.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct ConsistencyCheckSetting {
+    pub enabled: bool,
+    pub repair: bool,
+}
+
+impl Default for ConsistencyCheckSetting {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            repair: false,
+        }
+    }
+}
+
+/// order queries run by `POST /admin/cache/warm` to fill the orders cache.
+#[derive(serde::Deserialize, Default)]
+pub struct CacheWarmSetting {
+    #[serde(default)]
+    pub presets: Vec<OrderQueryPreset>,
+}
+
+/// a common orders query relative to the day it is run.
+/// it covers from the start of the day `days` before to the end of today in utc,
+/// so clients querying whole days hit the warmed entry.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct OrderQueryPreset {
+    #[serde(default)]
+    pub keyword: String,
+    #[serde(default)]
+    pub status: String,
+    pub days: i64,
+    pub page: Option<u32>,
+}
+
+/// Window in which orders, shipments, transfers and registers could be deleted
+/// after created. older ones could only be deleted by users whose role,
+/// or sub role of the route, is at least `override_role`.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct DeleteGraceSetting {
+    /// no window is enforced if none.
+    pub days: Option<i64>,
+    pub override_role: UserRole,
+}
+
+impl Default for DeleteGraceSetting {
+    fn default() -> Self {
+        Self {
+            days: None,
+            override_role: UserRole::Full,
+        }
+    }
+}
+
+/// Schedule of exporting the shipments of the day through the utility.
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct DailyShipmentExportSetting {
+    pub enabled: bool,
+    /// local time of the day the export runs at.
+    pub at: chrono::NaiveTime,
+    /// where the utility stores the exported file, the utility default is used if none.
+    pub destination: Option<String>,
+}
+
+impl Default for DailyShipmentExportSetting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            at: chrono::NaiveTime::from_hms_opt(23, 30, 0).unwrap(),
+            destination: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct WsSetting {
+    /// capacity of the control message broadcast channel.
+    /// subscribers lagged behind more than it will be asked to resync.
+    pub channel_capacity: usize,
+    /// max concurrent control sockets, upgrades beyond it are refused with 503.
+    pub max_connections: usize,
+    /// a control socket sending nothing for this long is closed.
+    pub idle_timeout_secs: u64,
+    /// repeated control messages within this window are sent to a socket once,
+    /// with 0 only messages already queued are merged.
+    pub debounce_ms: u64,
+}
+
+impl Default for WsSetting {
+    fn default() -> Self {
+        Self {
+            channel_capacity: 1024,
+            max_connections: 256,
+            idle_timeout_secs: 60,
+            debounce_ms: 100,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default)]
+pub struct AssetsSetting {
+    /// url of an item image, `{code}` and `{color}` are replaced by the item's.
+    pub image_url_template: String,
+}
+
+impl AssetsSetting {
+    pub fn image_url(&self, code: &str, color: &str) -> String {
+        self.image_url_template
+            .replace("{code}", code)
+            .replace("{color}", color)
+    }
+}
+
+impl Default for AssetsSetting {
+    fn default() -> Self {
+        Self {
+            image_url_template: String::from(
+                "https://d2vg6jg1lu9m12.cloudfront.net/{code}_{color}.jpeg",
+            ),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -40,6 +354,43 @@ impl GoogleServiceSetting {
     }
 }
 
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifierBackend {
+    #[default]
+    Google,
+    Noop,
+}
+
+/// how failing notifications are retried.
+/// after `failure_threshold` consecutive failures the notifier stops calling the service
+/// for `open_secs` and queues notifications to be retried every `retry_interval_secs`.
+/// queued notifications failing `max_pending_attempts` retries are dropped.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotifierRetrySetting {
+    pub max_attempts: u32,
+    /// waited before the second attempt, doubled on every further attempt.
+    pub backoff_ms: u64,
+    pub failure_threshold: u32,
+    pub open_secs: u64,
+    pub retry_interval_secs: u64,
+    pub max_pending_attempts: u32,
+}
+
+impl Default for NotifierRetrySetting {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_ms: 500,
+            failure_threshold: 5,
+            open_secs: 60,
+            retry_interval_secs: 300,
+            max_pending_attempts: 20,
+        }
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct DatabaseSettings {
     pub username: String,
@@ -47,6 +398,56 @@ pub struct DatabaseSettings {
     pub port: u16,
     pub host: String,
     pub database_name: String,
+    #[serde(default)]
+    pub concern: ConcernSetting,
+    /// aggregations taking longer than this are logged as slow.
+    #[serde(default = "default_slow_query_threshold_ms")]
+    pub slow_query_threshold_ms: u64,
+}
+
+fn default_slow_query_threshold_ms() -> u64 {
+    1000
+}
+
+/// read and write concerns used by transactions.
+/// a single node deployment may lower them for latency, a large cluster may raise them.
+#[derive(serde::Deserialize)]
+pub struct ConcernSetting {
+    /// read concern level, such as "local", "majority" or "snapshot".
+    pub read: String,
+    /// "majority", a tag set name or the number of nodes to acknowledge writes.
+    pub write: String,
+    /// require writes to be written to the on-disk journal.
+    #[serde(default)]
+    pub journal: Option<bool>,
+}
+
+impl Default for ConcernSetting {
+    fn default() -> Self {
+        Self {
+            read: String::from("majority"),
+            write: String::from("majority"),
+            journal: None,
+        }
+    }
+}
+
+impl ConcernSetting {
+    pub fn transaction_options(&self) -> TransactionOptions {
+        let acknowledgment = match self.write.parse::<u32>() {
+            Ok(nodes) => Acknowledgment::Nodes(nodes),
+            Err(_) => Acknowledgment::from(self.write.clone()),
+        };
+        TransactionOptions::builder()
+            .read_concern(ReadConcern::custom(self.read.clone()))
+            .write_concern(
+                WriteConcern::builder()
+                    .w(acknowledgment)
+                    .journal(self.journal)
+                    .build(),
+            )
+            .build()
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -75,6 +476,8 @@ impl DatabaseSettings {
             port,
             host,
             database_name: _,
+            concern: _,
+            slow_query_threshold_ms: _,
         } = self;
         format!("mongodb://{host}:{port}")
     }
@@ -85,6 +488,8 @@ impl DatabaseSettings {
             port: _,
             host,
             database_name: _,
+            concern: _,
+            slow_query_threshold_ms: _,
         } = self;
         Secret::new(format!(
             "mongodb+srv://{username}:{password}@{host}",
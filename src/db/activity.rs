@@ -0,0 +1,122 @@
+use std::cmp::Reverse;
+
+use crate::error_result::Result;
+use axum::async_trait;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, DateTime, Uuid},
+    options::FindOptions,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{
+    mongo::{DbClient, ORDERS_COL, RETURNS_COL, SHIPMENT_COL, TRANSFERS_COL},
+    order::MongoOrder,
+    retrn::MongoReturn,
+    shipment::MongoShipment,
+    transfer::MongoTransfer,
+    ActivityRepo,
+};
+
+/// upper bound of documents read from each collection for one feed.
+pub const MAX_ACTIVITY_PER_COLLECTION: u32 = 100;
+
+#[async_trait]
+impl ActivityRepo for DbClient {
+    async fn find_recent_activity(&self, limit: u32) -> Result<Vec<MongoActivity>> {
+        Ok(find_recent_activity(self, limit).await?)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    Order,
+    Shipment,
+    Transfer,
+    Return,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoActivity {
+    pub kind: ActivityKind,
+    pub id: Uuid,
+    pub at: DateTime,
+    pub summary: String,
+}
+
+impl From<MongoOrder> for MongoActivity {
+    fn from(m: MongoOrder) -> Self {
+        Self {
+            kind: ActivityKind::Order,
+            id: m.id,
+            at: m.created_at,
+            summary: format!("{} {}", m.taobao_order_no, m.customer_id),
+        }
+    }
+}
+
+impl From<MongoShipment> for MongoActivity {
+    fn from(m: MongoShipment) -> Self {
+        Self {
+            kind: ActivityKind::Shipment,
+            id: m.id,
+            at: m.created_at,
+            summary: format!("{} {}", m.shipment_no, m.vendor),
+        }
+    }
+}
+
+impl From<MongoTransfer> for MongoActivity {
+    fn from(m: MongoTransfer) -> Self {
+        Self {
+            kind: ActivityKind::Transfer,
+            id: m.id,
+            at: m.created_at,
+            summary: format!("{} {}", m.shipment_no, m.shipment_vendor),
+        }
+    }
+}
+
+impl From<MongoReturn> for MongoActivity {
+    fn from(m: MongoReturn) -> Self {
+        Self {
+            kind: ActivityKind::Return,
+            id: m.id,
+            at: m.created_at,
+            summary: m.return_no,
+        }
+    }
+}
+
+/// merge the latest created orders, shipments, transfers and returns into one feed,
+/// newest first. deleted documents are gone from their collections so they are not listed.
+#[instrument(name = "find recent activity", skip(db))]
+pub async fn find_recent_activity(db: &DbClient, limit: u32) -> Result<Vec<MongoActivity>> {
+    let per_collection = limit.min(MAX_ACTIVITY_PER_COLLECTION);
+    let mut activities = Vec::new();
+    activities.extend(find_recent::<MongoOrder>(db, ORDERS_COL, per_collection).await?);
+    activities.extend(find_recent::<MongoShipment>(db, SHIPMENT_COL, per_collection).await?);
+    activities.extend(find_recent::<MongoTransfer>(db, TRANSFERS_COL, per_collection).await?);
+    activities.extend(find_recent::<MongoReturn>(db, RETURNS_COL, per_collection).await?);
+    activities.sort_by_key(|a| Reverse(a.at));
+    activities.truncate(limit as usize);
+    Ok(activities)
+}
+
+async fn find_recent<T>(db: &DbClient, col: &str, limit: u32) -> Result<Vec<MongoActivity>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync + Into<MongoActivity>,
+{
+    let options = FindOptions::builder()
+        .sort(doc! {"created_at":-1})
+        .limit(limit as i64)
+        .build();
+    let mut cursor = db.ph_db.collection::<T>(col).find(None, options).await?;
+    let mut outputs = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        outputs.push(doc?.into())
+    }
+    Ok(outputs)
+}
@@ -1,13 +1,16 @@
 use std::{collections::HashMap, fmt::Display};
 
-use mongodb::bson::{doc, Bson, Document, Uuid};
+use mongodb::bson::{self, doc, Bson, DateTime, Document, Uuid};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    db::mongo::USERS_COL,
+    db::mongo::{INVITES_COL, USERS_COL},
     error_result::{AuthError, Error},
 };
-use crate::{error_result::Result, server::AppPrivateRoute};
+use crate::{
+    error_result::Result,
+    server::{order::QueryOrdersMessage, shipment::QueryShipmentMessage, AppPrivateRoute},
+};
 
 use super::mongo::DbClient;
 
@@ -24,11 +27,16 @@ pub async fn check_is_username_occupied(db: &DbClient, username: &str) -> Result
     Ok(false)
 }
 
-pub async fn create_user(db: &DbClient, user: User) -> Result<()> {
+fn sub_role_to_doc(sub_role: &HashMap<AppPrivateRoute, UserRole>) -> Document {
     let mut sub_role_doc = Document::new();
-    for (path, role) in user.sub_role.iter() {
+    for (path, role) in sub_role.iter() {
         sub_role_doc.insert(path.to_string(), role);
     }
+    sub_role_doc
+}
+
+pub async fn create_user(db: &DbClient, user: User) -> Result<()> {
+    let sub_role_doc = sub_role_to_doc(&user.sub_role);
     let doc = doc! {
         "id":user.id,
         "username":user.username,
@@ -65,6 +73,93 @@ pub async fn find_user_by_username(db: &DbClient, username: &str) -> Result<User
     }
     Ok(res.unwrap())
 }
+/// replace all query presets of the user.
+pub async fn update_user_presets(db: &DbClient, id: Uuid, presets: &[QueryPreset]) -> Result<()> {
+    let filter = doc! {"id":id};
+    let update = doc! {
+        "$set":{
+            "presets":bson::to_bson(presets)?,
+        }
+    };
+    let res = db
+        .ph_db
+        .collection::<User>(USERS_COL)
+        .update_one(filter, update, None)
+        .await?;
+    if res.matched_count == 0 {
+        return Err(Error::Auth(AuthError::UserNotFound));
+    }
+    Ok(())
+}
+
+/// put the resource at the front of the recent resources of the user,
+/// an older entry of the same resource is removed and only `limit` entries are kept.
+pub async fn record_recent_resource(
+    db: &DbClient,
+    id: Uuid,
+    resource: &RecentResource,
+    limit: usize,
+) -> Result<()> {
+    let col = db.ph_db.collection::<User>(USERS_COL);
+    let filter = doc! {"id":id};
+    let pull = doc! {
+        "$pull":{
+            "recent":{"kind":bson::to_bson(&resource.kind)?,"id":resource.id},
+        }
+    };
+    col.update_one(filter.clone(), pull, None).await?;
+    let push = doc! {
+        "$push":{
+            "recent":{
+                "$each":[bson::to_bson(resource)?],
+                "$position":0,
+                "$slice":limit as i64,
+            },
+        }
+    };
+    let res = col.update_one(filter, push, None).await?;
+    if res.matched_count == 0 {
+        return Err(Error::Auth(AuthError::UserNotFound));
+    }
+    Ok(())
+}
+
+pub async fn create_invite(db: &DbClient, invite: &Invite) -> Result<()> {
+    let doc = doc! {
+        "id":invite.id,
+        "token":&invite.token,
+        "role":invite.role,
+        "sub_role":invite.sub_role.as_ref().map(sub_role_to_doc),
+        "created_by":invite.created_by,
+        "created_at":invite.created_at,
+        "used_at":Bson::Null,
+        "used_by":Bson::Null,
+    };
+    db.ph_db
+        .collection(INVITES_COL)
+        .insert_one(doc, None)
+        .await?;
+    Ok(())
+}
+
+/// mark the invite as used by the username.
+/// an invite could only be consumed once.
+pub async fn consume_invite(db: &DbClient, token: &str, username: &str) -> Result<Invite> {
+    let filter = doc! {"token":token,"used_at":Bson::Null};
+    let update = doc! {
+        "$set":{
+            "used_at":DateTime::now(),
+            "used_by":username,
+        }
+    };
+    let res = db
+        .ph_db
+        .collection::<Invite>(INVITES_COL)
+        .find_one_and_update(filter, update, None)
+        .await?;
+    res.ok_or(Error::Auth(AuthError::InvalidInviteToken))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct User {
     pub id: Uuid,
@@ -72,6 +167,41 @@ pub struct User {
     pub hash: String,
     pub role: UserRole,
     pub sub_role: HashMap<AppPrivateRoute, UserRole>,
+    #[serde(default)]
+    pub presets: Vec<QueryPreset>,
+    /// resources fetched by the user, the most recent first.
+    #[serde(default)]
+    pub recent: Vec<RecentResource>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecentResourceKind {
+    Order,
+    Shipment,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RecentResource {
+    pub kind: RecentResourceKind,
+    pub id: Uuid,
+    pub viewed_at: DateTime,
+}
+
+/// a named query saved by the user, so it follows the user across devices.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryPreset {
+    pub name: String,
+    #[serde(flatten)]
+    pub query: PresetQuery,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "kind", content = "query", rename_all = "snake_case")]
+pub enum PresetQuery {
+    Orders(QueryOrdersMessage),
+    Shipments(QueryShipmentMessage),
 }
 
 impl User {
@@ -87,6 +217,41 @@ impl User {
             hash,
             role,
             sub_role,
+            presets: Vec::new(),
+            recent: Vec::new(),
+        }
+    }
+}
+
+/// single use token for signing up without the shared secret.
+/// role and sub role of the new user will be preset if they are provided.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Invite {
+    pub id: Uuid,
+    pub token: String,
+    pub role: Option<UserRole>,
+    pub sub_role: Option<HashMap<AppPrivateRoute, UserRole>>,
+    pub created_by: Uuid,
+    pub created_at: DateTime,
+    pub used_at: Option<DateTime>,
+    pub used_by: Option<String>,
+}
+
+impl Invite {
+    pub fn new(
+        role: Option<UserRole>,
+        sub_role: Option<HashMap<AppPrivateRoute, UserRole>>,
+        created_by: Uuid,
+    ) -> Self {
+        Self {
+            id: Uuid::new(),
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            role,
+            sub_role,
+            created_by,
+            created_at: DateTime::now(),
+            used_at: None,
+            used_by: None,
         }
     }
 }
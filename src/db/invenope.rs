@@ -8,7 +8,10 @@ use tracing::{info, instrument};
 
 use super::{
     inventory::{InventoryLocation, MongoInventoryItem},
-    mongo::{DbClient, INVENTORY_COL, OPERATIONS_COL},
+    mongo::{
+        DbClient, INVENTORY_COL, OPERATIONS_COL, ORDERS_COL, REGISTERS_COL, RETURNS_COL,
+        TRANSFERS_COL,
+    },
 };
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -135,6 +138,33 @@ impl MongoInventoryOperation {
         Ok(())
     }
 
+    async fn set_self_countered_with_session(
+        &self,
+        db: &DbClient,
+        session: &mut ClientSession,
+    ) -> Result<()> {
+        info!("set operation counted id:{}", self.id);
+        let query = doc! {
+          "id":self.id,
+        };
+
+        let update = doc! {
+          "$set":{
+            "countered":true,
+          }
+        };
+        let res = db
+            .ph_db
+            .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+            .update_one_with_session(query, update, None, session)
+            .await?;
+        if res.matched_count == 0 {
+            return Err(Error::CanNotFindOperation(self.id.to_string()));
+        }
+        info!("set operation counted success");
+        Ok(())
+    }
+
     async fn update_self_count(&self, db: &DbClient, inc: i32) -> Result<()> {
         info!("update operation id:{} inc:{}", self.id, inc);
         let query = doc! {
@@ -157,6 +187,33 @@ impl MongoInventoryOperation {
         Ok(())
     }
 
+    async fn update_self_count_with_session(
+        &self,
+        db: &DbClient,
+        inc: i32,
+        session: &mut ClientSession,
+    ) -> Result<()> {
+        info!("update operation id:{} inc:{}", self.id, inc);
+        let query = doc! {
+          "id":self.id,
+        };
+        let update = doc! {
+         "$inc":{
+          "count":inc,
+         }
+        };
+        let res = db
+            .ph_db
+            .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+            .update_one_with_session(query, update, None, session)
+            .await?;
+        if res.matched_count == 0 {
+            return Err(Error::CanNotFindOperation(self.id.to_string()));
+        }
+        info!("operation update success");
+        Ok(())
+    }
+
     #[instrument(name="operation run full backward",skip(db,self,operation_type),fields(
        operation_id=%self.id,
        target_item=%self.item_code_ext,
@@ -245,6 +302,81 @@ impl MongoInventoryOperation {
         Ok(Some(id))
     }
 
+    async fn run_backward_with_session(
+        &self,
+        db: &DbClient,
+        operation_type: MongoOperationType,
+        session: &mut ClientSession,
+    ) -> Result<Option<Uuid>> {
+        if self.count == 0 {
+            info!("operation count is 0 pass run backward");
+            return Ok(None);
+        }
+        if self.countered {
+            info!("operation is countered pass run backward");
+            return Ok(None);
+        }
+        info!(
+            "generate new backward {:?} operation: inventory item:{} location:{:?} count: {}",
+            &operation_type, &self.item_code_ext, &self.location, &self.count
+        );
+        self.set_self_countered_with_session(db, session).await?;
+        let backward = Self::new_countered(
+            &self.item_code_ext,
+            self.related_id,
+            operation_type,
+            -self.count,
+            self.location.to_owned(),
+        );
+        let id = backward.run_self_with_session(db, false, session).await?;
+        Ok(Some(id))
+    }
+
+    pub async fn run_partial_backward_with_session(
+        &self,
+        db: &DbClient,
+        backward_count: u32,
+        operation_type: MongoOperationType,
+        session: &mut ClientSession,
+    ) -> Result<Option<Uuid>> {
+        if self.count == 0 {
+            info!("operation count is 0 pass run backward");
+            return Ok(None);
+        }
+        if self.countered {
+            info!("operation is countered pass run backward");
+            return Ok(None);
+        }
+        if self.count.unsigned_abs() < backward_count {
+            return Err(Error::PartialBackwardCountOver(
+                backward_count,
+                self.count as u32,
+            ));
+        }
+        if self.count.unsigned_abs() == backward_count {
+            info!("operation count is equal to backward run backward directly");
+            let id = self
+                .run_backward_with_session(db, operation_type, session)
+                .await?;
+            return Ok(id);
+        }
+        let mut backward_count = backward_count as i32;
+        if self.count.is_positive() {
+            backward_count = -backward_count
+        }
+        let backward = Self::new_countered(
+            &self.item_code_ext,
+            self.related_id,
+            operation_type,
+            backward_count,
+            self.location.to_owned(),
+        );
+        let id = backward.run_self_with_session(db, false, session).await?;
+        self.update_self_count_with_session(db, backward_count, session)
+            .await?;
+        Ok(Some(id))
+    }
+
     #[instrument(name="run inventory operation",skip(self,db),fields(
         operation_id=%self.id,
         target_item=%self.item_code_ext,
@@ -364,6 +496,28 @@ impl MongoInventoryOperation {
     }
 }
 
+/// the kind of entity an operation's `related_id` points to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelatedKind {
+    Register,
+    Order,
+    Transfer,
+    Return,
+}
+
+impl RelatedKind {
+    /// the collection the related entities are stored in.
+    pub fn collection(&self) -> &'static str {
+        match self {
+            RelatedKind::Register => REGISTERS_COL,
+            RelatedKind::Order => ORDERS_COL,
+            RelatedKind::Transfer => TRANSFERS_COL,
+            RelatedKind::Return => RETURNS_COL,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum MongoOperationType {
@@ -378,6 +532,9 @@ pub enum MongoOperationType {
     ConcealOrderItem,
     Ordered,
     Move,
+    ExpireGuarantee,
+    HoldOrderItem,
+    Adjust,
 }
 
 impl From<MongoOperationType> for Bson {
@@ -396,6 +553,9 @@ impl From<MongoOperationType> for Bson {
             }
             MongoOperationType::Ordered => Bson::String(String::from("ordered")),
             MongoOperationType::Move => Bson::String(String::from("move")),
+            MongoOperationType::ExpireGuarantee => Bson::String(String::from("expire_guarantee")),
+            MongoOperationType::HoldOrderItem => Bson::String(String::from("hold_order_item")),
+            MongoOperationType::Adjust => Bson::String(String::from("adjust")),
         }
     }
 }
@@ -1,21 +1,39 @@
 use crate::{
-    db::{invenope::MongoOperationType, mongo::OPERATIONS_COL},
+    db::{
+        invenope::{MongoOperationType, RelatedKind},
+        mongo::{
+            ADJUSTMENTS_COL, LOCATION_SWAPS_COL, OPERATIONS_COL, ORDER_ITEMS_COL, REGISTERS_COL,
+            REORDER_POINTS_COL,
+        },
+        order::OrderItemStatus,
+    },
     error_result::{Error, Result},
-    server::inventory::InventoryQuery,
+    server::{
+        auth::SETTINGS,
+        inventory::{AdjustInventoryInput, InventoryQuery, SwapLocationInput},
+    },
 };
 use axum::async_trait;
-use futures::StreamExt;
+use chrono::{DateTime, Local, Utc};
+use futures::{stream::BoxStream, StreamExt};
 use mongodb::bson::{self, Bson};
 use mongodb::bson::{doc, Document};
-use mongodb::{bson::Uuid, ClientSession};
+use mongodb::{
+    bson::Uuid,
+    error::UNKNOWN_TRANSACTION_COMMIT_RESULT,
+    options::{FindOptions, ReplaceOptions},
+    ClientSession,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use strum::{EnumIter, IntoEnumIterator};
-use tracing::info;
+use tracing::{info, instrument};
 
 use super::{
     invenope::{MongoInventoryOperation, Operations},
     mongo::{DbClient, INVENTORY_COL},
-    InventoryRepo,
+    slow_query::QueryTimer,
+    InventoryOperation, InventoryRepo,
 };
 #[async_trait]
 impl InventoryRepo for DbClient {
@@ -26,6 +44,112 @@ impl InventoryRepo for DbClient {
         Ok(query_inventory(self, query).await?)
     }
 
+    async fn query_inventory_facets(&self, query: &InventoryQuery) -> Result<HashMap<String, u64>> {
+        Ok(query_inventory_facets(self, query).await?)
+    }
+
+    async fn stream_inventory(
+        &self,
+        query: &InventoryQuery,
+    ) -> Result<BoxStream<'static, Result<MongoInventoryOutput>>> {
+        Ok(stream_inventory(self, query).await?)
+    }
+
+    async fn find_dead_stock(&self, since: DateTime<Utc>) -> Result<Vec<MongoInventoryOutput>> {
+        Ok(find_dead_stock(self, since.into()).await?)
+    }
+
+    async fn find_inventory_changed_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MongoInventoryOutput>> {
+        Ok(find_inventory_changed_since(self, since.into()).await?)
+    }
+
+    async fn find_guarantee_mismatches(&self) -> Result<Vec<GuaranteeMismatch>> {
+        Ok(find_guarantee_mismatches(self).await?)
+    }
+
+    async fn find_negative_balances(&self) -> Result<Vec<NegativeBalance>> {
+        Ok(find_negative_balances(self).await?)
+    }
+
+    async fn find_reorder_points(&self) -> Result<Vec<MongoReorderPoint>> {
+        Ok(find_reorder_points(self).await?)
+    }
+
+    async fn set_reorder_point(
+        &self,
+        item_code: &str,
+        min_quantity: u32,
+    ) -> Result<MongoReorderPoint> {
+        Ok(set_reorder_point(self, item_code, min_quantity).await?)
+    }
+
+    async fn audit_inventory_locations(&self, backfill: bool) -> Result<Vec<LocationAudit>> {
+        Ok(audit_inventory_locations(self, backfill).await?)
+    }
+
+    async fn adjust_inventory_bulk(
+        &self,
+        inputs: Vec<AdjustInventoryInput>,
+        created_by: Uuid,
+    ) -> Result<Vec<MongoInventoryAdjustment>> {
+        let mut session = self.client.start_session(None).await?;
+        let options = SETTINGS.database.concern.transaction_options();
+        session.start_transaction(options).await?;
+        let adjustments =
+            match adjust_inventory_bulk_with_session(self, &inputs, created_by, &mut session).await
+            {
+                Ok(adjustments) => adjustments,
+                Err(e) => {
+                    session.abort_transaction().await?;
+                    return Err(e);
+                }
+            };
+        loop {
+            if let Err(ref error) = session.commit_transaction().await {
+                if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                    continue;
+                }
+            }
+            break;
+        }
+        Ok(adjustments)
+    }
+
+    async fn swap_location(
+        &self,
+        input: SwapLocationInput,
+        created_by: Uuid,
+    ) -> Result<MongoLocationSwap> {
+        if input.from == input.to || input.count == 0 {
+            return Err(Error::InvalidLocationSwap(format!(
+                "{} of {} from {:?} to {:?}",
+                input.count, input.item_code_ext, input.from, input.to
+            )));
+        }
+        let mut session = self.client.start_session(None).await?;
+        let options = SETTINGS.database.concern.transaction_options();
+        session.start_transaction(options).await?;
+        let swap = match swap_location_with_session(self, &input, created_by, &mut session).await {
+            Ok(swap) => swap,
+            Err(e) => {
+                session.abort_transaction().await?;
+                return Err(e);
+            }
+        };
+        loop {
+            if let Err(ref error) = session.commit_transaction().await {
+                if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                    continue;
+                }
+            }
+            break;
+        }
+        Ok(swap)
+    }
+
     async fn get_inventory_item_operations(
         &self,
         item_code_ext: &str,
@@ -33,12 +157,48 @@ impl InventoryRepo for DbClient {
         Ok(find_inventory_item_operations_by_item_code_ext(self, item_code_ext).await?)
     }
 
+    async fn get_inventory_item_ledger(&self, item_code_ext: &str) -> Result<Vec<LedgerEntry>> {
+        if find_inventory_by_item_code_ext(self, item_code_ext)
+            .await?
+            .is_none()
+        {
+            return Err(Error::InventoryItemNotFound(item_code_ext.to_owned()));
+        }
+        let operations =
+            find_inventory_item_operations_by_item_code_ext(self, item_code_ext).await?;
+        Ok(build_ledger(operations))
+    }
+
     async fn find_inventory_by_item_code_ext(
         &self,
         item_code_ext: &str,
     ) -> Result<Option<MongoInventoryItem>> {
         Ok(find_inventory_by_item_code_ext(self, item_code_ext).await?)
     }
+
+    async fn find_supply_sources(&self, item_code_ext: &str) -> Result<Vec<MongoSupplySource>> {
+        Ok(find_supply_sources(self, item_code_ext).await?)
+    }
+
+    async fn get_operations_by_related_id(
+        &self,
+        related_id: Uuid,
+    ) -> Result<Vec<MongoInventoryOperation>> {
+        Ok(find_operations_by_related_id(self, related_id).await?)
+    }
+
+    async fn find_operations_in_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        operation_type: Option<MongoOperationType>,
+        related_kind: Option<RelatedKind>,
+    ) -> Result<Vec<MongoInventoryOperation>> {
+        Ok(
+            find_operations_in_range(self, from.into(), to.into(), operation_type, related_kind)
+                .await?,
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -58,6 +218,55 @@ pub struct MongoInventoryOutput {
     pub created_at: mongodb::bson::DateTime,
     pub update_at: mongodb::bson::DateTime,
     pub operation_ids: Vec<Uuid>,
+    /// the total quantity of this variant over every location
+    /// is under the reorder point of its item.
+    #[serde(default)]
+    pub below_reorder: bool,
+}
+
+/// the total quantity below which each variant of an item should be reordered,
+/// keyed by `item_code_ext[0..11]`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoReorderPoint {
+    pub item_code: String,
+    pub min_quantity: u32,
+    pub update_at: mongodb::bson::DateTime,
+}
+
+/// a single stocktake correction, stored in the adjustments collection.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoInventoryAdjustment {
+    pub item_code_ext: String,
+    pub location: InventoryLocation,
+    pub delta: i32,
+    pub note: String,
+    pub operation_id: Uuid,
+    pub quantity: u32,
+}
+
+/// a register the item arrived by.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoSupplySource {
+    pub register_id: Uuid,
+    pub register_no: String,
+    pub arrival_date: mongodb::bson::DateTime,
+    pub location: InventoryLocation,
+    pub count: i32,
+}
+
+/// stock recorded at `from` but found at `to`,
+/// moved by a pair of `Move` operations related to the swap.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoLocationSwap {
+    pub id: Uuid,
+    pub created_at: mongodb::bson::DateTime,
+    pub created_by: Uuid,
+    pub item_code_ext: String,
+    pub from: InventoryLocation,
+    pub to: InventoryLocation,
+    pub count: u32,
+    pub reason: String,
+    pub operation_ids: Vec<Uuid>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy)]
@@ -66,7 +275,58 @@ pub struct Quantity {
     pub quantity: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Copy, EnumIter)]
+/// how a location is stored, displayed and treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocationInfo {
+    /// the value stored in the database, as same as the serde name.
+    pub code: &'static str,
+    pub kanji: &'static str,
+    /// items at this location have been paid for clearance.
+    pub paid: bool,
+    /// new inventory items are created with their stock at this location.
+    pub receives_new_stock: bool,
+}
+
+/// the single source of the locations, in the order of `InventoryLocation`'s variants.
+/// adding a location adds an entry here and a variant there.
+pub const LOCATIONS: &[LocationInfo] = &[
+    LocationInfo {
+        code: "jp",
+        kanji: "日本",
+        paid: false,
+        receives_new_stock: true,
+    },
+    LocationInfo {
+        code: "cn",
+        kanji: "中国",
+        paid: false,
+        receives_new_stock: false,
+    },
+    LocationInfo {
+        code: "pcn",
+        kanji: "中国済",
+        paid: true,
+        receives_new_stock: false,
+    },
+];
+
+impl LocationInfo {
+    /// quantity docs of a new inventory item, one per location of `locations`.
+    /// the locations receiving new stock will be set to `count` value.
+    pub fn quantity_docs(locations: &[LocationInfo], count: i32) -> Vec<Document> {
+        locations
+            .iter()
+            .map(|info| {
+                doc! {
+                  "location":info.code,
+                  "quantity":if info.receives_new_stock { count } else { 0 },
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, Copy, EnumIter)]
 #[serde(rename_all = "lowercase")]
 pub enum InventoryLocation {
     JP,
@@ -75,47 +335,34 @@ pub enum InventoryLocation {
 }
 
 impl InventoryLocation {
+    pub fn info(self) -> &'static LocationInfo {
+        &LOCATIONS[self as usize]
+    }
+
     /// will create a new inventory collection quantity docs
     /// and jp location will be set to `count` value
     pub fn create_new_inventory_quantity_docs(count: i32) -> Vec<Document> {
-        let mut docs = Vec::new();
-        for location in InventoryLocation::iter() {
-            match location {
-                InventoryLocation::JP => docs.push(doc! {
-                  "location":location,
-                  "quantity":count
-                }),
-                _ => docs.push(doc! {
-                  "location":location,
-                  "quantity":0
-                }),
-            }
-        }
-        docs
+        LocationInfo::quantity_docs(LOCATIONS, count)
     }
 
     pub fn kanjified(&self) -> String {
-        match self {
-            Self::JP => String::from("日本"),
-            Self::CN => String::from("中国"),
-            Self::PCN => String::from("中国済"),
-        }
+        self.info().kanji.to_string()
+    }
+
+    pub fn is_paid(self) -> bool {
+        self.info().paid
     }
 }
 
-impl From<InventoryLocation> for Bson {
-    fn from(l: InventoryLocation) -> Self {
-        match l {
-            InventoryLocation::CN => Bson::String(String::from("cn")),
-            InventoryLocation::JP => Bson::String(String::from("jp")),
-            InventoryLocation::PCN => Bson::String(String::from("pcn")),
-        }
+impl std::fmt::Display for InventoryLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info().code)
     }
 }
 
-impl InventoryLocation {
-    pub fn is_paid(self) -> bool {
-        matches!(self, InventoryLocation::PCN)
+impl From<InventoryLocation> for Bson {
+    fn from(l: InventoryLocation) -> Self {
+        Bson::String(l.info().code.to_string())
     }
 }
 
@@ -187,7 +434,7 @@ pub async fn find_inventory_item_operations_by_item_code_ext(
     }
     operations_vec[0]
         .operations
-        .sort_by(|a, b| b.time.cmp(&a.time));
+        .sort_by_key(|o| std::cmp::Reverse(o.time));
     Ok(operations_vec[0]
         .to_owned()
         .operations
@@ -195,6 +442,264 @@ pub async fn find_inventory_item_operations_by_item_code_ext(
         .collect())
 }
 
+/// an operation of the item with the per location balance right after it.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub operation: InventoryOperation,
+    pub balance: Vec<LedgerBalance>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerBalance {
+    pub location: InventoryLocation,
+    /// could be negative in the middle of a tampered history.
+    pub quantity: i32,
+}
+
+/// sort the operations in chronological order and accumulate their counts by location.
+pub fn build_ledger(mut operations: Vec<MongoInventoryOperation>) -> Vec<LedgerEntry> {
+    operations.sort_by_key(|o| o.time);
+    let mut balance = InventoryLocation::iter()
+        .map(|location| LedgerBalance {
+            location,
+            quantity: 0,
+        })
+        .collect::<Vec<_>>();
+    operations
+        .into_iter()
+        .map(|operation| {
+            if let Some(b) = balance
+                .iter_mut()
+                .find(|b| b.location == operation.location)
+            {
+                b.quantity += operation.count;
+            }
+            LedgerEntry {
+                operation: operation.into(),
+                balance: balance.clone(),
+            }
+        })
+        .collect()
+}
+
+/// an operation that took a location balance of the item below zero.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NegativeBalance {
+    pub item_code_ext: String,
+    pub location: InventoryLocation,
+    pub operation_id: Uuid,
+    /// the balance of the location right after the operation.
+    pub quantity: i32,
+}
+
+/// replays the operations of one item and reports every operation
+/// moving a location from a non negative balance below zero.
+pub fn find_negative_dips(
+    item_code_ext: &str,
+    operations: Vec<MongoInventoryOperation>,
+) -> Vec<NegativeBalance> {
+    let mut dips = Vec::new();
+    let mut previous = vec![0; LOCATIONS.len()];
+    for entry in build_ledger(operations) {
+        for (i, balance) in entry.balance.iter().enumerate() {
+            if balance.quantity < 0 && previous[i] >= 0 {
+                dips.push(NegativeBalance {
+                    item_code_ext: item_code_ext.to_owned(),
+                    location: balance.location,
+                    operation_id: entry.operation.id,
+                    quantity: balance.quantity,
+                });
+            }
+            previous[i] = balance.quantity;
+        }
+    }
+    dips
+}
+
+async fn find_reorder_points(db: &DbClient) -> Result<Vec<MongoReorderPoint>> {
+    let options = FindOptions::builder().sort(doc! {"item_code":1}).build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoReorderPoint>(REORDER_POINTS_COL)
+        .find(None, options)
+        .await?;
+    let mut points = Vec::new();
+    while let Some(point) = cursor.next().await {
+        points.push(point?);
+    }
+    Ok(points)
+}
+
+#[instrument(name = "set reorder point", skip(db))]
+async fn set_reorder_point(
+    db: &DbClient,
+    item_code: &str,
+    min_quantity: u32,
+) -> Result<MongoReorderPoint> {
+    if item_code.len() != 11
+        || !item_code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(Error::InvalidItemCode(item_code.to_owned()));
+    }
+    let point = MongoReorderPoint {
+        item_code: item_code.to_owned(),
+        min_quantity,
+        update_at: Local::now().into(),
+    };
+    let options = ReplaceOptions::builder().upsert(true).build();
+    db.ph_db
+        .collection::<MongoReorderPoint>(REORDER_POINTS_COL)
+        .replace_one(doc! {"item_code":item_code}, &point, options)
+        .await?;
+    info!("reorder point of {item_code} is set to {min_quantity}");
+    Ok(point)
+}
+
+#[instrument(name = "find negative balances", skip(db))]
+async fn find_negative_balances(db: &DbClient) -> Result<Vec<NegativeBalance>> {
+    let options = FindOptions::builder()
+        .sort(doc! {"item_code_ext":1,"time":1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+        .find(None, options)
+        .await?;
+    // operations come sorted by item, so only one item's operations are held at a time.
+    let mut dips = Vec::new();
+    let mut operations: Vec<MongoInventoryOperation> = Vec::new();
+    while let Some(operation) = cursor.next().await {
+        let operation = operation?;
+        if operations
+            .first()
+            .is_some_and(|first| first.item_code_ext != operation.item_code_ext)
+        {
+            let item_code_ext = operations[0].item_code_ext.clone();
+            dips.extend(find_negative_dips(
+                &item_code_ext,
+                std::mem::take(&mut operations),
+            ));
+        }
+        operations.push(operation);
+    }
+    if let Some(first) = operations.first() {
+        let item_code_ext = first.item_code_ext.clone();
+        dips.extend(find_negative_dips(&item_code_ext, operations));
+    }
+    Ok(dips)
+}
+
+/// arrival operations of the item joined to their registers, oldest arrival first.
+/// arrivals of deleted registers are left out.
+#[instrument(name = "find supply sources", skip(db))]
+async fn find_supply_sources(db: &DbClient, item_code_ext: &str) -> Result<Vec<MongoSupplySource>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "item_code_ext":item_code_ext,
+            "operation_type":MongoOperationType::Arrival,
+          }
+        },
+        doc! {
+          "$lookup":{
+            "from":REGISTERS_COL,
+            "localField":"related_id",
+            "foreignField":"id",
+            "as":"register",
+          }
+        },
+        doc! {"$unwind":"$register"},
+        doc! {"$sort":{"register.arrival_date":1,"time":1}},
+        doc! {
+          "$project":{
+            "_id":0,
+            "register_id":"$register.id",
+            "register_no":"$register.no",
+            "arrival_date":"$register.arrival_date",
+            "location":1,
+            "count":1,
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut sources = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        sources.push(bson::from_document(doc?)?);
+    }
+    Ok(sources)
+}
+
+pub async fn find_operations_by_related_id(
+    db: &DbClient,
+    related_id: Uuid,
+) -> Result<Vec<MongoInventoryOperation>> {
+    let filter = doc! {
+      "related_id":related_id,
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! {"time":1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+        .find(filter, options)
+        .await?;
+    let mut operations = Vec::new();
+    while let Some(operation) = cursor.next().await {
+        operations.push(operation?)
+    }
+    Ok(operations)
+}
+
+async fn find_operations_in_range(
+    db: &DbClient,
+    from: bson::DateTime,
+    to: bson::DateTime,
+    operation_type: Option<MongoOperationType>,
+    related_kind: Option<RelatedKind>,
+) -> Result<Vec<MongoInventoryOperation>> {
+    let mut filter = doc! {
+      "time":{"$gte":from,"$lt":to},
+    };
+    if let Some(operation_type) = operation_type {
+        filter.insert("operation_type", operation_type);
+    }
+    let mut pipeline = vec![doc! {"$match":filter}];
+    // keep the operations whose related id is found in the kind's collection only.
+    if let Some(related_kind) = related_kind {
+        pipeline.push(doc! {
+          "$lookup":{
+            "from":related_kind.collection(),
+            "localField":"related_id",
+            "foreignField":"id",
+            "as":"related",
+          }
+        });
+        pipeline.push(doc! {"$match":{"related":{"$ne":[]}}});
+        pipeline.push(doc! {"$project":{"related":0}});
+    }
+    pipeline.push(doc! {"$sort":{"time":1,"_id":1}});
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut operations = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        operations.push(bson::from_document(doc?)?)
+    }
+    Ok(operations)
+}
+
 // pub async fn find_inventory_operation_by_id(
 //   db: &DbClient,
 //   operation_id: &str,
@@ -212,10 +717,9 @@ pub async fn find_inventory_item_operations_by_item_code_ext(
 
 const ITEMS_PER_PAGE_LOCAL: u32 = 35;
 
-async fn query_inventory(
-    db: &DbClient,
-    query: InventoryQuery,
-) -> Result<(bool, Vec<MongoInventoryOutput>)> {
+/// stages joining items and filtering inventory by the query.
+/// category filter is skipped when `with_category` is false.
+fn inventory_query_pipeline(query: &InventoryQuery, with_category: bool) -> Vec<Document> {
     let mut pipeline = vec![
         doc! {
           "$addFields":{
@@ -265,7 +769,7 @@ async fn query_inventory(
         })
     }
 
-    if let Some(location) = query.location {
+    if let Some(location) = &query.location {
         let locations: Vec<&str> = location.rsplit(',').collect();
         pipeline.push(doc! {
           "$match":{
@@ -281,20 +785,18 @@ async fn query_inventory(
         })
     }
 
-    if let Some(category) = query.category {
-        let category_content = category.to_concrete_content();
-        let category_vec: Vec<&str> = category_content.trim().split(' ').collect();
-        let mut or_value = vec![];
-        for category in category_vec {
-            or_value.push(doc! {"item.category":category});
-        }
-        pipeline.push(doc! {
-          "$match":{
-              "$or": or_value
-          }
-        })
-    }
-    if !query.keyword.is_empty() {
+    if let Some(codes) = &query.codes {
+        // matched first so only the requested items go through the lookups.
+        let codes: Vec<&str> = codes.split(',').map(str::trim).collect();
+        pipeline.insert(
+            0,
+            doc! {
+              "$match":{
+                "item_code_ext":{"$in":codes}
+              }
+            },
+        );
+    } else if !query.keyword.is_empty() {
         pipeline.push(doc! {
           "$match":{
           "$or":[
@@ -316,6 +818,46 @@ async fn query_inventory(
         }
         })
     }
+    if let Some(category) = query.category.as_ref().filter(|_| with_category) {
+        let category_content = category.to_concrete_content();
+        let category_vec: Vec<&str> = category_content.trim().split(' ').collect();
+        let mut or_value = vec![];
+        for category in category_vec {
+            or_value.push(doc! {"item.category":category});
+        }
+        pipeline.push(doc! {
+          "$match":{
+              "$or": or_value
+          }
+        })
+    }
+    pipeline
+}
+
+/// `inventory_query_pipeline` projected into `MongoInventoryOutput`.
+fn inventory_output_pipeline(query: &InventoryQuery) -> Vec<Document> {
+    let mut pipeline = inventory_query_pipeline(query, true);
+    pipeline.push(doc! {
+      "$lookup":{
+        "from":REORDER_POINTS_COL,
+        "localField":"item_code",
+        "foreignField":"item_code",
+        "as":"reorder_point",
+      }
+    });
+    pipeline.push(doc! {
+      "$addFields":{
+        "below_reorder":{
+          "$let":{
+            "vars":{"point":{"$arrayElemAt":["$reorder_point",0]}},
+            "in":{"$and":[
+              {"$ne":[{"$type":"$$point"},"missing"]},
+              {"$lt":[{"$sum":"$quantity.quantity"},"$$point.min_quantity"]},
+            ]},
+          }
+        }
+      }
+    });
     pipeline.push(doc! {
       "$project":{
         "item_code_pre":0,
@@ -324,9 +866,19 @@ async fn query_inventory(
         "item":0,
         "color_no":0,
         "size_no":0,
+        "reorder_point":0,
       }
     });
+    pipeline
+}
+
+async fn query_inventory(
+    db: &DbClient,
+    query: InventoryQuery,
+) -> Result<(bool, Vec<MongoInventoryOutput>)> {
+    let mut pipeline = inventory_output_pipeline(&query);
     if query.page.is_none() {
+        let timer = QueryTimer::start("query inventory", &pipeline);
         let mut cursor = db
             .ph_db
             .collection::<MongoInventoryItem>(INVENTORY_COL)
@@ -336,6 +888,7 @@ async fn query_inventory(
         while let Some(doc) = cursor.next().await {
             items.push(bson::from_document(doc?)?)
         }
+        timer.finish();
         return Ok((false, items));
     }
 
@@ -350,6 +903,7 @@ async fn query_inventory(
         "$skip":skip
     });
 
+    let timer = QueryTimer::start("query inventory", &pipeline);
     let mut cursor = db
         .ph_db
         .collection::<MongoInventoryItem>(INVENTORY_COL)
@@ -359,8 +913,336 @@ async fn query_inventory(
     while let Some(doc) = cursor.next().await {
         items.push(bson::from_document(doc?)?)
     }
+    timer.finish();
     Ok(((items.len() as u32) == ITEMS_PER_PAGE_LOCAL, items))
 }
+
+/// matched inventory items in the order of `query_inventory`, decoded one by one
+/// from the cursor. `page` of the query is ignored.
+async fn stream_inventory(
+    db: &DbClient,
+    query: &InventoryQuery,
+) -> Result<BoxStream<'static, Result<MongoInventoryOutput>>> {
+    let cursor = db
+        .ph_db
+        .collection::<MongoInventoryItem>(INVENTORY_COL)
+        .aggregate(inventory_output_pipeline(query), None)
+        .await?;
+    Ok(cursor
+        .map(|doc| Ok(bson::from_document::<MongoInventoryOutput>(doc?)?))
+        .boxed())
+}
+
+/// count matched inventory items by category.
+/// the category filter itself is not applied so counts of every category are available.
+async fn query_inventory_facets(
+    db: &DbClient,
+    query: &InventoryQuery,
+) -> Result<HashMap<String, u64>> {
+    let mut pipeline = inventory_query_pipeline(query, false);
+    pipeline.push(doc! {
+      "$group":{
+        "_id":{"$ifNull":[{"$arrayElemAt":["$item.category",0]},""]},
+        "count":{"$sum":1},
+      }
+    });
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryItem>(INVENTORY_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut facets = HashMap::new();
+    while let Some(doc) = cursor.next().await {
+        let doc = doc?;
+        let category = doc.get_str("_id").unwrap_or_default().to_string();
+        let count = match doc.get("count") {
+            Some(Bson::Int32(c)) => *c as u64,
+            Some(Bson::Int64(c)) => *c as u64,
+            _ => 0,
+        };
+        facets.insert(category, count);
+    }
+    Ok(facets)
+}
+/// inventory items in stock which have no order item created since `since`.
+async fn find_dead_stock(
+    db: &DbClient,
+    since: mongodb::bson::DateTime,
+) -> Result<Vec<MongoInventoryOutput>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "quantity":{
+              "$elemMatch":{
+                "quantity":{
+                  "$gt":0
+                }
+              }
+            }
+          }
+        },
+        doc! {
+          "$lookup":{
+            "from":ORDER_ITEMS_COL,
+            "let":{"code":"$item_code_ext"},
+            "pipeline":[
+              {"$match":{
+                "$expr":{
+                  "$and":[
+                    {"$eq":["$item_code_ext","$$code"]},
+                    {"$gte":["$created_at",since]},
+                  ]
+                }
+              }},
+              {"$limit":1},
+            ],
+            "as":"recent_order_items",
+          }
+        },
+        doc! {
+          "$match":{
+            "recent_order_items":{"$size":0}
+          }
+        },
+        doc! {
+          "$project":{
+            "recent_order_items":0,
+          }
+        },
+        doc! {
+          "$sort":{
+            "item_code_ext":1
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryItem>(INVENTORY_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut items = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        items.push(bson::from_document(doc?)?)
+    }
+    Ok(items)
+}
+
+/// an inventory item whose quantity does not hold every location exactly once.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationAudit {
+    pub item_code_ext: String,
+    pub missing: Vec<InventoryLocation>,
+    pub duplicated: Vec<InventoryLocation>,
+    pub backfilled: bool,
+}
+
+#[instrument(name = "audit inventory locations", skip(db))]
+async fn audit_inventory_locations(db: &DbClient, backfill: bool) -> Result<Vec<LocationAudit>> {
+    let locations = InventoryLocation::iter().collect::<Vec<_>>();
+    let mut or_value = locations
+        .iter()
+        .map(|location| doc! {"quantity.location":{"$ne":location}})
+        .collect::<Vec<_>>();
+    or_value.push(doc! {
+      "$expr":{"$ne":[{"$size":"$quantity"},locations.len() as i64]}
+    });
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryItem>(INVENTORY_COL)
+        .find(doc! {"$or":or_value}, None)
+        .await?;
+    let mut audits = Vec::new();
+    while let Some(inventory) = cursor.next().await {
+        let inventory = inventory?;
+        let mut seen = HashSet::new();
+        let mut duplicated = Vec::new();
+        for quantity in inventory.quantity.iter() {
+            if !seen.insert(quantity.location) && !duplicated.contains(&quantity.location) {
+                duplicated.push(quantity.location);
+            }
+        }
+        let missing = locations
+            .iter()
+            .filter(|location| !seen.contains(*location))
+            .copied()
+            .collect::<Vec<_>>();
+        let backfilled = backfill && !missing.is_empty();
+        if backfilled {
+            info!(
+                "backfill locations {:?} of {}",
+                missing, inventory.item_code_ext
+            );
+            let quantities = missing
+                .iter()
+                .map(|location| doc! {"location":location,"quantity":0})
+                .collect::<Vec<_>>();
+            db.ph_db
+                .collection::<MongoInventoryItem>(INVENTORY_COL)
+                .update_one(
+                    doc! {"item_code_ext":&inventory.item_code_ext},
+                    doc! {
+                      "$push":{"quantity":{"$each":quantities}},
+                      "$set":{"update_at":Local::now()},
+                    },
+                    None,
+                )
+                .await?;
+        }
+        audits.push(LocationAudit {
+            item_code_ext: inventory.item_code_ext,
+            missing,
+            duplicated,
+            backfilled,
+        });
+    }
+    Ok(audits)
+}
+
+async fn find_inventory_changed_since(
+    db: &DbClient,
+    since: mongodb::bson::DateTime,
+) -> Result<Vec<MongoInventoryOutput>> {
+    let filter = doc! {
+      "update_at":{"$gt":since}
+    };
+    let options = FindOptions::builder()
+        .sort(doc! {"update_at":1,"item_code_ext":1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOutput>(INVENTORY_COL)
+        .find(filter, options)
+        .await?;
+    let mut items = Vec::new();
+    while let Some(item) = cursor.next().await {
+        items.push(item?)
+    }
+    Ok(items)
+}
+
+/// an item/location whose guaranteed order items are not covered by its reservations.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GuaranteeMismatch {
+    pub item_code_ext: String,
+    pub location: InventoryLocation,
+    pub guaranteed: u32,
+    /// units taken from stock by ordered operations and not shipped yet.
+    pub reserved: u32,
+    pub in_stock: u32,
+}
+
+#[derive(Deserialize, Debug, Hash, PartialEq, Eq)]
+struct ItemLocationKey {
+    item_code_ext: String,
+    location: InventoryLocation,
+}
+
+#[derive(Deserialize, Debug)]
+struct ItemLocationCount {
+    _id: ItemLocationKey,
+    guaranteed: i64,
+    shipped: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ItemLocationReserved {
+    _id: ItemLocationKey,
+    reserved: i64,
+}
+
+/// guaranteeing an order item takes its unit from stock by an ordered operation,
+/// so guaranteed and shipped items of an item/location never outnumber the ordered units.
+#[instrument(name = "find guarantee mismatches", skip(db))]
+async fn find_guarantee_mismatches(db: &DbClient) -> Result<Vec<GuaranteeMismatch>> {
+    let items_pipeline = vec![
+        doc! {
+          "$match":{
+            "status":{"$in":[OrderItemStatus::Guaranteed,OrderItemStatus::Shipped]},
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":{"item_code_ext":"$item_code_ext","location":"$location"},
+            "guaranteed":{"$sum":{"$cond":[{"$eq":["$status",OrderItemStatus::Guaranteed]},1,0]}},
+            "shipped":{"$sum":{"$cond":[{"$eq":["$status",OrderItemStatus::Shipped]},1,0]}},
+          }
+        },
+        doc! {
+          "$match":{
+            "guaranteed":{"$gt":0},
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .aggregate(items_pipeline, None)
+        .await?;
+    let mut counts = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        counts.push(bson::from_document::<ItemLocationCount>(doc?)?);
+    }
+    if counts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let codes = counts
+        .iter()
+        .map(|c| c._id.item_code_ext.as_str())
+        .collect::<Vec<_>>();
+    let operations_pipeline = vec![
+        doc! {
+          "$match":{
+            "item_code_ext":{"$in":codes},
+            "operation_type":MongoOperationType::Ordered,
+            "countered":false,
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":{"item_code_ext":"$item_code_ext","location":"$location"},
+            "reserved":{"$sum":{"$multiply":["$count",-1]}},
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(OPERATIONS_COL)
+        .aggregate(operations_pipeline, None)
+        .await?;
+    let mut reserved = HashMap::new();
+    while let Some(doc) = cursor.next().await {
+        let output = bson::from_document::<ItemLocationReserved>(doc?)?;
+        reserved.insert(output._id, output.reserved);
+    }
+    let mut mismatches = Vec::new();
+    for count in counts {
+        let available = reserved.get(&count._id).copied().unwrap_or(0) - count.shipped;
+        if count.guaranteed <= available {
+            continue;
+        }
+        let in_stock = find_inventory_by_item_code_ext(db, &count._id.item_code_ext)
+            .await?
+            .and_then(|i| {
+                i.quantity
+                    .into_iter()
+                    .find(|q| q.location == count._id.location)
+            })
+            .map(|q| q.quantity)
+            .unwrap_or(0);
+        mismatches.push(GuaranteeMismatch {
+            item_code_ext: count._id.item_code_ext,
+            location: count._id.location,
+            guaranteed: count.guaranteed as u32,
+            reserved: available.max(0) as u32,
+            in_stock,
+        });
+    }
+    mismatches.sort_by(|a, b| a.item_code_ext.cmp(&b.item_code_ext));
+    Ok(mismatches)
+}
+
 pub async fn find_inventory_by_item_code_ext(
     db: &DbClient,
     item_code_ext: &str,
@@ -394,24 +1276,51 @@ pub async fn find_inventory_by_item_code_ext_with_session(
     Ok(res)
 }
 
-pub async fn shift_inventory_quantity(
+/// check the requested distribution of `item_code_ext` before any `Move` operation is issued.
+/// every location of the inventory should be requested exactly once,
+/// and the requested total should equal to the current total.
+pub async fn check_shift_inventory_quantity(
     db: &DbClient,
     item_code_ext: &str,
     quantity: &[Quantity],
-    related_id: Uuid,
-) -> Result<Vec<Uuid>> {
-    let mut operation_ids = Vec::new();
-    let inventory_opt = find_inventory_by_item_code_ext(db, item_code_ext).await?;
-    if inventory_opt.is_none() {
-        return Err(Error::InventoryNotFound);
+) -> Result<MongoInventoryItem> {
+    let inventory = find_inventory_by_item_code_ext(db, item_code_ext)
+        .await?
+        .ok_or(Error::InventoryNotFound)?;
+    let mut requested_locations = HashSet::new();
+    for requested in quantity.iter() {
+        if !requested_locations.insert(requested.location) {
+            return Err(Error::InvalidTransferQuantity(format!(
+                "location {:?} of {item_code_ext} is requested more than once",
+                requested.location
+            )));
+        }
+    }
+    for current in inventory.quantity.iter() {
+        if !requested_locations.remove(&current.location) {
+            return Err(Error::InvalidTransferQuantity(format!(
+                "location {:?} of {item_code_ext} is not requested",
+                current.location
+            )));
+        }
+    }
+    if let Some(location) = requested_locations.iter().next() {
+        return Err(Error::InvalidTransferQuantity(format!(
+            "location {location:?} of {item_code_ext} is not in inventory"
+        )));
     }
-    let inventory = inventory_opt.unwrap();
     // check if the requested accumulated inventory quantity of
     // every location equal to current
     let current_quantity = inventory.quantity.iter().fold(0, |mut acc, current_q| {
         acc += current_q.quantity;
         acc
     });
+    if let Some(requested) = quantity.iter().find(|q| q.quantity > current_quantity) {
+        return Err(Error::InvalidTransferQuantity(format!(
+            "quantity {} at {:?} of {item_code_ext} exceeds the total {current_quantity}",
+            requested.quantity, requested.location
+        )));
+    }
     let request_quantity = quantity.iter().fold(0, |mut acc, current_q| {
         acc += current_q.quantity;
         acc
@@ -419,11 +1328,23 @@ pub async fn shift_inventory_quantity(
     if current_quantity != request_quantity {
         return Err(Error::Changed);
     }
-    //////////////////////////////////////////////////////////
+    Ok(inventory)
+}
 
-    let zip = inventory.quantity.iter().zip(quantity);
+pub async fn shift_inventory_quantity(
+    db: &DbClient,
+    item_code_ext: &str,
+    quantity: &[Quantity],
+    related_id: Uuid,
+) -> Result<Vec<Uuid>> {
+    let mut operation_ids = Vec::new();
+    let inventory = check_shift_inventory_quantity(db, item_code_ext, quantity).await?;
     // loop over every location
-    for (current_quantity, requested_quantity) in zip {
+    for current_quantity in inventory.quantity.iter() {
+        let requested_quantity = quantity
+            .iter()
+            .find(|q| q.location == current_quantity.location)
+            .ok_or(Error::Changed)?;
         if current_quantity.quantity == requested_quantity.quantity {
             continue;
         }
@@ -445,3 +1366,153 @@ pub async fn shift_inventory_quantity(
 
     Ok(operation_ids)
 }
+
+#[instrument(name = "swap location inner", skip(db, session))]
+async fn swap_location_with_session(
+    db: &DbClient,
+    input: &SwapLocationInput,
+    created_by: Uuid,
+    session: &mut ClientSession,
+) -> Result<MongoLocationSwap> {
+    let inventory = find_inventory_by_item_code_ext_with_session(db, &input.item_code_ext, session)
+        .await?
+        .ok_or_else(|| Error::InventoryItemNotFound(input.item_code_ext.clone()))?;
+    let current = inventory
+        .quantity
+        .iter()
+        .find(|q| q.location == input.from)
+        .map(|q| q.quantity)
+        .unwrap_or(0);
+    if current < input.count {
+        return Err(Error::InventoryQuantityNegative(
+            input.item_code_ext.clone(),
+            format!("{:?}", input.from),
+        ));
+    }
+    let swap_id = Uuid::new();
+    let mut operation_ids = Vec::with_capacity(2);
+    for (count, location) in [
+        (-(input.count as i32), input.from),
+        (input.count as i32, input.to),
+    ] {
+        let operation_id = MongoInventoryOperation::new(
+            &input.item_code_ext,
+            swap_id,
+            MongoOperationType::Move,
+            count,
+            location,
+        )
+        .run_self_with_session(db, false, session)
+        .await?;
+        operation_ids.push(operation_id);
+    }
+    let swap = MongoLocationSwap {
+        id: swap_id,
+        created_at: bson::DateTime::now(),
+        created_by,
+        item_code_ext: input.item_code_ext.clone(),
+        from: input.from,
+        to: input.to,
+        count: input.count,
+        reason: input.reason.trim().to_owned(),
+        operation_ids,
+    };
+    db.ph_db
+        .collection::<MongoLocationSwap>(LOCATION_SWAPS_COL)
+        .insert_one_with_session(&swap, None, session)
+        .await?;
+    info!("swap location id:{swap_id} success");
+    Ok(swap)
+}
+
+#[instrument(name = "adjust inventory bulk inner", skip(db, inputs, session))]
+async fn adjust_inventory_bulk_with_session(
+    db: &DbClient,
+    inputs: &[AdjustInventoryInput],
+    created_by: Uuid,
+    session: &mut ClientSession,
+) -> Result<Vec<MongoInventoryAdjustment>> {
+    let adjustment_id = Uuid::new();
+    let mut adjustments = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let inventory =
+            match find_inventory_by_item_code_ext_with_session(db, &input.item_code_ext, session)
+                .await?
+            {
+                Some(inventory) => inventory,
+                None => {
+                    info!(
+                        "code:{} not found,create new empty inventory item",
+                        &input.item_code_ext
+                    );
+                    MongoInventoryOperation::new(
+                        &input.item_code_ext,
+                        adjustment_id,
+                        MongoOperationType::CreateEmpty,
+                        0,
+                        InventoryLocation::JP,
+                    )
+                    .run_self_with_session(db, true, session)
+                    .await?;
+                    find_inventory_by_item_code_ext_with_session(db, &input.item_code_ext, session)
+                        .await?
+                        .ok_or_else(|| Error::InventoryItemNotFound(input.item_code_ext.clone()))?
+                }
+            };
+        let current = inventory
+            .quantity
+            .iter()
+            .find(|q| q.location == input.location)
+            .map(|q| q.quantity)
+            .unwrap_or(0);
+        let quantity = current as i64 + input.delta as i64;
+        if quantity < 0 {
+            return Err(Error::InventoryQuantityNegative(
+                input.item_code_ext.clone(),
+                format!("{:?}", input.location),
+            ));
+        }
+        let operation_id = MongoInventoryOperation::new(
+            &input.item_code_ext,
+            adjustment_id,
+            MongoOperationType::Adjust,
+            input.delta,
+            input.location,
+        )
+        .run_self_with_session(db, false, session)
+        .await?;
+        adjustments.push(MongoInventoryAdjustment {
+            item_code_ext: input.item_code_ext.clone(),
+            location: input.location,
+            delta: input.delta,
+            note: input.note.trim().to_owned(),
+            operation_id,
+            quantity: quantity as u32,
+        });
+    }
+    let items = adjustments
+        .iter()
+        .map(|a| {
+            doc! {
+              "item_code_ext":&a.item_code_ext,
+              "location":a.location,
+              "delta":a.delta,
+              "note":&a.note,
+              "operation_id":a.operation_id,
+              "quantity":a.quantity,
+            }
+        })
+        .collect::<Vec<_>>();
+    let doc = doc! {
+      "id":adjustment_id,
+      "created_at":bson::DateTime::now(),
+      "created_by":created_by,
+      "items":items,
+    };
+    db.ph_db
+        .collection(ADJUSTMENTS_COL)
+        .insert_one_with_session(doc, None, session)
+        .await?;
+    info!("adjust inventory id:{adjustment_id} success");
+    Ok(adjustments)
+}
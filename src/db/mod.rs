@@ -1,45 +1,76 @@
+pub mod activity;
 pub mod auth;
 pub mod invenope;
 pub mod inventory;
 pub mod mongo;
+pub mod notification;
 pub mod order;
 pub mod register;
 pub mod retrn;
 pub mod shipment;
+pub mod slow_query;
 pub mod transfer;
 
 use crate::{
+    configuration::RoundingStrategy,
     error_result::Result,
     server::{
-        inventory::InventoryQuery, retrn::NewReturnInputItem, transfer::NewTransferInputItem,
-        NewShipmentInput, OrderRegisterInput,
+        inventory::{AdjustInventoryInput, InventoryQuery, SwapLocationInput},
+        retrn::NewReturnInputItem,
+        transfer::NewTransferInputItem,
+        InputOrderItem, NewShipmentInput, OrderRegisterInput,
     },
 };
 use axum::async_trait;
-use chrono::{serde::ts_seconds, DateTime, Utc};
+use chrono::{
+    serde::{ts_seconds, ts_seconds_option},
+    DateTime, Utc,
+};
+use futures::stream::BoxStream;
 use mongodb::{
     bson::doc,
     bson::{oid::ObjectId, Bson, Uuid},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum::EnumIter;
 
 use self::{
-    auth::User,
-    invenope::{MongoInventoryOperation, MongoOperationType},
-    inventory::{InventoryLocation, MongoInventoryItem, MongoInventoryOutput, Quantity},
+    activity::{ActivityKind, MongoActivity},
+    auth::{Invite, QueryPreset, RecentResource, User},
+    invenope::{MongoInventoryOperation, MongoOperationType, RelatedKind},
+    inventory::{
+        GuaranteeMismatch, InventoryLocation, LedgerEntry, LocationAudit, MongoInventoryAdjustment,
+        MongoInventoryItem, MongoInventoryOutput, MongoLocationSwap, MongoReorderPoint,
+        MongoSupplySource, NegativeBalance, Quantity,
+    },
     mongo::{DbClient, ITEMS_COL},
+    notification::MongoPendingNotification,
     order::{
-        ConcealItemOutput, DeleteOrderOutput, MongoOrderItem, MongoOrderOutput, OrderItemStatus,
+        ConcealItemOutput, DeleteOrderOutput, LocationCount, MongoOrderItem, MongoOrderOutput,
+        OrderCursor, OrderItemPlan, OrderItemStatus, StatusCount,
     },
     register::{MongoRegisterItem, MongoRegisterOutput},
     retrn::{MongoReturnItem, MongoReturnOutput},
-    shipment::{MongoShipment, MongoShipmentOutput, ShipmentStatus, ShipmentVendor},
-    transfer::{MongoTransfer, MongoTransferOutput},
+    shipment::{
+        DuplicateShipmentItem, MongoShipment, MongoShipmentCounts, MongoShipmentOutput,
+        ShipmentInconsistency, ShipmentStatus, ShipmentVendor,
+    },
+    transfer::{MongoTransfer, MongoTransferItem, MongoTransferOutput, TransferSummaryRow},
 };
 
 #[async_trait]
 pub trait PhDataBase: Send + Sync + 'static {
-    async fn find_one_by_item_code(&self, code: &str) -> Result<Option<PhItem>>;
+    /// the catalog item of `code`, only searched in `bucket` if it is given.
+    /// without a bucket any item of the code may be returned.
+    async fn find_one_by_item_code(
+        &self,
+        code: &str,
+        bucket: Option<&str>,
+    ) -> Result<Option<PhItem>>;
+
+    /// catalog items of any of `codes` in any bucket.
+    async fn find_by_item_codes(&self, codes: &[String]) -> Result<Vec<PhItem>>;
 }
 
 #[async_trait]
@@ -67,21 +98,105 @@ pub trait InventoryRepo: Send + Sync + 'static {
         query: InventoryQuery,
     ) -> Result<(bool, Vec<MongoInventoryOutput>)>;
 
+    /// count of matched inventory items grouped by item category.
+    async fn query_inventory_facets(&self, query: &InventoryQuery) -> Result<HashMap<String, u64>>;
+
+    /// same items as `query_inventory` without paging, without collecting them in memory.
+    async fn stream_inventory(
+        &self,
+        query: &InventoryQuery,
+    ) -> Result<BoxStream<'static, Result<MongoInventoryOutput>>>;
+
+    /// inventory items in stock but not ordered since `since`.
+    async fn find_dead_stock(&self, since: DateTime<Utc>) -> Result<Vec<MongoInventoryOutput>>;
+
+    /// inventory items updated after `since`, oldest update first.
+    async fn find_inventory_changed_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MongoInventoryOutput>>;
+
+    /// item/locations having more guaranteed order items than their ordered reservations.
+    async fn find_guarantee_mismatches(&self) -> Result<Vec<GuaranteeMismatch>>;
+
+    /// operations that historically drove a location balance of their item below zero.
+    async fn find_negative_balances(&self) -> Result<Vec<NegativeBalance>>;
+
+    /// every reorder point, sorted by item code.
+    async fn find_reorder_points(&self) -> Result<Vec<MongoReorderPoint>>;
+
+    /// create or replace the reorder point of `item_code`.
+    async fn set_reorder_point(
+        &self,
+        item_code: &str,
+        min_quantity: u32,
+    ) -> Result<MongoReorderPoint>;
+
+    /// inventory items whose quantity does not hold every location exactly once.
+    /// missing locations are added with 0 quantity if `backfill` is true.
+    async fn audit_inventory_locations(&self, backfill: bool) -> Result<Vec<LocationAudit>>;
+
+    /// run all adjustments in one transaction, missing items will be created.
+    /// nothing is changed if any of them failed.
+    async fn adjust_inventory_bulk(
+        &self,
+        inputs: Vec<AdjustInventoryInput>,
+        created_by: Uuid,
+    ) -> Result<Vec<MongoInventoryAdjustment>>;
+
+    /// move `count` of an item recorded at `from` to `to` in one transaction.
+    /// will return error if `from` has less than `count`.
+    async fn swap_location(
+        &self,
+        input: SwapLocationInput,
+        created_by: Uuid,
+    ) -> Result<MongoLocationSwap>;
+
     async fn get_inventory_item_operations(
         &self,
         item_code_ext: &str,
     ) -> Result<Vec<MongoInventoryOperation>>;
 
+    /// operations of the item in chronological order with running balances.
+    async fn get_inventory_item_ledger(&self, item_code_ext: &str) -> Result<Vec<LedgerEntry>>;
+
+    /// registers the item arrived by, see `MongoSupplySource`.
+    async fn find_supply_sources(&self, item_code_ext: &str) -> Result<Vec<MongoSupplySource>>;
+
     async fn find_inventory_by_item_code_ext(
         &self,
         item_code_ext: &str,
     ) -> Result<Option<MongoInventoryItem>>;
+
+    /// find operations caused by the order/register/transfer/return which has `related_id`.
+    async fn get_operations_by_related_id(
+        &self,
+        related_id: Uuid,
+    ) -> Result<Vec<MongoInventoryOperation>>;
+
+    /// operations happened in `[from, to)` ordered by time, optionally of one type only
+    /// and caused by one kind of entity only.
+    async fn find_operations_in_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        operation_type: Option<MongoOperationType>,
+        related_kind: Option<RelatedKind>,
+    ) -> Result<Vec<MongoInventoryOperation>>;
 }
 
 #[async_trait]
 pub trait OrderRepo: Send + Sync + 'static {
     async fn create_order(&self, input: OrderRegisterInput) -> Result<()>;
 
+    /// the guaranteed/back ordering plan of `input` against the current inventory.
+    /// nothing is written.
+    async fn preview_order(&self, input: &OrderRegisterInput) -> Result<Vec<OrderItemPlan>>;
+
+    /// the plans of `items` of any orders, the stock is consumed in input order.
+    /// nothing is written.
+    async fn preview_order_items(&self, items: &[InputOrderItem]) -> Result<Vec<OrderItemPlan>>;
+
     async fn query_orders(
         &self,
         keyword: &str,
@@ -91,10 +206,26 @@ pub trait OrderRepo: Send + Sync + 'static {
         page: Option<u32>,
     ) -> Result<(bool, Vec<MongoOrderOutput>)>;
 
+    /// a page of `query_orders` after `cursor` instead of a page number.
+    async fn query_orders_after(
+        &self,
+        keyword: &str,
+        status: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        cursor: Option<OrderCursor>,
+    ) -> Result<Vec<MongoOrderOutput>>;
+
     /// why need this? frond end will load order first then load its order items.
     /// because order items need be update their state independently.
     async fn get_order_by_id(&self, id: Uuid) -> Result<MongoOrderOutput>;
 
+    /// inventory operations the order caused.
+    async fn get_order_operations(&self, id: Uuid) -> Result<Vec<MongoInventoryOperation>>;
+
+    /// orders of provided ids, missing ids are skipped.
+    async fn get_orders_by_ids(&self, ids: &[Uuid]) -> Result<Vec<MongoOrderOutput>>;
+
     async fn get_order_by_taobao_no(&self, taobao_order_no: &str) -> Result<Vec<MongoOrderOutput>>;
     /// delete an order. if its related order items is guaranteed order item.
     /// this will release guaranteed inventory.
@@ -109,6 +240,9 @@ pub trait OrderRepo: Send + Sync + 'static {
 
     async fn get_order_item_by_id(&self, order_item_id: Uuid) -> Result<MongoOrderItem>;
 
+    /// order items in the order of `ids`, fails if any of them can not be shipped.
+    async fn get_shippable_order_items(&self, ids: &[Uuid]) -> Result<Vec<MongoOrderItem>>;
+
     /// update the order's note and this will update order's
     /// related order items' note as well.
     /// and update their update_at field.
@@ -129,7 +263,59 @@ pub trait OrderRepo: Send + Sync + 'static {
         items: Vec<RegisterItem>,
     ) -> Result<Vec<MongoOrderItem>>;
 
+    /// order items of the scanned `item_code_ext` in `status` at any location, newest first.
+    async fn locate_order_items(
+        &self,
+        item_code_ext: &str,
+        status: &OrderItemStatus,
+    ) -> Result<Vec<MongoOrderItem>>;
+
+    /// order items with a rate out of range, e.g. legacy or manually edited ones.
+    async fn find_invalid_rate_order_items(&self) -> Result<Vec<MongoOrderItem>>;
+
+    /// guaranteed order items per location.
+    async fn count_awaiting_shipment_items(&self) -> Result<Vec<LocationCount>>;
+
+    /// distinct customer ids starting with `prefix` case-insensitively, sorted.
+    /// at most `CUSTOMER_IDS_LIMIT` ids.
+    async fn find_customer_ids_by_prefix(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// orders ordered within the range having at least one item in each status.
+    async fn count_orders_by_status(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StatusCount>>;
+
+    /// guaranteed order items of the customer ordered within the range, oldest first.
+    async fn find_guaranteed_items_by_customer(
+        &self,
+        customer_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<MongoOrderItem>>;
+
     async fn update_order_item_rate(&self, id: Uuid, rate: f64) -> Result<()>;
+
+    /// set `customer_id_normalized` of orders and order items stored before it existed.
+    /// will return the number of updated documents.
+    async fn backfill_customer_id_normalized(&self) -> Result<u64>;
+
+    /// hold a guaranteed order item releasing its reservation when `hold` is true,
+    /// otherwise guarantee a held order item again.
+    async fn update_order_item_hold(&self, id: Uuid, hold: bool) -> Result<MongoOrderItem>;
+
+    /// reopen a concealed order item, it is guaranteed again if inventory is left
+    /// at its location, otherwise back to backordering.
+    async fn reopen_order_item(&self, id: Uuid) -> Result<MongoOrderItem>;
+
+    /// revert guaranteed order items which have not been updated since `before`
+    /// back to backordering and release their reserved inventory.
+    /// will return the reverted order items.
+    async fn expire_stale_guaranteed_items(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<MongoOrderItem>>;
 }
 
 #[async_trait]
@@ -161,6 +347,39 @@ pub trait ShipmentRepo: Send + Sync + 'static {
 
     async fn update_shipment_status(&self, shipment_id: Uuid, status: &str) -> Result<()>;
 
+    /// move an arrived shipment back to ongoing.
+    /// will return error if the shipment is already ongoing.
+    async fn reopen_shipment(&self, shipment_id: Uuid) -> Result<()>;
+
+    /// set exported_at of shipments to now.
+    async fn mark_shipments_exported(&self, shipment_ids: &[Uuid]) -> Result<()>;
+
+    /// shipments and order items diverged from each other, see `ShipmentInconsistency`.
+    async fn find_shipment_inconsistencies(&self) -> Result<Vec<ShipmentInconsistency>>;
+
+    async fn repair_shipment_inconsistency(
+        &self,
+        inconsistency: &ShipmentInconsistency,
+    ) -> Result<()>;
+
+    /// order items listed by more than one shipment's `order_item_ids`.
+    async fn find_duplicate_shipment_items(&self) -> Result<Vec<DuplicateShipmentItem>>;
+
+    /// shipments in the date range never exported.
+    async fn find_unexported_shipments(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<MongoShipment>>;
+
+    /// shipments of the status in the date range with their item counts.
+    async fn find_shipments_by_status(
+        &self,
+        status: ShipmentStatus,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<MongoShipmentCounts>>;
+
     async fn update_shipment_no(
         &self,
         current_shipment_no: &str,
@@ -199,6 +418,13 @@ pub trait TransferRepo: Send + Sync + 'static {
         keyword: Option<String>,
     ) -> Result<Vec<MongoTransferOutput>>;
 
+    /// units moved by vendor and month within the range.
+    async fn summarize_transfers(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<TransferSummaryRow>>;
+
     async fn delete_transfer_by_id(&self, id: Uuid) -> Result<()>;
 
     async fn find_transfer_by_shipment_id(
@@ -244,6 +470,16 @@ pub trait TransferRepo: Send + Sync + 'static {
         new_vender: ShipmentVendor,
         new_location: InventoryLocation,
     ) -> Result<()>;
+
+    /// move the transfer's destination to `new_location` keeping its vendor,
+    /// fails if the current destination quantities were already consumed.
+    async fn update_transfer_location_by_transfer_id(
+        &self,
+        transfer_id: Uuid,
+        new_location: InventoryLocation,
+    ) -> Result<()>;
+
+    async fn repair_transfer_by_id(&self, transfer_id: Uuid) -> Result<Vec<MongoTransferItem>>;
 }
 
 #[async_trait]
@@ -266,7 +502,33 @@ pub trait ReturnRepo: Send + Sync + 'static {
     async fn get_return_by_id(&self, id: Uuid) -> Result<MongoReturnOutput>;
 
     async fn delete_return_by_id(&self, id: Uuid) -> Result<()>;
+
+    /// create a return restoring one unit of a shipped order item, linked to that item.
+    async fn create_return_from_order_item(&self, order_item_id: Uuid)
+        -> Result<MongoReturnOutput>;
 }
+#[async_trait]
+pub trait ActivityRepo: Send + Sync + 'static {
+    /// recent created orders, shipments, transfers and returns, newest first.
+    async fn find_recent_activity(&self, limit: u32) -> Result<Vec<MongoActivity>>;
+}
+
+#[async_trait]
+pub trait NotificationRepo: Send + Sync + 'static {
+    async fn insert_pending_notification(
+        &self,
+        notification: &MongoPendingNotification,
+    ) -> Result<()>;
+
+    /// oldest first.
+    async fn find_pending_notifications(&self, limit: i64)
+        -> Result<Vec<MongoPendingNotification>>;
+
+    async fn delete_pending_notification(&self, id: Uuid) -> Result<()>;
+
+    async fn increment_pending_notification_attempts(&self, id: Uuid) -> Result<()>;
+}
+
 #[async_trait]
 pub trait SMTAuthDataBase: Send + Sync + 'static {
     async fn check_is_username_occupied(&self, username: &str) -> Result<bool>;
@@ -276,6 +538,19 @@ pub trait SMTAuthDataBase: Send + Sync + 'static {
     async fn find_user(&self, id: Uuid) -> Result<User>;
 
     async fn find_user_by_username(&self, username: &str) -> Result<User>;
+
+    async fn create_invite(&self, invite: &Invite) -> Result<()>;
+
+    async fn consume_invite(&self, token: &str, username: &str) -> Result<Invite>;
+
+    async fn update_user_presets(&self, id: Uuid, presets: &[QueryPreset]) -> Result<()>;
+
+    async fn record_recent_resource(
+        &self,
+        id: Uuid,
+        resource: &RecentResource,
+        limit: usize,
+    ) -> Result<()>;
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -333,6 +608,11 @@ impl PhItem {
         }
     }
 
+    /// created by `new_dummy` for an item not registered yet.
+    pub fn is_dummy(&self) -> bool {
+        self.item_name.is_none() && self.label.is_empty() && self.url.is_empty()
+    }
+
     pub async fn insert_self(&self, db: &DbClient) -> Result<()> {
         let doc = doc! {
           "_id":self._id,
@@ -357,14 +637,8 @@ impl PhItem {
         Ok(())
     }
 
-    pub fn get_discounted_price(&self, discount_rate: f64) -> u32 {
-        let float_num = (self.price as f64) * discount_rate;
-
-        if (float_num - float_num.trunc()) < 0.5 {
-            float_num.round() as u32
-        } else {
-            float_num.ceil() as u32
-        }
+    pub fn get_discounted_price(&self, discount_rate: f64, rounding: RoundingStrategy) -> u32 {
+        rounding.apply((self.price as f64) * discount_rate)
     }
 }
 
@@ -474,6 +748,8 @@ pub struct InventoryOutput {
     #[serde(with = "ts_seconds")]
     pub update_at: DateTime<Utc>,
     pub operation_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub below_reorder: bool,
 }
 
 impl From<MongoInventoryOutput> for InventoryOutput {
@@ -483,6 +759,49 @@ impl From<MongoInventoryOutput> for InventoryOutput {
             quantity: m.quantity,
             update_at: m.update_at.to_chrono(),
             operation_ids: m.operation_ids,
+            below_reorder: m.below_reorder,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplySource {
+    pub register_id: Uuid,
+    pub register_no: String,
+    #[serde(with = "ts_seconds")]
+    pub arrival_date: DateTime<Utc>,
+    pub location: InventoryLocation,
+    pub count: i32,
+}
+
+impl From<MongoSupplySource> for SupplySource {
+    fn from(m: MongoSupplySource) -> Self {
+        Self {
+            register_id: m.register_id,
+            register_no: m.register_no,
+            arrival_date: m.arrival_date.to_chrono(),
+            location: m.location,
+            count: m.count,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderPoint {
+    pub item_code: String,
+    pub min_quantity: u32,
+    #[serde(with = "ts_seconds")]
+    pub update_at: DateTime<Utc>,
+}
+
+impl From<MongoReorderPoint> for ReorderPoint {
+    fn from(m: MongoReorderPoint) -> Self {
+        Self {
+            item_code: m.item_code,
+            min_quantity: m.min_quantity,
+            update_at: m.update_at.to_chrono(),
         }
     }
 }
@@ -514,7 +833,7 @@ impl From<MongoInventoryOperation> for InventoryOperation {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, EnumIter)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type")]
 pub enum OperationType {
@@ -529,6 +848,39 @@ pub enum OperationType {
     ConcealOrderItem,
     Ordered,
     Move,
+    ExpireGuarantee,
+    HoldOrderItem,
+    Adjust,
+}
+
+impl OperationType {
+    /// the display label in `locale`, one of "ja", "zh" and "en".
+    /// other locales or none fall back to "ja".
+    pub fn label(&self, locale: Option<&str>) -> &'static str {
+        let (ja, zh, en) = match self {
+            OperationType::CreateEmpty => ("新規作成", "新建", "create empty"),
+            OperationType::Arrival => ("入荷", "到货", "arrival"),
+            OperationType::Returned => ("返品", "退货", "returned"),
+            OperationType::DeleteRegister => ("入荷削除", "删除到货", "delete register"),
+            OperationType::DeleteOrder => ("注文削除", "删除订单", "delete order"),
+            OperationType::DeleteReturn => ("返品削除", "删除退货", "delete return"),
+            OperationType::DeleteTransfer => ("移動削除", "删除调货", "delete transfer"),
+            OperationType::UpdateTransfer => ("移動更新", "更新调货", "update transfer"),
+            OperationType::ConcealOrderItem => {
+                ("注文商品取消", "取消订单商品", "conceal order item")
+            }
+            OperationType::Ordered => ("注文", "下单", "ordered"),
+            OperationType::Move => ("移動", "调货", "move"),
+            OperationType::ExpireGuarantee => ("確保期限切れ", "保留过期", "expire guarantee"),
+            OperationType::HoldOrderItem => ("注文商品保留", "订单商品挂起", "hold order item"),
+            OperationType::Adjust => ("棚卸調整", "盘点调整", "adjust"),
+        };
+        match locale {
+            Some("zh") => zh,
+            Some("en") => en,
+            _ => ja,
+        }
+    }
 }
 
 impl From<MongoOperationType> for OperationType {
@@ -545,6 +897,9 @@ impl From<MongoOperationType> for OperationType {
             MongoOperationType::ConcealOrderItem => OperationType::ConcealOrderItem,
             MongoOperationType::Ordered => OperationType::Ordered,
             MongoOperationType::Move => OperationType::Move,
+            MongoOperationType::ExpireGuarantee => OperationType::ExpireGuarantee,
+            MongoOperationType::HoldOrderItem => OperationType::HoldOrderItem,
+            MongoOperationType::Adjust => OperationType::Adjust,
         }
     }
 }
@@ -620,6 +975,35 @@ pub struct OrderItem {
     pub order_id: Uuid,
     pub note: String,
     pub shipment_id: Option<Uuid>,
+    /// the catalog item, only attached on request by `attach_item_details`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_detail: Option<ReplyPhItem>,
+}
+
+/// attach the catalog item to each order item with a single catalog query.
+/// items missing from the catalog get a dummy detail whose optional fields are null.
+pub async fn attach_item_details<D: PhDataBase + ?Sized>(
+    db: &D,
+    items: &mut [OrderItem],
+) -> Result<()> {
+    let mut codes = items
+        .iter()
+        .map(|item| item.item_code_ext[..11].to_string())
+        .collect::<Vec<_>>();
+    codes.sort();
+    codes.dedup();
+    let mut catalog = HashMap::new();
+    for ph_item in db.find_by_item_codes(&codes).await? {
+        catalog.entry(ph_item.code.clone()).or_insert(ph_item);
+    }
+    for item in items.iter_mut() {
+        let ph_item = catalog
+            .get(&item.item_code_ext[..11])
+            .cloned()
+            .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
+        item.item_detail = Some(ph_item.into());
+    }
+    Ok(())
 }
 
 impl From<MongoOrderItem> for OrderItem {
@@ -637,6 +1021,7 @@ impl From<MongoOrderItem> for OrderItem {
             order_id: m.order_id,
             note: m.note,
             shipment_id: m.shipment_id,
+            item_detail: None,
         }
     }
 }
@@ -657,6 +1042,14 @@ pub struct Shipment {
     shipment_date: DateTime<Utc>,
     items: Vec<OrderItem>,
     status: ShipmentStatus,
+    #[serde(with = "ts_seconds_option")]
+    exported_at: Option<DateTime<Utc>>,
+}
+
+impl Shipment {
+    pub async fn attach_item_details<D: PhDataBase + ?Sized>(&mut self, db: &D) -> Result<()> {
+        attach_item_details(db, &mut self.items).await
+    }
 }
 
 impl From<MongoShipmentOutput> for Shipment {
@@ -671,6 +1064,7 @@ impl From<MongoShipmentOutput> for Shipment {
             shipment_date: m.shipment_date.to_chrono(),
             items: m.items.into_iter().map(|i| i.into()).collect::<Vec<_>>(),
             status: m.status,
+            exported_at: m.exported_at.map(|d| d.to_chrono()),
         }
     }
 }
@@ -688,6 +1082,7 @@ pub struct Return {
     pub return_date: DateTime<Utc>,
     pub note: String,
     pub items: Vec<ReturnItem>,
+    pub order_item_id: Option<Uuid>,
 }
 
 impl From<MongoReturnOutput> for Return {
@@ -700,6 +1095,7 @@ impl From<MongoReturnOutput> for Return {
             return_date: m.return_date.to_chrono(),
             note: m.note,
             items: m.items.into_iter().map(|i| i.into()).collect(),
+            order_item_id: m.order_item_id,
         }
     }
 }
@@ -719,3 +1115,24 @@ impl From<MongoReturnItem> for ReturnItem {
         }
     }
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityItem {
+    pub kind: ActivityKind,
+    pub id: Uuid,
+    #[serde(with = "ts_seconds")]
+    pub at: DateTime<Utc>,
+    pub summary: String,
+}
+
+impl From<MongoActivity> for ActivityItem {
+    fn from(m: MongoActivity) -> Self {
+        Self {
+            kind: m.kind,
+            id: m.id,
+            at: m.at.to_chrono(),
+            summary: m.summary,
+        }
+    }
+}
@@ -1,9 +1,10 @@
 use super::{
-    auth::{self, User},
+    auth::{self, Invite, QueryPreset, RecentResource, User},
     PhDataBase, PhItem, SMTAuthDataBase,
 };
 use crate::error_result::Result;
 use axum::async_trait;
+use futures::StreamExt;
 use mongodb::bson::Uuid;
 use mongodb::{bson::doc, options::ClientOptions, Client, Database};
 use tracing::info;
@@ -18,6 +19,11 @@ pub const RETURNS_COL: &str = "returns";
 pub const TRANSFERS_COL: &str = "transfers";
 pub const ITEMS_COL: &str = "items";
 pub const USERS_COL: &str = "users";
+pub const INVITES_COL: &str = "invites";
+pub const ADJUSTMENTS_COL: &str = "adjustments";
+pub const LOCATION_SWAPS_COL: &str = "location_swaps";
+pub const PENDING_NOTIFICATIONS_COL: &str = "pending_notifications";
+pub const REORDER_POINTS_COL: &str = "reorder_points";
 
 #[derive(Clone, Debug)]
 pub struct DbClient {
@@ -42,10 +48,17 @@ impl DbClient {
 
 #[async_trait]
 impl PhDataBase for DbClient {
-    async fn find_one_by_item_code(&self, item_code: &str) -> Result<Option<PhItem>> {
-        let query = doc! {
+    async fn find_one_by_item_code(
+        &self,
+        item_code: &str,
+        bucket: Option<&str>,
+    ) -> Result<Option<PhItem>> {
+        let mut query = doc! {
             "code":item_code
         };
+        if let Some(bucket) = bucket {
+            query.insert("bucket", bucket);
+        }
         let item_op = self
             .ph_db
             .collection::<PhItem>(ITEMS_COL)
@@ -53,6 +66,19 @@ impl PhDataBase for DbClient {
             .await?;
         Ok(item_op)
     }
+
+    async fn find_by_item_codes(&self, codes: &[String]) -> Result<Vec<PhItem>> {
+        let mut cursor = self
+            .ph_db
+            .collection::<PhItem>(ITEMS_COL)
+            .find(doc! {"code":{"$in":codes}}, None)
+            .await?;
+        let mut items = Vec::new();
+        while let Some(item) = cursor.next().await {
+            items.push(item?)
+        }
+        Ok(items)
+    }
 }
 
 #[async_trait]
@@ -72,4 +98,25 @@ impl SMTAuthDataBase for DbClient {
     async fn find_user_by_username(&self, username: &str) -> Result<User> {
         Ok(auth::find_user_by_username(self, username).await?)
     }
+
+    async fn create_invite(&self, invite: &Invite) -> Result<()> {
+        Ok(auth::create_invite(self, invite).await?)
+    }
+
+    async fn consume_invite(&self, token: &str, username: &str) -> Result<Invite> {
+        Ok(auth::consume_invite(self, token, username).await?)
+    }
+
+    async fn update_user_presets(&self, id: Uuid, presets: &[QueryPreset]) -> Result<()> {
+        Ok(auth::update_user_presets(self, id, presets).await?)
+    }
+
+    async fn record_recent_resource(
+        &self,
+        id: Uuid,
+        resource: &RecentResource,
+        limit: usize,
+    ) -> Result<()> {
+        Ok(auth::record_recent_resource(self, id, resource, limit).await?)
+    }
 }
@@ -0,0 +1,104 @@
+use crate::{error_result::Result, services::notifier::Notification};
+use axum::async_trait;
+use futures::StreamExt;
+use mongodb::{
+    bson::{doc, DateTime, Uuid},
+    options::FindOptions,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::{
+    mongo::{DbClient, PENDING_NOTIFICATIONS_COL},
+    NotificationRepo,
+};
+
+#[async_trait]
+impl NotificationRepo for DbClient {
+    async fn insert_pending_notification(
+        &self,
+        notification: &MongoPendingNotification,
+    ) -> Result<()> {
+        self.ph_db
+            .collection::<MongoPendingNotification>(PENDING_NOTIFICATIONS_COL)
+            .insert_one(notification, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_pending_notifications(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<MongoPendingNotification>> {
+        Ok(find_pending_notifications(self, limit).await?)
+    }
+
+    async fn delete_pending_notification(&self, id: Uuid) -> Result<()> {
+        self.ph_db
+            .collection::<MongoPendingNotification>(PENDING_NOTIFICATIONS_COL)
+            .delete_one(doc! {"id":id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn increment_pending_notification_attempts(&self, id: Uuid) -> Result<()> {
+        self.ph_db
+            .collection::<MongoPendingNotification>(PENDING_NOTIFICATIONS_COL)
+            .update_one(doc! {"id":id}, doc! {"$inc":{"attempts":1}}, None)
+            .await?;
+        Ok(())
+    }
+}
+
+/// a notification which could not be delivered, kept to be retried later.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MongoPendingNotification {
+    pub id: Uuid,
+    pub title: String,
+    pub notes: String,
+    pub created_at: DateTime,
+    /// failed retries since queued.
+    pub attempts: u32,
+}
+
+impl MongoPendingNotification {
+    pub fn new(notification: &Notification) -> Self {
+        Self {
+            id: Uuid::new(),
+            title: notification.title.clone(),
+            notes: notification.notes.clone(),
+            created_at: DateTime::now(),
+            attempts: 0,
+        }
+    }
+}
+
+impl From<MongoPendingNotification> for Notification {
+    fn from(m: MongoPendingNotification) -> Self {
+        Self {
+            title: m.title,
+            notes: m.notes,
+        }
+    }
+}
+
+#[instrument(name = "find pending notifications", skip(db))]
+async fn find_pending_notifications(
+    db: &DbClient,
+    limit: i64,
+) -> Result<Vec<MongoPendingNotification>> {
+    let options = FindOptions::builder()
+        .sort(doc! {"created_at":1})
+        .limit(limit)
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoPendingNotification>(PENDING_NOTIFICATIONS_COL)
+        .find(None, options)
+        .await?;
+    let mut notifications = Vec::new();
+    while let Some(notification) = cursor.next().await {
+        notifications.push(notification?);
+    }
+    Ok(notifications)
+}
@@ -9,30 +9,30 @@ use crate::{
         InventoryRepo, PhDataBase,
     },
     error_result::{Error, Result},
-    server::{transfer::NewTransferInputItem, InputOrderItem, OrderRegisterInput},
+    server::{auth::SETTINGS, transfer::NewTransferInputItem, InputOrderItem, OrderRegisterInput},
 };
 use async_recursion::async_recursion;
 use axum::async_trait;
+use base64::{engine::general_purpose, Engine as _};
 use chrono::prelude::*;
 use chrono::serde::ts_seconds;
 use futures::StreamExt;
 use mongodb::{
-    bson::{self, bson, doc, Bson, Document, Uuid},
+    bson::{self, doc, Bson, Document, Uuid},
     error::UNKNOWN_TRANSACTION_COMMIT_RESULT,
-    options::{
-        Acknowledgment, AggregateOptions, Collation, ReadConcern, TransactionOptions, WriteConcern,
-    },
+    options::{AggregateOptions, Collation},
     ClientSession,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info, instrument};
-
-use self::domain::TaobaoOrderNo;
+use std::collections::HashMap;
+use strum::{EnumIter, IntoEnumIterator};
+use tracing::{info, instrument, warn};
 
 use super::{
     invenope::{MongoInventoryOperation, MongoOperationType, Operations},
-    inventory::{InventoryLocation, MongoInventoryItem},
+    inventory::{InventoryLocation, MongoInventoryItem, Quantity},
     mongo::{DbClient, ORDERS_COL, ORDER_ITEMS_COL},
+    slow_query::QueryTimer,
     OrderRepo, PhItem, RegisterItem,
 };
 
@@ -51,9 +51,12 @@ impl OrderRepo for DbClient {
     #[instrument(name = "create order in db", skip(self, input))]
     async fn create_order(&self, input: OrderRegisterInput) -> Result<()> {
         info!("new create order request");
+        if let Some(e) = input.validate().into_iter().next() {
+            return Err(e.into());
+        }
         let order_builder = MongoOrderBuilder::new(
             TaobaoOrderNo::parse(&input.taobao_order_no)?,
-            &input.customer_id,
+            CustomerId::parse(&input.customer_id)?,
             &input.note,
             &input.items,
             input.order_datetime.into(),
@@ -62,6 +65,17 @@ impl OrderRepo for DbClient {
         Ok(())
     }
 
+    async fn preview_order(&self, input: &OrderRegisterInput) -> Result<Vec<OrderItemPlan>> {
+        if let Some(e) = input.validate().into_iter().next() {
+            return Err(e.into());
+        }
+        Ok(preview_order_items(self, &input.items).await?)
+    }
+
+    async fn preview_order_items(&self, items: &[InputOrderItem]) -> Result<Vec<OrderItemPlan>> {
+        Ok(preview_order_items(self, items).await?)
+    }
+
     async fn query_orders(
         &self,
         keyword: &str,
@@ -73,6 +87,17 @@ impl OrderRepo for DbClient {
         Ok(query_orders(self, keyword, status, from.into(), to.into(), page).await?)
     }
 
+    async fn query_orders_after(
+        &self,
+        keyword: &str,
+        status: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        cursor: Option<OrderCursor>,
+    ) -> Result<Vec<MongoOrderOutput>> {
+        Ok(query_orders_after(self, keyword, status, from.into(), to.into(), cursor).await?)
+    }
+
     async fn check_then_update_order_status(
         &self,
         items: Vec<RegisterItem>,
@@ -83,6 +108,42 @@ impl OrderRepo for DbClient {
         Ok(get_order_by_id(self, id).await?)
     }
 
+    async fn get_order_operations(&self, id: Uuid) -> Result<Vec<MongoInventoryOperation>> {
+        Ok(find_order_operations_by_id(self, id).await?)
+    }
+
+    async fn get_orders_by_ids(&self, ids: &[Uuid]) -> Result<Vec<MongoOrderOutput>> {
+        let pipeline = vec![
+            doc! {
+              "$match":{
+                "id":{"$in":ids}
+              }
+            },
+            doc! {
+              "$lookup":{
+                  "from": ORDER_ITEMS_COL,
+                  "localField": "order_item_ids",
+                  "foreignField": "id",
+                  "as": "items",
+              },
+            },
+        ];
+
+        let mut cursor = self
+            .ph_db
+            .collection::<MongoOrderOutput>(ORDERS_COL)
+            .aggregate(pipeline, None)
+            .await?;
+        let mut outputs = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            let output: MongoOrderOutput = bson::from_document(doc?)?;
+            outputs.push(output);
+        }
+        // keep the requested order
+        outputs.sort_by_key(|o| ids.iter().position(|id| id == &o.id));
+        Ok(outputs)
+    }
+
     async fn get_order_by_taobao_no(&self, taobao_order_no: &str) -> Result<Vec<MongoOrderOutput>> {
         let taobao_no = TaobaoOrderNo::parse(taobao_order_no)?;
         let pipeline = vec![
@@ -123,6 +184,10 @@ impl OrderRepo for DbClient {
         Ok(find_order_item_by_id(self, order_item_id).await?)
     }
 
+    async fn get_shippable_order_items(&self, ids: &[Uuid]) -> Result<Vec<MongoOrderItem>> {
+        Ok(ensure_order_items_shippable(self, ids).await?)
+    }
+
     async fn conceal_order_item(&self, order_item_id: Uuid) -> Result<ConcealItemOutput> {
         info!("new conceal order item request id:{}", order_item_id);
         Ok(conceal_order_item(self, order_item_id).await?)
@@ -145,6 +210,76 @@ impl OrderRepo for DbClient {
         let rate = OrderItemRate::parse(rate)?;
         Ok(update_order_item_rate(self, id, rate).await?)
     }
+
+    async fn locate_order_items(
+        &self,
+        item_code_ext: &str,
+        status: &OrderItemStatus,
+    ) -> Result<Vec<MongoOrderItem>> {
+        Ok(find_order_items_by_code_status(self, item_code_ext.trim(), status).await?)
+    }
+
+    async fn find_invalid_rate_order_items(&self) -> Result<Vec<MongoOrderItem>> {
+        Ok(find_invalid_rate_order_items(self).await?)
+    }
+
+    async fn count_awaiting_shipment_items(&self) -> Result<Vec<LocationCount>> {
+        Ok(count_awaiting_shipment_items(self).await?)
+    }
+
+    async fn find_customer_ids_by_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(find_customer_ids_by_prefix(self, prefix).await?)
+    }
+
+    async fn count_orders_by_status(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StatusCount>> {
+        Ok(count_orders_by_status(self, from.into(), to.into()).await?)
+    }
+
+    async fn find_guaranteed_items_by_customer(
+        &self,
+        customer_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<MongoOrderItem>> {
+        Ok(find_guaranteed_items_by_customer(
+            self,
+            customer_id,
+            from.map(Into::into),
+            to.map(Into::into),
+        )
+        .await?)
+    }
+
+    async fn backfill_customer_id_normalized(&self) -> Result<u64> {
+        Ok(backfill_customer_id_normalized(self).await?)
+    }
+
+    async fn reopen_order_item(&self, id: Uuid) -> Result<MongoOrderItem> {
+        let mut order_item = find_order_item_by_id(self, id).await?;
+        order_item.reopen(self).await?;
+        Ok(order_item)
+    }
+
+    async fn update_order_item_hold(&self, id: Uuid, hold: bool) -> Result<MongoOrderItem> {
+        let mut order_item = find_order_item_by_id(self, id).await?;
+        match hold {
+            true => order_item.hold(self).await?,
+            false => order_item.release_hold(self).await?,
+        }
+        Ok(order_item)
+    }
+
+    async fn expire_stale_guaranteed_items(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<MongoOrderItem>> {
+        info!("expire guaranteed order items not updated since {}", before);
+        Ok(expire_stale_guaranteed_items(self, before.into()).await?)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -155,6 +290,8 @@ pub struct MongoOrder {
     pub order_datetime: mongodb::bson::DateTime,
     pub taobao_order_no: String,
     pub customer_id: String,
+    #[serde(default)]
+    pub customer_id_normalized: String,
     pub note: String,
     pub order_item_ids: Vec<Uuid>,
     pub operation_ids: Vec<Uuid>,
@@ -176,6 +313,7 @@ impl MongoOrder {
             order_datetime,
             taobao_order_no: taobao_order_no.to_owned(),
             customer_id: customer_id.to_owned(),
+            customer_id_normalized: normalize_customer_id(customer_id),
             note: note.to_owned(),
             order_item_ids: order_item_ids.to_owned(),
             operation_ids: operation_ids.to_owned(),
@@ -190,6 +328,7 @@ impl MongoOrder {
           "order_datetime":self.order_datetime,
           "taobao_order_no":&self.taobao_order_no,
           "customer_id":&self.customer_id,
+          "customer_id_normalized":&self.customer_id_normalized,
           "note":&self.note,
           "order_item_ids":&self.order_item_ids,
           "operation_ids":&self.operation_ids,
@@ -214,7 +353,7 @@ pub struct MongoOrderBuilder {
 impl MongoOrderBuilder {
     pub fn new(
         taobao_order_no: TaobaoOrderNo,
-        customer_id: &str,
+        customer_id: CustomerId,
         note: &str,
         items: &[InputOrderItem],
         order_datetime: bson::DateTime,
@@ -222,7 +361,7 @@ impl MongoOrderBuilder {
         Self {
             order_id: Uuid::new(),
             taobao_order_no: taobao_order_no.get_inner(),
-            customer_id: customer_id.trim().to_owned(),
+            customer_id: customer_id.get_inner(),
             note: note.trim().to_owned(),
             items: items.to_owned(),
             order_datetime,
@@ -256,92 +395,112 @@ impl MongoOrderBuilder {
             }
             let inventory =
                 get_inventory_item(db, &input_item.item_code_ext, self.order_id).await?;
-            // WARNING
-            // this process need order in inventory.quantity and input_item.quantity to be same.
-            let zipped = inventory.quantity.iter().zip(input_item.quantity.iter());
-            // this will see in stock and requested by location continuously.
-            for (in_stock, requested) in zipped {
-                debug!(
-                    "in_stock location:{:?},requested location: {:?}",
-                    in_stock.location, requested.location
+            let plans = plan_order_item(
+                &input_item.item_code_ext,
+                &inventory.quantity,
+                &input_item.quantity,
+            );
+            for plan in plans {
+                info!(
+                    "location:{:?} will be guaranteed:{} back ordered:{}",
+                    plan.location, plan.guaranteed, plan.backordered
                 );
-                assert!(in_stock.location == requested.location);
-                // if not requested in this location pass it.
-                if requested.quantity == 0 {
-                    info!(
-                        "location:{:?} is requested quantity:{} so pass",
-                        &requested.location, requested.quantity
-                    );
-                    continue;
-                }
-                // if quantity in stock of this location is 0 publish back ordering Order item immediately.
-                if in_stock.quantity == 0 {
-                    info!(
-                        "location:{:?} have 0 in stock inventory create backordering order item",
-                        requested.location
-                    );
-                    let item_b_ids = create_backordering_order_item(
+                if plan.guaranteed > 0 {
+                    let (operation_id, item_ids) = create_guaranteed_order_item(
                         db,
                         self,
                         input_item,
-                        requested.location,
-                        requested.quantity,
+                        plan.location,
+                        plan.guaranteed,
                     )
                     .await?;
-                    order_item_ids.extend(item_b_ids);
-                    continue;
+                    operation_ids.push(operation_id);
+                    order_item_ids.extend(item_ids);
                 }
-                // if theres is in stock quantity and it can cover requested quantity.
-                // publish order item by requested quantity.
-                if in_stock.quantity >= requested.quantity {
-                    info!(
-                    "in stock location:{:?} has enough quantity:{} which be requested :{} publish new inventory operation",
-                    &in_stock.location, in_stock.quantity, requested.quantity
-                    );
-                    let (operation_id, item_ids) = create_guaranteed_order_item(
+                if plan.backordered > 0 {
+                    let item_b_ids = create_backordering_order_item(
                         db,
                         self,
                         input_item,
-                        requested.location,
-                        requested.quantity,
+                        plan.location,
+                        plan.backordered,
                     )
                     .await?;
-                    operation_ids.push(operation_id);
-                    order_item_ids.extend(item_ids);
-                    continue;
+                    order_item_ids.extend(item_b_ids);
                 }
-                // last there is in stock quantity but it is not enough for requested.
-                // publish order item for every in stock quantity for guaranteed.
-                // then publish left requested quantity by back ordering.
-                info!(
-                "inventory in stock location:{:?} is not enough in stock:{} which be requested:{},publish new Guaranteed and BackOrdering order item",
-                &in_stock.location, in_stock.quantity, requested.quantity
-                );
-                let (operation_id, item_ids) = create_guaranteed_order_item(
-                    db,
-                    self,
-                    input_item,
-                    requested.location,
-                    in_stock.quantity,
-                )
-                .await?;
-                operation_ids.push(operation_id);
-                order_item_ids.extend(item_ids);
-                let item_b_ids = create_backordering_order_item(
-                    db,
-                    self,
-                    input_item,
-                    requested.location,
-                    requested.quantity - in_stock.quantity,
-                )
-                .await?;
-                order_item_ids.extend(item_b_ids);
             }
         }
         Ok((order_item_ids, operation_ids))
     }
 }
 
+/// how many order items of a location would be guaranteed or back ordered.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderItemPlan {
+    pub item_code_ext: String,
+    pub location: InventoryLocation,
+    pub guaranteed: u32,
+    pub backordered: u32,
+}
+
+/// split the requested quantity of every location into guaranteed by the in stock quantity
+/// and back ordered for the rest. locations requested 0 are skipped.
+pub fn plan_order_item(
+    item_code_ext: &str,
+    in_stock: &[Quantity],
+    requested: &[Quantity],
+) -> Vec<OrderItemPlan> {
+    requested
+        .iter()
+        .filter(|requested| requested.quantity > 0)
+        .map(|requested| {
+            let in_stock = in_stock
+                .iter()
+                .find(|in_stock| in_stock.location == requested.location)
+                .map(|in_stock| in_stock.quantity)
+                .unwrap_or(0);
+            let guaranteed = in_stock.min(requested.quantity);
+            OrderItemPlan {
+                item_code_ext: item_code_ext.to_owned(),
+                location: requested.location,
+                guaranteed,
+                backordered: requested.quantity - guaranteed,
+            }
+        })
+        .collect()
+}
+
+/// plan every input item against the current inventory without writing anything.
+/// items sharing a code consume the stock in input order as `create_order_items` does.
+async fn preview_order_items(
+    db: &DbClient,
+    items: &[InputOrderItem],
+) -> Result<Vec<OrderItemPlan>> {
+    let mut consumed: HashMap<(&str, InventoryLocation), u32> = HashMap::new();
+    let mut plans = Vec::new();
+    for input_item in items.iter() {
+        let mut in_stock = find_inventory_by_item_code_ext(db, &input_item.item_code_ext)
+            .await?
+            .map(|inventory| inventory.quantity)
+            .unwrap_or_default();
+        for quantity in in_stock.iter_mut() {
+            let used = consumed
+                .get(&(input_item.item_code_ext.as_str(), quantity.location))
+                .copied()
+                .unwrap_or(0);
+            quantity.quantity = quantity.quantity.saturating_sub(used);
+        }
+        for plan in plan_order_item(&input_item.item_code_ext, &in_stock, &input_item.quantity) {
+            *consumed
+                .entry((input_item.item_code_ext.as_str(), plan.location))
+                .or_insert(0) += plan.guaranteed;
+            plans.push(plan);
+        }
+    }
+    Ok(plans)
+}
+
 #[instrument(name = "get inventory item", skip(db, order_id, item_code_ext))]
 async fn get_inventory_item(
     db: &DbClient,
@@ -374,7 +533,7 @@ async fn get_inventory_item(
 
 #[instrument(name = "create dummy phitem", skip(db))]
 async fn create_dummy_phitem(db: &DbClient, item_code: &str, item_price: u32) -> Result<()> {
-    let item_opt = db.find_one_by_item_code(item_code).await?;
+    let item_opt = db.find_one_by_item_code(item_code, None).await?;
     if item_opt.is_none() {
         info!("item is not found in db create a new dummy",);
         PhItem::new_dummy(item_code, item_price)
@@ -449,6 +608,8 @@ pub struct MongoOrderItem {
     pub update_at: mongodb::bson::DateTime,
     pub order_datetime: mongodb::bson::DateTime,
     pub customer_id: String,
+    #[serde(default)]
+    pub customer_id_normalized: String,
     pub item_code_ext: String,
     pub rate: f64,
     pub location: InventoryLocation,
@@ -476,6 +637,7 @@ impl MongoOrderItem {
             update_at: Local::now().into(),
             order_datetime,
             customer_id: customer_id.to_owned(),
+            customer_id_normalized: normalize_customer_id(customer_id),
             item_code_ext: item_code_ext.to_owned(),
             rate,
             location: location.to_owned(),
@@ -502,6 +664,7 @@ impl MongoOrderItem {
               "order_datetime":self.order_datetime,
               "item_code_ext":&self.item_code_ext,
               "customer_id":&self.customer_id,
+              "customer_id_normalized":&self.customer_id_normalized,
               "rate":self.rate,
               "location":&self.location,
               "note":&self.note,
@@ -544,8 +707,8 @@ impl MongoOrderItem {
             self.id, self.order_id
         );
         match self.status {
-            OrderItemStatus::BackOrdering => {
-                info!("order_item is backordering skip inventory operation check");
+            OrderItemStatus::BackOrdering | OrderItemStatus::Held => {
+                info!("order_item reserves no inventory skip inventory operation check");
                 // update order
                 update_order_update_at_by_id(db, self.order_id).await?;
                 // update order item
@@ -596,6 +759,7 @@ impl MongoOrderItem {
                         shipment.vendor,
                         &items,
                     )
+                    .source_order_item(self.id)
                     .publish_new_transfer(db)
                     .await?;
                 }
@@ -603,33 +767,34 @@ impl MongoOrderItem {
             }
             OrderItemStatus::Guaranteed => {
                 info!("order item is guaranteed");
-                let order_operations = find_order_operations_by_id(db, self.order_id).await?;
-                for operation in order_operations {
-                    match operation.operation_type {
-                        MongoOperationType::Ordered | MongoOperationType::CreateEmpty
-                            if (operation.item_code_ext == self.item_code_ext)
-                                && (operation.location == self.location) =>
-                        {
-                            info!(
-                                "found match operation id:{} count:{} location:{:?} run backward ",
-                                operation.id, operation.count, &operation.location
-                            );
-                            operation
-                                .run_partial_backward(db, 1, MongoOperationType::ConcealOrderItem)
-                                .await?;
-                            // update order
-                            update_order_update_at_by_id(db, self.order_id).await?;
-                            // update order item
-                            update_order_item_to_conceal_by_id(db, self.id).await?;
-                        }
-                        _ => (),
-                    }
-                }
+                let operation = self.find_own_reservation(db).await?;
+                info!(
+                    "found match operation id:{} count:{} location:{:?} run backward ",
+                    operation.id, operation.count, &operation.location
+                );
+                operation
+                    .run_partial_backward(db, 1, MongoOperationType::ConcealOrderItem)
+                    .await?;
+                // update order
+                update_order_update_at_by_id(db, self.order_id).await?;
+                // update order item
+                update_order_item_to_conceal_by_id(db, self.id).await?;
                 Ok(None)
             }
             OrderItemStatus::Concealed => Ok(None),
         }
     }
+    /// Check a order item could be shipped, only guaranteed item can be shipped.
+    fn ensure_shippable(&self) -> Result<()> {
+        if self.status != OrderItemStatus::Guaranteed {
+            return Err(Error::ItemNotShippable {
+                id: self.id.to_string(),
+                status: self.status.to_string(),
+            });
+        }
+        Ok(())
+    }
+
     /// Update a order item's status to shipped.
     #[instrument(name="update order item to shipped",skip(self,db),fields(
         id=%self.id,
@@ -638,15 +803,19 @@ impl MongoOrderItem {
         location=?self.location,
     ))]
     async fn update_self_status_to_shipped(&self, db: &DbClient, shipment_id: Uuid) -> Result<()> {
-        assert!(self.status == OrderItemStatus::Guaranteed);
+        self.ensure_shippable()?;
         let now = Local::now();
         // update order item
         info!(
             "update order item id:{} status to shipped by {}",
             self.id, shipment_id
         );
+        // only matches while still guaranteed and unshipped,
+        // a concurrent shipment may have taken it since read.
         let query = doc! {
           "id":self.id,
+          "status":OrderItemStatus::Guaranteed,
+          "shipment_id":Bson::Null,
         };
         let update = doc! {
           "$set":{
@@ -655,10 +824,15 @@ impl MongoOrderItem {
             "shipment_id":shipment_id,
           }
         };
-        db.ph_db
+        let result = db
+            .ph_db
             .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
             .update_one(query, update, None)
             .await?;
+        if result.matched_count == 0 {
+            warn!("order item {} is no longer guaranteed", self.id);
+            return Err(Error::ItemsClaimedConcurrently(vec![self.id.to_string()]));
+        }
 
         // update order
         let query = doc! {
@@ -690,15 +864,19 @@ impl MongoOrderItem {
         shipment_id: Uuid,
         session: &mut ClientSession,
     ) -> Result<()> {
-        assert!(self.status == OrderItemStatus::Guaranteed);
+        self.ensure_shippable()?;
         let now = Local::now();
         // update order item
         info!(
             "update order item id:{} status to shipped by {}",
             self.id, shipment_id
         );
+        // only matches while still guaranteed and unshipped,
+        // a concurrent shipment may have taken it since read.
         let query = doc! {
           "id":self.id,
+          "status":OrderItemStatus::Guaranteed,
+          "shipment_id":Bson::Null,
         };
         let update = doc! {
           "$set":{
@@ -707,10 +885,23 @@ impl MongoOrderItem {
             "shipment_id":shipment_id,
           }
         };
-        db.ph_db
+        let result = db
+            .ph_db
             .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
             .update_one_with_session(query, update, None, session)
-            .await?;
+            .await
+            .map_err(|e| {
+                // the item is being written by a concurrent transaction.
+                if is_write_conflict(&e) {
+                    Error::ItemsClaimedConcurrently(vec![self.id.to_string()])
+                } else {
+                    e.into()
+                }
+            })?;
+        if result.matched_count == 0 {
+            warn!("order item {} is no longer guaranteed", self.id);
+            return Err(Error::ItemsClaimedConcurrently(vec![self.id.to_string()]));
+        }
 
         // update order
         let query = doc! {
@@ -774,67 +965,286 @@ impl MongoOrderItem {
         Ok(())
     }
 
-    #[allow(dead_code)]
-    async fn update_self_status_to_guaranteed(&self, db: &DbClient) -> Result<()> {
-        assert!(self.status != OrderItemStatus::Guaranteed);
-        assert!(self.status != OrderItemStatus::Shipped);
-        let operation = MongoInventoryOperation::new(
-            &self.item_code_ext,
-            self.order_id,
-            MongoOperationType::Ordered,
-            -1,
-            self.location.to_owned(),
-        );
-        let operation_id = operation.run_self(db, false).await?;
+    /// Revert a guaranteed order item back to backordering.
+    /// its reserved inventory will be released by a partial backward of the order's `Ordered` operation.
+    #[instrument(name="expire order item guarantee",skip(self,db),fields(
+        id=%self.id,
+        customer_id=%self.customer_id,
+        item=%self.item_code_ext,
+        location=?self.location,
+    ))]
+    async fn expire_guarantee(&mut self, db: &DbClient) -> Result<()> {
+        if self.status != OrderItemStatus::Guaranteed {
+            return Err(Error::InvalidOperation);
+        }
+        self.release_guarantee(
+            db,
+            MongoOperationType::ExpireGuarantee,
+            OrderItemStatus::BackOrdering,
+        )
+        .await?;
+        info!("expire order item:{} guarantee success", self.id);
+        Ok(())
+    }
+
+    /// Put a guaranteed order item on hold, its reserved inventory is released
+    /// in the same way as `expire_guarantee`.
+    #[instrument(name="hold order item",skip(self,db),fields(
+        id=%self.id,
+        customer_id=%self.customer_id,
+        item=%self.item_code_ext,
+        location=?self.location,
+    ))]
+    async fn hold(&mut self, db: &DbClient) -> Result<()> {
+        if self.status != OrderItemStatus::Guaranteed {
+            return Err(Error::InvalidItemStatusTransition {
+                id: self.id.to_string(),
+                from: self.status.to_string(),
+                to: OrderItemStatus::Held.to_string(),
+            });
+        }
+        self.release_guarantee(db, MongoOperationType::HoldOrderItem, OrderItemStatus::Held)
+            .await?;
+        info!("hold order item:{} success", self.id);
+        Ok(())
+    }
+
+    /// Guarantee a held order item again, fails if its location has no inventory left.
+    #[instrument(name="release order item hold",skip(self,db),fields(
+        id=%self.id,
+        customer_id=%self.customer_id,
+        item=%self.item_code_ext,
+        location=?self.location,
+    ))]
+    async fn release_hold(&mut self, db: &DbClient) -> Result<()> {
+        if self.status != OrderItemStatus::Held {
+            return Err(Error::InvalidItemStatusTransition {
+                id: self.id.to_string(),
+                from: self.status.to_string(),
+                to: OrderItemStatus::Guaranteed.to_string(),
+            });
+        }
+        let in_stock = find_inventory_by_item_code_ext(db, &self.item_code_ext)
+            .await?
+            .and_then(|inventory| {
+                inventory
+                    .quantity
+                    .into_iter()
+                    .find(|q| q.location == self.location)
+            })
+            .map(|q| q.quantity)
+            .unwrap_or(0);
+        if in_stock == 0 {
+            return Err(Error::InventoryQuantityNegative(
+                self.item_code_ext.clone(),
+                self.location.kanjified(),
+            ));
+        }
+        self.update_self_status_to_guaranteed(db).await?;
+        self.status = OrderItemStatus::Guaranteed;
+        info!("release order item:{} hold success", self.id);
+        Ok(())
+    }
+
+    /// Reopen a concealed order item in a transaction, it is guaranteed again
+    /// if its location has inventory left, otherwise it goes back to backordering.
+    #[instrument(name="reopen order item",skip(self,db),fields(
+        id=%self.id,
+        customer_id=%self.customer_id,
+        item=%self.item_code_ext,
+        location=?self.location,
+    ))]
+    async fn reopen(&mut self, db: &DbClient) -> Result<()> {
+        if self.status != OrderItemStatus::Concealed {
+            return Err(Error::InvalidItemStatusTransition {
+                id: self.id.to_string(),
+                from: self.status.to_string(),
+                to: OrderItemStatus::BackOrdering.to_string(),
+            });
+        }
+        // a shipped item has left with its shipment, reopening it would reserve inventory twice.
+        if self.shipment_id.is_some() {
+            return Err(Error::ItemAlreadyShipped {
+                id: self.id.to_string(),
+            });
+        }
+        let mut session = db.client.start_session(None).await?;
+        let options = SETTINGS.database.concern.transaction_options();
+        session.start_transaction(options).await?;
+        let status = match self.reopen_with_session(db, &mut session).await {
+            Ok(status) => status,
+            Err(e) => {
+                session.abort_transaction().await?;
+                return Err(e);
+            }
+        };
+        loop {
+            if let Err(ref error) = session.commit_transaction().await {
+                if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                    continue;
+                }
+            }
+            break;
+        }
+        self.status = status;
+        info!("reopen order item:{} as {} success", self.id, self.status);
+        Ok(())
+    }
+
+    async fn reopen_with_session(
+        &self,
+        db: &DbClient,
+        session: &mut ClientSession,
+    ) -> Result<OrderItemStatus> {
+        let in_stock =
+            find_inventory_by_item_code_ext_with_session(db, &self.item_code_ext, session)
+                .await?
+                .and_then(|inventory| {
+                    inventory
+                        .quantity
+                        .into_iter()
+                        .find(|q| q.location == self.location)
+                })
+                .map(|q| q.quantity)
+                .unwrap_or(0);
+        if in_stock > 0 {
+            self.update_self_status_to_guaranteed_with_session(db, session)
+                .await?;
+            return Ok(OrderItemStatus::Guaranteed);
+        }
         let now = Local::now();
-        // update order item
-        info!(
-            "update order item id:{} status to guaranteed by new register",
-            self.id
-        );
         let query = doc! {
           "id":self.id,
+          "status":OrderItemStatus::Concealed,
+          "shipment_id":Bson::Null,
         };
         let update = doc! {
           "$set":{
             "update_at":now,
-            "status":OrderItemStatus::Guaranteed,
-          },
+            "status":OrderItemStatus::BackOrdering,
+          }
         };
-        db.ph_db
+        let result = db
+            .ph_db
             .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
-            .update_one(query, update, None)
+            .update_one_with_session(query, update, None, session)
             .await?;
-
-        // update order
-        let query = doc! {
-          "id":self.order_id,
-        };
-        let update = doc! {
-          "$set":{
-            "update_at":now,
-          },
-          "$push":{
-            "operation_ids":operation_id
-          }
-        };
-        info!(
-            "update order:{} update at, push operation id:{}",
-            self.order_id, operation_id
-        );
+        // status has been changed by others since read.
+        if result.matched_count == 0 {
+            warn!("order item {} is no longer concealed", self.id);
+            return Err(Error::Changed);
+        }
         db.ph_db
             .collection::<MongoOrder>(ORDERS_COL)
-            .update_one(query, update, None)
+            .update_one_with_session(
+                doc! {"id":self.order_id},
+                doc! {"$set":{"update_at":now}},
+                None,
+                session,
+            )
             .await?;
-        info!("update order item:{} to guaranteed success", self.id);
-        Ok(())
+        Ok(OrderItemStatus::BackOrdering)
     }
 
-    async fn update_self_status_to_guaranteed_with_session(
-        &self,
+    /// the not countered ordered operation reserving this item's code at its location.
+    /// besides being listed in the order's `operation_ids` the operation must be related to
+    /// the order itself, so a wrongly linked operation of another order is never released.
+    async fn find_own_reservation(&self, db: &DbClient) -> Result<MongoInventoryOperation> {
+        find_order_operations_by_id(db, self.order_id)
+            .await?
+            .into_iter()
+            .find(|operation| {
+                operation.operation_type == MongoOperationType::Ordered
+                    && operation.related_id == self.order_id
+                    && operation.item_code_ext == self.item_code_ext
+                    && operation.location == self.location
+                    && operation.count < 0
+                    && !operation.countered
+            })
+            .ok_or_else(|| Error::CanNotFindOperation(self.id.to_string()))
+    }
+
+    /// release the inventory reserved by a guaranteed order item with a partial backward
+    /// of the order's `Ordered` operation, then move the item to `status`.
+    /// both run in one transaction, so the item is never moved without its release.
+    async fn release_guarantee(
+        &mut self,
         db: &DbClient,
+        operation_type: MongoOperationType,
+        status: OrderItemStatus,
+    ) -> Result<()> {
+        let operation = self.find_own_reservation(db).await?;
+        info!(
+            "found match operation id:{} count:{} location:{:?} run backward ",
+            operation.id, operation.count, &operation.location
+        );
+        let mut session = db.client.start_session(None).await?;
+        let options = SETTINGS.database.concern.transaction_options();
+        session.start_transaction(options).await?;
+        if let Err(e) = self
+            .release_guarantee_with_session(db, &operation, operation_type, &status, &mut session)
+            .await
+        {
+            session.abort_transaction().await?;
+            return Err(e);
+        }
+        loop {
+            if let Err(ref error) = session.commit_transaction().await {
+                if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                    continue;
+                }
+            }
+            break;
+        }
+        self.status = status;
+        Ok(())
+    }
+
+    async fn release_guarantee_with_session(
+        &self,
+        db: &DbClient,
+        operation: &MongoInventoryOperation,
+        operation_type: MongoOperationType,
+        status: &OrderItemStatus,
         session: &mut ClientSession,
     ) -> Result<()> {
+        let now = Local::now();
+        let query = doc! {
+          "id":self.id,
+          "status":OrderItemStatus::Guaranteed,
+        };
+        let update = doc! {
+          "$set":{
+            "update_at":now,
+            "status":status,
+          }
+        };
+        let result = db
+            .ph_db
+            .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+            .update_one_with_session(query, update, None, session)
+            .await?;
+        // status has been changed by others since read.
+        if result.matched_count == 0 {
+            warn!("order item {} is no longer guaranteed", self.id);
+            return Err(Error::Changed);
+        }
+        operation
+            .run_partial_backward_with_session(db, 1, operation_type, session)
+            .await?;
+        db.ph_db
+            .collection::<MongoOrder>(ORDERS_COL)
+            .update_one_with_session(
+                doc! {"id":self.order_id},
+                doc! {"$set":{"update_at":now}},
+                None,
+                session,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn update_self_status_to_guaranteed(&self, db: &DbClient) -> Result<()> {
         assert!(self.status != OrderItemStatus::Guaranteed);
         assert!(self.status != OrderItemStatus::Shipped);
         let operation = MongoInventoryOperation::new(
@@ -844,7 +1254,7 @@ impl MongoOrderItem {
             -1,
             self.location.to_owned(),
         );
-        let operation_id = operation.run_self_with_session(db, false, session).await?;
+        let operation_id = operation.run_self(db, false).await?;
         let now = Local::now();
         // update order item
         info!(
@@ -861,9 +1271,76 @@ impl MongoOrderItem {
           },
         };
         db.ph_db
+            .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+            .update_one(query, update, None)
+            .await?;
+
+        // update order
+        let query = doc! {
+          "id":self.order_id,
+        };
+        let update = doc! {
+          "$set":{
+            "update_at":now,
+          },
+          "$push":{
+            "operation_ids":operation_id
+          }
+        };
+        info!(
+            "update order:{} update at, push operation id:{}",
+            self.order_id, operation_id
+        );
+        db.ph_db
+            .collection::<MongoOrder>(ORDERS_COL)
+            .update_one(query, update, None)
+            .await?;
+        info!("update order item:{} to guaranteed success", self.id);
+        Ok(())
+    }
+
+    async fn update_self_status_to_guaranteed_with_session(
+        &self,
+        db: &DbClient,
+        session: &mut ClientSession,
+    ) -> Result<()> {
+        assert!(self.status != OrderItemStatus::Guaranteed);
+        assert!(self.status != OrderItemStatus::Shipped);
+        let operation = MongoInventoryOperation::new(
+            &self.item_code_ext,
+            self.order_id,
+            MongoOperationType::Ordered,
+            -1,
+            self.location.to_owned(),
+        );
+        let operation_id = operation.run_self_with_session(db, false, session).await?;
+        let now = Local::now();
+        // update order item
+        info!(
+            "update order item id:{} status to guaranteed by new register",
+            self.id
+        );
+        // only matches while the item is still in the status it was read with.
+        let query = doc! {
+          "id":self.id,
+          "status":&self.status,
+          "shipment_id":Bson::Null,
+        };
+        let update = doc! {
+          "$set":{
+            "update_at":now,
+            "status":OrderItemStatus::Guaranteed,
+          },
+        };
+        let result = db
+            .ph_db
             .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
             .update_one_with_session(query, update, None, session)
             .await?;
+        if result.matched_count == 0 {
+            warn!("order item {} is no longer {}", self.id, self.status);
+            return Err(Error::Changed);
+        }
 
         // update order
         let query = doc! {
@@ -890,19 +1367,56 @@ impl MongoOrderItem {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, EnumIter)]
 #[serde(rename_all = "lowercase")]
 pub enum OrderItemStatus {
     BackOrdering,
+    /// held on purpose (e.g. awaiting payment), reserves no inventory like `BackOrdering`
+    /// but is never guaranteed automatically.
+    Held,
     Guaranteed,
     Shipped,
     Concealed,
 }
 
+impl std::fmt::Display for OrderItemStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderItemStatus::BackOrdering => f.write_str("backordering"),
+            OrderItemStatus::Held => f.write_str("held"),
+            OrderItemStatus::Guaranteed => f.write_str("guaranteed"),
+            OrderItemStatus::Shipped => f.write_str("shipped"),
+            OrderItemStatus::Concealed => f.write_str("concealed"),
+        }
+    }
+}
+
+impl std::str::FromStr for OrderItemStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OrderItemStatus::iter()
+            .find(|status| status.to_string() == s)
+            .ok_or_else(|| Error::UnknownOrderItemStatus(s.to_owned()))
+    }
+}
+
+impl OrderItemStatus {
+    /// parse comma separated statuses, an empty string is no status.
+    pub fn parse_list(s: &str) -> Result<Vec<Self>> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Vec::new());
+        }
+        s.split(',').map(|status| status.trim().parse()).collect()
+    }
+}
+
 impl From<OrderItemStatus> for Bson {
     fn from(o: OrderItemStatus) -> Self {
         match o {
             OrderItemStatus::BackOrdering => Bson::String(String::from("backordering")),
+            OrderItemStatus::Held => Bson::String(String::from("held")),
             OrderItemStatus::Guaranteed => Bson::String(String::from("guaranteed")),
             OrderItemStatus::Shipped => Bson::String(String::from("shipped")),
             OrderItemStatus::Concealed => Bson::String(String::from("concealed")),
@@ -952,24 +1466,21 @@ async fn find_order_operations_by_id(
         let output: Operations = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::OrderNotFound(id.to_string()));
+    }
     Ok(outputs[0].operations.to_owned())
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-struct OrderId {
-    id: Uuid,
-}
-
 pub const ITEMS_PER_PAGE: u32 = 10;
 
-pub async fn query_orders(
-    db: &DbClient,
+/// the stages of `query_orders` matching orders, before sorted.
+fn query_orders_filter_pipeline(
     keyword: &str,
     status: &str,
     from: bson::DateTime,
     to: bson::DateTime,
-    page: Option<u32>,
-) -> Result<(bool, Vec<MongoOrderOutput>)> {
+) -> Result<Vec<Document>> {
     let mut pipeline = vec![
         doc! {
           "$match":{
@@ -990,32 +1501,34 @@ pub async fn query_orders(
     ];
 
     if !keyword.is_empty() {
+        let mut or_doc = vec![
+            doc! {"taobao_order_no":{
+                        "$regex":keyword,
+                        "$options":"i"
+            }},
+            doc! {"customer_id":{
+                        "$regex":keyword,
+                        "$options":"i"
+            }},
+            doc! {"items.item_code_ext":{
+                        "$regex":keyword,
+                        "$options":"i"
+            }},
+            doc! {"note":{
+                        "$regex":keyword,
+                        "$options":"i"
+            }},
+        ];
+        or_doc.extend(customer_id_normalized_filters(keyword));
         pipeline.push(doc! {
           "$match":{
-            "$or":[
-              {"taobao_order_no":{
-                          "$regex":keyword,
-                          "$options":"i"
-              }},
-              {"customer_id":{
-                          "$regex":keyword,
-                          "$options":"i"
-              }},
-              {"items.item_code_ext":{
-                          "$regex":keyword,
-                          "$options":"i"
-              }},
-              {"note":{
-                          "$regex":keyword,
-                          "$options":"i"
-              }}
-            ]
+            "$or":or_doc
           }
         })
     }
 
-    if !status.is_empty() {
-        let statuses: Vec<&str> = status.trim().split(',').collect();
+    let statuses = OrderItemStatus::parse_list(status)?;
+    if !statuses.is_empty() {
         let mut or_doc = Vec::new();
         for status in statuses {
             or_doc.push(doc! {"items.status":status})
@@ -1027,11 +1540,26 @@ pub async fn query_orders(
             },
         })
     }
+    Ok(pipeline)
+}
+
+pub async fn query_orders(
+    db: &DbClient,
+    keyword: &str,
+    status: &str,
+    from: bson::DateTime,
+    to: bson::DateTime,
+    page: Option<u32>,
+) -> Result<(bool, Vec<MongoOrderOutput>)> {
+    let mut pipeline = query_orders_filter_pipeline(keyword, status, from, to)?;
     pipeline.push(doc! {
     "$sort":{
         "created_at":-1,
         "taobao_order_no":-1,
         "order_datetime":-1,
+        // orders inserted together can tie on every key above,
+        // the unique id keeps their order stable across pages.
+        "id":-1,
     }});
     let collation = Collation::builder()
         .locale("en_US")
@@ -1041,6 +1569,7 @@ pub async fn query_orders(
     // we return full result.
     let option = AggregateOptions::builder().collation(collation).build();
     if page.is_none() {
+        let timer = QueryTimer::start("query orders", &pipeline);
         let mut cursor = db
             .ph_db
             .collection::<Document>(ORDERS_COL)
@@ -1051,6 +1580,7 @@ pub async fn query_orders(
             let output: MongoOrderOutput = bson::from_document(doc?)?;
             outputs.push(output);
         }
+        timer.finish();
         return Ok((false, outputs));
     }
     // reach here means this is a paged request
@@ -1065,6 +1595,7 @@ pub async fn query_orders(
         "$skip":skip
     });
 
+    let timer = QueryTimer::start("query orders", &pipeline);
     let mut cursor = db
         .ph_db
         .collection::<Document>(ORDERS_COL)
@@ -1075,9 +1606,107 @@ pub async fn query_orders(
         let output: MongoOrderOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    timer.finish();
     Ok(((outputs.len() as u32) == ITEMS_PER_PAGE, outputs))
 }
 
+/// the position after an order in the cursor mode of `query_orders`,
+/// where orders are sorted by `created_at` then `id`, newest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderCursor {
+    pub created_at: bson::DateTime,
+    pub id: Uuid,
+}
+
+impl OrderCursor {
+    pub fn of(order: &MongoOrderOutput) -> Self {
+        Self {
+            created_at: order.created_at,
+            id: order.id,
+        }
+    }
+
+    /// the cursor after a full page, none for the last page.
+    pub fn next_of(page: &[MongoOrderOutput]) -> Option<Self> {
+        if page.len() as u32 != ITEMS_PER_PAGE {
+            return None;
+        }
+        page.last().map(Self::of)
+    }
+
+    /// opaque to clients, url safe base64 of `{created_at millis}.{id}`.
+    pub fn encode(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(format!(
+            "{}.{}",
+            self.created_at.timestamp_millis(),
+            self.id
+        ))
+    }
+
+    pub fn decode(token: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCursor(token.to_owned());
+        let decoded = general_purpose::URL_SAFE_NO_PAD
+            .decode(token)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(invalid)?;
+        let (millis, id) = decoded.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            created_at: bson::DateTime::from_millis(millis.parse().map_err(|_| invalid())?),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// a page of `query_orders` after `cursor`, the first page if none.
+/// new orders sort before any cursor, so paging never repeats an order while they are inserted.
+pub async fn query_orders_after(
+    db: &DbClient,
+    keyword: &str,
+    status: &str,
+    from: bson::DateTime,
+    to: bson::DateTime,
+    cursor: Option<OrderCursor>,
+) -> Result<Vec<MongoOrderOutput>> {
+    let mut pipeline = query_orders_filter_pipeline(keyword, status, from, to)?;
+    if let Some(cursor) = cursor {
+        // matched before the lookup, right after the range.
+        pipeline.insert(
+            1,
+            doc! {
+              "$match":{
+                "$or":[
+                  {"created_at":{"$lt":cursor.created_at}},
+                  {"created_at":cursor.created_at,"id":{"$lt":cursor.id}},
+                ]
+              }
+            },
+        );
+    }
+    pipeline.push(doc! {
+      "$sort":{
+        "created_at":-1,
+        "id":-1,
+      }
+    });
+    pipeline.push(doc! {
+        "$limit":ITEMS_PER_PAGE
+    });
+    let timer = QueryTimer::start("query orders after", &pipeline);
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut outputs = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        let output: MongoOrderOutput = bson::from_document(doc?)?;
+        outputs.push(output);
+    }
+    timer.finish();
+    Ok(outputs)
+}
+
 pub async fn get_order_by_id(db: &DbClient, id: Uuid) -> Result<MongoOrderOutput> {
     let pipeline = vec![
         doc! {
@@ -1153,6 +1782,9 @@ pub async fn delete_order(db: &DbClient, id: Uuid) -> Result<DeleteOrderOutput>
         let output: DeletePreOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::OrderNotFound(id.to_string()));
+    }
     let mut item_is_shipped_ids = vec![];
     for mut order_items in outputs[0].order_items.clone().into_iter() {
         if order_items.conceal(db).await?.is_some() {
@@ -1186,6 +1818,54 @@ pub async fn find_order_item_by_id(db: &DbClient, id: Uuid) -> Result<MongoOrder
         .ok_or_else(|| Error::OrderItemNotFound(id.to_string()))
 }
 
+/// check all provided order items can be shipped before any write.
+/// an already shipped item is rejected first, other unshippable items are reported together.
+/// the found items are returned in the order of `ids`.
+#[instrument(name = "ensure order items shippable", skip(db))]
+pub async fn ensure_order_items_shippable(
+    db: &DbClient,
+    ids: &[Uuid],
+) -> Result<Vec<MongoOrderItem>> {
+    let filter = doc! {
+      "id":{"$in":ids},
+    };
+    let items = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .find(filter, None)
+        .await?
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut unshippable = Vec::new();
+    let mut found = Vec::new();
+    for id in ids {
+        let item = items
+            .iter()
+            .find(|item| &item.id == id)
+            .ok_or_else(|| Error::OrderItemNotFound(id.to_string()))?;
+        // an item still pointing to a shipment would be listed by two shipments.
+        if item.status == OrderItemStatus::Shipped || item.shipment_id.is_some() {
+            return Err(Error::ItemAlreadyShipped {
+                id: item.id.to_string(),
+            });
+        }
+        if let Err(Error::ItemNotShippable { id, status }) = item.ensure_shippable() {
+            unshippable.push((id, status));
+        }
+        found.push(item.to_owned());
+    }
+    if unshippable.len() == 1 {
+        let (id, status) = unshippable.remove(0);
+        return Err(Error::ItemNotShippable { id, status });
+    }
+    if !unshippable.is_empty() {
+        return Err(Error::ItemsNotShippable(unshippable));
+    }
+    Ok(found)
+}
+
 async fn update_order_update_at_by_id(db: &DbClient, id: Uuid) -> Result<()> {
     let query = doc! {
       "id":id,
@@ -1203,6 +1883,14 @@ async fn update_order_update_at_by_id(db: &DbClient, id: Uuid) -> Result<()> {
     Ok(())
 }
 
+/// WriteConflict, raised when a document is written by two transactions at a time.
+pub(crate) fn is_write_conflict(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        mongodb::error::ErrorKind::Command(mongodb::error::CommandError { code: 112, .. })
+    )
+}
+
 /// find the order item by provided id, then update its status to shipped.
 #[instrument(name = "update order item to shipped", skip(db))]
 pub async fn update_order_item_status_to_shipped_by_id(
@@ -1302,27 +1990,22 @@ pub async fn query_order_items(
       "status":status,
     };
     if !keyword.is_empty() {
-        let bson = bson! {
-        [
-                  {"item_code_ext":{
-                      "$regex":keyword,
-                      "$options":"i"
-                  }},
-                  {"customer_id":{
-                      "$regex":keyword,
-                      "$options":"i"
-                  }},
-                  {"customer_id":{
-                      "$regex":keyword,
-                      "$options":"i"
-                  }},
-                  {"note":{
-                      "$regex":keyword,
-                      "$options":"i"
-                  }},
-        ]
-        };
-        filter.insert("$or", bson);
+        let mut or_doc = vec![
+            doc! {"item_code_ext":{
+                "$regex":keyword,
+                "$options":"i"
+            }},
+            doc! {"customer_id":{
+                "$regex":keyword,
+                "$options":"i"
+            }},
+            doc! {"note":{
+                "$regex":keyword,
+                "$options":"i"
+            }},
+        ];
+        or_doc.extend(customer_id_normalized_filters(keyword));
+        filter.insert("$or", or_doc);
     }
     let options = mongodb::options::FindOptions::builder()
         .sort(doc! {"order_datetime":1})
@@ -1339,6 +2022,277 @@ pub async fn query_order_items(
     Ok(outputs)
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LocationCount {
+    pub location: InventoryLocation,
+    pub count: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StatusCount {
+    pub status: OrderItemStatus,
+    pub count: u32,
+}
+
+/// order items whose rate is out of (0, 1], the most recently updated first.
+async fn find_invalid_rate_order_items(db: &DbClient) -> Result<Vec<MongoOrderItem>> {
+    let query = doc! {
+      "$or":[
+        {"rate":{"$lte":0.0}},
+        {"rate":{"$gt":1.0}},
+      ]
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! {"update_at":-1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .find(query, options)
+        .await?;
+    let mut outputs = Vec::new();
+    while let Some(output) = cursor.next().await {
+        outputs.push(output?)
+    }
+    Ok(outputs)
+}
+
+/// guaranteed order items of the customer, optionally ordered within `from`..=`to`, oldest first.
+async fn find_guaranteed_items_by_customer(
+    db: &DbClient,
+    customer_id: &str,
+    from: Option<mongodb::bson::DateTime>,
+    to: Option<mongodb::bson::DateTime>,
+) -> Result<Vec<MongoOrderItem>> {
+    let mut query = doc! {
+      "status":OrderItemStatus::Guaranteed,
+      "$or":[
+        {"customer_id_normalized":normalize_customer_id(customer_id)},
+        {"customer_id":customer_id},
+      ]
+    };
+    let mut range = doc! {};
+    if let Some(from) = from {
+        range.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        range.insert("$lte", to);
+    }
+    if !range.is_empty() {
+        query.insert("order_datetime", range);
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! {"order_datetime":1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .find(query, options)
+        .await?;
+    let mut outputs = Vec::new();
+    while let Some(output) = cursor.next().await {
+        outputs.push(output?)
+    }
+    Ok(outputs)
+}
+
+/// guaranteed order items grouped by location, locations without any are counted as 0.
+async fn count_awaiting_shipment_items(db: &DbClient) -> Result<Vec<LocationCount>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "status":OrderItemStatus::Guaranteed,
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":"$location",
+            "count":{"$sum":1},
+          }
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "location":"$_id",
+            "count":1,
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut counts = HashMap::new();
+    while let Some(doc) = cursor.next().await {
+        let count = bson::from_document::<LocationCount>(doc?)?;
+        counts.insert(count.location, count.count);
+    }
+    Ok(InventoryLocation::iter()
+        .map(|location| LocationCount {
+            location,
+            count: counts.get(&location).copied().unwrap_or(0),
+        })
+        .collect())
+}
+
+/// orders within the range having at least one item in each status,
+/// an order is counted once per status of its items.
+async fn count_orders_by_status(
+    db: &DbClient,
+    from: bson::DateTime,
+    to: bson::DateTime,
+) -> Result<Vec<StatusCount>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "order_datetime":{
+              "$gte":from,
+              "$lte":to,
+            }
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":"$status",
+            "orders":{"$addToSet":"$order_id"},
+          }
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "status":"$_id",
+            "count":{"$size":"$orders"},
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut counts = HashMap::new();
+    while let Some(doc) = cursor.next().await {
+        let count = bson::from_document::<StatusCount>(doc?)?;
+        counts.insert(count.status, count.count);
+    }
+    Ok(OrderItemStatus::iter()
+        .map(|status| StatusCount {
+            count: counts.get(&status).copied().unwrap_or(0),
+            status,
+        })
+        .collect())
+}
+
+pub const CUSTOMER_IDS_LIMIT: i64 = 20;
+
+async fn find_customer_ids_by_prefix(db: &DbClient, prefix: &str) -> Result<Vec<String>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "customer_id":{
+              "$regex":format!("^{}", regex::escape(prefix.trim())),
+              "$options":"i",
+            }
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":"$customer_id",
+          }
+        },
+        doc! {
+          "$sort":{
+            "_id":1
+          }
+        },
+        doc! {
+          "$limit":CUSTOMER_IDS_LIMIT
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut customer_ids = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        if let Ok(customer_id) = doc?.get_str("_id") {
+            customer_ids.push(customer_id.to_owned());
+        }
+    }
+    Ok(customer_ids)
+}
+
+/// exact and partial matches of the normalized `keyword` on `customer_id_normalized`.
+/// none when the keyword is only whitespace, an empty regex would match everything.
+fn customer_id_normalized_filters(keyword: &str) -> Vec<Document> {
+    let normalized = normalize_customer_id(keyword);
+    if normalized.is_empty() {
+        return Vec::new();
+    }
+    vec![
+        doc! {"customer_id_normalized":&normalized},
+        doc! {"customer_id_normalized":{
+            "$regex":regex::escape(&normalized),
+        }},
+    ]
+}
+
+/// set `customer_id_normalized` of orders and order items stored before it existed.
+/// will return the number of updated documents.
+#[instrument(name = "backfill customer_id_normalized", skip(db))]
+async fn backfill_customer_id_normalized(db: &DbClient) -> Result<u64> {
+    let filter = doc! {"customer_id_normalized":{"$exists":false}};
+    let mut updated = 0;
+    for col in [ORDERS_COL, ORDER_ITEMS_COL] {
+        let collection = db.ph_db.collection::<Document>(col);
+        let customer_ids = collection
+            .distinct("customer_id", filter.clone(), None)
+            .await?;
+        for customer_id in customer_ids.iter().filter_map(Bson::as_str) {
+            let mut query = filter.clone();
+            query.insert("customer_id", customer_id);
+            let update = doc! {
+              "$set":{"customer_id_normalized":normalize_customer_id(customer_id)}
+            };
+            updated += collection
+                .update_many(query, update, None)
+                .await?
+                .modified_count;
+        }
+    }
+    info!("backfilled customer_id_normalized of {updated} documents");
+    Ok(updated)
+}
+
+/// like `find_order_items_by_code_status_location` at any location,
+/// the most recently updated comes first.
+#[instrument(name = "find order items by code and status", skip(db))]
+async fn find_order_items_by_code_status(
+    db: &DbClient,
+    item_code_ext: &str,
+    status: &OrderItemStatus,
+) -> Result<Vec<MongoOrderItem>> {
+    let filter = doc! {
+      "item_code_ext":item_code_ext,
+      "status":status,
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! {"update_at":-1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .find(filter, options)
+        .await?;
+    let mut outputs = Vec::new();
+    while let Some(output) = cursor.next().await {
+        outputs.push(output?)
+    }
+    Ok(outputs)
+}
+
 #[instrument(name = "find order items by code,status and location", skip(db))]
 async fn find_order_items_by_code_status_location(
     db: &DbClient,
@@ -1409,10 +2363,7 @@ pub async fn check_then_update_order_status(
     items: Vec<RegisterItem>,
 ) -> Result<Vec<MongoOrderItem>> {
     let mut session = db.client.start_session(None).await?;
-    let options = TransactionOptions::builder()
-        .read_concern(ReadConcern::majority())
-        .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
-        .build();
+    let options = SETTINGS.database.concern.transaction_options();
     session.start_transaction(options).await?;
     let mut res_items = Vec::new();
     for input_item in items {
@@ -1538,7 +2489,44 @@ async fn check_then_update_item_with_session(
     }
     Ok(())
 }
-use domain::OrderItemRate;
+/// find guaranteed order items which have not been updated since `before`,
+/// then revert them to backordering.
+/// shipped and concealed order items are never touched, items failing to expire are skipped.
+#[instrument(name = "inner expire stale guaranteed items", skip(db))]
+pub async fn expire_stale_guaranteed_items(
+    db: &DbClient,
+    before: bson::DateTime,
+) -> Result<Vec<MongoOrderItem>> {
+    let filter = doc! {
+      "status":OrderItemStatus::Guaranteed,
+      "update_at":{
+        "$lt":before,
+      },
+    };
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! {"order_datetime":1})
+        .build();
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .find(filter, options)
+        .await?;
+    let mut stale_items = Vec::new();
+    while let Some(output) = cursor.next().await {
+        stale_items.push(output?)
+    }
+    let mut expired_items = Vec::new();
+    for mut item in stale_items {
+        // one item failing to expire should not hold back the rest.
+        if let Err(e) = item.expire_guarantee(db).await {
+            warn!("failed to expire order item:{} guarantee: {e}", item.id);
+            continue;
+        }
+        expired_items.push(item);
+    }
+    Ok(expired_items)
+}
+
 #[instrument(name = "update order item rate inner", skip(db, id, rate))]
 async fn update_order_item_rate(db: &DbClient, id: Uuid, rate: OrderItemRate) -> Result<()> {
     info!("update order item {id} rate to {}", rate.get_inner());
@@ -1560,7 +2548,10 @@ async fn update_order_item_rate(db: &DbClient, id: Uuid, rate: OrderItemRate) ->
     Ok(())
 }
 
-pub use domain::OrderValidateError;
+pub use domain::{
+    normalize_customer_id, CustomerId, ItemCodeExt, OrderItemRate, OrderValidateError,
+    TaobaoOrderNo,
+};
 mod domain {
     use chrono::NaiveDateTime;
     use thiserror::Error;
@@ -1576,6 +2567,13 @@ mod domain {
 
         #[error("order item rate out of range")]
         OrderItemRateOutOfRange,
+        #[error("order item code {0} is not 13 alphanumeric characters")]
+        OrderItemCodeInvalid(String),
+
+        #[error("customer id contains a control character or a path separator")]
+        CustomerIdInvalidCharacter,
+        #[error("customer id is longer than {} characters", CUSTOMER_ID_MAX_CHARS)]
+        CustomerIdTooLong,
     }
     /// aka 支付宝交易号
     /// parse requirement:
@@ -1609,11 +2607,76 @@ mod domain {
         }
     }
 
+    pub const CUSTOMER_ID_MAX_CHARS: usize = 64;
+
+    /// the customer's taobao account name, it ends up in export file names and notifications.
+    /// parse requirement:
+    /// 1. no control characters, e.g. line breaks
+    /// 2. no path separators
+    /// 3. at most `CUSTOMER_ID_MAX_CHARS` characters after trimmed
+    pub struct CustomerId(String);
+
+    impl CustomerId {
+        pub fn parse(input: &str) -> Result<Self, OrderValidateError> {
+            let input = input.trim();
+            if input
+                .chars()
+                .any(|c| c.is_control() || c == '/' || c == '\\')
+            {
+                return Err(OrderValidateError::CustomerIdInvalidCharacter);
+            }
+            if input.chars().count() > CUSTOMER_ID_MAX_CHARS {
+                return Err(OrderValidateError::CustomerIdTooLong);
+            }
+            Ok(CustomerId(String::from(input)))
+        }
+
+        pub fn get_inner(self) -> String {
+            self.0
+        }
+    }
+
+    /// folds a customer id into a comparable form:
+    /// trims it, folds full-width ascii and ideographic space to half-width,
+    /// drops inner whitespace and lowercases it.
+    pub fn normalize_customer_id(input: &str) -> String {
+        input
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '\u{3000}' => ' ',
+                '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap_or(c),
+                _ => c,
+            })
+            .filter(|c| !c.is_whitespace())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    }
+
+    /// item code with its size and color, e.g. `A2121FSY06693`.
+    /// parse requirement:
+    /// 1. 13 characters
+    /// 2. ascii alphanumeric
+    pub struct ItemCodeExt(String);
+
+    impl ItemCodeExt {
+        pub fn parse(input: &str) -> Result<Self, OrderValidateError> {
+            if input.len() != 13 || !input.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(OrderValidateError::OrderItemCodeInvalid(input.to_owned()));
+            }
+            Ok(ItemCodeExt(String::from(input)))
+        }
+
+        pub fn get_inner(self) -> String {
+            self.0
+        }
+    }
+
     pub struct OrderItemRate(f64);
 
     impl OrderItemRate {
         pub fn parse(input: f64) -> Result<Self, OrderValidateError> {
-            if input <= 0.0 || input > 1.0 {
+            if !(input > 0.0 && input <= 1.0) {
                 return Err(OrderValidateError::OrderItemRateOutOfRange);
             }
             Ok(OrderItemRate(input))
@@ -1622,6 +2685,14 @@ mod domain {
         pub fn get_inner(&self) -> f64 {
             self.0
         }
+
+        /// `input` if it is in range, otherwise 1.0 as no discount.
+        pub fn clamp(input: f64) -> f64 {
+            match Self::parse(input) {
+                Ok(rate) => rate.get_inner(),
+                Err(_) => 1.0,
+            }
+        }
     }
 }
 #[derive(Serialize)]
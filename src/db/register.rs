@@ -132,7 +132,9 @@ impl MongoRegisterBuilder {
         for item in self.items.iter() {
             if item.is_manual {
                 info!("detected manual input item");
-                let item_opt = db.find_one_by_item_code(&item.item_code_ext[0..11]).await?;
+                let item_opt = db
+                    .find_one_by_item_code(&item.item_code_ext[0..11], None)
+                    .await?;
                 if item_opt.is_none() {
                     info!(
                         "item is not found in db create a new dummy for {} price:{}",
@@ -207,10 +209,6 @@ pub async fn find_register_by_no(db: &DbClient, no: &str) -> Result<Vec<MongoReg
     Ok(registers)
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-struct RegisterIds {
-    id: Uuid,
-}
 const ITEMS_PER_PAGE_LOCAL: u32 = 9;
 pub async fn query_registers(
     db: &DbClient,
@@ -319,6 +317,9 @@ pub async fn get_register_by_id(db: &DbClient, id: Uuid) -> Result<MongoRegister
         let output: MongoRegisterOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::RegisterNotFound(id.to_string()));
+    }
     Ok(outputs[0].to_owned())
 }
 
@@ -384,5 +385,8 @@ async fn find_operations_by_register_id(
         operations.push(register);
     }
 
-    Ok(operations[0].to_owned().operations)
+    operations
+        .first()
+        .map(|register| register.operations.to_owned())
+        .ok_or_else(|| Error::RegisterNotFound(id.to_string()))
 }
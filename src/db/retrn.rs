@@ -1,14 +1,21 @@
 use crate::{
-    db::{invenope::MongoInventoryOperation, mongo::OPERATIONS_COL},
-    error_result::Result,
-    server::retrn::NewReturnInputItem,
+    db::{
+        invenope::{MongoInventoryOperation, MongoOperationType},
+        inventory::{is_operation_could_be_backward_safely, InventoryLocation},
+        mongo::{OPERATIONS_COL, ORDER_ITEMS_COL},
+        order::{find_order_item_by_id, is_write_conflict, MongoOrderItem, OrderItemStatus},
+        shipment::{get_shipment_by_id, normalize_shipment_no},
+    },
+    error_result::{Error, Result},
+    server::{auth::SETTINGS, retrn::NewReturnInputItem},
 };
 use axum::async_trait;
 use chrono::{DateTime as ChronoDT, Local, Utc};
 use futures::StreamExt;
 use mongodb::bson::{self, doc, DateTime, Document, Uuid};
+use mongodb::{error::UNKNOWN_TRANSACTION_COMMIT_RESULT, ClientSession};
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{info, instrument};
 
 use super::{
     invenope::Operations,
@@ -46,6 +53,14 @@ impl ReturnRepo for DbClient {
     async fn delete_return_by_id(&self, id: Uuid) -> Result<()> {
         Ok(delete_return_by_id(self, id).await?)
     }
+
+    async fn create_return_from_order_item(
+        &self,
+        order_item_id: Uuid,
+    ) -> Result<MongoReturnOutput> {
+        let retrn = create_return_from_order_item(self, order_item_id).await?;
+        Ok(get_return_by_id(self, retrn.id).await?)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -57,6 +72,9 @@ pub struct MongoReturn {
     pub return_date: DateTime,
     pub note: String,
     pub operation_ids: Vec<Uuid>,
+    /// the shipped order item this return restores, `None` for standalone returns.
+    #[serde(default)]
+    pub order_item_id: Option<Uuid>,
 }
 
 impl MongoReturn {
@@ -71,10 +89,11 @@ impl MongoReturn {
             id,
             created_at: Local::now().into(),
             update_at: Local::now().into(),
-            return_no: return_no.trim().to_owned(),
+            return_no: normalize_shipment_no(return_no),
             return_date,
             note: note.trim().to_owned(),
             operation_ids: operation_ids.to_owned(),
+            order_item_id: None,
         }
     }
 
@@ -87,7 +106,8 @@ impl MongoReturn {
           "return_no":&self.return_no,
           "return_date":self.return_date,
           "note":&self.note,
-          "operation_ids":&self.operation_ids
+          "operation_ids":&self.operation_ids,
+          "order_item_id":self.order_item_id,
         };
         db.ph_db
             .collection(RETURNS_COL)
@@ -96,6 +116,30 @@ impl MongoReturn {
         info!("insert new return success");
         Ok(())
     }
+
+    async fn insert_self_with_session(
+        &self,
+        db: &DbClient,
+        session: &mut ClientSession,
+    ) -> Result<()> {
+        info!("insert new return id:{} no:{}", self.id, &self.return_no);
+        let doc = doc! {
+          "id":self.id,
+          "created_at":self.created_at,
+          "update_at":self.update_at,
+          "return_no":&self.return_no,
+          "return_date":self.return_date,
+          "note":&self.note,
+          "operation_ids":&self.operation_ids,
+          "order_item_id":self.order_item_id,
+        };
+        db.ph_db
+            .collection(RETURNS_COL)
+            .insert_one_with_session(doc, None, session)
+            .await?;
+        info!("insert new return success");
+        Ok(())
+    }
 }
 
 pub struct MongoReturnBuilder {
@@ -115,7 +159,7 @@ impl MongoReturnBuilder {
     ) -> Self {
         Self {
             return_id: Uuid::new(),
-            return_no: return_no.to_owned(),
+            return_no: normalize_shipment_no(return_no),
             return_date,
             note: note.to_owned(),
             items: items.to_owned(),
@@ -152,6 +196,112 @@ impl MongoReturnBuilder {
     }
 }
 
+/// restore one shipped unit sent back by the customer.
+/// the unit goes back to where the shipment delivered it, same as concealing a shipped item.
+/// an order item is returned at most once, the check and the insert run in one transaction.
+#[instrument(name = "create return from order item", skip(db))]
+pub async fn create_return_from_order_item(
+    db: &DbClient,
+    order_item_id: Uuid,
+) -> Result<MongoReturn> {
+    let item = find_order_item_by_id(db, order_item_id).await?;
+    if item.status != OrderItemStatus::Shipped {
+        return Err(Error::ItemNotReturnable {
+            id: item.id.to_string(),
+            status: item.status.to_string(),
+        });
+    }
+    let mut session = db.client.start_session(None).await?;
+    let options = SETTINGS.database.concern.transaction_options();
+    session.start_transaction(options).await?;
+    let retrn = match create_return_from_order_item_with_session(db, &item, &mut session).await {
+        Ok(retrn) => retrn,
+        Err(e) => {
+            session.abort_transaction().await?;
+            return Err(e);
+        }
+    };
+    loop {
+        if let Err(ref error) = session.commit_transaction().await {
+            if error.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+                continue;
+            }
+        }
+        break;
+    }
+    info!("return {} restored order item {}", retrn.id, item.id);
+    Ok(retrn)
+}
+
+async fn create_return_from_order_item_with_session(
+    db: &DbClient,
+    item: &MongoOrderItem,
+    session: &mut ClientSession,
+) -> Result<MongoReturn> {
+    // the order item is written first, so a concurrent return of the same item
+    // conflicts with this transaction instead of passing the check below as well.
+    let result = db
+        .ph_db
+        .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+        .update_one_with_session(
+            doc! {"id":item.id,"status":OrderItemStatus::Shipped},
+            doc! {"$set":{"update_at":Local::now()}},
+            None,
+            session,
+        )
+        .await
+        .map_err(|e| {
+            if is_write_conflict(&e) {
+                Error::ItemAlreadyReturned(item.id.to_string())
+            } else {
+                e.into()
+            }
+        })?;
+    // status has been changed by others since read.
+    if result.matched_count == 0 {
+        return Err(Error::Changed);
+    }
+    let returned = db
+        .ph_db
+        .collection::<MongoReturn>(RETURNS_COL)
+        .find_one_with_session(doc! {"order_item_id":item.id}, None, session)
+        .await?;
+    if returned.is_some() {
+        return Err(Error::ItemAlreadyReturned(item.id.to_string()));
+    }
+    let shipment_id = item
+        .shipment_id
+        .ok_or_else(|| Error::ShipmentNotFound(item.id.to_string()))?;
+    let shipment = get_shipment_by_id(db, shipment_id).await?;
+    let location = if item.location != InventoryLocation::JP {
+        item.location
+    } else if shipment.vendor.is_clearance_vendor() {
+        InventoryLocation::PCN
+    } else {
+        InventoryLocation::CN
+    };
+    let return_id = Uuid::new();
+    let operation_id = MongoInventoryOperation::new(
+        &item.item_code_ext,
+        return_id,
+        MongoOperationType::Returned,
+        1,
+        location,
+    )
+    .run_self_with_session(db, false, session)
+    .await?;
+    let mut retrn = MongoReturn::new(
+        return_id,
+        &shipment.shipment_no,
+        Local::now().into(),
+        &format!("{}さん注文出荷後、返品分", &item.customer_id),
+        &[operation_id],
+    );
+    retrn.order_item_id = Some(item.id);
+    retrn.insert_self_with_session(db, session).await?;
+    Ok(retrn)
+}
+
 async fn find_operations_by_return_id(
     db: &DbClient,
     return_id: Uuid,
@@ -182,12 +332,22 @@ async fn find_operations_by_return_id(
         operations.push(output);
     }
 
-    Ok(operations[0].to_owned().operations)
+    operations
+        .first()
+        .map(|output| output.operations.to_owned())
+        .ok_or_else(|| Error::ReturnNotFound(return_id.to_string()))
 }
 
 pub async fn delete_return_by_id(db: &DbClient, id: Uuid) -> Result<()> {
     info!("try to delete return id:{}", id);
     let operations = find_operations_by_return_id(db, id).await?;
+    // the returned stock may have been ordered or transferred since,
+    // check every operation first so nothing is reversed if any of them is unsafe.
+    for operation in operations.iter() {
+        if !is_operation_could_be_backward_safely(db, operation).await? {
+            return Err(Error::InvalidOperation);
+        }
+    }
     for operation in operations {
         operation
             .run_backward(db, super::invenope::MongoOperationType::DeleteReturn)
@@ -204,11 +364,6 @@ pub async fn delete_return_by_id(db: &DbClient, id: Uuid) -> Result<()> {
     Ok(())
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-struct ReturnId {
-    id: Uuid,
-}
-
 pub async fn query_returns(
     db: &DbClient,
     from: DateTime,
@@ -281,6 +436,8 @@ pub struct MongoReturnOutput {
     pub return_date: DateTime,
     pub note: String,
     pub items: Vec<MongoReturnItem>,
+    #[serde(default)]
+    pub order_item_id: Option<Uuid>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -316,5 +473,8 @@ pub async fn get_return_by_id(db: &DbClient, id: Uuid) -> Result<MongoReturnOutp
         let output: MongoReturnOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::ReturnNotFound(id.to_string()));
+    }
     Ok(outputs[0].to_owned())
 }
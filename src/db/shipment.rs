@@ -1,7 +1,7 @@
 use crate::{
     db::mongo::SHIPMENT_COL,
     error_result::{Error, Result},
-    server::NewShipmentInput,
+    server::{auth::SETTINGS, NewShipmentInput},
 };
 use axum::async_trait;
 use chrono::prelude::*;
@@ -10,19 +10,23 @@ use futures::StreamExt;
 use mongodb::{
     bson::{self, doc, Bson, DateTime, Document, Uuid},
     error::UNKNOWN_TRANSACTION_COMMIT_RESULT,
-    options::{Acknowledgment, ReadConcern, TransactionOptions, WriteConcern},
+    options::FindOptions,
     ClientSession,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use tracing::{info, instrument};
 
 use super::{
+    inventory::InventoryLocation,
     mongo::{DbClient, ORDER_ITEMS_COL},
     order::{
-        update_order_item_status_to_shipped_by_id_with_session, MongoOrderItem, OrderItemStatus,
-        ITEMS_PER_PAGE,
+        ensure_order_items_shippable, update_order_item_status_to_shipped_by_id_with_session,
+        MongoOrderItem, OrderItemStatus, ITEMS_PER_PAGE,
     },
-    ShipmentRepo,
+    slow_query::QueryTimer,
+    transfer::MongoTransferItem,
+    OrderItem, ShipmentRepo,
 };
 
 #[async_trait]
@@ -106,6 +110,81 @@ impl ShipmentRepo for DbClient {
         Ok(update_shipment_status(self, shipment_id, status).await?)
     }
 
+    async fn reopen_shipment(&self, shipment_id: Uuid) -> Result<()> {
+        Ok(reopen_shipment(self, shipment_id).await?)
+    }
+
+    async fn mark_shipments_exported(&self, shipment_ids: &[Uuid]) -> Result<()> {
+        let query = doc! {
+          "id":{"$in":shipment_ids},
+        };
+        let update = doc! {
+          "$set":{
+            "exported_at":Local::now(),
+          }
+        };
+        info!("mark shipments {:?} exported", shipment_ids);
+        self.ph_db
+            .collection::<MongoShipment>(SHIPMENT_COL)
+            .update_many(query, update, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_shipment_inconsistencies(&self) -> Result<Vec<ShipmentInconsistency>> {
+        Ok(find_shipment_inconsistencies(self).await?)
+    }
+
+    async fn repair_shipment_inconsistency(
+        &self,
+        inconsistency: &ShipmentInconsistency,
+    ) -> Result<()> {
+        Ok(repair_shipment_inconsistency(self, inconsistency).await?)
+    }
+
+    async fn find_duplicate_shipment_items(&self) -> Result<Vec<DuplicateShipmentItem>> {
+        Ok(find_duplicate_shipment_items(self).await?)
+    }
+
+    async fn find_unexported_shipments(
+        &self,
+        from: ChronoDT<Utc>,
+        to: ChronoDT<Utc>,
+    ) -> Result<Vec<MongoShipment>> {
+        let from: DateTime = from.into();
+        let to: DateTime = to.into();
+        let query = doc! {
+          "shipment_date":{
+            "$gte":from,
+            "$lte":to,
+          },
+          // legacy shipments have no this field
+          "exported_at":null,
+        };
+        let options = FindOptions::builder()
+            .sort(doc! {"shipment_date":-1})
+            .build();
+        let mut cursor = self
+            .ph_db
+            .collection::<MongoShipment>(SHIPMENT_COL)
+            .find(query, options)
+            .await?;
+        let mut outputs = Vec::new();
+        while let Some(shipment) = cursor.next().await {
+            outputs.push(shipment?)
+        }
+        Ok(outputs)
+    }
+
+    async fn find_shipments_by_status(
+        &self,
+        status: ShipmentStatus,
+        from: ChronoDT<Utc>,
+        to: ChronoDT<Utc>,
+    ) -> Result<Vec<MongoShipmentCounts>> {
+        Ok(find_shipments_by_status(self, status, from.into(), to.into()).await?)
+    }
+
     async fn update_shipment_no(
         &self,
         current_shipment_no: &str,
@@ -120,7 +199,7 @@ impl ShipmentRepo for DbClient {
         };
         let update = doc! {
           "$set":{
-            "shipment_no":new_shipment_no,
+            "shipment_no":normalize_shipment_no(new_shipment_no),
           }
         };
         info!("update shipment's shipment_no");
@@ -141,7 +220,7 @@ impl ShipmentRepo for DbClient {
         };
         let update = doc! {
           "$set":{
-            "shipment_no":new_shipment_no,
+            "shipment_no":normalize_shipment_no(new_shipment_no),
           }
         };
         info!("update shipment's shipment_no");
@@ -173,11 +252,6 @@ impl ShipmentRepo for DbClient {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-struct ShipmentId {
-    id: Uuid,
-}
-
 /// Shipment object used in mongo db
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MongoShipment {
@@ -190,9 +264,100 @@ pub struct MongoShipment {
     pub shipment_date: DateTime,
     pub order_item_ids: Vec<Uuid>,
     pub status: ShipmentStatus,
+    /// when the shipment was exported last time, `None` if never exported.
+    #[serde(default)]
+    pub exported_at: Option<DateTime>,
+}
+
+/// a shipment with the counts of its items instead of the items.
+#[derive(Deserialize, Debug, Clone)]
+pub struct MongoShipmentCounts {
+    pub id: Uuid,
+    pub created_at: DateTime,
+    pub update_at: DateTime,
+    pub shipment_no: String,
+    pub note: String,
+    pub vendor: ShipmentVendor,
+    pub shipment_date: DateTime,
+    pub status: ShipmentStatus,
+    #[serde(default)]
+    pub exported_at: Option<DateTime>,
+    /// items not concealed.
+    pub item_count: u32,
+    pub concealed_count: u32,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Copy)]
+/// item codes of a shipment and its transfers not found on the other side.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferDiff {
+    pub items_without_transfer: Vec<String>,
+    pub transfers_without_item: Vec<String>,
+}
+
+/// compare the non concealed shipment items' codes with the transfers' positive count item codes.
+/// both sides are sorted and deduplicated.
+pub fn diff_shipment_transfers(
+    shipment_items: &[MongoOrderItem],
+    transfer_items: &[MongoTransferItem],
+) -> TransferDiff {
+    let shipped = shipment_items
+        .iter()
+        .filter(|item| item.status != OrderItemStatus::Concealed)
+        .map(|item| item.item_code_ext.as_str())
+        .collect::<BTreeSet<_>>();
+    let transferred = transfer_items
+        .iter()
+        .filter(|item| item.count.is_positive())
+        .map(|item| item.item_code_ext.as_str())
+        .collect::<BTreeSet<_>>();
+    TransferDiff {
+        items_without_transfer: shipped
+            .difference(&transferred)
+            .map(|code| code.to_string())
+            .collect(),
+        transfers_without_item: transferred
+            .difference(&shipped)
+            .map(|code| code.to_string())
+            .collect(),
+    }
+}
+
+/// units of an item to pick at a location.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PickingListRow {
+    pub location: InventoryLocation,
+    pub item_code_ext: String,
+    pub item_name: Option<String>,
+    pub count: u32,
+}
+
+/// group the non concealed items by location then item code,
+/// locations follow the location table so pickers walk JP first.
+/// item names are taken from attached item details.
+pub fn build_picking_list(items: &[OrderItem]) -> Vec<PickingListRow> {
+    let mut rows: BTreeMap<(usize, &str), PickingListRow> = BTreeMap::new();
+    for item in items
+        .iter()
+        .filter(|item| item.status != OrderItemStatus::Concealed)
+    {
+        rows.entry((item.location as usize, item.item_code_ext.as_str()))
+            .or_insert_with(|| PickingListRow {
+                location: item.location,
+                item_code_ext: item.item_code_ext.clone(),
+                item_name: item
+                    .item_detail
+                    .as_ref()
+                    .and_then(|detail| detail.item_name.clone()),
+                count: 0,
+            })
+            .count += 1;
+    }
+    rows.into_values().collect()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum ShipmentVendor {
     YY,
@@ -257,17 +422,17 @@ impl From<ShipmentVendor> for Bson {
     }
 }
 
-impl ToString for ShipmentVendor {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for ShipmentVendor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ShipmentVendor::YY => String::from("yy"),
-            ShipmentVendor::SS => String::from("ss"),
-            ShipmentVendor::SD => String::from("sd"),
-            ShipmentVendor::BC => String::from("bc"),
-            ShipmentVendor::Ems => String::from("ems"),
-            ShipmentVendor::ML => String::from("ml"),
-            ShipmentVendor::SJ => String::from("sj"),
-            ShipmentVendor::PML => String::from("pml"),
+            ShipmentVendor::YY => f.write_str("yy"),
+            ShipmentVendor::SS => f.write_str("ss"),
+            ShipmentVendor::SD => f.write_str("sd"),
+            ShipmentVendor::BC => f.write_str("bc"),
+            ShipmentVendor::Ems => f.write_str("ems"),
+            ShipmentVendor::ML => f.write_str("ml"),
+            ShipmentVendor::SJ => f.write_str("sj"),
+            ShipmentVendor::PML => f.write_str("pml"),
         }
     }
 }
@@ -284,6 +449,8 @@ pub struct MongoShipmentOutput {
     pub shipment_date: DateTime,
     pub items: Vec<MongoOrderItem>,
     pub status: ShipmentStatus,
+    #[serde(default)]
+    pub exported_at: Option<DateTime>,
 }
 
 impl MongoShipment {
@@ -299,12 +466,13 @@ impl MongoShipment {
             id: Uuid::new(),
             created_at: Local::now().into(),
             update_at: Local::now().into(),
-            shipment_no: shipment_no.trim().to_owned(),
+            shipment_no: normalize_shipment_no(shipment_no),
             note: note.trim().to_owned(),
             vendor: vendor.to_owned(),
             shipment_date,
             order_item_ids: order_item_ids.to_owned(),
             status: ShipmentStatus::Ongoing,
+            exported_at: None,
         }
     }
     /// the main function to publish a new shipment, will create a new , update its related order
@@ -317,12 +485,15 @@ impl MongoShipment {
         shipment_date: DateTime,
         order_item_ids: &[Uuid],
     ) -> Result<Uuid> {
+        // a duplicated id would be shipped twice
+        let mut order_item_ids = order_item_ids.to_vec();
+        let mut seen = HashSet::new();
+        order_item_ids.retain(|id| seen.insert(*id));
+        let order_item_ids = order_item_ids.as_slice();
+        ensure_order_items_shippable(db, order_item_ids).await?;
         let mut session = db.client.start_session(None).await?;
 
-        let options = TransactionOptions::builder()
-            .read_concern(ReadConcern::majority())
-            .write_concern(WriteConcern::builder().w(Acknowledgment::Majority).build())
-            .build();
+        let options = SETTINGS.database.concern.transaction_options();
         session.start_transaction(options).await?;
         let shipment = MongoShipment::new(shipment_no, note, vendor, shipment_date, order_item_ids);
         for order_item_id in order_item_ids {
@@ -335,6 +506,13 @@ impl MongoShipment {
             .await
             {
                 match error {
+                    // the item was shipped or concealed by a concurrent request after checked
+                    // above. a write conflict has already aborted the transaction on the server,
+                    // so no further write is issued.
+                    Error::ItemsClaimedConcurrently(_) => {
+                        session.abort_transaction().await?;
+                        return Err(error);
+                    }
                     Error::Mongodb(e) => {
                         if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) {
                             continue;
@@ -428,6 +606,78 @@ impl MongoShipment {
     }
 }
 
+pub async fn find_shipments_by_status(
+    db: &DbClient,
+    status: ShipmentStatus,
+    from: DateTime,
+    to: DateTime,
+) -> Result<Vec<MongoShipmentCounts>> {
+    let pipeline = vec![
+        doc! {
+          "$match":{
+            "status":status,
+            "shipment_date":{
+              "$gte":from,
+              "$lte":to,
+            }
+          }
+        },
+        doc! {
+          "$lookup":{
+              "from": ORDER_ITEMS_COL,
+              "localField": "order_item_ids",
+              "foreignField": "id",
+              "as": "items",
+          },
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "id":1,
+            "created_at":1,
+            "update_at":1,
+            "shipment_no":1,
+            "note":1,
+            "vendor":1,
+            "shipment_date":1,
+            "status":1,
+            "exported_at":1,
+            "item_count":{
+              "$size":{
+                "$filter":{
+                  "input":"$items",
+                  "cond":{"$ne":["$$this.status",OrderItemStatus::Concealed]},
+                }
+              }
+            },
+            "concealed_count":{
+              "$size":{
+                "$filter":{
+                  "input":"$items",
+                  "cond":{"$eq":["$$this.status",OrderItemStatus::Concealed]},
+                }
+              }
+            },
+          }
+        },
+        doc! {
+          "$sort":{
+            "shipment_date":-1
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut outputs = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        outputs.push(bson::from_document(doc?)?);
+    }
+    Ok(outputs)
+}
+
 pub async fn query_shipments(
     db: &DbClient,
     keyword: &str,
@@ -503,12 +753,14 @@ pub async fn query_shipments(
 
     pipeline.push(doc! {
       "$sort":{
-        "update_at":-1
+        "update_at":-1,
+        "id":-1,
       }
     });
     // page is none means this is a non-paged request.
     // we return full result.
     if page.is_none() {
+        let timer = QueryTimer::start("query shipments", &pipeline);
         let mut cursor = db
             .ph_db
             .collection::<Document>(SHIPMENT_COL)
@@ -519,6 +771,7 @@ pub async fn query_shipments(
             let output: MongoShipmentOutput = bson::from_document(doc?)?;
             outputs.push(output);
         }
+        timer.finish();
         return Ok((false, outputs));
     }
 
@@ -533,6 +786,7 @@ pub async fn query_shipments(
     pipeline.push(doc! {
         "$skip":skip
     });
+    let timer = QueryTimer::start("query shipments", &pipeline);
     let mut cursor = db
         .ph_db
         .collection::<Document>(SHIPMENT_COL)
@@ -543,6 +797,7 @@ pub async fn query_shipments(
         let output: MongoShipmentOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    timer.finish();
     Ok(((outputs.len() as u32) == ITEMS_PER_PAGE, outputs))
 }
 
@@ -589,6 +844,9 @@ pub async fn get_shipment_by_id(db: &DbClient, id: Uuid) -> Result<MongoShipment
         let output: MongoShipmentOutput = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::ShipmentNotFound(id.to_string()));
+    }
     Ok(outputs[0].to_owned())
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -622,6 +880,9 @@ pub async fn delete_shipment(db: &DbClient, shipment_id: Uuid) -> Result<Vec<Uui
         let output: OrderItems = bson::from_document(doc?)?;
         outputs.push(output);
     }
+    if outputs.is_empty() {
+        return Err(Error::ShipmentNotFound(shipment_id.to_string()));
+    }
 
     for mut item in outputs[0]
         .items
@@ -679,3 +940,327 @@ pub async fn update_shipment_status(db: &DbClient, shipment_id: Uuid, status: &s
 
     Ok(())
 }
+
+#[instrument(name = "reopen shipment inner", skip(db))]
+pub async fn reopen_shipment(db: &DbClient, shipment_id: Uuid) -> Result<()> {
+    let shipment = get_shipment_by_id(db, shipment_id).await?;
+    if shipment.status == ShipmentStatus::Ongoing {
+        return Err(Error::ShipmentAlreadyOngoing(shipment_id.to_string()));
+    }
+    let query = doc! {
+      "id":shipment_id,
+      "status":ShipmentStatus::Arrival,
+    };
+    let update = doc! {
+      "$set":{
+        "status":ShipmentStatus::Ongoing,
+      }
+    };
+    let res = db
+        .ph_db
+        .collection::<MongoShipment>(SHIPMENT_COL)
+        .update_one(query, update, None)
+        .await?;
+    // status has been changed by others between the check and update.
+    if res.matched_count == 0 {
+        return Err(Error::Changed);
+    }
+    info!("reopen shipment :{shipment_id} success");
+    Ok(())
+}
+
+/// a divergence between shipments and the order items they ship,
+/// left by a shipment publish interrupted outside a committed transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShipmentInconsistency {
+    /// the shipment lists the order item but the item is neither shipped nor concealed.
+    ItemNotShipped {
+        shipment_id: Uuid,
+        item_id: Uuid,
+        status: OrderItemStatus,
+    },
+    /// the order item is shipped by the shipment but the shipment does not list it.
+    ShipmentMissingItem { shipment_id: Uuid, item_id: Uuid },
+    /// the order item is shipped by a shipment which does not exist.
+    ShipmentNotFound {
+        shipment_id: Option<Uuid>,
+        item_id: Uuid,
+    },
+}
+
+#[derive(Deserialize)]
+struct ListedItemRow {
+    shipment_id: Uuid,
+    item_id: Uuid,
+    status: OrderItemStatus,
+}
+
+#[derive(Deserialize)]
+struct ShippedItemRow {
+    shipment_id: Option<Uuid>,
+    item_id: Uuid,
+    shipment_found: bool,
+}
+
+#[instrument(name = "find shipment inconsistencies", skip(db))]
+async fn find_shipment_inconsistencies(db: &DbClient) -> Result<Vec<ShipmentInconsistency>> {
+    let mut inconsistencies = Vec::new();
+    let pipeline = vec![
+        doc! {"$project":{"id":1,"order_item_ids":1}},
+        doc! {"$unwind":"$order_item_ids"},
+        doc! {
+          "$lookup":{
+            "from":ORDER_ITEMS_COL,
+            "localField":"order_item_ids",
+            "foreignField":"id",
+            "as":"item",
+          }
+        },
+        doc! {"$unwind":"$item"},
+        doc! {
+          "$match":{
+            "item.status":{"$nin":[OrderItemStatus::Shipped,OrderItemStatus::Concealed]}
+          }
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "shipment_id":"$id",
+            "item_id":"$item.id",
+            "status":"$item.status",
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    while let Some(doc) = cursor.next().await {
+        let row: ListedItemRow = bson::from_document(doc?)?;
+        inconsistencies.push(ShipmentInconsistency::ItemNotShipped {
+            shipment_id: row.shipment_id,
+            item_id: row.item_id,
+            status: row.status,
+        });
+    }
+
+    let pipeline = vec![
+        doc! {"$match":{"status":OrderItemStatus::Shipped}},
+        doc! {
+          "$lookup":{
+            "from":SHIPMENT_COL,
+            "localField":"shipment_id",
+            "foreignField":"id",
+            "as":"shipment",
+          }
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "shipment_id":1,
+            "item_id":"$id",
+            "shipment_found":{"$gt":[{"$size":"$shipment"},0]},
+            "listed":{
+              "$in":[
+                "$id",
+                {"$ifNull":[{"$arrayElemAt":["$shipment.order_item_ids",0]},[]]},
+              ]
+            },
+          }
+        },
+        doc! {"$match":{"listed":false}},
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    while let Some(doc) = cursor.next().await {
+        let row: ShippedItemRow = bson::from_document(doc?)?;
+        let inconsistency = match row.shipment_id {
+            Some(shipment_id) if row.shipment_found => ShipmentInconsistency::ShipmentMissingItem {
+                shipment_id,
+                item_id: row.item_id,
+            },
+            shipment_id => ShipmentInconsistency::ShipmentNotFound {
+                shipment_id,
+                item_id: row.item_id,
+            },
+        };
+        inconsistencies.push(inconsistency);
+    }
+    Ok(inconsistencies)
+}
+
+/// an order item listed by more than one shipment.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateShipmentItem {
+    pub item_id: Uuid,
+    pub shipment_ids: Vec<Uuid>,
+}
+
+#[instrument(name = "find duplicate shipment items", skip(db))]
+async fn find_duplicate_shipment_items(db: &DbClient) -> Result<Vec<DuplicateShipmentItem>> {
+    let pipeline = vec![
+        doc! {"$project":{"id":1,"order_item_ids":1}},
+        doc! {"$unwind":"$order_item_ids"},
+        doc! {
+          "$group":{
+            "_id":"$order_item_ids",
+            "shipment_ids":{"$addToSet":"$id"},
+          }
+        },
+        doc! {"$match":{"shipment_ids.1":{"$exists":true}}},
+        doc! {"$sort":{"_id":1}},
+        doc! {
+          "$project":{
+            "_id":0,
+            "item_id":"$_id",
+            "shipment_ids":1,
+          }
+        },
+    ];
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut duplicates = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        duplicates.push(bson::from_document(doc?)?);
+    }
+    Ok(duplicates)
+}
+
+/// complete or undo the interrupted publish.
+/// a listed guaranteed item is shipped, other listed items are pulled out of the shipment,
+/// an unlisted shipped item is added to its shipment, or back to guaranteed if the shipment is gone.
+#[instrument(name = "repair shipment inconsistency", skip(db))]
+async fn repair_shipment_inconsistency(
+    db: &DbClient,
+    inconsistency: &ShipmentInconsistency,
+) -> Result<()> {
+    let now = Local::now();
+    match inconsistency {
+        ShipmentInconsistency::ItemNotShipped {
+            shipment_id,
+            item_id,
+            status: OrderItemStatus::Guaranteed,
+        } => {
+            info!("ship order item {item_id} by shipment {shipment_id}");
+            db.ph_db
+                .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+                .update_one(
+                    doc! {"id":item_id},
+                    doc! {"$set":{
+                      "update_at":now,
+                      "status":OrderItemStatus::Shipped,
+                      "shipment_id":shipment_id,
+                    }},
+                    None,
+                )
+                .await?;
+        }
+        ShipmentInconsistency::ItemNotShipped {
+            shipment_id,
+            item_id,
+            ..
+        } => {
+            info!("pull order item {item_id} out of shipment {shipment_id}");
+            db.ph_db
+                .collection::<MongoShipment>(SHIPMENT_COL)
+                .update_one(
+                    doc! {"id":shipment_id},
+                    doc! {
+                      "$pull":{"order_item_ids":item_id},
+                      "$set":{"update_at":now},
+                    },
+                    None,
+                )
+                .await?;
+        }
+        ShipmentInconsistency::ShipmentMissingItem {
+            shipment_id,
+            item_id,
+        } => {
+            info!("add order item {item_id} to shipment {shipment_id}");
+            db.ph_db
+                .collection::<MongoShipment>(SHIPMENT_COL)
+                .update_one(
+                    doc! {"id":shipment_id},
+                    doc! {
+                      "$addToSet":{"order_item_ids":item_id},
+                      "$set":{"update_at":now},
+                    },
+                    None,
+                )
+                .await?;
+        }
+        ShipmentInconsistency::ShipmentNotFound { item_id, .. } => {
+            info!("restore order item {item_id} of a missing shipment to guaranteed");
+            db.ph_db
+                .collection::<MongoOrderItem>(ORDER_ITEMS_COL)
+                .update_one(
+                    doc! {"id":item_id},
+                    doc! {"$set":{
+                      "update_at":now,
+                      "status":OrderItemStatus::Guaranteed,
+                      "shipment_id":Bson::Null,
+                    }},
+                    None,
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub use domain::{normalize_shipment_no, validate_shipment_no};
+mod domain {
+    use std::collections::HashMap;
+
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+
+    use super::ShipmentVendor;
+    use crate::{
+        error_result::{Error, Result},
+        server::auth::SETTINGS,
+    };
+
+    static SHIPMENT_NO_PATTERNS: Lazy<HashMap<ShipmentVendor, Regex>> = Lazy::new(|| {
+        SETTINGS
+            .shipment
+            .no_patterns
+            .iter()
+            .map(|(vendor, pattern)| {
+                (
+                    *vendor,
+                    Regex::new(pattern).expect("Failed to compile shipment no pattern"),
+                )
+            })
+            .collect()
+    });
+
+    /// the form shipment and return numbers are saved in,
+    /// every write site goes through this so `by_no` lookups never miss by a stray space.
+    pub fn normalize_shipment_no(no: &str) -> String {
+        no.trim().to_owned()
+    }
+
+    /// check the shipment no matches the format of the vendor.
+    /// the input is normalized before matching as same as saving.
+    pub fn validate_shipment_no(vendor: &ShipmentVendor, no: &str) -> Result<()> {
+        let no = normalize_shipment_no(no);
+        let no = no.as_str();
+        match SHIPMENT_NO_PATTERNS.get(vendor) {
+            Some(pattern) if !pattern.is_match(no) => Err(Error::InvalidShipmentNo(format!(
+                "{no} is not a valid {} shipment no",
+                vendor.stringify_vendor()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
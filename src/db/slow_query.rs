@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use mongodb::bson::{Bson, Document};
+use tracing::warn;
+
+use crate::server::auth::SETTINGS;
+
+/// fields holding customer data, their values are masked in logs.
+/// search keywords may be customer names too, so every `$regex` is masked as well.
+const PII_FIELDS: &[&str] = &[
+    "customer_id",
+    "customer_id_normalized",
+    "taobao_order_no",
+    "note",
+    "$regex",
+];
+
+const REDACTED: &str = "***";
+
+/// times an aggregation from its start until its results are read.
+pub struct QueryTimer {
+    name: &'static str,
+    filters: Vec<Document>,
+    started: Instant,
+}
+
+impl QueryTimer {
+    /// should be started before the pipeline is moved into `aggregate`.
+    pub fn start(name: &'static str, pipeline: &[Document]) -> Self {
+        Self {
+            name,
+            filters: redacted_match_filters(pipeline),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn finish(self) {
+        let threshold = Duration::from_millis(SETTINGS.database.slow_query_threshold_ms);
+        if let Some(message) =
+            slow_query_message(self.name, self.started.elapsed(), threshold, &self.filters)
+        {
+            warn!("{message}");
+        }
+    }
+}
+
+/// the warning logged for a query taking `elapsed`, none if it was not slower than `threshold`.
+pub fn slow_query_message(
+    name: &str,
+    elapsed: Duration,
+    threshold: Duration,
+    filters: &[Document],
+) -> Option<String> {
+    if elapsed <= threshold {
+        return None;
+    }
+    let filters = filters
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "slow query {name} took {}ms, filters: [{filters}]",
+        elapsed.as_millis()
+    ))
+}
+
+/// the `$match` stages of the pipeline with values of customer fields masked.
+pub fn redacted_match_filters(pipeline: &[Document]) -> Vec<Document> {
+    pipeline
+        .iter()
+        .filter_map(|stage| stage.get_document("$match").ok())
+        .map(redact_document)
+        .collect()
+}
+
+fn redact_document(document: &Document) -> Document {
+    document
+        .iter()
+        .map(|(key, value)| {
+            let value = if PII_FIELDS.contains(&key.as_str()) {
+                Bson::String(REDACTED.to_owned())
+            } else {
+                redact_value(value)
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+fn redact_value(value: &Bson) -> Bson {
+    match value {
+        Bson::Document(d) => Bson::Document(redact_document(d)),
+        Bson::Array(a) => Bson::Array(a.iter().map(redact_value).collect()),
+        other => other.clone(),
+    }
+}
@@ -1,9 +1,12 @@
 use crate::{
     db::{
         invenope::MongoOperationType,
-        inventory::{is_operation_could_be_backward_safely, shift_inventory_quantity},
+        inventory::{
+            check_shift_inventory_quantity, find_inventory_by_item_code_ext,
+            is_operation_could_be_backward_safely, shift_inventory_quantity,
+        },
         mongo::{OPERATIONS_COL, TRANSFERS_COL},
-        shipment::get_shipment_by_no,
+        shipment::{get_shipment_by_no, normalize_shipment_no},
     },
     error_result::{Error, Result},
     server::transfer::NewTransferInputItem,
@@ -105,6 +108,14 @@ impl TransferRepo for DbClient {
         Ok(query_transfers(self, from.into(), to.into(), keyword).await?)
     }
 
+    async fn summarize_transfers(
+        &self,
+        from: ChronoDT<Utc>,
+        to: ChronoDT<Utc>,
+    ) -> Result<Vec<TransferSummaryRow>> {
+        Ok(summarize_transfers(self, from.into(), to.into()).await?)
+    }
+
     async fn find_transfer_by_shipment_no(
         &self,
         shipment_no: &str,
@@ -167,7 +178,7 @@ impl TransferRepo for DbClient {
         };
         let update = doc! {
           "$set":{
-            "shipment_no":new_shipment_no,
+            "shipment_no":normalize_shipment_no(new_shipment_no),
           }
         };
         self.ph_db
@@ -187,7 +198,7 @@ impl TransferRepo for DbClient {
         };
         let update = doc! {
           "$set":{
-            "shipment_no":new_shipment_no,
+            "shipment_no":normalize_shipment_no(new_shipment_no),
           }
         };
         self.ph_db
@@ -236,26 +247,8 @@ impl TransferRepo for DbClient {
         new_vender: ShipmentVendor,
         new_location: InventoryLocation,
     ) -> Result<()> {
-        let old_operations = find_operations_by_transfer_id(self, transfer_id).await?;
-        let mut new_operation_ids = Vec::new();
-        for operation in old_operations {
-            if operation.count > 0 {
-                operation
-                    .run_backward(self, MongoOperationType::UpdateTransfer)
-                    .await?;
-                let new_operation = MongoInventoryOperation::new(
-                    &operation.item_code_ext,
-                    transfer_id,
-                    MongoOperationType::UpdateTransfer,
-                    operation.count,
-                    new_location,
-                );
-                let id = new_operation.run_self(self, false).await?;
-                new_operation_ids.push(id);
-                continue;
-            }
-            new_operation_ids.push(operation.id);
-        }
+        let new_operation_ids =
+            relocate_transfer_operations(self, transfer_id, new_location).await?;
         let query = doc! {
           "id":transfer_id,
         };
@@ -269,8 +262,43 @@ impl TransferRepo for DbClient {
             .collection::<MongoTransfer>(TRANSFERS_COL)
             .update_many(query, update, None)
             .await?;
+        let operations = find_operations_by_transfer_id(self, transfer_id).await?;
+        update_transfer_distribution_by_id(self, transfer_id, &transfer_effect(&operations))
+            .await?;
         Ok(())
     }
+
+    async fn update_transfer_location_by_transfer_id(
+        &self,
+        transfer_id: Uuid,
+        new_location: InventoryLocation,
+    ) -> Result<()> {
+        self.check_operations_backward_safety_by_transfer_id(transfer_id)
+            .await?;
+        let new_operation_ids =
+            relocate_transfer_operations(self, transfer_id, new_location).await?;
+        let query = doc! {
+          "id":transfer_id,
+        };
+        let update = doc! {
+          "$set":{
+            "operation_ids":new_operation_ids,
+            "update_at":DateTime::now(),
+          }
+        };
+        self.ph_db
+            .collection::<MongoTransfer>(TRANSFERS_COL)
+            .update_one(query, update, None)
+            .await?;
+        let operations = find_operations_by_transfer_id(self, transfer_id).await?;
+        update_transfer_distribution_by_id(self, transfer_id, &transfer_effect(&operations))
+            .await?;
+        Ok(())
+    }
+
+    async fn repair_transfer_by_id(&self, transfer_id: Uuid) -> Result<Vec<MongoTransferItem>> {
+        Ok(repair_transfer_by_id(self, transfer_id).await?)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -284,9 +312,16 @@ pub struct MongoTransfer {
     pub shipment_vendor: ShipmentVendor,
     pub note: String,
     pub operation_ids: Vec<Uuid>,
+    /// the expected inventory effect of the transfer, used to repair its operations.
+    #[serde(default)]
+    pub distribution: Vec<MongoTransferItem>,
+    /// the order item concealed after shipped which the transfer moves back.
+    #[serde(default)]
+    pub source_order_item_id: Option<Uuid>,
 }
 
 impl MongoTransfer {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         id: Uuid,
         shipment_id: Option<Uuid>,
@@ -295,6 +330,7 @@ impl MongoTransfer {
         transfer_date: DateTime,
         shipment_vendor: ShipmentVendor,
         operation_ids: &[Uuid],
+        distribution: &[MongoTransferItem],
     ) -> Self {
         Self {
             id,
@@ -306,6 +342,8 @@ impl MongoTransfer {
             shipment_vendor,
             note: note.to_owned(),
             operation_ids: operation_ids.to_owned(),
+            distribution: distribution.to_owned(),
+            source_order_item_id: None,
         }
     }
 
@@ -323,7 +361,9 @@ impl MongoTransfer {
           "transfer_date":self.transfer_date,
           "note":&self.note,
           "shipment_vendor":&self.shipment_vendor,
-          "operation_ids":&self.operation_ids
+          "operation_ids":&self.operation_ids,
+          "distribution":distribution_docs(&self.distribution),
+          "source_order_item_id":self.source_order_item_id,
         };
         db.ph_db
             .collection(TRANSFERS_COL)
@@ -341,6 +381,7 @@ pub struct MongoTransferBuilder {
     pub note: String,
     pub shipment_vendor: ShipmentVendor,
     pub items: Vec<NewTransferInputItem>,
+    pub source_order_item_id: Option<Uuid>,
 }
 
 impl MongoTransferBuilder {
@@ -353,20 +394,51 @@ impl MongoTransferBuilder {
     ) -> Self {
         Self {
             transfer_id: Uuid::new(),
-            shipment_no: shipment_no.trim().to_owned(),
+            shipment_no: normalize_shipment_no(shipment_no),
             transfer_date,
             shipment_vendor,
             note: note.trim().to_owned(),
             items: items.to_owned(),
+            source_order_item_id: None,
         }
     }
 
+    /// mark the transfer as moving back the concealed shipped order item.
+    /// publishing is skipped if a transfer of the order item already exists.
+    pub fn source_order_item(mut self, order_item_id: Uuid) -> Self {
+        self.source_order_item_id = Some(order_item_id);
+        self
+    }
+
     #[instrument(name="publish new transfer",skip(self,db),fields(
         transfer_id=%self.transfer_id,
         shipment_no=%self.shipment_no,
     ))]
     pub async fn publish_new_transfer(&self, db: &DbClient) -> Result<MongoTransfer> {
         info!("try publish new transfer id:{}", self.transfer_id);
+        if let Some(order_item_id) = self.source_order_item_id {
+            if let Some(transfer) = find_transfer_by_source_order_item(db, order_item_id).await? {
+                info!(
+                    "transfer id:{} of order item id:{} exists, skip publish",
+                    transfer.id, order_item_id
+                );
+                return Ok(transfer);
+            }
+        }
+        // validate every item first, so a bad item won't leave the former items moved.
+        // items without inventory are collected to be reported at once.
+        let mut missing = Vec::new();
+        for item in self.items.iter() {
+            match check_shift_inventory_quantity(db, &item.item_code_ext, &item.quantity).await {
+                Err(Error::InventoryNotFound) => missing.push(item.item_code_ext.clone()),
+                res => {
+                    res?;
+                }
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::InventoryItemsMissing(missing));
+        }
         let mut operation_ids = Vec::new();
         for item in self.items.iter() {
             info!("try shift {}'s inventory", item.item_code_ext);
@@ -375,6 +447,8 @@ impl MongoTransferBuilder {
                     .await?;
             operation_ids.append(&mut ids);
         }
+        let operations = find_operations_by_ids(db, &operation_ids).await?;
+        let distribution = transfer_effect(&operations);
         info!("check if shipment no:{} existing.", &self.shipment_no);
         let shipments = get_shipment_by_no(db, &self.shipment_no).await?;
         if !shipments.is_empty() {
@@ -382,7 +456,7 @@ impl MongoTransferBuilder {
                 "shipment no:{} exists, so use shipment's infos",
                 &self.shipment_no
             );
-            let transfer = MongoTransfer::new(
+            let mut transfer = MongoTransfer::new(
                 self.transfer_id,
                 Some(shipments[0].id),
                 &self.shipment_no,
@@ -390,7 +464,9 @@ impl MongoTransferBuilder {
                 shipments[0].shipment_date,
                 shipments[0].vendor,
                 &operation_ids,
+                &distribution,
             );
+            transfer.source_order_item_id = self.source_order_item_id;
             info!("publish new transfer id:{} success", self.transfer_id);
             transfer.insert_self(db).await?;
             return Ok(transfer);
@@ -399,7 +475,7 @@ impl MongoTransferBuilder {
             "shipment no:{} not exists, use input infos",
             &self.shipment_no
         );
-        let transfer = MongoTransfer::new(
+        let mut transfer = MongoTransfer::new(
             self.transfer_id,
             None,
             &self.shipment_no,
@@ -407,7 +483,9 @@ impl MongoTransferBuilder {
             self.transfer_date,
             self.shipment_vendor,
             &operation_ids,
+            &distribution,
         );
+        transfer.source_order_item_id = self.source_order_item_id;
         transfer.insert_self(db).await?;
         info!("publish new transfer id:{} success", self.transfer_id);
 
@@ -428,13 +506,43 @@ pub struct MongoTransferOutput {
     pub items: Vec<MongoTransferItem>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct MongoTransferItem {
     pub item_code_ext: String,
     pub count: i32,
     pub location: InventoryLocation,
 }
 
+/// reverse positive operations of the transfer and re-create them at `new_location`,
+/// returns the operation ids the transfer should hold afterwards.
+async fn relocate_transfer_operations(
+    db: &DbClient,
+    transfer_id: Uuid,
+    new_location: InventoryLocation,
+) -> Result<Vec<Uuid>> {
+    let old_operations = find_operations_by_transfer_id(db, transfer_id).await?;
+    let mut new_operation_ids = Vec::new();
+    for operation in old_operations {
+        if operation.count > 0 {
+            operation
+                .run_backward(db, MongoOperationType::UpdateTransfer)
+                .await?;
+            let new_operation = MongoInventoryOperation::new(
+                &operation.item_code_ext,
+                transfer_id,
+                MongoOperationType::UpdateTransfer,
+                operation.count,
+                new_location,
+            );
+            let id = new_operation.run_self(db, false).await?;
+            new_operation_ids.push(id);
+            continue;
+        }
+        new_operation_ids.push(operation.id);
+    }
+    Ok(new_operation_ids)
+}
+
 pub async fn find_transfer_by_id(id: Uuid, db: &DbClient) -> Result<MongoTransferOutput> {
     let pipeline = vec![
         doc! {
@@ -473,12 +581,8 @@ pub async fn find_transfer_by_id(id: Uuid, db: &DbClient) -> Result<MongoTransfe
     Ok(outputs[0].to_owned())
 }
 
-pub async fn query_transfers(
-    db: &DbClient,
-    from: DateTime,
-    to: DateTime,
-    keyword: Option<String>,
-) -> Result<Vec<MongoTransferOutput>> {
+/// transfers within the range with their operations as `items`, the newest first.
+fn query_transfers_pipeline(from: DateTime, to: DateTime, keyword: Option<&str>) -> Vec<Document> {
     let mut pipeline = vec![
         doc! {
           "$match":{
@@ -502,7 +606,7 @@ pub async fn query_transfers(
           },
         },
     ];
-    if let Some(keyword) = keyword.as_deref() {
+    if let Some(keyword) = keyword {
         pipeline.push(doc! {
           "$match":{
             "$or":[
@@ -522,7 +626,16 @@ pub async fn query_transfers(
           }
         })
     }
+    pipeline
+}
 
+pub async fn query_transfers(
+    db: &DbClient,
+    from: DateTime,
+    to: DateTime,
+    keyword: Option<String>,
+) -> Result<Vec<MongoTransferOutput>> {
+    let pipeline = query_transfers_pipeline(from, to, keyword.as_deref());
     let mut cursor = db
         .ph_db
         .collection::<Document>(TRANSFERS_COL)
@@ -536,6 +649,78 @@ pub async fn query_transfers(
     Ok(outputs)
 }
 
+/// units moved by the transfers of a vendor in a month.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferSummaryRow {
+    /// `YYYY-MM` in the server's local time.
+    pub month: String,
+    pub vendor: ShipmentVendor,
+    /// positive counts of the transfers' operations summed up.
+    pub units: i64,
+    pub transfers: u32,
+}
+
+/// the positive operations of `query_transfers` grouped by month and vendor,
+/// sorted by month then vendor.
+pub async fn summarize_transfers(
+    db: &DbClient,
+    from: DateTime,
+    to: DateTime,
+) -> Result<Vec<TransferSummaryRow>> {
+    let mut pipeline = query_transfers_pipeline(from, to, None);
+    let offset = Local::now().format("%:z").to_string();
+    pipeline.extend([
+        doc! {
+          "$unwind":"$items"
+        },
+        doc! {
+          "$match":{
+            "items.count":{"$gt":0}
+          }
+        },
+        doc! {
+          "$group":{
+            "_id":{
+              "month":{"$dateToString":{
+                "format":"%Y-%m",
+                "date":"$transfer_date",
+                "timezone":offset,
+              }},
+              "vendor":"$shipment_vendor",
+            },
+            "units":{"$sum":{"$toLong":"$items.count"}},
+            "transfers":{"$addToSet":"$id"},
+          }
+        },
+        doc! {
+          "$project":{
+            "_id":0,
+            "month":"$_id.month",
+            "vendor":"$_id.vendor",
+            "units":1,
+            "transfers":{"$size":"$transfers"},
+          }
+        },
+        doc! {
+          "$sort":{
+            "month":1,
+            "vendor":1,
+          }
+        },
+    ]);
+    let mut cursor = db
+        .ph_db
+        .collection::<Document>(TRANSFERS_COL)
+        .aggregate(pipeline, None)
+        .await?;
+    let mut rows = Vec::new();
+    while let Some(doc) = cursor.next().await {
+        rows.push(bson::from_document::<TransferSummaryRow>(doc?)?);
+    }
+    Ok(rows)
+}
+
 pub async fn delete_transfer_by_id(db: &DbClient, id: Uuid) -> Result<()> {
     info!("try to delete transfer id:{}", id);
     let operations = find_operations_by_transfer_id(db, id).await?;
@@ -589,7 +774,22 @@ pub async fn find_operations_by_transfer_id(
         transfers.push(output);
     }
 
-    Ok(transfers[0].to_owned().operations)
+    transfers
+        .first()
+        .map(|transfer| transfer.operations.to_owned())
+        .ok_or_else(|| Error::TransferNotFound(transfer_id.to_string()))
+}
+
+pub async fn find_transfer_by_source_order_item(
+    db: &DbClient,
+    order_item_id: Uuid,
+) -> Result<Option<MongoTransfer>> {
+    let filter = doc! {"source_order_item_id":order_item_id};
+    Ok(db
+        .ph_db
+        .collection::<MongoTransfer>(TRANSFERS_COL)
+        .find_one(filter, None)
+        .await?)
 }
 
 pub async fn find_transfer_by_shipment_id(
@@ -628,3 +828,183 @@ pub async fn find_transfer_by_shipment_id(
 
     Ok(Some(outputs))
 }
+
+async fn find_operations_by_ids(
+    db: &DbClient,
+    ids: &[Uuid],
+) -> Result<Vec<MongoInventoryOperation>> {
+    let query = doc! {
+      "id":{"$in":ids},
+    };
+    let mut cursor = db
+        .ph_db
+        .collection::<MongoInventoryOperation>(OPERATIONS_COL)
+        .find(query, None)
+        .await?;
+    let mut operations = Vec::new();
+    while let Some(operation) = cursor.next().await {
+        operations.push(operation?);
+    }
+    Ok(operations)
+}
+
+/// sum up the not countered operations' count by item and location.
+fn transfer_effect(operations: &[MongoInventoryOperation]) -> Vec<MongoTransferItem> {
+    let mut effect: Vec<MongoTransferItem> = Vec::new();
+    for operation in operations.iter().filter(|o| !o.countered) {
+        match effect.iter_mut().find(|item| {
+            item.item_code_ext == operation.item_code_ext && item.location == operation.location
+        }) {
+            Some(item) => item.count += operation.count,
+            None => effect.push(MongoTransferItem {
+                item_code_ext: operation.item_code_ext.clone(),
+                count: operation.count,
+                location: operation.location,
+            }),
+        }
+    }
+    effect.retain(|item| item.count != 0);
+    effect
+}
+
+fn distribution_docs(distribution: &[MongoTransferItem]) -> Vec<Document> {
+    distribution
+        .iter()
+        .map(|item| {
+            doc! {
+              "item_code_ext":&item.item_code_ext,
+              "count":item.count,
+              "location":&item.location,
+            }
+        })
+        .collect()
+}
+
+async fn update_transfer_distribution_by_id(
+    db: &DbClient,
+    transfer_id: Uuid,
+    distribution: &[MongoTransferItem],
+) -> Result<()> {
+    let query = doc! {
+      "id":transfer_id,
+    };
+    let update = doc! {
+      "$set":{
+        "distribution":distribution_docs(distribution),
+      }
+    };
+    db.ph_db
+        .collection::<MongoTransfer>(TRANSFERS_COL)
+        .update_one(query, update, None)
+        .await?;
+    Ok(())
+}
+
+/// recompute the difference between the transfer's stored distribution and its operations,
+/// then run compensating operations to reconcile them.
+#[instrument(name = "repair transfer", skip(db))]
+pub async fn repair_transfer_by_id(
+    db: &DbClient,
+    transfer_id: Uuid,
+) -> Result<Vec<MongoTransferItem>> {
+    let transfer = db
+        .ph_db
+        .collection::<MongoTransfer>(TRANSFERS_COL)
+        .find_one(doc! {"id":transfer_id}, None)
+        .await?
+        .ok_or_else(|| Error::TransferNotFound(transfer_id.to_string()))?;
+    if transfer.distribution.is_empty() {
+        return Err(Error::TransferDistributionMissing(transfer_id.to_string()));
+    }
+    let operations = find_operations_by_transfer_id(db, transfer_id).await?;
+    let actual = transfer_effect(&operations);
+    let mut compensations: Vec<MongoTransferItem> = Vec::new();
+    for expected in transfer.distribution.iter() {
+        let count = actual
+            .iter()
+            .find(|a| a.item_code_ext == expected.item_code_ext && a.location == expected.location)
+            .map(|a| a.count)
+            .unwrap_or(0);
+        compensations.push(MongoTransferItem {
+            count: expected.count - count,
+            ..expected.clone()
+        });
+    }
+    for a in actual.iter() {
+        if !transfer
+            .distribution
+            .iter()
+            .any(|e| e.item_code_ext == a.item_code_ext && e.location == a.location)
+        {
+            compensations.push(MongoTransferItem {
+                count: -a.count,
+                ..a.clone()
+            });
+        }
+    }
+    compensations.retain(|c| c.count != 0);
+    if compensations.is_empty() {
+        info!(
+            "transfer id:{} is consistent, nothing to repair",
+            transfer_id
+        );
+        return Ok(compensations);
+    }
+    for compensation in compensations.iter() {
+        for operation in operations.iter().filter(|o| {
+            o.count > 0 && !o.countered && o.item_code_ext == compensation.item_code_ext
+        }) {
+            if !is_operation_could_be_backward_safely(db, operation).await? {
+                return Err(Error::InvalidOperation);
+            }
+        }
+        if compensation.count < 0 {
+            let inventory = find_inventory_by_item_code_ext(db, &compensation.item_code_ext)
+                .await?
+                .ok_or_else(|| Error::InventoryItemNotFound(compensation.item_code_ext.clone()))?;
+            let current = inventory
+                .quantity
+                .iter()
+                .find(|q| q.location == compensation.location)
+                .map(|q| q.quantity as i32)
+                .unwrap_or(0);
+            if current + compensation.count < 0 {
+                return Err(Error::InventoryQuantityNegative(
+                    compensation.item_code_ext.clone(),
+                    format!("{:?}", compensation.location),
+                ));
+            }
+        }
+    }
+    let mut operation_ids = Vec::new();
+    for compensation in compensations.iter() {
+        info!(
+            "compensate item:{} location:{:?} count:{}",
+            compensation.item_code_ext, compensation.location, compensation.count
+        );
+        let operation = MongoInventoryOperation::new(
+            &compensation.item_code_ext,
+            transfer_id,
+            MongoOperationType::Move,
+            compensation.count,
+            compensation.location,
+        );
+        operation_ids.push(operation.run_self(db, false).await?);
+    }
+    let query = doc! {
+      "id":transfer_id,
+    };
+    let update = doc! {
+      "$set":{
+        "update_at":Local::now(),
+      },
+      "$push":{
+        "operation_ids":{"$each":operation_ids},
+      }
+    };
+    db.ph_db
+        .collection::<MongoTransfer>(TRANSFERS_COL)
+        .update_one(query, update, None)
+        .await?;
+    Ok(compensations)
+}
@@ -27,6 +27,14 @@ pub enum Error {
     TransferNotFound(String),
     #[error("can not find order item {0}")]
     OrderItemNotFound(String),
+    #[error("can not find shipment {0}")]
+    ShipmentNotFound(String),
+    #[error("can not find register {0}")]
+    RegisterNotFound(String),
+    #[error("can not find return {0}")]
+    ReturnNotFound(String),
+    #[error("can not find item {0}")]
+    ItemNotFound(String),
     #[error("OrderItemIsConcealed")]
     OrderItemIsConcealed,
     #[error("clearance vendor should match a paid location")]
@@ -49,6 +57,8 @@ pub enum Error {
     SerdeJsonBody(#[from] serde_json::Error),
     #[error(transparent)]
     BsonDe(#[from] mongodb::bson::de::Error),
+    #[error(transparent)]
+    BsonSer(#[from] mongodb::bson::ser::Error),
     #[error("ItemTypeNotPrepared")]
     ItemTypeNotPrepared,
     #[error(transparent)]
@@ -69,6 +79,54 @@ pub enum Error {
     InvalidOperation,
     #[error("Path not found")]
     PathNotFound,
+    #[error("shipment {0} is already ongoing")]
+    ShipmentAlreadyOngoing(String),
+    #[error("{0}")]
+    InvalidShipmentNo(String),
+    #[error("shipment has related transfers: {}", .0.join(","))]
+    ShipmentHasTransfers(Vec<String>),
+    #[error("order item {id} is not shippable, status: {status}")]
+    ItemNotShippable { id: String, status: String },
+    #[error("order items are not shippable: {}", .0.iter().map(|(id, status)| format!("{id}({status})")).collect::<Vec<_>>().join(","))]
+    ItemsNotShippable(Vec<(String, String)>),
+    #[error("order item {id} is already shipped")]
+    ItemAlreadyShipped { id: String },
+    #[error("order items were no longer guaranteed when shipping, taken by a concurrent request: {}", .0.join(","))]
+    ItemsClaimedConcurrently(Vec<String>),
+    #[error("order item {id} can not be changed from {from} to {to}")]
+    InvalidItemStatusTransition {
+        id: String,
+        from: String,
+        to: String,
+    },
+    #[error("order item {id} is not returnable, status: {status}")]
+    ItemNotReturnable { id: String, status: String },
+    #[error("order item {0} is already returned")]
+    ItemAlreadyReturned(String),
+    #[error("page {0} is beyond the max page {1}")]
+    PageOutOfRange(u32, u32),
+    #[error("{0} was created more than {1} days ago, deleting it needs the override role")]
+    DeleteGraceExpired(String, i64),
+    #[error("invalid cursor {0}")]
+    InvalidCursor(String),
+    #[error("invalid item code {0}")]
+    InvalidItemCode(String),
+    #[error("too many control connections, the limit is {0}")]
+    TooManyConnections(usize),
+    #[error("request body is larger than the limit of {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("transfer {0} has no stored distribution to repair from")]
+    TransferDistributionMissing(String),
+    #[error("quantity of {0} at {1} will be negative")]
+    InventoryQuantityNegative(String, String),
+    #[error("invalid transfer quantity: {0}")]
+    InvalidTransferQuantity(String),
+    #[error("invalid location swap: {0}")]
+    InvalidLocationSwap(String),
+    #[error("no inventory of {}", .0.join(", "))]
+    InventoryItemsMissing(Vec<String>),
+    #[error("unknown order item status: {0}")]
+    UnknownOrderItemStatus(String),
 }
 
 impl IntoResponse for Error {
@@ -84,6 +142,12 @@ impl IntoResponse for Error {
                 StatusCode::NOT_FOUND,
                 format!("inventory item {} not found", item),
             ),
+            Error::OrderNotFound(_)
+            | Error::OrderItemNotFound(_)
+            | Error::ShipmentNotFound(_)
+            | Error::RegisterNotFound(_)
+            | Error::ReturnNotFound(_)
+            | Error::ItemNotFound(_) => (StatusCode::NOT_FOUND, format!("{self}")),
             Error::OrderItemIsConcealed => (
                 StatusCode::FORBIDDEN,
                 String::from("order item has been changed"),
@@ -96,6 +160,33 @@ impl IntoResponse for Error {
             Error::OrderValidate(e) => (StatusCode::BAD_REQUEST, format!("{e}")),
             Error::VenderLocationNotMatch => (StatusCode::BAD_REQUEST, format!("{self}")),
             Error::PathNotFound => (StatusCode::NOT_FOUND, format!("{self}")),
+            Error::ShipmentAlreadyOngoing(_) => (StatusCode::CONFLICT, format!("{self}")),
+            Error::InvalidShipmentNo(_) => (StatusCode::BAD_REQUEST, format!("{self}")),
+            Error::ShipmentHasTransfers(_) => (StatusCode::CONFLICT, format!("{self}")),
+            Error::InventoryQuantityNegative(..) | Error::InvalidLocationSwap(_) => {
+                (StatusCode::BAD_REQUEST, format!("{self}"))
+            }
+            Error::PageOutOfRange(..) => (StatusCode::UNPROCESSABLE_ENTITY, format!("{self}")),
+            Error::DeleteGraceExpired(..) => (StatusCode::FORBIDDEN, format!("{self}")),
+            Error::InvalidCursor(_) | Error::InvalidItemCode(_) => {
+                (StatusCode::BAD_REQUEST, format!("{self}"))
+            }
+            Error::InvalidTransferQuantity(_)
+            | Error::InventoryItemsMissing(_)
+            | Error::UnknownOrderItemStatus(_) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, format!("{self}"))
+            }
+            Error::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, format!("{self}")),
+            Error::TooManyConnections(_) => (StatusCode::SERVICE_UNAVAILABLE, format!("{self}")),
+            Error::TransferDistributionMissing(_) => (StatusCode::CONFLICT, format!("{self}")),
+            Error::ItemNotShippable { .. }
+            | Error::ItemsNotShippable(_)
+            | Error::ItemAlreadyShipped { .. }
+            | Error::ItemsClaimedConcurrently(_) => (StatusCode::CONFLICT, format!("{self}")),
+            Error::InvalidItemStatusTransition { .. } => (StatusCode::CONFLICT, format!("{self}")),
+            Error::ItemNotReturnable { .. } | Error::ItemAlreadyReturned(_) => {
+                (StatusCode::CONFLICT, format!("{self}"))
+            }
             Error::Auth(e) => match e {
                 AuthError::CookieHeaderNotFound => (
                     StatusCode::UNAUTHORIZED,
@@ -105,6 +196,14 @@ impl IntoResponse for Error {
                     StatusCode::BAD_REQUEST,
                     String::from("invalid signup secret"),
                 ),
+                AuthError::InvalidInviteToken => (
+                    StatusCode::BAD_REQUEST,
+                    String::from("invalid invite token"),
+                ),
+                AuthError::SignupCredentialMissing => (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    String::from("signup secret or invite token is required"),
+                ),
                 AuthError::UsernameOccupied => (
                     StatusCode::BAD_REQUEST,
                     String::from("username is occupied"),
@@ -155,6 +254,10 @@ impl IntoResponse for Error {
 pub enum AuthError {
     #[error("invalid signup secret")]
     InvalidSignupSecret,
+    #[error("invalid invite token")]
+    InvalidInviteToken,
+    #[error("signup secret or invite token is required")]
+    SignupCredentialMissing,
     #[error("cookie header is not found")]
     CookieHeaderNotFound,
     #[error("username is occupied")]
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{mongo::DbClient, ActivityItem, ActivityRepo},
+    error_result::Result,
+};
+
+use super::AppState;
+
+const DEFAULT_ACTIVITY_LIMIT: u32 = 50;
+
+pub fn get_activity_router() -> Router<AppState> {
+    Router::new().route("/", get(find_recent_activity))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentActivityQuery {
+    pub limit: Option<u32>,
+}
+
+pub async fn find_recent_activity(
+    Query(query): Query<RecentActivityQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<ActivityItem>>> {
+    let activities = db
+        .find_recent_activity(query.limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT))
+        .await?;
+    Ok(Json(activities.into_iter().map(|a| a.into()).collect()))
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    routing::post,
+    Json, Router,
+};
+use chrono::{prelude::*, Duration};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::Sender;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::{
+    cache::OrderCache,
+    configuration::OrderQueryPreset,
+    db::{inventory::LocationAudit, mongo::DbClient, OrderRepo},
+    error_result::Result,
+    services::{consistency_check::run_location_audit, daily_shipment_export::local_day_start},
+};
+
+use super::{
+    auth::{UserInfo, SETTINGS},
+    order::QueryOrdersMessage,
+    ws::{send_control_message, ControlMessage},
+    AppState,
+};
+
+pub fn get_admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/cache/clear", post(clear_caches))
+        .route("/cache/warm", post(warm_caches))
+        .route("/inventory/location_audit", post(audit_inventory_locations))
+        .route("/broadcast_refresh", post(broadcast_refresh))
+        .route(
+            "/orders/backfill_customer_id",
+            post(backfill_customer_id_normalized),
+        )
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearCachesOutput {
+    pub evicted: usize,
+}
+
+#[instrument(name="clear caches",skip(user_info,cache),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn clear_caches(
+    user_info: UserInfo,
+    State(cache): State<Arc<dyn OrderCache>>,
+) -> Result<Json<ClearCachesOutput>> {
+    let evicted = cache.clear_orders() + cache.clear_ph_items();
+    info!("cleared {evicted} cache entries");
+    Ok(Json(ClearCachesOutput { evicted }))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmCachesOutput {
+    /// presets queried and cached, presets already cached are skipped.
+    pub warmed: usize,
+}
+
+/// the orders query of `preset` run at `now`, its days are local days
+/// as the ranges the client queries by.
+pub fn preset_query(preset: &OrderQueryPreset, now: DateTime<Utc>) -> QueryOrdersMessage {
+    let today = now.with_timezone(&Local).date_naive();
+    QueryOrdersMessage {
+        keyword: preset.keyword.clone(),
+        status: preset.status.clone(),
+        from: local_day_start(today - Duration::days(preset.days)),
+        to: local_day_start(today + Duration::days(1)) - Duration::seconds(1),
+        page: preset.page,
+        cursor: None,
+    }
+}
+
+/// run the configured order query presets and cache their results,
+/// so the first queries after clearing the caches are not slow.
+#[instrument(name="warm caches",skip(user_info,db,cache),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn warm_caches(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+) -> Result<Json<WarmCachesOutput>> {
+    let now = Utc::now();
+    let mut warmed = 0;
+    for preset in SETTINGS.cache_warm.presets.iter() {
+        let message = preset_query(preset, now);
+        if cache.contains_orders(&message) {
+            continue;
+        }
+        let (_, output) = db
+            .query_orders(
+                &message.keyword,
+                &message.status,
+                message.from,
+                message.to,
+                message.page,
+            )
+            .await?;
+        cache.set_orders(message, output);
+        warmed += 1;
+    }
+    info!("warmed {warmed} order queries");
+    Ok(Json(WarmCachesOutput { warmed }))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocationAuditQuery {
+    #[serde(default)]
+    pub backfill: bool,
+}
+
+#[instrument(name="audit inventory locations",skip(user_info,db,sender),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn audit_inventory_locations(
+    user_info: UserInfo,
+    Query(query): Query<LocationAuditQuery>,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+) -> Result<Json<Vec<LocationAudit>>> {
+    let audits = run_location_audit(&db, query.backfill).await?;
+    if audits.iter().any(|audit| audit.backfilled) {
+        send_control_message(&sender, ControlMessage::RefreshInventory);
+    }
+    Ok(Json(audits))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastRefreshOutput {
+    /// clients connected when the refresh was sent.
+    pub receivers: usize,
+}
+
+/// ask every connected client to refetch everything.
+#[instrument(name="broadcast refresh",skip(user_info,sender),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn broadcast_refresh(
+    user_info: UserInfo,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+) -> Result<Json<BroadcastRefreshOutput>> {
+    let receivers = sender.receiver_count();
+    send_control_message(&sender, ControlMessage::ResyncAll);
+    info!("broadcast refresh to {receivers} clients");
+    Ok(Json(BroadcastRefreshOutput { receivers }))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillOutput {
+    pub updated: u64,
+}
+
+/// one-off backfill of `customer_id_normalized` for orders stored before it existed,
+/// their customer ids are not found by normalized search until then.
+#[instrument(name="backfill customer id normalized",skip(user_info,db,cache),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn backfill_customer_id_normalized(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+) -> Result<Json<BackfillOutput>> {
+    let updated = db.backfill_customer_id_normalized().await?;
+    if updated > 0 {
+        cache.clear_orders();
+    }
+    Ok(Json(BackfillOutput { updated }))
+}
@@ -11,7 +11,7 @@ use base64::{engine::general_purpose, Engine as _};
 use chrono::prelude::*;
 use jsonwebtoken::{
     decode, encode, errors::ErrorKind as JWTErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
-    Validation,
+    TokenData, Validation,
 };
 use once_cell::sync::Lazy;
 use pbkdf2::password_hash::{
@@ -20,7 +20,7 @@ use pbkdf2::password_hash::{
 use pbkdf2::Pbkdf2;
 use reqwest::header::CONTENT_TYPE;
 use secrecy::{ExposeSecret, Secret};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::str;
 use tracing::{info, instrument};
 use uuid::Uuid;
@@ -28,7 +28,7 @@ use uuid::Uuid;
 use crate::{
     configuration::{get_configuration, Settings},
     db::{
-        auth::{User, UserRole},
+        auth::{Invite, QueryPreset, RecentResourceKind, User, UserRole},
         mongo::DbClient,
     },
     error_result::Result,
@@ -56,11 +56,16 @@ pub static SETTINGS: Lazy<Settings> =
 pub struct SignUpMessage {
     username: String,
     password: Secret<String>,
-    role: UserRole,
+    role: Option<UserRole>,
+    #[serde(default)]
     sub_role: HashMap<AppPrivateRoute, UserRole>,
-    secret: String,
+    secret: Option<String>,
+    invite_token: Option<String>,
 }
 
+/// user could sign up with either an invite token or the shared signup secret.
+/// invited users get the roles preset by the invite, visitor if none,
+/// the requested roles are only used with the signup secret.
 #[instrument(name = "sign up new user", skip(message, db),fields(
     request_id=%Uuid::new_v4(),
     username=%message.username,
@@ -69,29 +74,93 @@ pub async fn sign_up(
     State(db): State<Arc<DbClient>>,
     Json(message): Json<SignUpMessage>,
 ) -> Result<impl IntoResponse> {
-    if message.secret != *SETTINGS.signup_secret.expose_secret() {
-        info!("{secret} is incorrect", secret = message.secret);
-        return Err(Error::Auth(AuthError::InvalidSignupSecret));
+    match (&message.secret, &message.invite_token) {
+        (None, None) => return Err(Error::Auth(AuthError::SignupCredentialMissing)),
+        (Some(secret), None) if !is_valid_signup_secret(secret) => {
+            info!("{secret} is incorrect");
+            return Err(Error::Auth(AuthError::InvalidSignupSecret));
+        }
+        _ => {}
     }
     if db.check_is_username_occupied(&message.username).await? {
         info!("{} is occupied", message.username);
         return Err(Error::Auth(AuthError::UsernameOccupied));
     }
+    let (role, sub_role) = match message.invite_token.as_deref() {
+        // the requested roles are not checked against the inviter, so they are ignored.
+        Some(token) => {
+            let invite = db.consume_invite(token, &message.username).await?;
+            info!("consumed invite id:{}", invite.id);
+            (
+                invite.role.unwrap_or(UserRole::Visitor),
+                invite.sub_role.unwrap_or_default(),
+            )
+        }
+        None => (message.role.unwrap_or(UserRole::Visitor), message.sub_role),
+    };
     let handler = tokio::task::spawn_blocking(move || {
         generate_password_hash(message.password.expose_secret())
     });
     let password_hash = handler.await??;
     info!("create new user :{}", message.username);
-    let user = User::new(
-        message.username,
-        password_hash,
-        message.role,
-        message.sub_role,
-    );
+    let user = User::new(message.username, password_hash, role, sub_role);
     db.create_user(user).await?;
     Ok(StatusCode::CREATED)
 }
 
+#[inline]
+fn is_valid_signup_secret(secret: &str) -> bool {
+    std::iter::once(&SETTINGS.signup_secret)
+        .chain(SETTINGS.auth.previous_signup_secrets.iter())
+        .any(|s| s.expose_secret() == secret)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInviteMessage {
+    role: Option<UserRole>,
+    sub_role: Option<HashMap<AppPrivateRoute, UserRole>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInviteResponse {
+    token: String,
+}
+
+/// a user could not preset a role higher than their own.
+#[instrument(name = "create invite", skip(user_info, message, db),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id,
+))]
+pub async fn create_invite(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    Json(message): Json<CreateInviteMessage>,
+) -> Result<impl IntoResponse> {
+    let presets = message
+        .role
+        .iter()
+        .chain(message.sub_role.iter().flat_map(|s| s.values()));
+    for preset in presets {
+        if *preset < user_info.role {
+            return Err(Error::Auth(AuthError::PermissionNotEnough {
+                got: Some(user_info.role),
+                need: *preset,
+            }));
+        }
+    }
+    let invite = Invite::new(message.role, message.sub_role, user_info.user_id.into());
+    db.create_invite(&invite).await?;
+    info!("created invite id:{}", invite.id);
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateInviteResponse {
+            token: invite.token,
+        }),
+    ))
+}
+
 #[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GetUserInfoResponse {
@@ -112,6 +181,55 @@ pub async fn get_user_info_handler(
     }))
 }
 
+pub async fn get_user_presets(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<QueryPreset>>> {
+    let user = db.find_user(user_info.user_id.into()).await?;
+    Ok(Json(user.presets))
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentResourceResponse {
+    kind: RecentResourceKind,
+    id: Uuid,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    viewed_at: DateTime<Utc>,
+}
+
+/// orders and shipments recently fetched by the user, the most recent first.
+pub async fn get_recent_resources(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<RecentResourceResponse>>> {
+    let user = db.find_user(user_info.user_id.into()).await?;
+    Ok(Json(
+        user.recent
+            .into_iter()
+            .map(|resource| RecentResourceResponse {
+                kind: resource.kind,
+                id: resource.id.to_uuid_1(),
+                viewed_at: resource.viewed_at.to_chrono(),
+            })
+            .collect(),
+    ))
+}
+
+#[instrument(name = "update user presets", skip(user_info, db, presets),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id,
+))]
+pub async fn update_user_presets(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    Json(presets): Json<Vec<QueryPreset>>,
+) -> Result<Json<Vec<QueryPreset>>> {
+    db.update_user_presets(user_info.user_id.into(), &presets)
+        .await?;
+    Ok(Json(presets))
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginMessage {
@@ -229,18 +347,41 @@ fn verify_password(password: &str, password_hash: &str) -> Result<()> {
         .map_err(|_| Error::Auth(AuthError::InvalidPassword))
 }
 
+/// try to decode the token with every secret in order,
+/// the next secret is tried only if the signature is invalid.
+fn decode_with_secrets<'a, T: DeserializeOwned>(
+    token: &str,
+    secrets: impl IntoIterator<Item = &'a Secret<String>>,
+) -> std::result::Result<TokenData<T>, jsonwebtoken::errors::Error> {
+    let validation = Validation::new(Algorithm::HS512);
+    let mut last_error = None;
+    for secret in secrets {
+        match decode::<T>(
+            token,
+            &DecodingKey::from_secret(secret.expose_secret().as_bytes()),
+            &validation,
+        ) {
+            Err(e) if e.kind() == &JWTErrorKind::InvalidSignature => last_error = Some(e),
+            res => return res,
+        }
+    }
+    Err(last_error.unwrap_or_else(|| JWTErrorKind::InvalidSignature.into()))
+}
+
 #[inline]
 pub fn parse_access_token(
     token: &str,
     uri_opt: impl Into<Option<String>>,
     is_auth_token: bool,
 ) -> Result<Uuid> {
-    let decoded = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(SETTINGS.access_token_secret.expose_secret().as_bytes()),
-        &Validation::new(Algorithm::HS512),
-    )
-    .map_err(|e| {
+    let secrets = std::iter::once(&SETTINGS.access_token_secret).chain(
+        SETTINGS
+            .auth
+            .previous_secrets
+            .iter()
+            .map(|s| &s.access_token_secret),
+    );
+    let decoded = decode_with_secrets::<Claims>(token, secrets).map_err(|e| {
         if e.kind() == &JWTErrorKind::ExpiredSignature {
             if is_auth_token {
                 return Error::Auth(AuthError::TokenNeedRefresh);
@@ -257,12 +398,15 @@ pub fn parse_access_token(
 
 #[inline]
 pub fn parse_refresh_token(token: &str) -> Result<Uuid> {
-    let decoded = decode::<RefreshClaims>(
-        token,
-        &DecodingKey::from_secret(SETTINGS.refresh_token_secret.expose_secret().as_bytes()),
-        &Validation::new(Algorithm::HS512),
-    )
-    .map_err(|e| Error::Auth(e.into()))?;
+    let secrets = std::iter::once(&SETTINGS.refresh_token_secret).chain(
+        SETTINGS
+            .auth
+            .previous_secrets
+            .iter()
+            .map(|s| &s.refresh_token_secret),
+    );
+    let decoded =
+        decode_with_secrets::<RefreshClaims>(token, secrets).map_err(|e| Error::Auth(e.into()))?;
     Ok(decoded.claims.user_id)
 }
 
@@ -1,41 +1,81 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    result::Result as StdResult,
+    sync::Arc,
+};
 
 use axum::{
-    extract::{Path, Query, State},
-    response::IntoResponse,
+    async_trait,
+    extract::{FromRequestParts, Path, Query, State},
+    http::{
+        header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE},
+        request::Parts,
+    },
+    response::{IntoResponse, Response},
     Json,
 };
-use chrono::prelude::*;
+use chrono::{prelude::*, serde::ts_seconds};
+use mongodb::bson::Bson;
 use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 use tracing::{debug, instrument, warn};
 use uuid::Uuid;
 
+use crate::configuration::RoundingStrategy;
+use crate::db::shipment::ShipmentVendor;
 use crate::db::{
-    inventory::InventoryLocation, mongo::DbClient, InventoryRepo, PhDataBase, ShipmentRepo,
+    invenope::{MongoOperationType, RelatedKind},
+    inventory::InventoryLocation,
+    mongo::DbClient,
+    shipment::MongoShipmentOutput,
+    InventoryRepo, PhDataBase, ShipmentRepo,
 };
 use crate::{
-    db::{order::OrderItemStatus, PhItem, TransferRepo},
-    error_result::{validate_http_response, Result},
+    db::{
+        order::{normalize_customer_id, MongoOrderItem, OrderItemRate, OrderItemStatus},
+        transfer::MongoTransferItem,
+        OrderRepo, PhItem, TransferRepo,
+    },
+    error_result::{validate_http_response, Error, Result},
     server::auth::SETTINGS,
 };
 
-use super::{inventory::InventoryQuery, shipment::QueryShipmentMessage};
+use super::{
+    inventory::{Category, InventoryQuery},
+    shipment::QueryShipmentMessage,
+};
 
 #[derive(Serialize)]
 pub struct ExportQueryShipmentMessage {
     filename: String,
     rows: Vec<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination: Option<String>,
+}
+
+/// how the rows of a shipments export are grouped.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportSplit {
+    Vendor,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportSheet {
+    pub name: String,
+    pub rows: Vec<Vec<String>>,
 }
 
 #[instrument(name="export shipments",skip(message,db,http_client),fields(
     request_id = %Uuid::new_v4()
 ))]
 pub async fn export_shipments(
+    format: ExportFormat,
     Query(message): Query<QueryShipmentMessage>,
     State(db): State<Arc<DbClient>>,
     State(http_client): State<Arc<reqwest::Client>>,
 ) -> Result<impl IntoResponse> {
-    let mut items_map: HashMap<(String, String), usize> = std::collections::HashMap::new();
     let shipments = db
         .query_shipments(
             &message.keyword,
@@ -46,31 +86,124 @@ pub async fn export_shipments(
             message.page,
         )
         .await?;
-    let mut rows = Vec::new();
-    for shipment in shipments.1.iter() {
-        for item in shipment
-            .items
-            .iter()
-            .filter(|items| items.status != OrderItemStatus::Concealed)
-        {
-            let q = items_map
-                .entry((item.item_code_ext.clone(), item.rate.to_string()))
-                .or_insert(0);
-            *q += 1;
+    let shipment_ids = shipments.1.iter().map(|s| s.id).collect::<Vec<_>>();
+    let now = Local::now();
+    let filename = format!(
+        "{}年{}年{}日生成出荷一覧.xlsx",
+        now.year(),
+        now.month(),
+        now.day()
+    );
+    if message.split_by == Some(ExportSplit::Vendor) {
+        let sheets = build_vendor_sheets(
+            &db,
+            &shipments.1,
+            message.bucket.as_deref(),
+            message.include_concealed,
+        )
+        .await?;
+        // the utility renders a single sheet, so the sheet name leads each row instead.
+        let rows = flatten_sheets(sheets);
+        if format == ExportFormat::Csv {
+            db.mark_shipments_exported(&shipment_ids).await?;
+            return Ok(csv_response(&filename, &rows));
         }
+        let url = upload_query_shipment_export(&http_client, &filename, rows, None).await?;
+        db.mark_shipments_exported(&shipment_ids).await?;
+        return Ok(Json(ExportFileResponse { url, filename }).into_response());
     }
+    // rounding of the vendor filtered by, if any.
+    let vendor = serde_json::from_value::<ShipmentVendor>(message.vendor.clone().into()).ok();
+    let rounding = SETTINGS.shipment.rounding_for(vendor.as_ref());
+    let mut rows =
+        build_query_shipment_rows(&db, &shipments.1, message.bucket.as_deref(), rounding).await?;
+    if message.include_concealed {
+        rows.extend(
+            build_concealed_query_shipment_rows(
+                &db,
+                &shipments.1,
+                message.bucket.as_deref(),
+                rounding,
+            )
+            .await?,
+        );
+    }
+    if format == ExportFormat::Csv {
+        db.mark_shipments_exported(&shipment_ids).await?;
+        return Ok(csv_response(&filename, &rows));
+    }
+    let url = upload_query_shipment_export(&http_client, &filename, rows, None).await?;
+    db.mark_shipments_exported(&shipment_ids).await?;
 
-    let mut items = shipments
-        .1
-        .into_iter()
-        .flat_map(|shipment| shipment.items)
+    Ok(Json(ExportFileResponse { url, filename }).into_response())
+}
+
+/// marks rows of concealed items in the column every shipment export leaves empty.
+pub const CONCEALED_MARK: &str = "-";
+
+/// put `CONCEALED_MARK` in the empty column of rows built from concealed items.
+fn mark_concealed(mut rows: Vec<Vec<String>>) -> Vec<Vec<String>> {
+    for row in rows.iter_mut() {
+        row[4] = CONCEALED_MARK.to_string();
+    }
+    rows
+}
+
+/// rows of shipments, same item codes with the same rate are merged into one row.
+/// concealed items are left out.
+/// | 条形码 | 单件日元价格（不含税） | 尺码 | 色号 | | 数量 | 折扣 | 合计 |
+pub async fn build_query_shipment_rows(
+    db: &DbClient,
+    shipments: &[MongoShipmentOutput],
+    bucket: Option<&str>,
+    rounding: RoundingStrategy,
+) -> Result<Vec<Vec<String>>> {
+    let items = shipments
+        .iter()
+        .flat_map(|shipment| shipment.items.iter())
         .filter(|item| item.status != OrderItemStatus::Concealed)
         .collect::<Vec<_>>();
+    merge_query_shipment_rows(db, items, bucket, rounding).await
+}
+
+/// rows of the concealed items of shipments in the columns of `build_query_shipment_rows`,
+/// marked by `CONCEALED_MARK`.
+pub async fn build_concealed_query_shipment_rows(
+    db: &DbClient,
+    shipments: &[MongoShipmentOutput],
+    bucket: Option<&str>,
+    rounding: RoundingStrategy,
+) -> Result<Vec<Vec<String>>> {
+    let items = shipments
+        .iter()
+        .flat_map(|shipment| shipment.items.iter())
+        .filter(|item| item.status == OrderItemStatus::Concealed)
+        .collect::<Vec<_>>();
+    Ok(mark_concealed(
+        merge_query_shipment_rows(db, items, bucket, rounding).await?,
+    ))
+}
+
+async fn merge_query_shipment_rows(
+    db: &DbClient,
+    mut items: Vec<&MongoOrderItem>,
+    bucket: Option<&str>,
+    rounding: RoundingStrategy,
+) -> Result<Vec<Vec<String>>> {
+    let mut items_map: HashMap<(String, String), usize> = std::collections::HashMap::new();
+    let mut rows = Vec::new();
+    for item in items.iter() {
+        let q = items_map
+            .entry((item.item_code_ext.clone(), export_rate(item).to_string()))
+            .or_insert(0);
+        *q += 1;
+    }
     items.sort_by(|a, b| a.item_code_ext.cmp(&b.item_code_ext));
     for item in items.iter() {
-        if let Some(q) = items_map.get(&(item.item_code_ext.clone(), item.rate.to_string())) {
+        let rate = export_rate(item);
+        if let Some(q) = items_map.get(&(item.item_code_ext.clone(), rate.to_string())) {
             let item_detail = db
-                .find_one_by_item_code(&item.item_code_ext.as_str()[..11])
+                .find_one_by_item_code(&item.item_code_ext.as_str()[..11], bucket)
                 .await?
                 .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
             let price_without_tax = get_tax_exclusive_price(item_detail.price);
@@ -81,26 +214,138 @@ pub async fn export_shipments(
                 item.item_code_ext[12..13].to_string(),
                 String::from(""),
                 format!("{}", q),
-                stringify_rate(item.rate),
+                stringify_rate(rate),
                 format!(
                     "{}",
-                    (*q as f64 * price_without_tax as f64 * item.rate).ceil() as u32
+                    rounding.apply_total(*q as f64 * price_without_tax as f64 * rate)
                 ),
             ];
             rows.push(row);
-            items_map.remove(&(item.item_code_ext.clone(), item.rate.to_string()));
+            items_map.remove(&(item.item_code_ext.clone(), rate.to_string()));
         }
     }
-    let now = Local::now();
-    let filename = format!(
-        "{}年{}年{}日生成出荷一覧.xlsx",
-        now.year(),
-        now.month(),
-        now.day()
-    );
+    Ok(rows)
+}
+
+/// `build_query_shipment_rows` of each vendor's shipments rounded by the vendor,
+/// one sheet per vendor named after it in name order.
+/// rows of concealed items follow if `include_concealed` is set.
+pub async fn build_vendor_sheets(
+    db: &DbClient,
+    shipments: &[MongoShipmentOutput],
+    bucket: Option<&str>,
+    include_concealed: bool,
+) -> Result<Vec<ExportSheet>> {
+    let mut by_vendor: BTreeMap<String, Vec<MongoShipmentOutput>> = BTreeMap::new();
+    for shipment in shipments.iter() {
+        by_vendor
+            .entry(shipment.vendor.to_string())
+            .or_default()
+            .push(shipment.clone());
+    }
+    let mut sheets = Vec::with_capacity(by_vendor.len());
+    for (name, shipments) in by_vendor {
+        let rounding = SETTINGS.shipment.rounding_for(Some(&shipments[0].vendor));
+        let mut rows = build_query_shipment_rows(db, &shipments, bucket, rounding).await?;
+        if include_concealed {
+            rows.extend(
+                build_concealed_query_shipment_rows(db, &shipments, bucket, rounding).await?,
+            );
+        }
+        sheets.push(ExportSheet { name, rows });
+    }
+    Ok(sheets)
+}
+
+/// rows of all sheets in one, each row led by the name of its sheet.
+pub fn flatten_sheets(sheets: Vec<ExportSheet>) -> Vec<Vec<String>> {
+    sheets
+        .into_iter()
+        .flat_map(|sheet| {
+            let name = sheet.name;
+            sheet
+                .rows
+                .into_iter()
+                .map(move |row| std::iter::once(name.clone()).chain(row).collect::<Vec<_>>())
+        })
+        .collect()
+}
+
+/// shipped items sold at one discount rate.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscountBand {
+    pub rate: f64,
+    /// the rate as exported, such as `20%Off`.
+    pub label: String,
+    pub count: u32,
+    /// discounted tax exclusive prices summed up.
+    pub revenue: u64,
+}
+
+/// group the shipped items of shipments by their exported rate, no discount first.
+/// prices are rounded by each shipment's vendor as the shipment exports do.
+pub async fn build_discount_breakdown(
+    db: &DbClient,
+    shipments: &[MongoShipmentOutput],
+) -> Result<Vec<DiscountBand>> {
+    let mut codes = shipments
+        .iter()
+        .flat_map(|shipment| shipment.items.iter())
+        .map(|item| item.item_code_ext[..11].to_string())
+        .collect::<Vec<_>>();
+    codes.sort();
+    codes.dedup();
+    let mut catalog = HashMap::new();
+    for ph_item in db.find_by_item_codes(&codes).await? {
+        catalog.entry(ph_item.code.clone()).or_insert(ph_item);
+    }
+    let mut bands: Vec<DiscountBand> = Vec::new();
+    for shipment in shipments.iter() {
+        let rounding = SETTINGS.shipment.rounding_for(Some(&shipment.vendor));
+        for item in shipment
+            .items
+            .iter()
+            .filter(|item| item.status == OrderItemStatus::Shipped)
+        {
+            let rate = export_rate(item);
+            let price = catalog
+                .get(&item.item_code_ext[..11])
+                .map(|ph_item| {
+                    get_tax_exclusive_price(ph_item.get_discounted_price(rate, rounding))
+                })
+                .unwrap_or(0);
+            let label = stringify_rate(rate);
+            match bands.iter_mut().find(|band| band.label == label) {
+                Some(band) => {
+                    band.count += 1;
+                    band.revenue += price as u64;
+                }
+                None => bands.push(DiscountBand {
+                    rate,
+                    label,
+                    count: 1,
+                    revenue: price as u64,
+                }),
+            }
+        }
+    }
+    bands.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+    Ok(bands)
+}
+
+/// send rows of `build_query_shipment_rows` to the utility, returns the download url.
+/// the utility also stores the file to `destination` if it is given.
+pub async fn upload_query_shipment_export(
+    http_client: &reqwest::Client,
+    filename: &str,
+    rows: Vec<Vec<String>>,
+    destination: Option<&str>,
+) -> Result<String> {
     let message = ExportQueryShipmentMessage {
-        filename: filename.clone(),
+        filename: filename.to_string(),
         rows,
+        destination: destination.map(|d| d.to_string()),
     };
     let resp = http_client
         .post(format!(
@@ -110,11 +355,9 @@ pub async fn export_shipments(
         .json(&message)
         .send()
         .await?;
-    let url = validate_http_response::<DownLoadUrlResponse>(resp)
+    Ok(validate_http_response::<DownLoadUrlResponse>(resp)
         .await?
-        .url;
-
-    Ok(Json(ExportFileResponse { url, filename }))
+        .url)
 }
 
 /// export a single shipment includes below column:
@@ -230,23 +473,125 @@ pub struct ExportFileResponse {
     url: String,
     filename: String,
 }
+
+/// the file format an export is requested in, xlsx via the utility by default.
+/// csv is requested by `?format=csv` or an `Accept: text/csv` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Xlsx,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct ExportFormatQuery {
+    format: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ExportFormat
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> StdResult<Self, Self::Rejection> {
+        if let Ok(Query(query)) = Query::<ExportFormatQuery>::from_request_parts(parts, state).await
+        {
+            if let Some(format) = query.format {
+                return Ok(match format.eq_ignore_ascii_case("csv") {
+                    true => ExportFormat::Csv,
+                    false => ExportFormat::Xlsx,
+                });
+            }
+        }
+        let accept_csv = parts
+            .headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.contains("text/csv"));
+        Ok(match accept_csv {
+            true => ExportFormat::Csv,
+            false => ExportFormat::Xlsx,
+        })
+    }
+}
+
+/// rows as a comma-separated text, fields containing a comma, a quote or a line break
+/// are quoted with their quotes doubled.
+pub fn rows_to_csv(rows: &[Vec<String>]) -> String {
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .map(|field| {
+                    if field.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", field.replace('"', "\"\""))
+                    } else {
+                        field.to_owned()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .map(|line| line + "\r\n")
+        .collect()
+}
+
+/// a csv attachment of `rows`, named after the xlsx `filename`.
+fn csv_response(filename: &str, rows: &[Vec<String>]) -> Response {
+    let filename = format!("{}.csv", filename.trim_end_matches(".xlsx"));
+    // non ascii file names are sent percent encoded as RFC 5987 describes.
+    let encoded = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect::<String>();
+    (
+        [
+            (CONTENT_TYPE, String::from("text/csv; charset=utf-8")),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename*=UTF-8''{encoded}"),
+            ),
+        ],
+        rows_to_csv(rows),
+    )
+        .into_response()
+}
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportLocaleQuery {
+    locale: Option<String>,
+    /// catalog the items are looked up in, any catalog if none.
+    bucket: Option<String>,
+    /// exports concealed items of a shipment in rows marked by `CONCEALED_MARK`,
+    /// they are left out by default.
+    /// the ordered export always lists them with `CONCEALED_MARK` as the customer.
+    #[serde(default)]
+    include_concealed: bool,
+}
+
 /// export a single shipment includes below column:
 /// | 品牌 | 商品 | 数量 | 单件日元价格（不含税） | 合集日元价格（不含税） | 产地 | 材质 | 条形码 |
+/// concealed items are left out unless `includeConcealed` is set.
 #[instrument(name = "export single shipment except color", skip(db))]
 pub async fn export_shipment_by_id_except_color_no(
+    format: ExportFormat,
     Path(id): Path<Uuid>,
+    Query(query): Query<ExportLocaleQuery>,
     State(db): State<Arc<DbClient>>,
     State(http_client): State<Arc<reqwest::Client>>,
 ) -> Result<impl IntoResponse> {
     let shipment = db.get_shipment_by_id(id.into()).await?;
     // find all shipments include the above one
-    let shipment_items = db
-        .find_shipments_by_no(&shipment.shipment_no)
-        .await?
+    let shipments = db.find_shipments_by_no(&shipment.shipment_no).await?;
+    let shipment_ids = shipments.iter().map(|s| s.id).collect::<Vec<_>>();
+    let (concealed_items, shipment_items): (Vec<_>, Vec<_>) = shipments
         .into_iter()
         .flat_map(|shipment| shipment.items)
-        .filter(|item| item.status != OrderItemStatus::Concealed)
-        .collect::<Vec<_>>();
+        .partition(|item| item.status == OrderItemStatus::Concealed);
     // find all transfers
     let transfer_items = db
         .find_transfer_by_shipment_no(&shipment.shipment_no)
@@ -254,6 +599,132 @@ pub async fn export_shipment_by_id_except_color_no(
         .into_iter()
         .flat_map(|transfer| transfer.items)
         .collect::<Vec<_>>();
+    let rounding = SETTINGS.shipment.rounding_for(Some(&shipment.vendor));
+    let mut rows = build_shipment_rows(
+        &db,
+        &shipment_items,
+        &transfer_items,
+        query.locale.as_deref(),
+        query.bucket.as_deref(),
+        rounding,
+    )
+    .await?;
+    if query.include_concealed {
+        let concealed_rows = build_shipment_rows(
+            &db,
+            &concealed_items,
+            &[],
+            query.locale.as_deref(),
+            query.bucket.as_deref(),
+            rounding,
+        )
+        .await?;
+        rows.extend(mark_concealed(concealed_rows));
+    }
+    let shipment_datetime = shipment
+        .shipment_date
+        .to_chrono()
+        .with_timezone(&Local)
+        .format("%Y%m%d")
+        .to_string();
+
+    let filename = format!(
+        "{}_eliad草纸_{}_{}.xlsx",
+        &shipment.vendor.stringify_vendor(),
+        shipment_datetime,
+        &shipment.shipment_no
+    );
+    if format == ExportFormat::Csv {
+        db.mark_shipments_exported(&shipment_ids).await?;
+        return Ok(csv_response(&filename, &rows));
+    }
+    debug!("generated new file");
+    let message = ExportSingleShipmentMessage {
+        filename: filename.clone(),
+        rows,
+        shipment_no: shipment.shipment_no,
+    };
+    let resp = http_client
+        .post(format!(
+            "{}/export/single_shipment",
+            SETTINGS.utility.get_utility_url()
+        ))
+        .json(&message)
+        .send()
+        .await?;
+    let url = validate_http_response::<DownLoadUrlResponse>(resp)
+        .await?
+        .url;
+    db.mark_shipments_exported(&shipment_ids).await?;
+
+    Ok(Json(ExportFileResponse { url, filename }).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreviewInput {
+    item_ids: Vec<Uuid>,
+}
+
+/// export guaranteed items selected for a pending shipment in the same columns as
+/// `export_shipment_by_id_except_color_no`, no shipment need to be persisted.
+#[instrument(name = "export shipment preview", skip(db, http_client))]
+pub async fn export_shipment_preview(
+    format: ExportFormat,
+    Query(query): Query<ExportLocaleQuery>,
+    State(db): State<Arc<DbClient>>,
+    State(http_client): State<Arc<reqwest::Client>>,
+    Json(input): Json<ExportPreviewInput>,
+) -> Result<impl IntoResponse> {
+    let ids = input
+        .item_ids
+        .into_iter()
+        .map(|id| id.into())
+        .collect::<Vec<_>>();
+    let items = db.get_shippable_order_items(&ids).await?;
+    let rows = build_shipment_rows(
+        &db,
+        &items,
+        &[],
+        query.locale.as_deref(),
+        query.bucket.as_deref(),
+        SETTINGS.shipment.default_rounding,
+    )
+    .await?;
+    let filename = format!("eliad草纸_预览_{}.xlsx", Local::now().format("%Y%m%d"));
+    if format == ExportFormat::Csv {
+        return Ok(csv_response(&filename, &rows));
+    }
+    let message = ExportSingleShipmentMessage {
+        filename: filename.clone(),
+        rows,
+        shipment_no: String::new(),
+    };
+    let resp = http_client
+        .post(format!(
+            "{}/export/single_shipment",
+            SETTINGS.utility.get_utility_url()
+        ))
+        .json(&message)
+        .send()
+        .await?;
+    let url = validate_http_response::<DownLoadUrlResponse>(resp)
+        .await?
+        .url;
+
+    Ok(Json(ExportFileResponse { url, filename }).into_response())
+}
+
+/// rows of a single shipment except color no, same item codes are merged into one row.
+/// | 品牌 | 商品 | 数量 | 单件日元价格（不含税） | 合集日元价格（不含税） | 产地 | 材质 | 条形码 |
+pub async fn build_shipment_rows(
+    db: &DbClient,
+    shipment_items: &[MongoOrderItem],
+    transfer_items: &[MongoTransferItem],
+    locale: Option<&str>,
+    bucket: Option<&str>,
+    rounding: RoundingStrategy,
+) -> Result<Vec<Vec<String>>> {
     let mut rows = Vec::new();
     //FIXME Is there a better way to do this??
     let mut items_map: HashMap<&str, usize> = std::collections::HashMap::new();
@@ -266,11 +737,12 @@ pub async fn export_shipment_by_id_except_color_no(
         // loop over all shipment items set the discount rate to the lowest value
         // then even there are multi discount rate for same item_code discount rate will be the lowest value
         // transfer item as well
+        let rate = export_rate(item);
         let current_rate = rates_map
             .entry(&item.item_code_ext.as_str()[..11])
-            .or_insert(item.rate);
-        if item.rate > *current_rate {
-            rates_map.insert(&item.item_code_ext.as_str()[..11], item.rate);
+            .or_insert(rate);
+        if rate > *current_rate {
+            rates_map.insert(&item.item_code_ext.as_str()[..11], rate);
         }
     }
 
@@ -287,20 +759,20 @@ pub async fn export_shipment_by_id_except_color_no(
     for item in shipment_items.iter() {
         if let Some(q) = items_map.get(&item.item_code_ext.as_str()[..11]) {
             let item_detail = db
-                .find_one_by_item_code(&item.item_code_ext.as_str()[..11])
+                .find_one_by_item_code(&item.item_code_ext.as_str()[..11], bucket)
                 .await?
                 .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
             let rate = rates_map
                 .get(&item.item_code_ext.as_str()[..11])
                 .unwrap_or(&1.0);
-            let item_type = get_item_type(&item.item_code_ext.as_str()[5..8]);
+            let item_type = get_item_type(&item.item_code_ext.as_str()[5..8], locale);
             let row = vec![
                 String::from("pinkhouse"),
                 item_type,
                 format!("{}", q),
                 format!(
                     "{}",
-                    get_tax_exclusive_price(item_detail.get_discounted_price(*rate))
+                    get_tax_exclusive_price(item_detail.get_discounted_price(*rate, rounding))
                 ),
                 String::from(""),
                 item_detail.made_in.unwrap_or_else(|| String::from("")),
@@ -317,10 +789,10 @@ pub async fn export_shipment_by_id_except_color_no(
     {
         if let Some(q) = items_map.get(&item.item_code_ext.as_str()[..11]) {
             let item_detail = db
-                .find_one_by_item_code(&item.item_code_ext.as_str()[..11])
+                .find_one_by_item_code(&item.item_code_ext.as_str()[..11], bucket)
                 .await?
                 .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
-            let item_type = get_item_type(&item.item_code_ext.as_str()[5..8]);
+            let item_type = get_item_type(&item.item_code_ext.as_str()[5..8], locale);
             let row = vec![
                 String::from("pinkhouse"),
                 item_type,
@@ -335,69 +807,41 @@ pub async fn export_shipment_by_id_except_color_no(
             items_map.remove(&item.item_code_ext.as_str()[..11]);
         }
     }
-    let shipment_datetime = shipment
-        .shipment_date
-        .to_chrono()
-        .with_timezone(&Local)
-        .format("%Y%m%d")
-        .to_string();
-
-    let filename = format!(
-        "{}_eliad草纸_{}_{}.xlsx",
-        &shipment.vendor.stringify_vendor(),
-        shipment_datetime,
-        &shipment.shipment_no
-    );
-    debug!("generated new file");
-    let message = ExportSingleShipmentMessage {
-        filename: filename.clone(),
-        rows,
-        shipment_no: shipment.shipment_no,
-    };
-    let resp = http_client
-        .post(format!(
-            "{}/export/single_shipment",
-            SETTINGS.utility.get_utility_url()
-        ))
-        .json(&message)
-        .send()
-        .await?;
-    let url = validate_http_response::<DownLoadUrlResponse>(resp)
-        .await?
-        .url;
-
-    Ok(Json(ExportFileResponse { url, filename }))
+    Ok(rows)
 }
 
 /// export a single shipment includes below column:
 /// | 序号 | 品牌 | 商品 | 单件日元价格（不含税） | 产地 | 材质 | 条形码 |
 #[instrument(name = "export single shipment contained ordered", skip(db))]
 pub async fn export_shipment_ordered(
+    format: ExportFormat,
     Path(id): Path<Uuid>,
+    Query(query): Query<ExportLocaleQuery>,
     State(db): State<Arc<DbClient>>,
     State(http_client): State<Arc<reqwest::Client>>,
 ) -> Result<impl IntoResponse> {
     let shipment = db.get_shipment_by_id(id.into()).await?;
     // find all shipments include the above one
-    let mut shipment_items = db
-        .find_shipments_by_no(&shipment.shipment_no)
-        .await?
+    let shipments = db.find_shipments_by_no(&shipment.shipment_no).await?;
+    let shipment_ids = shipments.iter().map(|s| s.id).collect::<Vec<_>>();
+    let mut shipment_items = shipments
         .into_iter()
         .flat_map(|shipment| shipment.items)
         .collect::<Vec<_>>();
     // find all transfers
     shipment_items.sort_by(|a, b| a.customer_id.cmp(&b.customer_id));
+    let rounding = SETTINGS.shipment.rounding_for(Some(&shipment.vendor));
     let mut rows = Vec::new();
     for (i, item) in shipment_items.iter().enumerate() {
         let item_detail = db
-            .find_one_by_item_code(&item.item_code_ext.as_str()[..11])
+            .find_one_by_item_code(&item.item_code_ext.as_str()[..11], query.bucket.as_deref())
             .await?
             .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
-        let rate = item.rate;
-        let item_type = get_item_type(&item.item_code_ext.as_str()[5..8]);
-        // if order is concealed set customer id to empty string
+        let rate = export_rate(item);
+        let item_type = get_item_type(&item.item_code_ext.as_str()[5..8], query.locale.as_deref());
+        // concealed items are always listed, marked in place of the customer id
         let customer_id = if item.status == OrderItemStatus::Concealed {
-            String::from("-")
+            CONCEALED_MARK.to_string()
         } else {
             item.customer_id.to_string()
         };
@@ -407,7 +851,7 @@ pub async fn export_shipment_ordered(
             item_type,
             format!(
                 "{}",
-                get_tax_exclusive_price(item_detail.get_discounted_price(rate))
+                get_tax_exclusive_price(item_detail.get_discounted_price(rate, rounding))
             ),
             item_detail.made_in.unwrap_or_else(|| String::from("")),
             item_detail.material.clone(),
@@ -429,6 +873,10 @@ pub async fn export_shipment_ordered(
         shipment_datetime,
         &shipment.shipment_no
     );
+    if format == ExportFormat::Csv {
+        db.mark_shipments_exported(&shipment_ids).await?;
+        return Ok(csv_response(&filename, &rows));
+    }
     debug!("generated new file");
     let message = ExportSingleShipmentMessage {
         filename: filename.clone(),
@@ -446,8 +894,116 @@ pub async fn export_shipment_ordered(
     let url = validate_http_response::<DownLoadUrlResponse>(resp)
         .await?
         .url;
+    db.mark_shipments_exported(&shipment_ids).await?;
 
-    Ok(Json(ExportFileResponse { url, filename }))
+    Ok(Json(ExportFileResponse { url, filename }).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportInvoiceQuery {
+    customer_id: String,
+    locale: Option<String>,
+    bucket: Option<String>,
+}
+
+/// export an invoice of one customer's items in a shipment as csv, see `build_invoice_rows`.
+/// the utility has no invoice sheet, so it is always rendered here.
+#[instrument(name = "export shipment invoice", skip(db))]
+pub async fn export_shipment_invoice(
+    Path(id): Path<Uuid>,
+    Query(query): Query<ExportInvoiceQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<impl IntoResponse> {
+    let shipment = db.get_shipment_by_id(id.into()).await?;
+    // find all shipments include the above one
+    let shipments = db.find_shipments_by_no(&shipment.shipment_no).await?;
+    let shipment_items = shipments
+        .into_iter()
+        .flat_map(|shipment| shipment.items)
+        .collect::<Vec<_>>();
+    let rows = build_invoice_rows(
+        &db,
+        &shipment_items,
+        &query.customer_id,
+        query.locale.as_deref(),
+        query.bucket.as_deref(),
+        SETTINGS.shipment.rounding_for(Some(&shipment.vendor)),
+    )
+    .await?;
+    if rows.is_empty() {
+        return Err(Error::OrderItemNotFound(format!(
+            "of customer {} in shipment {}",
+            query.customer_id, shipment.shipment_no
+        )));
+    }
+    let shipment_datetime = shipment
+        .shipment_date
+        .to_chrono()
+        .with_timezone(&Local)
+        .format("%Y%m%d")
+        .to_string();
+    let filename = format!(
+        "{}_{}_{}_invoice.csv",
+        shipment_datetime, &shipment.shipment_no, &query.customer_id
+    );
+    Ok(csv_response(&filename, &rows))
+}
+
+/// invoice rows of `customer_id`'s items, compared after normalization.
+/// concealed items are excluded.
+/// the last row is the total of the discounted prices.
+/// | 序号 | 条形码 | 商品 | 尺码 | 色号 | 单件日元价格（不含税） | 折扣 | 折后日元价格（不含税） |
+pub async fn build_invoice_rows(
+    db: &DbClient,
+    shipment_items: &[MongoOrderItem],
+    customer_id: &str,
+    locale: Option<&str>,
+    bucket: Option<&str>,
+    rounding: RoundingStrategy,
+) -> Result<Vec<Vec<String>>> {
+    let customer_id = normalize_customer_id(customer_id);
+    let mut items = shipment_items
+        .iter()
+        .filter(|item| normalize_customer_id(&item.customer_id) == customer_id)
+        .filter(|item| item.status != OrderItemStatus::Concealed)
+        .collect::<Vec<_>>();
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    items.sort_by(|a, b| a.item_code_ext.cmp(&b.item_code_ext));
+    let mut rows = Vec::new();
+    let mut total = 0;
+    for (i, item) in items.iter().enumerate() {
+        let item_detail = db
+            .find_one_by_item_code(&item.item_code_ext.as_str()[..11], bucket)
+            .await?
+            .unwrap_or_else(|| PhItem::new_dummy(&item.item_code_ext, 0));
+        let rate = export_rate(item);
+        let discounted = get_tax_exclusive_price(item_detail.get_discounted_price(rate, rounding));
+        total += discounted;
+        rows.push(vec![
+            (i + 1).to_string(),
+            item.item_code_ext[0..11].to_string(),
+            get_item_type(&item.item_code_ext.as_str()[5..8], locale),
+            item.item_code_ext[11..12].to_string(),
+            item.item_code_ext[12..13].to_string(),
+            format!("{}", get_tax_exclusive_price(item_detail.price)),
+            stringify_rate(rate),
+            format!("{}", discounted),
+        ]);
+    }
+    rows.push(vec![
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from(""),
+        String::from("合计"),
+        format!("{}", total),
+    ]);
+    Ok(rows)
 }
 
 #[derive(Serialize)]
@@ -460,27 +1016,38 @@ struct ExportJPInventoryMessage {
 #[serde(rename_all = "camelCase")]
 pub struct ExportInventoryQuery {
     location: InventoryLocation,
+    /// only items of the category are exported if set.
+    #[serde(default)]
+    category: Option<Category>,
 }
 
 /// export inventory includes below column:
 /// 图片 | 条形码 | 尺码 | 色号 | 数量 | 所在地 |
+/// filtered by the location and optionally by the category of items.
 #[instrument(name = "export inventory include all location", skip(db))]
 pub async fn export_jp_inventory(
+    format: ExportFormat,
     Query(export_location): Query<ExportInventoryQuery>,
     State(db): State<Arc<DbClient>>,
     State(http_client): State<Arc<reqwest::Client>>,
 ) -> Result<impl IntoResponse> {
-    let location_query = if export_location.location == InventoryLocation::JP {
-        String::from("jp")
-    } else {
-        String::from("cn,pcn")
+    // jp is exported alone, every other location is exported together as cn.
+    let exported = |location: InventoryLocation| {
+        (location == InventoryLocation::JP) == (export_location.location == InventoryLocation::JP)
     };
+    let location_query = InventoryLocation::iter()
+        .filter(|location| exported(*location))
+        .map(|location| location.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
     let query = InventoryQuery {
         keyword: String::from(""),
-        category: None,
+        category: export_location.category,
         show_zero_quantity: false,
         location: Some(location_query),
+        codes: None,
         page: None,
+        with_facets: false,
     };
     let (_, inventory) = db.query_inventory(query).await?;
     let mut rows = vec![];
@@ -488,16 +1055,15 @@ pub async fn export_jp_inventory(
         let item_code = &inventory_item.item_code_ext[0..11];
         let item_size = &inventory_item.item_code_ext[11..12];
         let item_color = &inventory_item.item_code_ext[12..13];
-        let item_q = if export_location.location == InventoryLocation::JP {
-            inventory_item.quantity[0].quantity.to_string()
-        } else {
-            (inventory_item.quantity[1].quantity + inventory_item.quantity[2].quantity).to_string()
-        };
+        let item_q = inventory_item
+            .quantity
+            .iter()
+            .filter(|q| exported(q.location))
+            .map(|q| q.quantity)
+            .sum::<u32>()
+            .to_string();
         rows.push(vec![
-            format!(
-                "https://d2vg6jg1lu9m12.cloudfront.net/{}_{}.jpeg",
-                item_code, item_color
-            ),
+            SETTINGS.assets.image_url(item_code, item_color),
             item_code.to_string(),
             item_size.to_string(),
             item_color.to_string(),
@@ -513,6 +1079,9 @@ pub async fn export_jp_inventory(
         now.day(),
         export_location.location.kanjified(),
     );
+    if format == ExportFormat::Csv {
+        return Ok(csv_response(&filename, &rows));
+    }
     let message = ExportJPInventoryMessage {
         filename: filename.clone(),
         rows,
@@ -529,54 +1098,115 @@ pub async fn export_jp_inventory(
         .await?
         .url;
 
-    Ok(Json(ExportFileResponse { url, filename }))
+    Ok(Json(ExportFileResponse { url, filename }).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportOperationsQuery {
+    #[serde(with = "ts_seconds")]
+    from: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    to: DateTime<Utc>,
+    #[serde(rename = "type")]
+    operation_type: Option<MongoOperationType>,
+    #[serde(rename = "relatedKind")]
+    related_kind: Option<RelatedKind>,
 }
 
-fn get_tax_exclusive_price(i: u32) -> u32 {
+/// export inventory operations happened in `[from, to)` as csv, includes below column:
+/// 时间 | 条形码 | 操作类型 | 数量 | 所在地 | 关联ID |
+/// the utility has no operations sheet, so it is always rendered here.
+#[instrument(name = "export inventory operations", skip(db))]
+pub async fn export_inventory_operations(
+    Query(query): Query<ExportOperationsQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<impl IntoResponse> {
+    let rows = build_operation_rows(
+        &db,
+        query.from,
+        query.to,
+        query.operation_type,
+        query.related_kind,
+    )
+    .await?;
+    let filename = format!(
+        "{}至{}库存变动.csv",
+        query.from.with_timezone(&Local).format("%Y%m%d"),
+        query.to.with_timezone(&Local).format("%Y%m%d"),
+    );
+    Ok(csv_response(&filename, &rows))
+}
+
+/// rows of inventory operations in time order.
+/// | 时间 | 条形码 | 操作类型 | 数量 | 所在地 | 关联ID |
+pub async fn build_operation_rows(
+    db: &DbClient,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    operation_type: Option<MongoOperationType>,
+    related_kind: Option<RelatedKind>,
+) -> Result<Vec<Vec<String>>> {
+    let operations = db
+        .find_operations_in_range(from, to, operation_type, related_kind)
+        .await?;
+    Ok(operations
+        .into_iter()
+        .map(|operation| {
+            vec![
+                operation
+                    .time
+                    .to_chrono()
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+                operation.item_code_ext,
+                Bson::from(operation.operation_type)
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                operation.count.to_string(),
+                operation.location.kanjified(),
+                operation.related_id.to_string(),
+            ]
+        })
+        .collect())
+}
+
+pub fn get_tax_exclusive_price(i: u32) -> u32 {
     (i as f64 / 1.1).round() as u32
 }
 
-fn stringify_rate(i: f64) -> String {
+/// rates out of (0, 1] come from legacy or manually edited order items,
+/// they are exported as no discount instead of making negative or huge totals.
+pub fn export_rate(item: &MongoOrderItem) -> f64 {
+    let rate = OrderItemRate::clamp(item.rate);
+    if rate != item.rate {
+        warn!(
+            "order item id:{} has invalid rate {}, exported as {}",
+            item.id, item.rate, rate
+        );
+    }
+    rate
+}
+
+/// fractional discounts keep one decimal, e.g. 0.875 is "12.5%Off".
+pub fn stringify_rate(i: f64) -> String {
     if i == 1.0 {
         return String::from("-");
     }
-    format!("{}%Off", ((1.0 - i) * 100.0).round() as u32)
-}
-
-fn get_item_type(input: &str) -> String {
-    match input {
-        "FB_" => String::from("衬衫"),
-        "FBY" => String::from("长衬衫"),
-        "FS_" => String::from("半裙"),
-        "FSY" => String::from("半裙"),
-        "FA_" => String::from("连衣裙"),
-        "FAY" => String::from("连衣裙"),
-        "FP_" => String::from("裤子"),
-        "FJM" => String::from("外套"),
-        "KPO" => String::from("毛衣"),
-        "UPO" => String::from("针织衫"),
-        "UBY" => String::from("长上衣"),
-        "UA_" => String::from("针织连衣裙"),
-        "UCD" => String::from("针织开衫"),
-        "KCD" => String::from("毛线开衫"),
-        "PSH" => String::from("鞋子"),
-        "PBG" => String::from("包包"),
-        "PSC" => String::from("袜子"),
-        "PE_" => String::from("配饰"),
-        "FC_" => String::from("大衣"),
-        "UTR" => String::from("卫衣"),
-        "UTS" => String::from("T恤"),
-        "PSE" => String::from("围巾"),
-        "PAC" => String::from("首饰"),
-        "FJ_" => String::from("外套夹克"),
-        "UP_" => String::from("裤子"),
-        "PHT" => String::from("帽子"),
-        "FV_" => String::from("马甲"),
-        "LJM" => String::from("皮夹克"),
-        "KV_" => String::from("针织马甲"),
-        "PHH" => String::from("画册"),
-        _ => {
-            warn!("{input} is not presented above");
+    let off = ((1.0 - i) * 1000.0).round() / 10.0;
+    if off.fract() == 0.0 {
+        return format!("{}%Off", off as u32);
+    }
+    format!("{:.1}%Off", off)
+}
+
+/// unknown prefix will be returned as it is.
+fn get_item_type(input: &str, locale: Option<&str>) -> String {
+    match SETTINGS.item_type_map.get_label(input, locale) {
+        Some(label) => label.to_string(),
+        None => {
+            warn!("{input} is not presented in item type map");
             input.to_string()
         }
     }
@@ -2,34 +2,77 @@ use std::sync::Arc;
 
 use crate::{
     db::{mongo::DbClient, InventoryRepo},
-    error_result::Result,
+    error_result::{Error, Result},
 };
 use axum::{
+    body::{Bytes, StreamBody},
     extract::{Path, Query, State},
-    routing::get,
+    http::header::CONTENT_TYPE,
+    response::IntoResponse,
+    routing::{get, post, put},
     Json, Router,
 };
-use serde::Deserialize;
+use chrono::{serde::ts_seconds, DateTime, Duration, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum::IntoEnumIterator;
+use tokio::sync::broadcast::Sender;
+use tracing::instrument;
+use uuid::Uuid;
 
-use crate::db::{inventory::Quantity, InventoryOperation, InventoryOutput};
+use crate::db::{
+    inventory::{
+        GuaranteeMismatch, InventoryLocation, LedgerEntry, MongoInventoryAdjustment,
+        MongoLocationSwap, NegativeBalance, Quantity,
+    },
+    InventoryOperation, InventoryOutput, OperationType, ReorderPoint, SupplySource,
+};
 
-use super::{export::export_jp_inventory, AppState, PagedResponse};
+use super::{
+    auth::UserInfo,
+    ensure_page_within_limit,
+    export::{export_inventory_operations, export_jp_inventory},
+    middleware::{compression_exempt, upload_limited},
+    ws::{send_control_message, ControlMessage},
+    AppState, PagedResponse,
+};
 
 pub fn get_inventory_router() -> Router<AppState> {
     Router::new()
         .route("/", get(query_inventory))
+        .route("/stream", get(stream_inventory))
         .route(
             "/operations/:item_code_ext",
             get(get_inventory_item_operations),
         )
+        .route("/ledger/:item_code_ext", get(get_inventory_item_ledger))
+        .route("/supply_sources/:item_code_ext", get(find_supply_sources))
+        .route(
+            "/operations/by_related/:related_id",
+            get(get_operations_by_related_id),
+        )
+        .route("/operations/export", get(export_inventory_operations))
+        .route("/operation_types", get(get_operation_types))
         .route(
             "/quantity/:item_code_ext",
-            get(get_inventory_quantity_by_item_code_ext),
+            compression_exempt(get(get_inventory_quantity_by_item_code_ext)),
+        )
+        .route("/changes", get(find_inventory_changes))
+        .route("/dead_stock", get(find_dead_stock))
+        .route(
+            "/integrity/guarantee_mismatch",
+            get(find_guarantee_mismatches),
         )
+        .route("/integrity/negatives", get(find_negative_balances))
+        .route("/reorder_points", get(find_reorder_points))
+        .route("/reorder_points/:item_code", put(set_reorder_point))
+        .route("/adjust_bulk", upload_limited(post(adjust_inventory_bulk)))
+        .route("/swap_location", post(swap_location))
         .route("/export", get(export_jp_inventory))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct InventoryQuery {
     pub keyword: String,
@@ -38,23 +81,162 @@ pub struct InventoryQuery {
     // because the unsupported of array query, this is this a workaround.
     // string like "jp,cn" will parsed into ["jp","cn"]
     pub location: Option<String>,
+    // the same workaround as `location`, "A2121FSY06693,A2121FSY07292" fetches exactly
+    // these items and the keyword is ignored.
+    #[serde(default)]
+    pub codes: Option<String>,
     pub page: Option<u32>,
+    #[serde(default)]
+    pub with_facets: bool,
+}
+
+#[derive(Serialize)]
+pub struct InventoryQueryResponse {
+    #[serde(flatten)]
+    page: PagedResponse<InventoryOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<HashMap<String, u64>>,
 }
 
 pub async fn query_inventory(
     Query(query): Query<InventoryQuery>,
     State(db): State<Arc<DbClient>>,
-) -> Result<Json<PagedResponse<InventoryOutput>>> {
+) -> Result<Json<InventoryQueryResponse>> {
+    ensure_page_within_limit(query.page)?;
     let current_page = query.page.unwrap_or(0);
-    let (has_next, items) = db.query_inventory(query).await?;
-    let res = PagedResponse {
-        data: items.into_iter().map(|i| i.into()).collect::<Vec<_>>(),
-        has_next,
-        next: current_page + 1,
+    let (facets, (has_next, items)) = if query.with_facets {
+        let (facets, page) = tokio::try_join!(
+            db.query_inventory_facets(&query),
+            db.query_inventory(query.clone())
+        )?;
+        (Some(facets), page)
+    } else {
+        (None, db.query_inventory(query).await?)
+    };
+    let res = InventoryQueryResponse {
+        page: PagedResponse {
+            data: items.into_iter().map(|i| i.into()).collect::<Vec<_>>(),
+            has_next,
+            next: current_page + 1,
+        },
+        facets,
     };
     Ok(res.into())
 }
 
+/// every matched inventory item as one json per line, written as the cursor yields them.
+pub async fn stream_inventory(
+    Query(query): Query<InventoryQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<impl IntoResponse> {
+    let items = db.stream_inventory(&query).await?.map(|item| {
+        let output: InventoryOutput = item?.into();
+        let mut line = serde_json::to_vec(&output)?;
+        line.push(b'\n');
+        Ok::<_, Error>(Bytes::from(line))
+    });
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(items),
+    ))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadStockQuery {
+    pub since_days: u32,
+}
+
+pub async fn find_dead_stock(
+    Query(query): Query<DeadStockQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<InventoryOutput>>> {
+    let since = Utc::now() - Duration::days(query.since_days as i64);
+    let res = db.find_dead_stock(since).await?;
+    Ok(res.into_iter().map(|i| i.into()).collect::<Vec<_>>().into())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InventoryChangesQuery {
+    #[serde(with = "ts_seconds")]
+    pub since: DateTime<Utc>,
+}
+
+/// inventory items updated after `since`, for clients refreshing incrementally on `RefreshInventory`.
+pub async fn find_inventory_changes(
+    Query(query): Query<InventoryChangesQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<InventoryOutput>>> {
+    let res = db.find_inventory_changed_since(query.since).await?;
+    Ok(res.into_iter().map(|i| i.into()).collect::<Vec<_>>().into())
+}
+
+pub async fn find_guarantee_mismatches(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<GuaranteeMismatch>>> {
+    Ok(db.find_guarantee_mismatches().await?.into())
+}
+
+pub async fn find_negative_balances(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<NegativeBalance>>> {
+    Ok(db.find_negative_balances().await?.into())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OperationTypeLabel {
+    #[serde(flatten)]
+    pub operation_type: OperationType,
+    pub label: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OperationTypesQuery {
+    pub locale: Option<String>,
+}
+
+/// every operation type with its display label, so clients need not hardcode them.
+pub async fn get_operation_types(
+    Query(query): Query<OperationTypesQuery>,
+) -> Json<Vec<OperationTypeLabel>> {
+    OperationType::iter()
+        .map(|operation_type| OperationTypeLabel {
+            label: operation_type.label(query.locale.as_deref()).to_string(),
+            operation_type,
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+pub async fn find_reorder_points(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<ReorderPoint>>> {
+    let res = db.find_reorder_points().await?;
+    Ok(res.into_iter().map(|p| p.into()).collect::<Vec<_>>().into())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderPointInput {
+    pub min_quantity: u32,
+}
+
+#[instrument(name="set reorder point",skip(user_info,db,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+))]
+pub async fn set_reorder_point(
+    user_info: UserInfo,
+    Path(item_code): Path<String>,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(input): Json<ReorderPointInput>,
+) -> Result<Json<ReorderPoint>> {
+    let res = db.set_reorder_point(&item_code, input.min_quantity).await?;
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    Ok(ReorderPoint::from(res).into())
+}
+
 pub async fn get_inventory_item_operations(
     Path(item_code_ext): Path<String>,
     State(db): State<Arc<DbClient>>,
@@ -63,6 +245,134 @@ pub async fn get_inventory_item_operations(
     Ok(res.into_iter().map(|o| o.into()).collect::<Vec<_>>().into())
 }
 
+pub async fn find_supply_sources(
+    Path(item_code_ext): Path<String>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<SupplySource>>> {
+    let res = db.find_supply_sources(&item_code_ext).await?;
+    Ok(res.into_iter().map(|s| s.into()).collect::<Vec<_>>().into())
+}
+
+pub async fn get_inventory_item_ledger(
+    Path(item_code_ext): Path<String>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<LedgerEntry>>> {
+    Ok(db.get_inventory_item_ledger(&item_code_ext).await?.into())
+}
+
+pub async fn get_operations_by_related_id(
+    Path(related_id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<InventoryOperation>>> {
+    let res = db.get_operations_by_related_id(related_id.into()).await?;
+    Ok(res.into_iter().map(|o| o.into()).collect::<Vec<_>>().into())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdjustInventoryInput {
+    pub item_code_ext: String,
+    pub location: InventoryLocation,
+    pub delta: i32,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdjustInventoryResult {
+    item_code_ext: String,
+    location: InventoryLocation,
+    delta: i32,
+    operation_id: Uuid,
+    quantity: u32,
+}
+
+impl From<MongoInventoryAdjustment> for AdjustInventoryResult {
+    fn from(m: MongoInventoryAdjustment) -> Self {
+        Self {
+            item_code_ext: m.item_code_ext,
+            location: m.location,
+            delta: m.delta,
+            operation_id: m.operation_id.into(),
+            quantity: m.quantity,
+        }
+    }
+}
+
+#[instrument(name="adjust inventory bulk",skip(user_info,inputs,db,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+    count = inputs.len(),
+))]
+pub async fn adjust_inventory_bulk(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(inputs): Json<Vec<AdjustInventoryInput>>,
+) -> Result<Json<Vec<AdjustInventoryResult>>> {
+    let res = db
+        .adjust_inventory_bulk(inputs, user_info.user_id.into())
+        .await?;
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    Ok(res.into_iter().map(|a| a.into()).collect::<Vec<_>>().into())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapLocationInput {
+    pub item_code_ext: String,
+    pub from: InventoryLocation,
+    pub to: InventoryLocation,
+    pub count: u32,
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapLocationResult {
+    id: Uuid,
+    item_code_ext: String,
+    from: InventoryLocation,
+    to: InventoryLocation,
+    count: u32,
+    reason: String,
+    operation_ids: Vec<Uuid>,
+}
+
+impl From<MongoLocationSwap> for SwapLocationResult {
+    fn from(m: MongoLocationSwap) -> Self {
+        Self {
+            id: m.id.into(),
+            item_code_ext: m.item_code_ext,
+            from: m.from,
+            to: m.to,
+            count: m.count,
+            reason: m.reason,
+            operation_ids: m.operation_ids.into_iter().map(|id| id.into()).collect(),
+        }
+    }
+}
+
+#[instrument(name="swap location",skip(user_info,input,db,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+    item = %input.item_code_ext,
+))]
+pub async fn swap_location(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(input): Json<SwapLocationInput>,
+) -> Result<Json<SwapLocationResult>> {
+    let res = db.swap_location(input, user_info.user_id.into()).await?;
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    Ok(SwapLocationResult::from(res).into())
+}
+
 pub async fn get_inventory_quantity_by_item_code_ext(
     Path(item_code_ext): Path<String>,
     State(db): State<Arc<DbClient>>,
@@ -74,7 +384,7 @@ pub async fn get_inventory_quantity_by_item_code_ext(
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum Category {
     Top,
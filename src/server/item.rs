@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{mongo::DbClient, order::OrderItemRate, shipment::ShipmentVendor, PhDataBase},
+    error_result::{Error, Result},
+};
+
+use super::{auth::SETTINGS, export::get_tax_exclusive_price, AppState};
+
+pub fn get_item_router() -> Router<AppState> {
+    Router::new().route("/:code/price", get(get_item_price))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ItemPriceQuery {
+    pub rate: f64,
+    pub bucket: Option<String>,
+    /// the rounding configured for the vendor is used, the default one if none.
+    pub vendor: Option<ShipmentVendor>,
+}
+
+/// prices of an item at a rate, computed the same way as order items and exports.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemPrice {
+    pub price: u32,
+    pub discounted: u32,
+    pub tax_exclusive: u32,
+    pub tax_exclusive_discounted: u32,
+}
+
+pub async fn get_item_price(
+    Path(code): Path<String>,
+    Query(query): Query<ItemPriceQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<ItemPrice>> {
+    let rate = OrderItemRate::parse(query.rate)?;
+    let item = db
+        .find_one_by_item_code(&code, query.bucket.as_deref())
+        .await?
+        .ok_or_else(|| Error::ItemNotFound(code.clone()))?;
+    let rounding = SETTINGS.shipment.rounding_for(query.vendor.as_ref());
+    let discounted = item.get_discounted_price(rate.get_inner(), rounding);
+    Ok(Json(ItemPrice {
+        price: item.price,
+        discounted,
+        tax_exclusive: get_tax_exclusive_price(item.price),
+        tax_exclusive_discounted: get_tax_exclusive_price(discounted),
+    }))
+}
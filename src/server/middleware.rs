@@ -1,24 +1,37 @@
 use axum::{
     async_trait,
-    extract::{FromRequestParts, State, TypedHeader},
+    body::{boxed, Body, Full},
+    extract::{DefaultBodyLimit, FromRequestParts, State, TypedHeader},
     headers::{authorization::Bearer, Authorization, Cookie},
-    http::{request::Parts, Request},
-    middleware::Next,
-    response::{IntoResponse, Response},
+    http::{
+        header::CONTENT_TYPE, request::Parts, Extensions, HeaderMap, HeaderValue, Method, Request,
+        StatusCode, Version,
+    },
+    middleware::{from_fn, Next},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+    routing::MethodRouter,
     Extension,
 };
+use http_body::Limited;
+use mongodb::bson::DateTime;
+use serde_json::{json, Value};
+use tower_http::limit::RequestBodyLimitLayer;
 use tracing::error;
+use uuid::Uuid;
 
 use crate::{
-    db::SMTAuthDataBase,
+    db::{
+        auth::{RecentResource, RecentResourceKind},
+        SMTAuthDataBase,
+    },
     error_result::{AuthError, Error, Result},
 };
-use std::{result::Result as StdResult, sync::Arc};
+use std::{convert::Infallible, result::Result as StdResult, sync::Arc};
 
 use super::{
     auth::{
         parse_access_token, parse_refresh_token, RefreshAuthInfo, UserInfo, ACCESS_COOKIE_NAME,
-        REFRESH_COOKIE_NAME,
+        REFRESH_COOKIE_NAME, SETTINGS,
     },
     path_control::ApplicationPath,
     AppState,
@@ -169,3 +182,190 @@ pub async fn auth<B>(
     }
     Ok(next.run(req).await)
 }
+
+/// Record successful `GET /orders/:id` and `GET /shipment/:id` into the recent resources
+/// of the requesting user. Failing to record never fails the request.
+pub async fn record_recent<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
+    let resource = recent_resource_of(req.method(), req.uri().path());
+    let resource = match resource {
+        Some(resource) => resource,
+        None => return Ok(next.run(req).await),
+    };
+    let (mut parts, body) = req.into_parts();
+    let Extension(state) = Extension::<AppState>::from_request_parts(&mut parts, &())
+        .await
+        .map_err(|e| Error::HttpResponse(e.to_string()))?;
+    let user_info = UserInfo::from_request_parts(&mut parts, &()).await;
+    let res = next.run(Request::from_parts(parts, body)).await;
+    if !res.status().is_success() {
+        return Ok(res);
+    }
+    if let Ok(user_info) = user_info {
+        if let Err(e) = state
+            .db_client
+            .record_recent_resource(
+                user_info.user_id.into(),
+                &resource,
+                SETTINGS.recent_resources_limit,
+            )
+            .await
+        {
+            error!("failed to record recent resource: {e}");
+        }
+    }
+    Ok(res)
+}
+
+/// the path is relative to the private route, e.g. `/orders/<uuid>`.
+fn recent_resource_of(method: &Method, path: &str) -> Option<RecentResource> {
+    if method != Method::GET {
+        return None;
+    }
+    let mut segments = path.trim_matches('/').split('/');
+    let kind = match segments.next()? {
+        "orders" => RecentResourceKind::Order,
+        "shipment" => RecentResourceKind::Shipment,
+        _ => return None,
+    };
+    let id = Uuid::parse_str(segments.next()?).ok()?;
+    if segments.next().is_some() {
+        return None;
+    }
+    Some(RecentResource {
+        kind,
+        id: id.into(),
+        viewed_at: DateTime::now(),
+    })
+}
+
+pub const WARNINGS_HEADER: &str = "x-warnings";
+
+/// Non-fatal warnings of a successful request, e.g. `(StatusCode::CREATED, Warnings(warnings))`.
+/// they are sent as a json array in the `x-warnings` header,
+/// and as `warnings` of the envelope when it is requested.
+#[derive(Clone, Debug, Default)]
+pub struct Warnings(pub Vec<String>);
+
+impl IntoResponseParts for Warnings {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> StdResult<ResponseParts, Self::Error> {
+        if self.0.is_empty() {
+            return Ok(res);
+        }
+        match serde_json::to_string(&self.0)
+            .ok()
+            .and_then(|v| HeaderValue::from_str(&v).ok())
+        {
+            Some(value) => {
+                res.headers_mut().insert(WARNINGS_HEADER, value);
+            }
+            None => error!("warnings could not be a header value: {:?}", self.0),
+        }
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
+
+impl IntoResponse for Warnings {
+    fn into_response(self) -> Response {
+        (self, ()).into_response()
+    }
+}
+
+/// Wrap the response body into `{ "ok": true, "data": ... }` or
+/// `{ "ok": false, "error": { "code": <status>, "message": ... } }` when the request has
+/// `?envelope=true`. Other requests pass through untouched.
+/// `Warnings` of the response are added as `warnings`.
+pub async fn envelope<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
+    let wants_envelope = req
+        .uri()
+        .query()
+        .map(|q| q.split('&').any(|pair| pair == "envelope=true"))
+        .unwrap_or(false);
+    let res = next.run(req).await;
+    let status = res.status();
+    if !wants_envelope
+        || !(status.is_success() || status.is_client_error() || status.is_server_error())
+    {
+        return Ok(res);
+    }
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/json"))
+        .unwrap_or(false);
+    let (mut parts, body) = res.into_parts();
+    let bytes = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| Error::HttpResponse(e.to_string()))?;
+    let wrapped = if status.is_success() {
+        let data = if bytes.is_empty() {
+            Value::Null
+        } else if is_json {
+            serde_json::from_slice(&bytes)?
+        } else {
+            Value::String(String::from_utf8_lossy(&bytes).into_owned())
+        };
+        match parts.extensions.get::<Warnings>() {
+            Some(warnings) => json!({ "ok": true, "data": data, "warnings": warnings.0 }),
+            None => json!({ "ok": true, "data": data }),
+        }
+    } else {
+        json!({
+            "ok": false,
+            "error": { "code": status.as_u16(), "message": String::from_utf8_lossy(&bytes) },
+        })
+    };
+    let body = serde_json::to_vec(&wrapped)?;
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(Response::from_parts(parts, boxed(Full::from(body))))
+}
+
+/// Limit the request body of import routes to `max_upload_bytes` instead of axum's default limit.
+pub fn upload_limited(route: MethodRouter<AppState, Limited<Body>>) -> MethodRouter<AppState> {
+    route
+        .layer::<_, _, Infallible>(RequestBodyLimitLayer::new(SETTINGS.max_upload_bytes))
+        .layer(DefaultBodyLimit::disable())
+        .layer(from_fn(upload_limit))
+}
+
+/// Replace the bare 413 response of `RequestBodyLimitLayer` and the body extractors
+/// with a message telling the configured limit.
+pub async fn upload_limit<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
+    let res = next.run(req).await;
+    if res.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return Err(Error::PayloadTooLarge(SETTINGS.max_upload_bytes));
+    }
+    Ok(res)
+}
+
+/// Marks a response to be sent uncompressed even under the global `CompressionLayer`.
+#[derive(Clone, Copy, Debug)]
+pub struct CompressionExempt;
+
+/// Skip compression of the route, for frequently polled routes with tiny payloads
+/// where the gzip overhead dominates.
+pub fn compression_exempt(route: MethodRouter<AppState>) -> MethodRouter<AppState> {
+    route.layer(from_fn(mark_compression_exempt))
+}
+
+async fn mark_compression_exempt<B>(req: Request<B>, next: Next<B>) -> Response {
+    let mut res = next.run(req).await;
+    res.extensions_mut().insert(CompressionExempt);
+    res
+}
+
+/// compression predicate rejecting responses marked by `compression_exempt`.
+pub fn not_compression_exempt(
+    _: StatusCode,
+    _: Version,
+    _: &HeaderMap,
+    extensions: &Extensions,
+) -> bool {
+    extensions.get::<CompressionExempt>().is_none()
+}
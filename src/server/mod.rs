@@ -1,6 +1,9 @@
+pub mod activity;
+pub mod admin;
 pub mod auth;
 pub mod export;
 pub mod inventory;
+pub mod item;
 pub mod middleware;
 pub mod order;
 pub mod path_control;
@@ -13,25 +16,40 @@ pub mod ws;
 use crate::{
     application_path_gen::PrivatePath,
     cache::OrderCache,
-    error_result::Result,
+    configuration::DeleteGraceSetting,
+    db::order::{CustomerId, ItemCodeExt, OrderItemRate, OrderValidateError, TaobaoOrderNo},
+    error_result::{Error, Result},
     server::{
-        auth::{get_user_info_handler, login, sign_up, token_refresh_handler, UserInfo},
+        activity::get_activity_router,
+        admin::get_admin_router,
+        auth::{
+            create_invite, get_recent_resources, get_user_info_handler, get_user_presets, login,
+            sign_up, token_refresh_handler, update_user_presets, UserInfo, SETTINGS,
+        },
         inventory::get_inventory_router,
+        item::get_item_router,
         retrn::get_return_router,
         shipment::get_shipment_router,
         transfer::get_transfer_router,
         ws::{handle_ws, ControlMessage},
     },
-    services::google_service::GoogleService,
+    services::{
+        consistency_check::spawn_consistency_check_task,
+        daily_shipment_export::spawn_daily_shipment_export_task,
+        guarantee_expiry::spawn_guarantee_expiry_task,
+        notifier::{notifier_from_settings, Notifier},
+    },
 };
 use axum::{
-    extract::FromRef,
-    http::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_TYPE, LOCATION},
+    extract::{FromRef, State},
+    http::header::{
+        HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_TYPE, LOCATION,
+    },
     http::StatusCode,
-    middleware::from_extractor,
+    middleware::{from_extractor, from_fn},
     response::IntoResponse,
     routing::{any, get, post},
-    Extension, Router,
+    Extension, Json, Router,
 };
 use chrono::prelude::*;
 use chrono::serde::ts_seconds;
@@ -40,10 +58,16 @@ use path_control::ApplicationPath;
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use std::{fmt::Display, net::TcpListener, sync::Arc};
-use tokio::sync::broadcast::Sender;
+use tokio::sync::{broadcast::Sender, Semaphore};
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
-use tower_http::{compression::CompressionLayer, cors::CorsLayer};
+use tower_http::{
+    compression::{
+        predicate::{DefaultPredicate, Predicate},
+        CompressionLayer,
+    },
+    cors::CorsLayer,
+};
 use tracing::{info, instrument};
 use uuid::Uuid;
 
@@ -58,7 +82,8 @@ pub struct AppState {
     order_cache: Arc<dyn OrderCache>,
     http_client: Arc<reqwest::Client>,
     sender: Arc<Sender<ControlMessage>>,
-    google_service: Arc<GoogleService>,
+    notifier: Arc<dyn Notifier>,
+    ws_permits: Arc<Semaphore>,
 }
 
 #[instrument(skip(db_client))]
@@ -92,20 +117,29 @@ pub async fn server_start(db_client: DbClient, listener: TcpListener) {
             CONTENT_LANGUAGE,
             CONTENT_ENCODING,
         ])
+        .expose_headers([HeaderName::from_static(middleware::WARNINGS_HEADER)])
         .allow_origin(origins);
-    let google_service = Arc::new(GoogleService::default());
-    let (orders_tx, _rx) = tokio::sync::broadcast::channel::<ControlMessage>(100);
+    let notifier = notifier_from_settings(SETTINGS.notifier, db.clone());
+    let (orders_tx, _rx) =
+        tokio::sync::broadcast::channel::<ControlMessage>(SETTINGS.ws.channel_capacity);
     let shared_tx = Arc::new(orders_tx);
+    spawn_guarantee_expiry_task(db.clone(), order_cache.clone(), shared_tx.clone());
+    spawn_daily_shipment_export_task(db.clone(), http_client.clone());
+    spawn_consistency_check_task(db.clone());
     let state = AppState {
         db_client: db,
         order_cache,
         http_client,
         sender: shared_tx,
-        google_service,
+        notifier,
+        ws_permits: Arc::new(Semaphore::new(SETTINGS.ws.max_connections)),
     };
     let layer = ServiceBuilder::new()
         .layer(TraceLayer::new_for_http())
-        .layer(CompressionLayer::new())
+        .layer(
+            CompressionLayer::new()
+                .compress_when(DefaultPredicate::new().and(middleware::not_compression_exempt)),
+        )
         .layer(cors);
     let PrivatePath {
         orders_path,
@@ -115,13 +149,23 @@ pub async fn server_start(db_client: DbClient, listener: TcpListener) {
         inventory_path,
         shipment_path,
         transfer_path,
+        activity_path,
         control_path,
         health_check_path,
         user_info_path,
+        invites_path,
+        admin_path,
+        items_path,
     } = PrivatePath::default();
     let control_route = Router::new().route("/", get(handle_ws));
-    let health_check_route = Router::new().route("/", get(health_check));
-    let user_info_route = Router::new().route("/", get(get_user_info_handler));
+    let health_check_route = Router::new()
+        .route("/", middleware::compression_exempt(get(health_check)))
+        .route("/detailed", get(detailed_health_check));
+    let user_info_route = Router::new()
+        .route("/", get(get_user_info_handler))
+        .route("/presets", get(get_user_presets).put(update_user_presets))
+        .route("/recent", get(get_recent_resources));
+    let invites_route = Router::new().route("/", post(create_invite));
     let private_route = Router::new()
         .nest(
             health_check_path.root_path().as_str(),
@@ -155,6 +199,10 @@ pub async fn server_start(db_client: DbClient, listener: TcpListener) {
             transfer_path.root_path().as_str(),
             transfer_path.inject_auth_router(get_transfer_router()),
         )
+        .nest(
+            activity_path.root_path().as_str(),
+            activity_path.inject_auth_router(get_activity_router()),
+        )
         .nest(
             control_path.root_path().as_str(),
             control_path.inject_auth_router(control_route),
@@ -163,6 +211,19 @@ pub async fn server_start(db_client: DbClient, listener: TcpListener) {
             user_info_path.root_path().as_str(),
             user_info_path.inject_auth_router(user_info_route),
         )
+        .nest(
+            invites_path.root_path().as_str(),
+            invites_path.inject_auth_router(invites_route),
+        )
+        .nest(
+            admin_path.root_path().as_str(),
+            admin_path.inject_auth_router(get_admin_router()),
+        )
+        .nest(
+            items_path.root_path().as_str(),
+            items_path.inject_auth_router(get_item_router()),
+        )
+        .route_layer(from_fn(middleware::record_recent))
         .route_layer(from_extractor::<UserInfo>());
     let sign_up_route = Router::new().route("/", post(sign_up));
     let login_route = Router::new().route("/", post(login));
@@ -177,7 +238,10 @@ pub async fn server_start(db_client: DbClient, listener: TcpListener) {
         .layer(Extension(state.clone()))
         .with_state(state);
 
-    let app = Router::new().nest("/api/v1", api_route).layer(layer);
+    let app = Router::new()
+        .nest("/api/v1", api_route)
+        .layer(from_fn(middleware::envelope))
+        .layer(layer);
     info!("server started at {}", listener.local_addr().unwrap());
     axum::Server::from_tcp(listener)
         .unwrap()
@@ -190,6 +254,64 @@ async fn health_check() -> Result<impl IntoResponse> {
     Ok(StatusCode::OK)
 }
 
+const HEALTH_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeStatus {
+    Ok,
+    Err,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DetailedHealth {
+    version: &'static str,
+    git_sha: &'static str,
+    db: ProbeStatus,
+    utility: ProbeStatus,
+    google: ProbeStatus,
+}
+
+/// running version and reachability of the dependencies,
+/// each probe gives up after `HEALTH_PROBE_TIMEOUT`.
+async fn detailed_health_check(
+    State(db): State<Arc<DbClient>>,
+    State(http_client): State<Arc<reqwest::Client>>,
+) -> Result<Json<DetailedHealth>> {
+    let db_probe = tokio::time::timeout(
+        HEALTH_PROBE_TIMEOUT,
+        db.ph_db.run_command(mongodb::bson::doc! {"ping":1}, None),
+    );
+    let utility_probe = probe_http(&http_client, SETTINGS.utility.get_utility_url());
+    let google_probe = probe_http(&http_client, SETTINGS.google_service.get_service_url());
+    let (db_res, utility, google) = tokio::join!(db_probe, utility_probe, google_probe);
+    let db = match db_res {
+        Ok(Ok(_)) => ProbeStatus::Ok,
+        _ => ProbeStatus::Err,
+    };
+    Ok(Json(DetailedHealth {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        db,
+        utility,
+        google,
+    }))
+}
+
+/// any http response means the service is reachable.
+async fn probe_http(http_client: &reqwest::Client, url: String) -> ProbeStatus {
+    match http_client
+        .get(url)
+        .timeout(HEALTH_PROBE_TIMEOUT)
+        .send()
+        .await
+    {
+        Ok(_) => ProbeStatus::Ok,
+        Err(_) => ProbeStatus::Err,
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderRegisterInput {
@@ -201,6 +323,28 @@ pub struct OrderRegisterInput {
     pub order_datetime: DateTime<Utc>,
 }
 
+impl OrderRegisterInput {
+    /// every invalid field of the order, empty if it can be created.
+    pub fn validate(&self) -> Vec<OrderValidateError> {
+        let mut errors = Vec::new();
+        if let Err(e) = TaobaoOrderNo::parse(&self.taobao_order_no) {
+            errors.push(e);
+        }
+        if let Err(e) = CustomerId::parse(&self.customer_id) {
+            errors.push(e);
+        }
+        for item in self.items.iter() {
+            if let Err(e) = ItemCodeExt::parse(&item.item_code_ext) {
+                errors.push(e);
+            }
+            if let Err(e) = OrderItemRate::parse(item.rate) {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct InputOrderItem {
@@ -222,6 +366,14 @@ pub struct NewShipmentInput {
     pub item_ids: Vec<Uuid>,
 }
 
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDetailsQuery {
+    /// embed the catalog item of each order item.
+    #[serde(default)]
+    pub with_item_details: bool,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PagedResponse<D> {
@@ -230,6 +382,62 @@ pub struct PagedResponse<D> {
     pub has_next: bool,
 }
 
+/// a page of a cursor paged query, `next_cursor` is none on the last page.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorPagedResponse<D> {
+    pub data: Vec<D>,
+    pub next_cursor: Option<String>,
+}
+
+/// reject pages beyond `max_page` before running a deep skip query.
+pub fn ensure_page_within_limit(page: Option<u32>) -> Result<()> {
+    match page {
+        Some(page) if page > SETTINGS.max_page => {
+            Err(Error::PageOutOfRange(page, SETTINGS.max_page))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// reject deleting the record `id` created before the configured grace window,
+/// unless the user's role or sub role of `route` is at least the override role.
+pub fn ensure_deletable(
+    user_info: &UserInfo,
+    route: AppPrivateRoute,
+    id: Uuid,
+    created_at: DateTime<Utc>,
+) -> Result<()> {
+    ensure_deletable_within(&SETTINGS.delete_grace, user_info, route, id, created_at)
+}
+
+/// `ensure_deletable` against the grace window of `setting`.
+pub fn ensure_deletable_within(
+    setting: &DeleteGraceSetting,
+    user_info: &UserInfo,
+    route: AppPrivateRoute,
+    id: Uuid,
+    created_at: DateTime<Utc>,
+) -> Result<()> {
+    let days = match setting.days {
+        Some(days) => days,
+        None => return Ok(()),
+    };
+    if Utc::now() - created_at <= chrono::Duration::days(days) {
+        return Ok(());
+    }
+    let overridden = user_info.role <= setting.override_role
+        || user_info
+            .sub_role
+            .get(&route)
+            .is_some_and(|role| *role <= setting.override_role);
+    if overridden {
+        info!("delete {id} after the grace window by override");
+        return Ok(());
+    }
+    Err(Error::DeleteGraceExpired(id.to_string(), days))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum AppPrivateRoute {
@@ -241,8 +449,12 @@ pub enum AppPrivateRoute {
     Return,
     Shipment,
     Transfer,
+    Activity,
     Control,
     UserInfo,
+    Invites,
+    Admin,
+    Items,
     Root,
 }
 
@@ -258,8 +470,12 @@ impl From<String> for AppPrivateRoute {
             "/return" => AppPrivateRoute::Return,
             "/shipment" => AppPrivateRoute::Shipment,
             "/transfer" => AppPrivateRoute::Transfer,
+            "/activity" => AppPrivateRoute::Activity,
             "/control" => AppPrivateRoute::Control,
             "/user_info" => AppPrivateRoute::UserInfo,
+            "/invites" => AppPrivateRoute::Invites,
+            "/admin" => AppPrivateRoute::Admin,
+            "/items" => AppPrivateRoute::Items,
             "/" => AppPrivateRoute::Root,
             _ => unreachable!(),
         }
@@ -277,8 +493,12 @@ impl Display for AppPrivateRoute {
             AppPrivateRoute::Return => f.write_str("return"),
             AppPrivateRoute::Shipment => f.write_str("shipment"),
             AppPrivateRoute::Transfer => f.write_str("transfer"),
+            AppPrivateRoute::Activity => f.write_str("activity"),
             AppPrivateRoute::Control => f.write_str("control"),
             AppPrivateRoute::UserInfo => f.write_str("user_info"),
+            AppPrivateRoute::Invites => f.write_str("invites"),
+            AppPrivateRoute::Admin => f.write_str("admin"),
+            AppPrivateRoute::Items => f.write_str("items"),
             AppPrivateRoute::Root => f.write_str("root"),
         }
     }
@@ -295,8 +515,12 @@ impl From<AppPrivateRoute> for Bson {
             AppPrivateRoute::Return => Bson::String(String::from("return")),
             AppPrivateRoute::Shipment => Bson::String(String::from("shipment")),
             AppPrivateRoute::Transfer => Bson::String(String::from("transfer")),
+            AppPrivateRoute::Activity => Bson::String(String::from("activity")),
             AppPrivateRoute::Control => Bson::String(String::from("control")),
             AppPrivateRoute::UserInfo => Bson::String(String::from("user_info")),
+            AppPrivateRoute::Invites => Bson::String(String::from("invites")),
+            AppPrivateRoute::Admin => Bson::String(String::from("admin")),
+            AppPrivateRoute::Items => Bson::String(String::from("items")),
             AppPrivateRoute::Root => Bson::String(String::from("root")),
         }
     }
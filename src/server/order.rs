@@ -3,44 +3,72 @@ use std::sync::Arc;
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
-    routing::{get, patch, put},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post, put},
     Json, Router,
 };
 use chrono::prelude::*;
 use chrono::serde::ts_seconds;
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
-use tracing::instrument;
+use tracing::{instrument, warn};
 use uuid::Uuid;
 
 use crate::{
     cache::OrderCache,
-    db::{mongo::DbClient, order::ITEMS_PER_PAGE, Order, OrderItem, OrderRepo, RegisterItem},
-    services::google_service::GoogleService,
+    db::{
+        attach_item_details,
+        mongo::DbClient,
+        order::{LocationCount, OrderCursor, OrderItemPlan, StatusCount, ITEMS_PER_PAGE},
+        InventoryOperation, InventoryRepo, Order, OrderItem, OrderRepo, PhDataBase, RegisterItem,
+        Shipment, ShipmentRepo,
+    },
+    services::notifier::{Notification, Notifier},
 };
 use crate::{db::order::OrderItemStatus, error_result::Result};
 
 use super::{
-    auth::{UserInfo, SETTINGS},
+    auth::UserInfo,
+    ensure_deletable, ensure_page_within_limit,
+    middleware::{compression_exempt, upload_limited, Warnings},
     ws::{send_control_message, send_control_messages, ControlMessage},
-    AppState, OrderRegisterInput, PagedResponse,
+    AppPrivateRoute, AppState, CursorPagedResponse, InputOrderItem, ItemDetailsQuery,
+    OrderRegisterInput, PagedResponse,
 };
 
 pub fn get_router() -> Router<AppState> {
     Router::new()
         .route("/", get(query_orders).post(create_new_order))
+        .route("/preview", post(preview_order))
+        .route("/bulk", upload_limited(post(create_new_orders_bulk)))
+        .route(
+            "/import/validate",
+            upload_limited(post(validate_order_import)),
+        )
+        .route("/batch", post(get_orders_by_ids))
+        .route("/status_counts", get(count_orders_by_status))
+        .route("/customers", get(find_customer_ids))
         .route("/:id", get(get_order_by_id).delete(delete_order))
         .route("/taobao_no/:taobao_no", get(get_order_by_taobao_no))
         .route("/:id/note", patch(update_order_note))
+        .route("/:id/operations", get(get_order_operations))
         .route("/check_then_update", put(check_then_update_order_status))
 }
 
 pub fn get_items_router() -> Router<AppState> {
     Router::new()
         .route("/", get(query_order_items))
+        .route("/locate", get(locate_order_items))
+        .route("/invalid_rates", get(find_invalid_rate_order_items))
+        .route(
+            "/awaiting_shipment/counts",
+            compression_exempt(get(count_awaiting_shipment_items)),
+        )
         .route("/:id", get(get_order_item_by_id).delete(conceal_order_item))
+        .route("/:id/lineage", get(get_order_item_lineage))
         .route("/:id/rate", patch(update_order_items_rate))
+        .route("/:id/hold", patch(update_order_item_hold))
+        .route("/:id/reopen", post(reopen_order_item))
 }
 
 #[instrument(name="create new order",skip(user_info,message,db,cache,sender),fields(
@@ -54,6 +82,7 @@ pub async fn create_new_order(
     State(sender): State<Arc<Sender<ControlMessage>>>,
     Json(message): Json<OrderRegisterInput>,
 ) -> Result<impl IntoResponse> {
+    let warnings = order_warnings(&db, &message.items).await?;
     db.create_order(message).await?;
     let messages = &[
         ControlMessage::RefreshOrderList,
@@ -63,7 +92,149 @@ pub async fn create_new_order(
     ];
     send_control_messages(sender, messages);
     cache.clear_orders();
-    Ok(StatusCode::CREATED)
+    Ok((StatusCode::CREATED, Warnings(warnings)))
+}
+
+/// which order items would be guaranteed or back ordered if the order was created now.
+pub async fn preview_order(
+    State(db): State<Arc<DbClient>>,
+    Json(message): Json<OrderRegisterInput>,
+) -> Result<Json<Vec<OrderItemPlan>>> {
+    Ok(db.preview_order(&message).await?.into())
+}
+
+/// an order of an import, `errors` is empty if it can be created
+/// and `plans` are only set for such orders.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderImportRow {
+    /// position of the order in the import, from 0.
+    pub index: usize,
+    pub taobao_order_no: String,
+    pub errors: Vec<String>,
+    pub plans: Vec<OrderItemPlan>,
+}
+
+/// validates the orders of a `/orders/bulk` import and plans the valid ones
+/// against the current inventory in import order. nothing is written.
+pub async fn validate_order_import(
+    State(db): State<Arc<DbClient>>,
+    Json(messages): Json<Vec<OrderRegisterInput>>,
+) -> Result<Json<Vec<OrderImportRow>>> {
+    let mut rows = Vec::with_capacity(messages.len());
+    let mut items = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        let errors = message
+            .validate()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            items.extend(message.items.iter().cloned());
+        }
+        rows.push(OrderImportRow {
+            index,
+            taobao_order_no: message.taobao_order_no.clone(),
+            errors,
+            plans: Vec::new(),
+        });
+    }
+    // every item of valid orders is planned once per location it requests.
+    let mut plans = db.preview_order_items(&items).await?.into_iter();
+    for (row, message) in rows.iter_mut().zip(messages.iter()) {
+        if !row.errors.is_empty() {
+            continue;
+        }
+        let count = message
+            .items
+            .iter()
+            .flat_map(|item| item.quantity.iter())
+            .filter(|quantity| quantity.quantity > 0)
+            .count();
+        row.plans.extend(plans.by_ref().take(count));
+    }
+    Ok(rows.into())
+}
+
+/// suspicious but valid order items, checked before the order is created
+/// because dummy items are created along with the order.
+async fn order_warnings(db: &DbClient, items: &[InputOrderItem]) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    for item in items {
+        if item.price == 0 {
+            warnings.push(format!("price of {} is 0", item.item_code_ext));
+        }
+        // malformed codes are left to the order validation.
+        let item_code = match item.item_code_ext.get(0..11) {
+            Some(item_code) => item_code,
+            None => continue,
+        };
+        match db.find_one_by_item_code(item_code, None).await? {
+            None => warnings.push(format!(
+                "item {item_code} is not registered, a dummy item is used"
+            )),
+            Some(ph_item) if ph_item.is_dummy() => {
+                warnings.push(format!("item {item_code} is a dummy item"))
+            }
+            Some(_) => (),
+        }
+    }
+    warnings.dedup();
+    Ok(warnings)
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkOrderResult {
+    taobao_order_no: String,
+    success: bool,
+    error: Option<String>,
+}
+
+/// create orders one by one, a failed order will not stop the rest.
+#[instrument(name="create new orders bulk",skip(user_info,messages,db,cache,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+    count = messages.len(),
+))]
+pub async fn create_new_orders_bulk(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(messages): Json<Vec<OrderRegisterInput>>,
+) -> Result<Json<Vec<BulkOrderResult>>> {
+    let mut results = Vec::with_capacity(messages.len());
+    for message in messages {
+        let taobao_order_no = message.taobao_order_no.clone();
+        let result = match db.create_order(message).await {
+            Ok(_) => BulkOrderResult {
+                taobao_order_no,
+                success: true,
+                error: None,
+            },
+            Err(e) => {
+                warn!("failed to create order {taobao_order_no}: {e}");
+                BulkOrderResult {
+                    taobao_order_no,
+                    success: false,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        results.push(result);
+    }
+    if results.iter().any(|r| r.success) {
+        let messages = &[
+            ControlMessage::RefreshOrderList,
+            ControlMessage::RefreshInventory,
+            ControlMessage::RefreshInventoryItemQuantity,
+            ControlMessage::RefreshWaitForShipmentItemList,
+        ];
+        send_control_messages(sender, messages);
+        cache.clear_orders();
+    }
+    Ok(Json(results))
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -76,21 +247,53 @@ pub struct QueryOrdersMessage {
     #[serde(with = "ts_seconds")]
     pub to: DateTime<Utc>,
     pub page: Option<u32>,
+    /// pages by the `nextCursor` of the former page instead of `page`,
+    /// an empty cursor requests the first page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 pub async fn query_orders(
     Query(message): Query<QueryOrdersMessage>,
     State(db): State<Arc<DbClient>>,
     State(cache): State<Arc<dyn OrderCache>>,
-) -> Result<Json<PagedResponse<Order>>> {
+) -> Result<Response> {
+    if let Some(token) = message.cursor.as_deref() {
+        let cursor = match token {
+            "" => None,
+            token => Some(OrderCursor::decode(token)?),
+        };
+        let output = match cache.get_orders(&message) {
+            Some(output) => output,
+            None => {
+                let output = db
+                    .query_orders_after(
+                        &message.keyword,
+                        &message.status,
+                        message.from,
+                        message.to,
+                        cursor,
+                    )
+                    .await?;
+                cache.set_orders(message, output.clone());
+                output
+            }
+        };
+        let res = CursorPagedResponse {
+            next_cursor: OrderCursor::next_of(&output).map(|cursor| cursor.encode()),
+            data: output.into_iter().map(|m| m.into()).collect::<Vec<Order>>(),
+        };
+        return Ok(Json(res).into_response());
+    }
+    ensure_page_within_limit(message.page)?;
     let current_page = message.page.unwrap_or(0);
     if let Some(output) = cache.get_orders(&message) {
         let res = PagedResponse {
             has_next: (output.len() as u32) == ITEMS_PER_PAGE,
-            data: output.into_iter().map(|m| m.into()).collect(),
+            data: output.into_iter().map(|m| m.into()).collect::<Vec<Order>>(),
             next: current_page + 1,
         };
-        return Ok(res.into());
+        return Ok(Json(res).into_response());
     }
     let (has_next, output) = db
         .query_orders(
@@ -105,22 +308,56 @@ pub async fn query_orders(
         cache.set_orders(message, output.clone());
     }
     let res = PagedResponse {
-        data: output.into_iter().map(|m| m.into()).collect(),
+        data: output.into_iter().map(|m| m.into()).collect::<Vec<Order>>(),
         next: current_page + 1,
         has_next,
     };
-    Ok(res.into())
+    Ok(Json(res).into_response())
 }
 
 pub async fn get_order_by_id(
     Path(id): Path<Uuid>,
+    Query(query): Query<ItemDetailsQuery>,
     State(db): State<Arc<DbClient>>,
 ) -> Result<Json<Order>> {
     let output = db.get_order_by_id(id.into()).await?;
-    let reply: Order = output.into();
+    let mut reply: Order = output.into();
+    if query.with_item_details {
+        attach_item_details(db.as_ref(), &mut reply.items).await?;
+    }
     Ok(reply.into())
 }
 
+pub async fn get_order_operations(
+    Path(id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<InventoryOperation>>> {
+    let res = db.get_order_operations(id.into()).await?;
+    Ok(res.into_iter().map(|o| o.into()).collect::<Vec<_>>().into())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BatchOrdersMessage {
+    pub ids: Vec<Uuid>,
+}
+
+pub async fn get_orders_by_ids(
+    State(db): State<Arc<DbClient>>,
+    Json(message): Json<BatchOrdersMessage>,
+) -> Result<Json<Vec<Order>>> {
+    let ids = message
+        .ids
+        .into_iter()
+        .map(|id| id.into())
+        .collect::<Vec<_>>();
+    let outputs = db.get_orders_by_ids(&ids).await?;
+    Ok(outputs
+        .into_iter()
+        .map(|o| o.into())
+        .collect::<Vec<Order>>()
+        .into())
+}
+
 pub async fn get_order_by_taobao_no(
     Path(taobao_order_no): Path<String>,
     State(db): State<Arc<DbClient>>,
@@ -129,7 +366,7 @@ pub async fn get_order_by_taobao_no(
     Ok(Json(output.into_iter().map(|o| o.into()).collect()))
 }
 
-#[instrument(name="delete order request",skip(user_info,db,cache,sender),fields(
+#[instrument(name="delete order request",skip(user_info,db,cache,sender,notifier),fields(
     request_id=%Uuid::new_v4(),
     action_by=%user_info.user_id,
 ))]
@@ -139,8 +376,15 @@ pub async fn delete_order(
     State(db): State<Arc<DbClient>>,
     State(cache): State<Arc<dyn OrderCache>>,
     State(sender): State<Arc<Sender<ControlMessage>>>,
-    State(google_service): State<Arc<GoogleService>>,
+    State(notifier): State<Arc<dyn Notifier>>,
 ) -> Result<impl IntoResponse> {
+    let order = db.get_order_by_id(order_id.into()).await?;
+    ensure_deletable(
+        &user_info,
+        AppPrivateRoute::Orders,
+        order_id,
+        order.created_at.to_chrono(),
+    )?;
     let output = db.delete_order(order_id.into()).await?;
     let messages = &[
         ControlMessage::RefreshOrderList,
@@ -151,13 +395,12 @@ pub async fn delete_order(
     send_control_messages(sender.clone(), messages);
     for item in output.deleted_items {
         if output.item_is_shipped_ids.contains(&item.id) {
-            google_service
-                .call_notify(
-                    SETTINGS.google_service.target_user_ex_id,
-                    SETTINGS.google_service.task_list_name.clone(),
-                    item.item_code_ext,
-                    format!("顧客名:{},メモ:{}", item.customer_id, item.note),
-                )
+            notifier
+                .notify(Notification::shipped_item_removed(
+                    &item.item_code_ext,
+                    &item.customer_id,
+                    &item.note,
+                ))
                 .await;
         }
         send_control_message(
@@ -177,7 +420,45 @@ pub async fn get_order_item_by_id(
     Ok(res.into())
 }
 
-#[instrument(name="conceal order item request",skip(user_info,db,cache,sender),fields(
+/// an order item with everything it came from and went through.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderItemLineage {
+    pub item: OrderItem,
+    pub order: Order,
+    pub shipment: Option<Shipment>,
+    pub operations: Vec<InventoryOperation>,
+}
+
+/// operations are related to the order rather than the item,
+/// so only the ones on the item's code are kept.
+pub async fn get_order_item_lineage(
+    Path(order_item_id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<OrderItemLineage>> {
+    let item = db.get_order_item_by_id(order_item_id.into()).await?;
+    let order = db.get_order_by_id(item.order_id).await?;
+    let shipment = match item.shipment_id {
+        Some(shipment_id) => Some(db.get_shipment_by_id(shipment_id).await?.into()),
+        None => None,
+    };
+    let operations = db
+        .get_operations_by_related_id(item.order_id)
+        .await?
+        .into_iter()
+        .filter(|o| o.item_code_ext == item.item_code_ext)
+        .map(|o| o.into())
+        .collect();
+    Ok(OrderItemLineage {
+        item: item.into(),
+        order: order.into(),
+        shipment,
+        operations,
+    }
+    .into())
+}
+
+#[instrument(name="conceal order item request",skip(user_info,db,cache,sender,notifier),fields(
     request_id=%Uuid::new_v4(),
     action_by=%user_info.user_id,
 ))]
@@ -187,20 +468,16 @@ pub async fn conceal_order_item(
     State(db): State<Arc<DbClient>>,
     State(cache): State<Arc<dyn OrderCache>>,
     State(sender): State<Arc<Sender<ControlMessage>>>,
-    State(google_service): State<Arc<GoogleService>>,
+    State(notifier): State<Arc<dyn Notifier>>,
 ) -> Result<impl IntoResponse> {
     let output = db.conceal_order_item(order_item_id.into()).await?;
     if output.is_shipped {
-        google_service
-            .call_notify(
-                SETTINGS.google_service.target_user_ex_id,
-                SETTINGS.google_service.task_list_name.clone(),
-                output.concealed_item.item_code_ext,
-                format!(
-                    "顧客名:{},メモ:{}",
-                    output.concealed_item.customer_id, output.concealed_item.note
-                ),
-            )
+        notifier
+            .notify(Notification::shipped_item_removed(
+                &output.concealed_item.item_code_ext,
+                &output.concealed_item.customer_id,
+                &output.concealed_item.note,
+            ))
             .await;
     }
     send_control_message(&sender, ControlMessage::RefreshOrderItem(order_item_id));
@@ -257,9 +534,7 @@ pub async fn check_then_update_order_status(
         for item in res.iter() {
             send_control_message(&sender, ControlMessage::RefreshOrderItem(item.id.into()));
         }
-        //update client's order item id for re-render validating state to a random id
-        //for preventing unnecessary re-renders
-        send_control_message(&sender, ControlMessage::RefreshOrderItem(Uuid::new_v4()));
+        send_control_message(&sender, ControlMessage::RefreshValidationState);
         send_control_message(&sender, ControlMessage::RefreshInventory);
         send_control_message(&sender, ControlMessage::RefreshWaitForShipmentItemList);
         cache.clear_orders();
@@ -292,6 +567,136 @@ pub async fn query_order_items(
         .into())
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocateOrderItemsQuery {
+    item_code_ext: String,
+    #[serde(default = "default_locate_status")]
+    status: OrderItemStatus,
+}
+
+fn default_locate_status() -> OrderItemStatus {
+    OrderItemStatus::Shipped
+}
+
+/// find which orders a scanned unit may belong to.
+pub async fn locate_order_items(
+    Query(query): Query<LocateOrderItemsQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<OrderItem>>> {
+    let outputs = db
+        .locate_order_items(&query.item_code_ext, &query.status)
+        .await?;
+    Ok(outputs
+        .into_iter()
+        .map(|o| o.into())
+        .collect::<Vec<_>>()
+        .into())
+}
+
+pub async fn find_invalid_rate_order_items(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<OrderItem>>> {
+    let outputs = db.find_invalid_rate_order_items().await?;
+    Ok(outputs
+        .into_iter()
+        .map(|o| o.into())
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// counts of guaranteed order items per location, for badges of the wait for shipment list.
+pub async fn count_awaiting_shipment_items(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<LocationCount>>> {
+    Ok(db.count_awaiting_shipment_items().await?.into())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StatusCountsQuery {
+    #[serde(with = "ts_seconds")]
+    pub from: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CustomerIdsQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/// customer ids already ordered, for autocompleting the customer id of a new order.
+pub async fn find_customer_ids(
+    Query(query): Query<CustomerIdsQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<String>>> {
+    Ok(db.find_customer_ids_by_prefix(&query.prefix).await?.into())
+}
+
+/// counts of orders having items in each status, for the order trend chart.
+pub async fn count_orders_by_status(
+    Query(query): Query<StatusCountsQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<StatusCount>>> {
+    Ok(db
+        .count_orders_by_status(query.from, query.to)
+        .await?
+        .into())
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateOrderItemHoldMessage {
+    hold: bool,
+}
+
+#[instrument(name="update order item hold",skip(user_info,db,cache,sender),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn update_order_item_hold(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Path(order_item_id): Path<Uuid>,
+    Json(message): Json<UpdateOrderItemHoldMessage>,
+) -> Result<Json<OrderItem>> {
+    let order_item = db
+        .update_order_item_hold(order_item_id.into(), message.hold)
+        .await?;
+    send_control_message(&sender, ControlMessage::RefreshOrderItem(order_item_id));
+    send_control_message(&sender, ControlMessage::RefreshOrderList);
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    send_control_message(&sender, ControlMessage::RefreshWaitForShipmentItemList);
+    cache.clear_orders();
+    Ok(Json(order_item.into()))
+}
+
+/// reopen an accidentally concealed order item.
+#[instrument(name="reopen order item",skip(user_info,db,cache,sender),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id
+))]
+pub async fn reopen_order_item(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Path(order_item_id): Path<Uuid>,
+) -> Result<Json<OrderItem>> {
+    let order_item = db.reopen_order_item(order_item_id.into()).await?;
+    send_control_message(&sender, ControlMessage::RefreshOrderItem(order_item_id));
+    send_control_message(&sender, ControlMessage::RefreshOrderList);
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    send_control_message(&sender, ControlMessage::RefreshWaitForShipmentItemList);
+    cache.clear_orders();
+    Ok(Json(order_item.into()))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateOrderItemRateMessage {
@@ -18,8 +18,10 @@ use crate::db::{mongo::DbClient, Register, RegisterRepo, StockRegisterInput};
 use crate::error_result::Result;
 
 use super::{
+    auth::UserInfo,
+    ensure_deletable, ensure_page_within_limit,
     ws::{send_control_message, ControlMessage},
-    AppState, PagedResponse,
+    AppPrivateRoute, AppState, PagedResponse,
 };
 
 pub fn get_router() -> Router<AppState> {
@@ -58,6 +60,7 @@ pub async fn query_registers(
     Query(message): Query<QueryRegistersMessage>,
     State(db): State<Arc<DbClient>>,
 ) -> Result<Json<PagedResponse<Register>>> {
+    ensure_page_within_limit(message.page)?;
     let (has_next, res) = db
         .query_registers(message.from, message.to, message.keyword, message.page)
         .await?;
@@ -78,14 +81,23 @@ pub async fn get_register_by_id(
     Ok(output.into())
 }
 
-#[instrument(name="delete register",skip(db,sender),fields(
-    request_id=%Uuid::new_v4()
+#[instrument(name="delete register",skip(user_info,db,sender),fields(
+    request_id=%Uuid::new_v4(),
+    action_by=%user_info.user_id,
 ))]
 pub async fn delete_stock_register(
+    user_info: UserInfo,
     Path(id): Path<Uuid>,
     State(db): State<Arc<DbClient>>,
     State(sender): State<Arc<Sender<ControlMessage>>>,
 ) -> Result<impl IntoResponse> {
+    let register = db.get_register_by_id(id.into()).await?;
+    ensure_deletable(
+        &user_info,
+        AppPrivateRoute::Registers,
+        id,
+        register.created_at.to_chrono(),
+    )?;
     db.delete_stock_register(id.into()).await?;
     send_control_message(&sender, ControlMessage::RefreshRegisterList);
     send_control_message(&sender, ControlMessage::RefreshInventory);
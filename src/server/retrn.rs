@@ -24,6 +24,7 @@ use super::{
 pub fn get_return_router() -> Router<AppState> {
     Router::new()
         .route("/", post(create_new_return).get(query_returns))
+        .route("/from_order", post(create_return_from_order))
         .route("/:id", delete(delete_return_by_id).get(get_return_by_id))
 }
 
@@ -62,6 +63,27 @@ pub async fn create_new_return(
     Ok(StatusCode::CREATED)
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReturnFromOrderInput {
+    pub order_item_id: Uuid,
+}
+
+pub async fn create_return_from_order(
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(input): Json<ReturnFromOrderInput>,
+) -> Result<impl IntoResponse> {
+    let retrn: Return = db
+        .create_return_from_order_item(input.order_item_id.into())
+        .await?
+        .into();
+    send_control_message(&sender, ControlMessage::RefreshReturnList);
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    Ok((StatusCode::CREATED, Json(retrn)))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryReturnMessage {
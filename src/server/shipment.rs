@@ -3,31 +3,41 @@ use std::sync::Arc;
 use crate::{
     cache::OrderCache,
     db::{inventory::InventoryLocation, mongo::DbClient, shipment::ShipmentStatus, TransferRepo},
-    error_result::Result,
+    error_result::{Error, Result},
 };
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::{IntoResponse, Response},
+    response::IntoResponse,
     routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use chrono::prelude::*;
-use chrono::serde::ts_seconds;
+use chrono::serde::{ts_seconds, ts_seconds_option};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
 use tracing::{info, instrument};
 use uuid::Uuid;
 
 use crate::db::{
-    shipment::{MongoShipment, ShipmentVendor},
-    Shipment, ShipmentRepo,
+    attach_item_details,
+    shipment::{
+        build_picking_list, diff_shipment_transfers, validate_shipment_no, DuplicateShipmentItem,
+        MongoShipment, MongoShipmentCounts, PickingListRow, ShipmentVendor, TransferDiff,
+    },
+    OrderItem, OrderRepo, Shipment, ShipmentRepo,
 };
 
 use super::{
-    export::{export_shipment_by_id_except_color_no, export_shipment_ordered, export_shipments},
+    auth::UserInfo,
+    ensure_deletable, ensure_page_within_limit,
+    export::{
+        build_discount_breakdown, export_shipment_by_id_except_color_no, export_shipment_invoice,
+        export_shipment_ordered, export_shipment_preview, export_shipments, DiscountBand,
+        ExportSplit,
+    },
     ws::{send_control_message, ControlMessage},
-    AppState, NewShipmentInput, PagedResponse,
+    AppPrivateRoute, AppState, ItemDetailsQuery, NewShipmentInput, PagedResponse,
 };
 
 pub fn get_shipment_router() -> Router<AppState> {
@@ -36,12 +46,26 @@ pub fn get_shipment_router() -> Router<AppState> {
         .route("/:id", delete(delete_shipment).get(get_shipment_by_id))
         .route("/:id/note", patch(update_shipment_note))
         .route("/:id/status", put(update_shipment_status))
+        .route("/:id/reopen", put(reopen_shipment))
+        .route("/:id/duplicate", post(duplicate_shipment))
         .route("/:id/no", put(update_shipment_no))
         .route("/:id/vendor", put(update_shipment_vendor))
         .route("/:id/export", get(export_shipment_by_id_except_color_no))
         .route("/:id/export_ordered", get(export_shipment_ordered))
+        .route("/:id/invoice", get(export_shipment_invoice))
+        .route("/:id/transfer_diff", get(get_shipment_transfer_diff))
+        .route("/:id/picking_list", get(get_shipment_picking_list))
         .route("/by_no/:no", get(find_shipment_by_no))
+        .route("/by_customer", post(ship_by_customer))
+        .route("/unexported", get(find_unexported_shipments))
+        .route("/discount_breakdown", get(get_discount_breakdown))
+        .route("/by_status/:status", get(find_shipments_by_status))
         .route("/export", get(export_shipments))
+        .route("/export_preview", post(export_shipment_preview))
+        .route(
+            "/integrity/duplicate_items",
+            get(find_duplicate_shipment_items),
+        )
 }
 
 pub async fn create_new_shipment(
@@ -50,17 +74,122 @@ pub async fn create_new_shipment(
     State(sender): State<Arc<Sender<ControlMessage>>>,
     Json(input): Json<NewShipmentInput>,
 ) -> Result<impl IntoResponse> {
+    validate_shipment_no(&input.vendor, &input.shipment_no)?;
     db.create_new_shipment(input.clone()).await?;
     send_control_message(&sender, ControlMessage::RefreshShipmentList);
     send_control_message(&sender, ControlMessage::RefreshWaitForShipmentItemList);
     for id in input.item_ids {
         send_control_message(&sender, ControlMessage::RefreshOrderItem(id));
     }
-    send_control_message(&sender, ControlMessage::RefreshOrderItem(Uuid::new_v4()));
+    send_control_message(&sender, ControlMessage::RefreshValidationState);
     cache.clear_orders();
     Ok(StatusCode::CREATED)
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateShipmentMessage {
+    pub shipment_no: String,
+    #[serde(with = "ts_seconds")]
+    pub shipment_date: DateTime<Utc>,
+    pub item_ids: Vec<Uuid>,
+}
+
+/// create a new shipment reusing the source shipment's vendor and note.
+/// the source's items are already shipped, so items are always provided by the caller.
+#[instrument(name="duplicate shipment",skip(user_info,message,db,cache,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+))]
+pub async fn duplicate_shipment(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Path(shipment_id): Path<Uuid>,
+    Json(message): Json<DuplicateShipmentMessage>,
+) -> Result<impl IntoResponse> {
+    let source = db.get_shipment_by_id(shipment_id.into()).await?;
+    let input = NewShipmentInput {
+        shipment_no: message.shipment_no,
+        note: source.note,
+        vendor: source.vendor,
+        shipment_date: message.shipment_date,
+        item_ids: message.item_ids,
+    };
+    create_new_shipment(State(db), State(cache), State(sender), Json(input)).await
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipByCustomerMessage {
+    pub customer_id: String,
+    pub shipment_no: String,
+    #[serde(default)]
+    pub note: String,
+    pub vendor: ShipmentVendor,
+    #[serde(with = "ts_seconds")]
+    pub shipment_date: DateTime<Utc>,
+    /// only items ordered at or after this.
+    #[serde(default, with = "ts_seconds_option")]
+    pub from: Option<DateTime<Utc>>,
+    /// only items ordered at or before this.
+    #[serde(default, with = "ts_seconds_option")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// ship all guaranteed items of the customer in one shipment.
+#[instrument(name="ship by customer",skip(user_info,message,db,cache,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+))]
+pub async fn ship_by_customer(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(cache): State<Arc<dyn OrderCache>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Json(message): Json<ShipByCustomerMessage>,
+) -> Result<impl IntoResponse> {
+    let items = db
+        .find_guaranteed_items_by_customer(&message.customer_id, message.from, message.to)
+        .await?;
+    if items.is_empty() {
+        return Err(Error::OrderItemNotFound(format!(
+            "guaranteed items of {}",
+            message.customer_id
+        )));
+    }
+    info!("ship {} items of {}", items.len(), message.customer_id);
+    let input = NewShipmentInput {
+        shipment_no: message.shipment_no,
+        note: message.note,
+        vendor: message.vendor,
+        shipment_date: message.shipment_date,
+        item_ids: items.into_iter().map(|item| item.id.into()).collect(),
+    };
+    create_new_shipment(State(db), State(cache), State(sender), Json(input)).await
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscountBreakdownQuery {
+    #[serde(with = "ts_seconds")]
+    pub from: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    pub to: DateTime<Utc>,
+}
+
+/// shipped items of the shipments dated in the range by discount band.
+pub async fn get_discount_breakdown(
+    Query(query): Query<DiscountBreakdownQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<DiscountBand>>> {
+    let (_, shipments) = db
+        .query_shipments("", query.from, query.to, "", "", None)
+        .await?;
+    Ok(Json(build_discount_breakdown(&db, &shipments).await?))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryShipmentMessage {
@@ -72,12 +201,22 @@ pub struct QueryShipmentMessage {
     pub status: String,
     pub vendor: String,
     pub page: Option<u32>,
+    /// catalog the items are looked up in when exporting, any catalog if none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    /// exports rows grouped by and led with the group instead of all rows together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split_by: Option<ExportSplit>,
+    /// exports concealed items in rows marked by `CONCEALED_MARK`, they are left out by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_concealed: bool,
 }
 
 pub async fn query_shipments(
     Query(message): Query<QueryShipmentMessage>,
     State(db): State<Arc<DbClient>>,
 ) -> Result<Json<PagedResponse<Shipment>>> {
+    ensure_page_within_limit(message.page)?;
     let (has_next, outputs) = db
         .query_shipments(
             &message.keyword,
@@ -113,18 +252,38 @@ pub struct GetShipmentByIdMessage {
 
 pub async fn get_shipment_by_id(
     Path(id): Path<Uuid>,
+    Query(query): Query<ItemDetailsQuery>,
     State(db): State<Arc<DbClient>>,
 ) -> Result<Json<Shipment>> {
     let mut output = db.get_shipment_by_id(id.into()).await?;
     output
         .items
         .sort_by(|a, b| a.customer_id.cmp(&b.customer_id));
-    Ok(Json(output.into()))
+    let mut reply: Shipment = output.into();
+    if query.with_item_details {
+        reply.attach_item_details(db.as_ref()).await?;
+    }
+    Ok(Json(reply))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteShipmentQuery {
+    #[serde(default)]
+    cascade_transfers: bool,
 }
 
-#[instrument(name = "delete shipment", skip(id, db, cache, sender))]
+/// transfers are related to the shipment by shipment no.
+/// the delete is rejected if related transfers exist unless `cascadeTransfers` is set,
+/// in that case transfers are deleted first.
+#[instrument(
+    name = "delete shipment",
+    skip(user_info, id, query, db, cache, sender)
+)]
 pub async fn delete_shipment(
+    user_info: UserInfo,
     Path(id): Path<Uuid>,
+    Query(query): Query<DeleteShipmentQuery>,
     State(db): State<Arc<DbClient>>,
     State(cache): State<Arc<dyn OrderCache>>,
     State(sender): State<Arc<Sender<ControlMessage>>>,
@@ -133,13 +292,37 @@ pub async fn delete_shipment(
         "got delete shipment request will delete shipment_id: {}",
         id
     );
+    let shipment = db.get_shipment_by_id(id.into()).await?;
+    ensure_deletable(
+        &user_info,
+        AppPrivateRoute::Shipment,
+        id,
+        shipment.created_at.to_chrono(),
+    )?;
+    let transfers = db
+        .find_mongo_transfer_by_shipment_no(&shipment.shipment_no)
+        .await?;
+    if !transfers.is_empty() {
+        if !query.cascade_transfers {
+            return Err(Error::ShipmentHasTransfers(
+                transfers.iter().map(|t| t.id.to_string()).collect(),
+            ));
+        }
+        for transfer in transfers {
+            info!("delete related transfer id:{}", transfer.id);
+            db.delete_transfer_by_id(transfer.id).await?;
+        }
+        send_control_message(&sender, ControlMessage::RefreshTransferList);
+        send_control_message(&sender, ControlMessage::RefreshInventory);
+        send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    }
     let item_ids = db.delete_shipment(id.into()).await?;
     send_control_message(&sender, ControlMessage::RefreshShipmentList);
     send_control_message(&sender, ControlMessage::RefreshWaitForShipmentItemList);
     for id in item_ids {
         send_control_message(&sender, ControlMessage::RefreshOrderItem(id.into()));
     }
-    send_control_message(&sender, ControlMessage::RefreshOrderItem(Uuid::new_v4()));
+    send_control_message(&sender, ControlMessage::RefreshValidationState);
     cache.clear_orders();
     Ok(StatusCode::OK)
 }
@@ -163,20 +346,75 @@ pub async fn update_shipment_note(
     Ok(StatusCode::OK)
 }
 
+/// item codes of the shipment without a transfer and the other way round,
+/// shipments sharing the shipment no are compared together as same as exports.
+/// the shipment's items to pick grouped by location then item code.
+pub async fn get_shipment_picking_list(
+    Path(shipment_id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<PickingListRow>>> {
+    let shipment = db.get_shipment_by_id(shipment_id.into()).await?;
+    let mut items = shipment
+        .items
+        .into_iter()
+        .map(OrderItem::from)
+        .collect::<Vec<_>>();
+    attach_item_details(db.as_ref(), &mut items).await?;
+    Ok(Json(build_picking_list(&items)))
+}
+
+pub async fn get_shipment_transfer_diff(
+    Path(shipment_id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<TransferDiff>> {
+    let shipment = db.get_shipment_by_id(shipment_id.into()).await?;
+    let shipment_items = db
+        .find_shipments_by_no(&shipment.shipment_no)
+        .await?
+        .into_iter()
+        .flat_map(|shipment| shipment.items)
+        .collect::<Vec<_>>();
+    let transfer_items = db
+        .find_transfer_by_shipment_no(&shipment.shipment_no)
+        .await?
+        .into_iter()
+        .flat_map(|transfer| transfer.items)
+        .collect::<Vec<_>>();
+    Ok(diff_shipment_transfers(&shipment_items, &transfer_items).into())
+}
+
 pub async fn find_shipment_by_no(
     Path(shipment_no): Path<String>,
     State(db): State<Arc<DbClient>>,
-) -> Result<Response> {
+) -> Result<Json<Vec<ShipmentLite>>> {
     let res: Vec<ShipmentLite> = db
         .find_shipment_by_no(&shipment_no)
         .await?
         .into_iter()
         .map(|s| s.into())
         .collect();
-    if res.is_empty() {
-        return Ok(StatusCode::NOT_FOUND.into_response());
-    }
-    Ok(Json(res).into_response())
+    Ok(Json(res))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UnexportedShipmentQuery {
+    #[serde(with = "ts_seconds")]
+    pub from: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    pub to: DateTime<Utc>,
+}
+
+pub async fn find_unexported_shipments(
+    Query(query): Query<UnexportedShipmentQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<ShipmentLite>>> {
+    let res: Vec<ShipmentLite> = db
+        .find_unexported_shipments(query.from, query.to)
+        .await?
+        .into_iter()
+        .map(|s| s.into())
+        .collect();
+    Ok(Json(res))
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -198,6 +436,22 @@ pub async fn update_shipment_status(
     Ok(StatusCode::OK)
 }
 
+#[instrument(name="reopen shipment",skip(user_info,db,sender),fields(
+    request_id = %Uuid::new_v4(),
+    action_by = %user_info.user_id,
+))]
+pub async fn reopen_shipment(
+    user_info: UserInfo,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Path(shipment_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    db.reopen_shipment(shipment_id.into()).await?;
+    send_control_message(&sender, ControlMessage::RefreshShipmentItem(shipment_id));
+    send_control_message(&sender, ControlMessage::RefreshTransferList);
+    Ok(StatusCode::OK)
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateShipmentNoMessage {
@@ -216,6 +470,7 @@ pub async fn update_shipment_no(
 ) -> Result<impl IntoResponse> {
     info!("got request of update shipment no");
     let shipment = db.get_shipment_by_id(shipment_id.into()).await?;
+    validate_shipment_no(&shipment.vendor, &message.shipment_no)?;
     if message.update_related_transfers {
         let transfers = db
             .find_mongo_transfer_by_shipment_no(&shipment.shipment_no)
@@ -293,6 +548,54 @@ pub async fn update_shipment_vendor(
     Ok(StatusCode::OK)
 }
 
+pub async fn find_shipments_by_status(
+    Path(status): Path<ShipmentStatus>,
+    Query(query): Query<UnexportedShipmentQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<ShipmentCounts>>> {
+    let res = db
+        .find_shipments_by_status(status, query.from, query.to)
+        .await?
+        .into_iter()
+        .map(|s| s.into())
+        .collect::<Vec<_>>();
+    Ok(Json(res))
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipmentCounts {
+    id: Uuid,
+    created_at: DateTime<Local>,
+    update_at: DateTime<Local>,
+    shipment_no: String,
+    note: String,
+    vendor: ShipmentVendor,
+    shipment_date: DateTime<Local>,
+    status: ShipmentStatus,
+    exported_at: Option<DateTime<Local>>,
+    item_count: u32,
+    concealed_count: u32,
+}
+
+impl From<MongoShipmentCounts> for ShipmentCounts {
+    fn from(m: MongoShipmentCounts) -> Self {
+        Self {
+            id: m.id.into(),
+            created_at: m.created_at.to_chrono().with_timezone(&Local),
+            update_at: m.update_at.to_chrono().with_timezone(&Local),
+            shipment_no: m.shipment_no,
+            note: m.note,
+            vendor: m.vendor,
+            shipment_date: m.shipment_date.to_chrono().with_timezone(&Local),
+            status: m.status,
+            exported_at: m.exported_at.map(|d| d.to_chrono().with_timezone(&Local)),
+            item_count: m.item_count,
+            concealed_count: m.concealed_count,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ShipmentLite {
@@ -305,6 +608,7 @@ pub struct ShipmentLite {
     shipment_date: DateTime<Local>,
     order_item_ids: Vec<Uuid>,
     status: ShipmentStatus,
+    exported_at: Option<DateTime<Local>>,
 }
 
 impl From<MongoShipment> for ShipmentLite {
@@ -319,6 +623,13 @@ impl From<MongoShipment> for ShipmentLite {
             shipment_date: m.shipment_date.to_chrono().with_timezone(&Local),
             order_item_ids: m.order_item_ids.into_iter().map(|i| i.into()).collect(),
             status: m.status,
+            exported_at: m.exported_at.map(|d| d.to_chrono().with_timezone(&Local)),
         }
     }
 }
+
+pub async fn find_duplicate_shipment_items(
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<DuplicateShipmentItem>>> {
+    Ok(db.find_duplicate_shipment_items().await?.into())
+}
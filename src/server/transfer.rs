@@ -4,8 +4,8 @@ use crate::{
     db::{
         inventory::{InventoryLocation, Quantity},
         mongo::DbClient,
-        shipment::ShipmentVendor,
-        transfer::{MongoTransferItem, MongoTransferOutput},
+        shipment::{validate_shipment_no, ShipmentVendor},
+        transfer::{MongoTransferItem, MongoTransferOutput, TransferSummaryRow},
     },
     error_result::Error,
 };
@@ -27,20 +27,25 @@ use crate::db::TransferRepo;
 use crate::error_result::Result;
 
 use super::{
+    auth::UserInfo,
+    ensure_deletable,
     shipment::ShipmentLite,
     ws::{send_control_message, ControlMessage},
-    AppState,
+    AppPrivateRoute, AppState,
 };
 
 pub fn get_transfer_router() -> Router<AppState> {
     Router::new()
         .route("/", post(create_new_transfer).get(query_transfers))
+        .route("/summary", get(summarize_transfers))
         .route(
             "/:id",
             delete(delete_transfer_by_id).get(find_transfer_by_id),
         )
         .route("/:id/shipments", get(find_shipments_by_id))
         .route("/:id/shipment_no", put(update_transfer_shipment_no))
+        .route("/:id/location", put(update_transfer_location))
+        .route("/:id/repair", post(repair_transfer_by_id))
         .route(
             "/shipment_no/:shipment_no",
             get(find_transfer_by_shipment_no),
@@ -166,16 +171,39 @@ pub struct QueryTransferMessage {
 }
 
 pub async fn delete_transfer_by_id(
+    user_info: UserInfo,
     Path(id): Path<Uuid>,
     State(db): State<Arc<DbClient>>,
     State(sender): State<Arc<Sender<ControlMessage>>>,
 ) -> Result<impl IntoResponse> {
+    let transfer = db.find_transfer_by_id(id.into()).await?;
+    ensure_deletable(
+        &user_info,
+        AppPrivateRoute::Transfer,
+        id,
+        transfer.created_at.to_chrono(),
+    )?;
     db.delete_transfer_by_id(id.into()).await?;
     send_control_message(&sender, ControlMessage::RefreshTransferList);
     send_control_message(&sender, ControlMessage::RefreshInventory);
     send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
     Ok(StatusCode::OK)
 }
+
+pub async fn repair_transfer_by_id(
+    Path(id): Path<Uuid>,
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+) -> Result<Json<Vec<TransferItem>>> {
+    let compensations = db.repair_transfer_by_id(id.into()).await?;
+    if !compensations.is_empty() {
+        send_control_message(&sender, ControlMessage::RefreshTransferList);
+        send_control_message(&sender, ControlMessage::RefreshInventory);
+        send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    }
+    Ok(Json(compensations.into_iter().map(|c| c.into()).collect()))
+}
+
 pub async fn query_transfers(
     Query(message): Query<QueryTransferMessage>,
     State(db): State<Arc<DbClient>>,
@@ -190,6 +218,22 @@ pub async fn query_transfers(
         .into())
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransferSummaryQuery {
+    #[serde(with = "ts_seconds")]
+    pub from: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    pub to: DateTime<Utc>,
+}
+
+/// monthly units moved by each vendor, for logistics planning.
+pub async fn summarize_transfers(
+    Query(query): Query<TransferSummaryQuery>,
+    State(db): State<Arc<DbClient>>,
+) -> Result<Json<Vec<TransferSummaryRow>>> {
+    Ok(db.summarize_transfers(query.from, query.to).await?.into())
+}
+
 pub async fn find_shipments_by_id(
     Path(id): Path<Uuid>,
     State(db): State<Arc<DbClient>>,
@@ -244,6 +288,7 @@ pub async fn update_transfer_shipment_no(
 ) -> Result<impl IntoResponse> {
     info!("got request of update transfer's shipment no");
     let transfer = db.find_transfer_by_id(transfer_id.into()).await?;
+    validate_shipment_no(&transfer.shipment_vendor, &message.shipment_no)?;
     match message.update_related_transfers {
         true => {
             db.update_transfers_shipment_no(&transfer.shipment_no, &message.shipment_no)
@@ -258,3 +303,33 @@ pub async fn update_transfer_shipment_no(
     send_control_message(&sender, ControlMessage::RefreshTransferList);
     Ok(StatusCode::OK)
 }
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTransferLocationMessage {
+    new_location: InventoryLocation,
+}
+
+/// correct the destination of a transfer's operations without touching its vendor.
+pub async fn update_transfer_location(
+    State(db): State<Arc<DbClient>>,
+    State(sender): State<Arc<Sender<ControlMessage>>>,
+    Path(transfer_id): Path<Uuid>,
+    Json(message): Json<UpdateTransferLocationMessage>,
+) -> Result<impl IntoResponse> {
+    info!("got request of update transfer's location");
+    if message.new_location == InventoryLocation::JP {
+        return Err(Error::InvalidOperation);
+    }
+    let transfer = db.find_transfer_by_id(transfer_id.into()).await?;
+    if transfer.shipment_vendor.is_clearance_vendor() && !message.new_location.is_paid() {
+        return Err(Error::VenderLocationNotMatch);
+    }
+    db.update_transfer_location_by_transfer_id(transfer_id.into(), message.new_location)
+        .await?;
+    info!("done request!");
+    send_control_message(&sender, ControlMessage::RefreshTransferList);
+    send_control_message(&sender, ControlMessage::RefreshInventory);
+    send_control_message(&sender, ControlMessage::RefreshInventoryItemQuantity);
+    Ok(StatusCode::OK)
+}
@@ -1,5 +1,11 @@
-use crate::error_result::Result;
-use std::{sync::Arc, time::Duration};
+use crate::{
+    error_result::{Error, Result},
+    server::auth::SETTINGS,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use axum::{
     extract::{
@@ -11,10 +17,14 @@ use axum::{
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
+use tokio::sync::{
+    broadcast::{error::RecvError, Receiver, Sender},
+    OwnedSemaphorePermit, Semaphore,
+};
+use tracing::warn;
 use uuid::Uuid;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ControlMessage {
     RefreshOrderList,
     RefreshInventory,
@@ -29,13 +39,31 @@ pub enum ControlMessage {
     RefreshWaitForShipmentItemList,
     RefreshNewShipmentBucket(Uuid),
     RefreshShipmentItem(Uuid),
+    /// ask the client to re-render the validating state of order items after a check.
+    RefreshValidationState,
+    /// ask the client to reload everything, sent when it missed some messages
+    /// or an admin asks every client to reload, e.g. after a data migration.
+    ResyncAll,
 }
 
 pub async fn handle_ws(
     ws: WebSocketUpgrade,
     State(orders_sender): State<Arc<Sender<ControlMessage>>>,
+    State(ws_permits): State<Arc<Semaphore>>,
 ) -> Result<impl IntoResponse> {
-    Ok(ws.on_upgrade(|socket| handle_subscribe_change(socket, orders_sender)))
+    let permit = acquire_ws_permit(&ws_permits)?;
+    Ok(ws.on_upgrade(|socket| async move {
+        handle_subscribe_change(socket, orders_sender).await;
+        drop(permit);
+    }))
+}
+
+/// a permit is held by each control socket until it is closed.
+pub fn acquire_ws_permit(ws_permits: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit> {
+    ws_permits
+        .clone()
+        .try_acquire_owned()
+        .map_err(|_| Error::TooManyConnections(SETTINGS.ws.max_connections))
 }
 #[derive(Serialize, Deserialize)]
 struct WsMsg {
@@ -59,14 +87,52 @@ enum WsEvent {
     RefreshInventoryItemQuantity,
     RefreshWaitForShipmentItemList,
     RefreshNewShipmentBucket,
+    RefreshValidationState,
+    ResyncAll,
+}
+
+impl From<ControlMessage> for WsMsg {
+    fn from(message: ControlMessage) -> Self {
+        let (event, message) = match message {
+            ControlMessage::Ping => (WsEvent::Ping, String::new()),
+            ControlMessage::Pong => (WsEvent::Pong, String::new()),
+            ControlMessage::RefreshOrderList => (WsEvent::RefreshOrderList, String::new()),
+            ControlMessage::RefreshInventory => (WsEvent::RefreshInventory, String::new()),
+            ControlMessage::RefreshOrderItem(id) => (WsEvent::RefreshOrderItem, id.to_string()),
+            ControlMessage::RefreshShipmentList => (WsEvent::RefreshShipmentList, String::new()),
+            ControlMessage::RefreshShipmentItem(id) => {
+                (WsEvent::RefreshShipmentItem, id.to_string())
+            }
+            ControlMessage::RefreshRegisterList => (WsEvent::RefreshRegisterList, String::new()),
+            ControlMessage::RefreshReturnList => (WsEvent::RefreshReturnList, String::new()),
+            ControlMessage::RefreshTransferList => (WsEvent::RefreshTransferList, String::new()),
+            ControlMessage::RefreshInventoryItemQuantity => {
+                (WsEvent::RefreshInventoryItemQuantity, String::new())
+            }
+            ControlMessage::RefreshWaitForShipmentItemList => {
+                (WsEvent::RefreshWaitForShipmentItemList, String::new())
+            }
+            ControlMessage::RefreshNewShipmentBucket(id) => {
+                (WsEvent::RefreshNewShipmentBucket, id.to_string())
+            }
+            ControlMessage::RefreshValidationState => {
+                (WsEvent::RefreshValidationState, String::new())
+            }
+            ControlMessage::ResyncAll => (WsEvent::ResyncAll, String::new()),
+        };
+        WsMsg { event, message }
+    }
 }
 
 pub async fn handle_subscribe_change(stream: WebSocket, sender: Arc<Sender<ControlMessage>>) {
     let mut rx = sender.subscribe();
     let cloned_sender = sender.clone();
     let (mut ws_sender, mut ws_receiver) = stream.split();
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let recv_last_seen = last_seen.clone();
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(Message::Text(text))) = ws_receiver.next().await {
+            *recv_last_seen.lock().unwrap() = Instant::now();
             // Add username before message.
             let msg = serde_json::from_str::<WsMsg>(&text).unwrap();
             if let WsEvent::Ping = msg.event {
@@ -76,209 +142,27 @@ pub async fn handle_subscribe_change(stream: WebSocket, sender: Arc<Sender<Contr
             };
         }
     });
+    let idle_timeout = Duration::from_secs(SETTINGS.ws.idle_timeout_secs);
     let mut ping_task = tokio::spawn(async move {
         while sender.send(ControlMessage::Ping).is_ok() {
             tokio::time::sleep(Duration::from_secs(20)).await;
+            if last_seen.lock().unwrap().elapsed() > idle_timeout {
+                warn!("control socket has been idle over {idle_timeout:?}, close it");
+                break;
+            }
         }
     });
+    let debounce = Duration::from_millis(SETTINGS.ws.debounce_ms);
     let mut send_task = tokio::spawn(async move {
-        while let Ok(message) = rx.recv().await {
-            tokio::time::sleep(Duration::from_millis(10)).await;
-            match message {
-                ControlMessage::Ping => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::Ping,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshOrderList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshOrderList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshInventory => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshInventory,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::Pong => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::Pong,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshOrderItem(id) => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshOrderItem,
-                                message: id.to_string(),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshShipmentList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshShipmentList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshShipmentItem(id) => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshShipmentItem,
-                                message: id.to_string(),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshRegisterList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshRegisterList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshReturnList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshReturnList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshTransferList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshTransferList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshInventoryItemQuantity => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshInventoryItemQuantity,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshWaitForShipmentItemList => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshWaitForShipmentItemList,
-                                message: String::from(""),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
-                }
-                ControlMessage::RefreshNewShipmentBucket(id) => {
-                    if ws_sender
-                        .send(Message::Text(
-                            json!(WsMsg {
-                                event: WsEvent::RefreshNewShipmentBucket,
-                                message: id.to_string(),
-                            })
-                            .to_string(),
-                        ))
-                        .await
-                        .is_err()
-                    {
-                        break;
-                    }
+        'send: while let Some(messages) = recv_control_messages_debounced(&mut rx, debounce).await {
+            for message in messages {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                if ws_sender
+                    .send(Message::Text(json!(WsMsg::from(message)).to_string()))
+                    .await
+                    .is_err()
+                {
+                    break 'send;
                 }
             }
         }
@@ -292,6 +176,37 @@ pub async fn handle_subscribe_change(stream: WebSocket, sender: Arc<Sender<Contr
     println!("closing connection...");
 }
 
+/// receive the next message for a subscriber. `None` means the channel is closed.
+/// a lagged subscriber is asked to resync instead of being disconnected.
+pub async fn recv_control_message(rx: &mut Receiver<ControlMessage>) -> Option<ControlMessage> {
+    match rx.recv().await {
+        Ok(message) => Some(message),
+        Err(RecvError::Lagged(count)) => {
+            warn!("subscriber lagged behind {count} messages, ask it to resync");
+            Some(ControlMessage::ResyncAll)
+        }
+        Err(RecvError::Closed) => None,
+    }
+}
+
+/// receive the next messages for a subscriber, messages arriving within `window` after the
+/// first one are batched with it and repeated ones are dropped, so a burst of the same
+/// refresh reaches the client once. `None` means the channel is closed.
+pub async fn recv_control_messages_debounced(
+    rx: &mut Receiver<ControlMessage>,
+    window: Duration,
+) -> Option<Vec<ControlMessage>> {
+    let mut messages = vec![recv_control_message(rx).await?];
+    let deadline = tokio::time::Instant::now() + window;
+    while let Ok(Some(message)) = tokio::time::timeout_at(deadline, recv_control_message(rx)).await
+    {
+        if !messages.contains(&message) {
+            messages.push(message);
+        }
+    }
+    Some(messages)
+}
+
 #[inline]
 pub fn send_control_message(sender: &Arc<Sender<ControlMessage>>, message: ControlMessage) {
     if sender.receiver_count() != 0 && sender.send(message).is_err() {
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    db::{
+        inventory::LocationAudit, mongo::DbClient, shipment::ShipmentInconsistency, InventoryRepo,
+        ShipmentRepo,
+    },
+    error_result::Result,
+    server::auth::SETTINGS,
+};
+
+/// Find shipments diverged from their order items and repair them if `repair` is true.
+/// returns what was found.
+#[instrument(name = "run consistency check", skip(db))]
+pub async fn run_consistency_check(
+    db: &DbClient,
+    repair: bool,
+) -> Result<Vec<ShipmentInconsistency>> {
+    let inconsistencies = db.find_shipment_inconsistencies().await?;
+    if inconsistencies.is_empty() {
+        info!("shipments are consistent with order items");
+        return Ok(inconsistencies);
+    }
+    for inconsistency in inconsistencies.iter() {
+        warn!("found shipment inconsistency: {inconsistency:?}");
+        if repair {
+            db.repair_shipment_inconsistency(inconsistency).await?;
+        }
+    }
+    info!(
+        "{} shipment inconsistencies found, repaired: {repair}",
+        inconsistencies.len()
+    );
+    Ok(inconsistencies)
+}
+
+/// Find inventory items not holding every location exactly once,
+/// missing locations are backfilled with 0 if `backfill` is true.
+#[instrument(name = "run location audit", skip(db))]
+pub async fn run_location_audit(db: &DbClient, backfill: bool) -> Result<Vec<LocationAudit>> {
+    let audits = db.audit_inventory_locations(backfill).await?;
+    for audit in audits.iter() {
+        warn!(
+            "inventory {} misses locations {:?}, duplicates locations {:?}",
+            audit.item_code_ext, audit.missing, audit.duplicated
+        );
+    }
+    info!(
+        "{} inventory items failed location audit, backfilled: {backfill}",
+        audits.len()
+    );
+    Ok(audits)
+}
+
+/// Spawn the consistency check once at startup if it is enabled in settings.
+pub fn spawn_consistency_check_task(db: Arc<DbClient>) {
+    let setting = &SETTINGS.consistency_check;
+    if !setting.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        if let Err(e) = run_consistency_check(&db, SETTINGS.consistency_check.repair).await {
+            error!("consistency check failed: {e}");
+        }
+        if let Err(e) = run_location_audit(&db, SETTINGS.consistency_check.repair).await {
+            error!("location audit failed: {e}");
+        }
+    });
+}
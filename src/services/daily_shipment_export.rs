@@ -0,0 +1,103 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{prelude::*, Duration as ChronoDuration};
+use mongodb::bson::Uuid;
+use tracing::{error, info, instrument};
+
+use crate::{
+    db::{mongo::DbClient, ShipmentRepo},
+    error_result::Result,
+    server::{
+        auth::SETTINGS,
+        export::{build_query_shipment_rows, upload_query_shipment_export},
+    },
+};
+
+/// Collect the shipments of the local `day` and build the same rows as `export_shipments`.
+/// returns the ids of the collected shipments together with the rows.
+pub async fn daily_shipment_rows(
+    db: &DbClient,
+    day: NaiveDate,
+) -> Result<(Vec<Uuid>, Vec<Vec<String>>)> {
+    let from = local_day_start(day);
+    let to = local_day_start(day + ChronoDuration::days(1)) - ChronoDuration::milliseconds(1);
+    let (_, shipments) = db.query_shipments("", from, to, "", "", None).await?;
+    let shipment_ids = shipments.iter().map(|s| s.id).collect::<Vec<_>>();
+    let rows =
+        build_query_shipment_rows(db, &shipments, None, SETTINGS.shipment.default_rounding).await?;
+    Ok((shipment_ids, rows))
+}
+
+/// Export the shipments of the local `day` to the configured destination.
+/// returns the download url, or none if there is no shipment on the day.
+#[instrument(name = "run daily shipment export", skip(db, http_client))]
+pub async fn run_daily_shipment_export(
+    db: &DbClient,
+    http_client: &reqwest::Client,
+    day: NaiveDate,
+) -> Result<Option<String>> {
+    let (shipment_ids, rows) = daily_shipment_rows(db, day).await?;
+    if shipment_ids.is_empty() {
+        info!("no shipment on {day}, skip export");
+        return Ok(None);
+    }
+    let filename = format!(
+        "{}年{}月{}日出荷一覧.xlsx",
+        day.year(),
+        day.month(),
+        day.day()
+    );
+    let url = upload_query_shipment_export(
+        http_client,
+        &filename,
+        rows,
+        SETTINGS.daily_shipment_export.destination.as_deref(),
+    )
+    .await?;
+    db.mark_shipments_exported(&shipment_ids).await?;
+    info!("daily shipment export of {day} uploaded: {url}");
+    Ok(Some(url))
+}
+
+/// Spawn the daily shipment export task if it is enabled in settings.
+pub fn spawn_daily_shipment_export_task(db: Arc<DbClient>, http_client: Arc<reqwest::Client>) {
+    let setting = &SETTINGS.daily_shipment_export;
+    if !setting.enabled {
+        return;
+    }
+    info!("daily shipment export task started: at = {}", setting.at);
+    tokio::spawn(async move {
+        loop {
+            let next = next_run_at(Local::now(), SETTINGS.daily_shipment_export.at);
+            let wait = (next - Local::now())
+                .to_std()
+                .unwrap_or(Duration::from_secs(0));
+            tokio::time::sleep(wait).await;
+            if let Err(e) = run_daily_shipment_export(&db, &http_client, next.date_naive()).await {
+                error!("daily shipment export failed: {e}");
+            }
+        }
+    });
+}
+
+/// the start of `day` in the local timezone.
+pub(crate) fn local_day_start(day: NaiveDate) -> DateTime<Utc> {
+    local_datetime(day.and_hms_opt(0, 0, 0).unwrap()).with_timezone(&Utc)
+}
+
+fn next_run_at(now: DateTime<Local>, at: NaiveTime) -> DateTime<Local> {
+    let today = local_datetime(now.date_naive().and_time(at));
+    if today > now {
+        return today;
+    }
+    local_datetime((now.date_naive() + ChronoDuration::days(1)).and_time(at))
+}
+
+// a local time may be skipped or repeated around a DST transition,
+// take the earliest one and fall back to reading it as utc.
+fn local_datetime(naive: NaiveDateTime) -> DateTime<Local> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
@@ -1,9 +1,12 @@
 use serde::Serialize;
 use std::sync::Arc;
-use tracing::{error, instrument};
+use tracing::instrument;
 use uuid::Uuid;
 
-use crate::server::auth::SETTINGS;
+use crate::{
+    error_result::{Error, Result},
+    server::auth::SETTINGS,
+};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -40,35 +43,29 @@ impl GoogleService {
         list_name: String,
         title: String,
         notes: String,
-    ) {
-        let http_client = self.http_client.clone();
-        tokio::task::spawn(async move {
-            let notify_task = NotifyTask {
-                list_name,
-                title,
-                notes,
-            };
-            let body = InsertTaskRequestBody {
-                user_ex_id,
-                task: notify_task,
-            };
-            let res = http_client
-                .post(format!(
-                    "{}/google/insert_task",
-                    SETTINGS.google_service.get_service_url()
-                ))
-                .json(&body)
-                .send()
-                .await;
-            if res.is_err() {
-                error!("http error:{:?}", res.unwrap());
-                return;
-            }
-            let resp = res.unwrap();
-            if resp.status().as_u16() >= 400 {
-                let err = resp.text().await.unwrap();
-                error!("http got bad response error: {}", err)
-            }
-        });
+    ) -> Result<()> {
+        let notify_task = NotifyTask {
+            list_name,
+            title,
+            notes,
+        };
+        let body = InsertTaskRequestBody {
+            user_ex_id,
+            task: notify_task,
+        };
+        let resp = self
+            .http_client
+            .post(format!(
+                "{}/google/insert_task",
+                SETTINGS.google_service.get_service_url()
+            ))
+            .json(&body)
+            .send()
+            .await?;
+        if resp.status().as_u16() >= 400 {
+            let err = resp.text().await?;
+            return Err(Error::HttpResponse(err));
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,75 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::sync::broadcast::Sender;
+use tracing::{error, info, instrument};
+
+use crate::{
+    cache::OrderCache,
+    db::{mongo::DbClient, order::MongoOrderItem, OrderRepo},
+    error_result::Result,
+    server::{
+        auth::SETTINGS,
+        ws::{send_control_message, ControlMessage},
+    },
+};
+
+/// Revert guaranteed order items which were not updated in `expiry_days` days back to
+/// backordering, then notify clients.
+#[instrument(name = "run guarantee expiry", skip(db, cache, sender))]
+pub async fn run_guarantee_expiry(
+    db: &DbClient,
+    cache: &Arc<dyn OrderCache>,
+    sender: &Arc<Sender<ControlMessage>>,
+    expiry_days: u32,
+) -> Result<Vec<MongoOrderItem>> {
+    let before = Utc::now() - chrono::Duration::days(expiry_days as i64);
+    let expired = db.expire_stale_guaranteed_items(before).await?;
+    if expired.is_empty() {
+        return Ok(expired);
+    }
+    info!("{} guaranteed order items expired", expired.len());
+    for item in expired.iter() {
+        send_control_message(sender, ControlMessage::RefreshOrderItem(item.id.into()));
+    }
+    send_control_message(sender, ControlMessage::RefreshOrderList);
+    send_control_message(sender, ControlMessage::RefreshInventory);
+    send_control_message(sender, ControlMessage::RefreshInventoryItemQuantity);
+    send_control_message(sender, ControlMessage::RefreshWaitForShipmentItemList);
+    cache.clear_orders();
+    Ok(expired)
+}
+
+/// Spawn the periodic guarantee expiry task if it is enabled in settings.
+pub fn spawn_guarantee_expiry_task(
+    db: Arc<DbClient>,
+    cache: Arc<dyn OrderCache>,
+    sender: Arc<Sender<ControlMessage>>,
+) {
+    let setting = &SETTINGS.guarantee_expiry;
+    if !setting.enabled {
+        return;
+    }
+    info!(
+        "guarantee expiry task started: expiry days = {} interval = {}s",
+        setting.guarantee_expiry_days, setting.check_interval_secs
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            SETTINGS.guarantee_expiry.check_interval_secs,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_guarantee_expiry(
+                &db,
+                &cache,
+                &sender,
+                SETTINGS.guarantee_expiry.guarantee_expiry_days,
+            )
+            .await
+            {
+                error!("guarantee expiry failed: {e}");
+            }
+        }
+    });
+}
@@ -1 +1,5 @@
+pub mod consistency_check;
+pub mod daily_shipment_export;
 pub mod google_service;
+pub mod guarantee_expiry;
+pub mod notifier;
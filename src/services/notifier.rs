@@ -0,0 +1,254 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+use tracing::{error, info, instrument};
+
+use crate::{
+    configuration::{NotifierBackend, NotifierRetrySetting},
+    db::{mongo::DbClient, notification::MongoPendingNotification, NotificationRepo},
+    error_result::Result,
+    server::auth::SETTINGS,
+};
+
+use super::google_service::GoogleService;
+
+/// a task to be handled by staff, e.g. a shipped item to be taken back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub notes: String,
+}
+
+impl Notification {
+    /// a shipped item of the customer was concealed or deleted.
+    pub fn shipped_item_removed(item_code_ext: &str, customer_id: &str, note: &str) -> Self {
+        Self {
+            title: item_code_ext.to_owned(),
+            notes: format!("顧客名:{},メモ:{}", customer_id, note),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync + 'static {
+    /// should not block the caller, failures are only logged.
+    async fn notify(&self, notification: Notification);
+}
+
+/// a service notifications are delivered through, unlike `Notifier` its failures are returned.
+#[async_trait]
+pub trait NotificationTransport: Send + Sync + 'static {
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+#[async_trait]
+impl NotificationTransport for GoogleService {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        self.call_notify(
+            SETTINGS.google_service.target_user_ex_id,
+            SETTINGS.google_service.task_list_name.clone(),
+            notification.title.clone(),
+            notification.notes.clone(),
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// stops calling a failing service for a while once it failed `failure_threshold` times in a row.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_for,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.open_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            state.consecutive_failures = 0;
+            state.open_until = Some(Instant::now() + self.open_for);
+        }
+    }
+}
+
+/// retries a transport with backoff, notifications still failing or refused by an open
+/// circuit are queued to the `pending_notifications` collection for `retry_pending`.
+#[derive(Clone)]
+pub struct RetryingNotifier {
+    transport: Arc<dyn NotificationTransport>,
+    db: Arc<DbClient>,
+    breaker: Arc<CircuitBreaker>,
+    max_attempts: u32,
+    backoff: Duration,
+    max_pending_attempts: u32,
+}
+
+impl RetryingNotifier {
+    pub fn new(
+        transport: Arc<dyn NotificationTransport>,
+        db: Arc<DbClient>,
+        setting: &NotifierRetrySetting,
+    ) -> Self {
+        Self {
+            transport,
+            db,
+            breaker: Arc::new(CircuitBreaker::new(
+                setting.failure_threshold,
+                Duration::from_secs(setting.open_secs),
+            )),
+            max_attempts: setting.max_attempts.max(1),
+            backoff: Duration::from_millis(setting.backoff_ms),
+            max_pending_attempts: setting.max_pending_attempts.max(1),
+        }
+    }
+
+    /// send the notification, queue it if every attempt failed.
+    #[instrument(name = "deliver notification", skip(self))]
+    pub async fn deliver(&self, notification: Notification) -> Result<()> {
+        let mut backoff = self.backoff;
+        for attempt in 1..=self.max_attempts {
+            if self.breaker.is_open() {
+                info!("notification circuit is open");
+                break;
+            }
+            match self.transport.send(&notification).await {
+                Ok(()) => {
+                    self.breaker.record_success();
+                    return Ok(());
+                }
+                Err(e) => {
+                    error!("notification attempt {attempt} failed: {e}");
+                    self.breaker.record_failure();
+                }
+            }
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        self.db
+            .insert_pending_notification(&MongoPendingNotification::new(&notification))
+            .await
+    }
+
+    /// send queued notifications once each, returns how many were delivered.
+    /// a notification failing its `max_pending_attempts`th retry is dropped.
+    #[instrument(name = "retry pending notifications", skip(self))]
+    pub async fn retry_pending(&self) -> Result<usize> {
+        let mut delivered = 0;
+        for pending in self.db.find_pending_notifications(100).await? {
+            if self.breaker.is_open() {
+                break;
+            }
+            let id = pending.id;
+            let attempts = pending.attempts + 1;
+            let notification = Notification::from(pending);
+            match self.transport.send(&notification).await {
+                Ok(()) => {
+                    self.breaker.record_success();
+                    self.db.delete_pending_notification(id).await?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    error!("pending notification {id} failed again: {e}");
+                    self.breaker.record_failure();
+                    if attempts >= self.max_pending_attempts {
+                        error!(
+                            "pending notification {id} dropped after {attempts} retries: {:?}",
+                            notification
+                        );
+                        self.db.delete_pending_notification(id).await?;
+                    } else {
+                        self.db.increment_pending_notification_attempts(id).await?;
+                    }
+                }
+            }
+        }
+        Ok(delivered)
+    }
+}
+
+#[async_trait]
+impl Notifier for RetryingNotifier {
+    async fn notify(&self, notification: Notification) {
+        let notifier = self.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = notifier.deliver(notification).await {
+                error!("failed to queue notification: {e}");
+            }
+        });
+    }
+}
+
+/// Spawn the task retrying queued notifications.
+pub fn spawn_pending_notification_task(notifier: RetryingNotifier) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            SETTINGS.notifier_retry.retry_interval_secs,
+        ));
+        loop {
+            interval.tick().await;
+            match notifier.retry_pending().await {
+                Ok(0) => {}
+                Ok(delivered) => info!("{delivered} pending notifications delivered"),
+                Err(e) => error!("retrying pending notifications failed: {e}"),
+            }
+        }
+    });
+}
+
+/// for deployments without a notification service.
+#[derive(Debug, Default)]
+pub struct NoopNotifier;
+
+#[async_trait]
+impl Notifier for NoopNotifier {
+    async fn notify(&self, notification: Notification) {
+        info!("notification dropped: {:?}", notification);
+    }
+}
+
+pub fn notifier_from_settings(backend: NotifierBackend, db: Arc<DbClient>) -> Arc<dyn Notifier> {
+    match backend {
+        NotifierBackend::Google => {
+            let notifier = RetryingNotifier::new(
+                Arc::new(GoogleService::default()),
+                db,
+                &SETTINGS.notifier_retry,
+            );
+            spawn_pending_notification_task(notifier.clone());
+            Arc::new(notifier)
+        }
+        NotifierBackend::Noop => Arc::new(NoopNotifier),
+    }
+}
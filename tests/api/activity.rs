@@ -0,0 +1,38 @@
+use oism_server::db::{activity::ActivityKind, ActivityItem};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn recent_activity_interleaves_by_created_at_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order1 = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order1.items[0].id])
+        .await;
+    let order2 = app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/activity"))
+        .query(&[("limit", 10)])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let activities: Vec<ActivityItem> = response.json().await.expect("Failed to deserialize json");
+    let feed = activities
+        .iter()
+        .map(|a| (a.kind, a.id.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        feed,
+        vec![
+            (ActivityKind::Order, order2.id.to_string()),
+            (ActivityKind::Shipment, shipment_id.to_string()),
+            (ActivityKind::Order, order1.id.to_string()),
+        ]
+    );
+    app.cleanup().await;
+}
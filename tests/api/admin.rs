@@ -0,0 +1,193 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{extract::State, Json};
+use chrono::{Duration, Local, NaiveTime, Utc};
+use mongodb::bson::{doc, Document};
+use oism_server::{
+    cache::{MapCache, OrderCache},
+    configuration::OrderQueryPreset,
+    db::{
+        auth::UserRole,
+        inventory::{InventoryLocation, LocationAudit},
+        mongo::INVENTORY_COL,
+        InventoryRepo,
+    },
+    server::{
+        admin::{broadcast_refresh, preset_query, warm_caches},
+        auth::{UserInfo, SETTINGS},
+        ws::ControlMessage,
+    },
+};
+
+use crate::helpers::spawn_app;
+
+#[test]
+fn preset_query_ranges_over_local_days() {
+    let preset = OrderQueryPreset {
+        keyword: String::new(),
+        status: String::new(),
+        days: 7,
+        page: None,
+    };
+    let now = Utc::now();
+    let message = preset_query(&preset, now);
+    let from = message.from.with_timezone(&Local);
+    let to = message.to.with_timezone(&Local);
+    let today = now.with_timezone(&Local).date_naive();
+    assert_eq!(from.date_naive(), today - Duration::days(7));
+    assert_eq!(from.time(), NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+    assert_eq!(to.date_naive(), today);
+    assert_eq!(to + Duration::seconds(1), from + Duration::days(8));
+}
+
+#[tokio::test]
+async fn clear_caches_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let now = Utc::now();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/orders"))
+        .query(&[
+            ("keyword", "".to_string()),
+            ("status", "".to_string()),
+            ("from", (now - Duration::days(1)).timestamp().to_string()),
+            ("to", (now + Duration::days(1)).timestamp().to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let clear = || async {
+        let response = app
+            .request_client
+            .post(format!("{private_base_uri}/admin/cache/clear"))
+            .send()
+            .await
+            .expect("Failed to request");
+        assert_eq!(200, response.status().as_u16());
+        let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+        body["evicted"].as_u64().expect("evicted should be number")
+    };
+    assert_eq!(clear().await, 1);
+    // the orders cache is empty now, nothing left to evict.
+    assert_eq!(clear().await, 0);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn audit_inventory_locations_backfills_missing_location() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.db
+        .ph_db
+        .collection::<Document>(INVENTORY_COL)
+        .update_one(
+            doc! {"item_code_ext":"A2121FSY00991"},
+            doc! {"$pull":{"quantity":{"location":"pcn"}}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper inventory");
+    let private_base_uri = &app.private_base_uri();
+    let client = &app.request_client;
+    let audit = |backfill: &'static str| async move {
+        let response = client
+            .post(format!("{private_base_uri}/admin/inventory/location_audit"))
+            .query(&[("backfill", backfill)])
+            .send()
+            .await
+            .expect("Failed to request");
+        assert_eq!(200, response.status().as_u16());
+        response
+            .json::<Vec<LocationAudit>>()
+            .await
+            .expect("Failed to deserialize")
+    };
+    let audits = audit("false").await;
+    assert_eq!(audits.len(), 1);
+    assert_eq!(audits[0].item_code_ext, "A2121FSY00991");
+    assert_eq!(audits[0].missing, vec![InventoryLocation::PCN]);
+    assert!(audits[0].duplicated.is_empty());
+    assert!(!audits[0].backfilled);
+    let audits = audit("true").await;
+    assert_eq!(audits.len(), 1);
+    assert!(audits[0].backfilled);
+    assert!(audit("false").await.is_empty());
+    let inventory = app
+        .db
+        .find_inventory_by_item_code_ext("A2121FSY00991")
+        .await
+        .expect("Failed to find inventory")
+        .expect("inventory should exist");
+    let pcn = inventory
+        .quantity
+        .iter()
+        .find(|q| q.location == InventoryLocation::PCN)
+        .expect("pcn should be backfilled");
+    assert_eq!(pcn.quantity, 0);
+    assert_eq!(inventory.quantity.len(), 3);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn broadcast_refresh_reaches_subscribers() {
+    let (tx, mut rx) = tokio::sync::broadcast::channel::<ControlMessage>(8);
+    let user_info = UserInfo {
+        user_id: uuid::Uuid::new_v4(),
+        role: UserRole::Editor,
+        sub_role: HashMap::new(),
+    };
+    let Json(output) = broadcast_refresh(user_info, State(Arc::new(tx)))
+        .await
+        .expect("Failed to broadcast refresh");
+    assert_eq!(output.receivers, 1);
+    let message = rx.recv().await.expect("Failed to receive message");
+    assert!(matches!(message, ControlMessage::ResyncAll));
+}
+
+#[tokio::test]
+async fn warm_caches_fills_presets() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let presets = &SETTINGS.cache_warm.presets;
+    assert!(!presets.is_empty());
+    let cache = MapCache::new();
+    let warm = || async {
+        let user_info = UserInfo {
+            user_id: uuid::Uuid::new_v4(),
+            role: UserRole::Editor,
+            sub_role: HashMap::new(),
+        };
+        let Json(output) = warm_caches(
+            user_info,
+            State(Arc::new(app.db.clone())),
+            State(cache.clone() as Arc<dyn OrderCache>),
+        )
+        .await
+        .expect("Failed to warm caches");
+        output.warmed
+    };
+    assert_eq!(warm().await, presets.len());
+    let now = Utc::now();
+    for preset in presets.iter() {
+        assert!(cache.contains_orders(&preset_query(preset, now)));
+    }
+    let all = preset_query(
+        presets
+            .iter()
+            .find(|preset| preset.status.is_empty())
+            .expect("a preset of every status"),
+        now,
+    );
+    assert_eq!(cache.get_orders(&all).map(|orders| orders.len()), Some(1));
+    // every preset is cached already.
+    assert_eq!(warm().await, 0);
+    app.cleanup().await;
+}
@@ -1,3 +1,8 @@
+use oism_server::db::{
+    auth::{QueryPreset, UserRole},
+    SMTAuthDataBase,
+};
+
 use crate::helpers::spawn_app;
 
 #[tokio::test]
@@ -127,3 +132,312 @@ async fn private_health_check_works() {
         .expect("Failed to execute request");
     assert!(response.status().is_success())
 }
+
+#[tokio::test]
+async fn error_with_envelope_works() {
+    let app = spawn_app().await;
+    let private_base_uri = format!("{}/api/v1/private", app.address);
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/health_check"))
+        .query(&[("envelope", "true")])
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(401, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(body["ok"], false);
+    assert_eq!(body["error"]["code"], 401);
+    assert_eq!(body["error"]["message"], "CookieHeaderNotFound");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn token_signed_by_previous_secret_works() {
+    let app = spawn_app().await;
+    let private_base_uri = format!("{}/api/v1/private", app.address);
+    app.signup_and_login().await;
+    let user_info: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/user_info"))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let claims = serde_json::json!({
+        "user_id":user_info["id"],
+        "exp":chrono::Utc::now().timestamp() + 60,
+    });
+    // configuration/local.yaml has this as a previous secret
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS512),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"previous_access_token_secret"),
+    )
+    .unwrap();
+    let response = reqwest::Client::new()
+        .get(format!("{private_base_uri}/health_check"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+    let unknown_token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS512),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"unknown_secret"),
+    )
+    .unwrap();
+    let response = reqwest::Client::new()
+        .get(format!("{private_base_uri}/health_check"))
+        .bearer_auth(unknown_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(401, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn signup_with_invite_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let token = app.create_invite(serde_json::json!({})).await;
+    let public_base_uri = app.public_base_uri();
+    let body = serde_json::json!({
+        "username":"invited",
+        "password":"123456",
+        "role":"viewer",
+        "inviteToken":token,
+    });
+    let response1 = app
+        .request_client
+        .post(format!("{public_base_uri}/signup"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response1.status().as_u16());
+    let response2 = app
+        .request_client
+        .post(format!("{public_base_uri}/login"))
+        .json(&serde_json::json!({"username":"invited","password":"123456"}))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response2.status().is_success());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn signup_with_used_invite_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let token = app.create_invite(serde_json::json!({})).await;
+    let public_base_uri = app.public_base_uri();
+    for (username, status) in [("invited1", 201), ("invited2", 400)] {
+        let body = serde_json::json!({
+            "username":username,
+            "password":"123456",
+            "role":"viewer",
+            "inviteToken":token,
+        });
+        let response = app
+            .request_client
+            .post(format!("{public_base_uri}/signup"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(status, response.status().as_u16());
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn signup_with_invite_preset_role_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let token = app
+        .create_invite(serde_json::json!({"role":"viewer"}))
+        .await;
+    let public_base_uri = app.public_base_uri();
+    let body = serde_json::json!({
+        "username":"invited",
+        "password":"123456",
+        "role":"full",
+        "inviteToken":token,
+    });
+    let response = app
+        .request_client
+        .post(format!("{public_base_uri}/signup"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+    let user = app
+        .db
+        .find_user_by_username("invited")
+        .await
+        .expect("invited user should exist");
+    assert_eq!(user.role, UserRole::Viewer);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn signup_with_invite_ignores_requested_role() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let token = app.create_invite(serde_json::json!({})).await;
+    let public_base_uri = app.public_base_uri();
+    let body = serde_json::json!({
+        "username":"invited",
+        "password":"123456",
+        "role":"full",
+        "subRole":{"orders":"full"},
+        "inviteToken":token,
+    });
+    let response = app
+        .request_client
+        .post(format!("{public_base_uri}/signup"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(201, response.status().as_u16());
+    let user = app
+        .db
+        .find_user_by_username("invited")
+        .await
+        .expect("invited user should exist");
+    assert_eq!(user.role, UserRole::Visitor);
+    assert!(user.sub_role.is_empty());
+    app.cleanup().await;
+}
+
+#[test]
+fn query_preset_round_trips_without_unset_options() {
+    let preset = serde_json::json!({
+        "name":"ems",
+        "kind":"shipments",
+        "query":{
+            "keyword":"",
+            "from":1672531200,
+            "to":1675209599,
+            "status":"",
+            "vendor":"ems",
+            "page":null,
+        },
+    });
+    let parsed: QueryPreset =
+        serde_json::from_value(preset.clone()).expect("Failed to deserialize preset");
+    assert_eq!(serde_json::to_value(parsed).unwrap(), preset);
+}
+
+#[tokio::test]
+async fn save_and_get_query_presets_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let presets = serde_json::json!([
+        {
+            "name":"backordering this month",
+            "kind":"orders",
+            "query":{
+                "keyword":"",
+                "status":"backordering",
+                "from":1672531200,
+                "to":1675209599,
+                "page":null,
+            },
+        },
+        {
+            "name":"ems",
+            "kind":"shipments",
+            "query":{
+                "keyword":"",
+                "from":1672531200,
+                "to":1675209599,
+                "status":"",
+                "vendor":"ems",
+                "page":null,
+            },
+        },
+    ]);
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/user_info/presets"))
+        .json(&presets)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/user_info/presets"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let saved: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(saved, presets);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn detailed_health_check_works() {
+    let app = spawn_app().await;
+    let private_base_uri = app.private_base_uri();
+    app.signup_and_login().await;
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/health_check/detailed"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+    let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["gitSha"].is_string());
+    assert_eq!(body["db"], "ok");
+    // no export utility is running in tests
+    assert_eq!(body["utility"], "err");
+    assert!(["ok", "err"].contains(&body["google"].as_str().unwrap()));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn recent_resources_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let order1 = app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    let order2 = app.create_order("customer2", &[("A2121FSY00991", 1)]).await;
+    for id in [order1.id, order2.id] {
+        let response = app
+            .request_client
+            .get(format!("{private_base_uri}/orders/{id}"))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert!(response.status().is_success());
+    }
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/user_info/recent"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let recent: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    let recent = recent.as_array().expect("recent should be an array");
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent[0]["kind"], "order");
+    assert_eq!(recent[0]["id"], order2.id.to_string());
+    assert_eq!(recent[1]["id"], order1.id.to_string());
+    app.cleanup().await;
+}
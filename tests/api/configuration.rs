@@ -0,0 +1,99 @@
+use mongodb::options::{Acknowledgment, ReadConcern, WriteConcern};
+use oism_server::{
+    configuration::{AssetsSetting, ConcernSetting, RoundingStrategy, ShipmentSetting},
+    db::shipment::ShipmentVendor,
+};
+
+fn concern_from_yaml(yaml: &str) -> ConcernSetting {
+    config::Config::builder()
+        .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+        .build()
+        .expect("Failed to build config")
+        .try_deserialize()
+        .expect("Failed to deserialize concern setting")
+}
+
+#[test]
+fn transaction_options_from_config_works() {
+    let options =
+        concern_from_yaml("read: local\nwrite: \"1\"\njournal: true\n").transaction_options();
+    assert_eq!(options.read_concern, Some(ReadConcern::local()));
+    assert_eq!(
+        options.write_concern,
+        Some(
+            WriteConcern::builder()
+                .w(Acknowledgment::Nodes(1))
+                .journal(true)
+                .build()
+        )
+    );
+
+    let options = ConcernSetting::default().transaction_options();
+    assert_eq!(options.read_concern, Some(ReadConcern::majority()));
+    assert_eq!(
+        options.write_concern,
+        Some(WriteConcern::builder().w(Acknowledgment::Majority).build())
+    );
+}
+
+#[test]
+fn rounding_strategy_works() {
+    for (value, floor, half_up, ceil) in [
+        (100.5, 100, 101, 101),
+        (100.4, 100, 100, 101),
+        (100.6, 100, 101, 101),
+        // float noise must not push an exact value to the next integer
+        (1000.0 * 0.7, 700, 700, 700),
+    ] {
+        assert_eq!(RoundingStrategy::Floor.apply(value), floor);
+        assert_eq!(RoundingStrategy::HalfUp.apply(value), half_up);
+        assert_eq!(RoundingStrategy::Ceil.apply(value), ceil);
+    }
+}
+
+#[test]
+fn legacy_rounding_keeps_previous_results() {
+    assert_eq!(RoundingStrategy::default(), RoundingStrategy::Legacy);
+    // discounted prices: round if the fraction is below .5, otherwise ceil
+    assert_eq!(RoundingStrategy::Legacy.apply(100.4), 100);
+    assert_eq!(RoundingStrategy::Legacy.apply(100.5), 101);
+    assert_eq!(RoundingStrategy::Legacy.apply(1000.0 * 0.7), 700);
+    // totals are always rounded up
+    assert_eq!(RoundingStrategy::Legacy.apply_total(834.15), 835);
+    assert_eq!(RoundingStrategy::Legacy.apply_total(834.0), 834);
+    assert_eq!(RoundingStrategy::HalfUp.apply_total(834.15), 834);
+}
+
+#[test]
+fn rounding_for_vendor_works() {
+    let setting: ShipmentSetting = config::Config::builder()
+        .add_source(config::File::from_str(
+            "default_rounding: floor\nrounding:\n  ems: ceil\n",
+            config::FileFormat::Yaml,
+        ))
+        .build()
+        .expect("Failed to build config")
+        .try_deserialize()
+        .expect("Failed to deserialize shipment setting");
+    assert_eq!(
+        setting.rounding_for(Some(&ShipmentVendor::Ems)),
+        RoundingStrategy::Ceil
+    );
+    assert_eq!(setting.rounding_for(None), RoundingStrategy::Floor);
+}
+
+#[test]
+fn image_url_template_works() {
+    let setting = AssetsSetting::default();
+    assert_eq!(
+        setting.image_url("A2121FSY066", "3"),
+        "https://d2vg6jg1lu9m12.cloudfront.net/A2121FSY066_3.jpeg"
+    );
+    let setting = AssetsSetting {
+        image_url_template: String::from("https://cdn.example.com/items/{code}/{color}.webp"),
+    };
+    assert_eq!(
+        setting.image_url("A2121FSY066", "3"),
+        "https://cdn.example.com/items/A2121FSY066/3.webp"
+    );
+}
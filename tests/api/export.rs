@@ -0,0 +1,746 @@
+use chrono::{Duration, Local, Utc};
+use mongodb::bson::{doc, Document};
+use oism_server::{
+    configuration::RoundingStrategy,
+    db::{
+        invenope::{MongoInventoryOperation, MongoOperationType, RelatedKind},
+        inventory::InventoryLocation,
+        mongo::{ITEMS_COL, ORDER_ITEMS_COL},
+        order::{OrderItemRate, OrderItemStatus},
+        shipment::ShipmentVendor,
+        OrderRepo, PhDataBase, PhItem, ShipmentRepo,
+    },
+    server::{
+        auth::SETTINGS,
+        export::{
+            build_invoice_rows, build_operation_rows, build_shipment_rows, build_vendor_sheets,
+            flatten_sheets, rows_to_csv, stringify_rate, ExportSheet, CONCEALED_MARK,
+        },
+    },
+    services::daily_shipment_export::daily_shipment_rows,
+};
+
+use crate::helpers::spawn_app;
+
+#[test]
+fn stringify_fractional_rate_works() {
+    assert_eq!(stringify_rate(1.0), "-");
+    assert_eq!(stringify_rate(0.7), "30%Off");
+    assert_eq!(stringify_rate(0.875), "12.5%Off");
+}
+
+#[test]
+fn flatten_sheets_leads_rows_with_sheet_name() {
+    let sheets = vec![
+        ExportSheet {
+            name: "ems".to_string(),
+            rows: vec![vec!["A2121FSY009".to_string(), "2".to_string()]],
+        },
+        ExportSheet {
+            name: "yy".to_string(),
+            rows: vec![vec!["A2121FSY066".to_string(), "1".to_string()]],
+        },
+    ];
+    assert_eq!(
+        flatten_sheets(sheets),
+        [["ems", "A2121FSY009", "2"], ["yy", "A2121FSY066", "1"],]
+    );
+}
+
+#[test]
+fn clamp_rate_works() {
+    assert_eq!(OrderItemRate::clamp(0.7), 0.7);
+    assert_eq!(OrderItemRate::clamp(1.0), 1.0);
+    assert_eq!(OrderItemRate::clamp(0.0), 1.0);
+    assert_eq!(OrderItemRate::clamp(-0.5), 1.0);
+    assert_eq!(OrderItemRate::clamp(7.0), 1.0);
+    assert_eq!(OrderItemRate::clamp(f64::NAN), 1.0);
+}
+
+#[test]
+fn discounted_price_rounding_works() {
+    // 1005 * 0.9 = 904.5
+    let item = PhItem::new_dummy("A2121FSY06693", 1005);
+    assert_eq!(item.get_discounted_price(0.9, RoundingStrategy::Floor), 904);
+    assert_eq!(
+        item.get_discounted_price(0.9, RoundingStrategy::HalfUp),
+        905
+    );
+    assert_eq!(item.get_discounted_price(0.9, RoundingStrategy::Ceil), 905);
+}
+
+#[test]
+fn item_type_label_in_locales_works() {
+    let item_type_map = &SETTINGS.item_type_map;
+    assert_eq!(item_type_map.get_label("FB_", Some("zh")), Some("衬衫"));
+    assert_eq!(item_type_map.get_label("FB_", Some("en")), Some("shirt"));
+    // fallback to the default locale
+    assert_eq!(item_type_map.get_label("FB_", None), Some("衬衫"));
+    assert_eq!(item_type_map.get_label("FB_", Some("fr")), Some("衬衫"));
+    assert_eq!(item_type_map.get_label("XXX", Some("en")), None);
+}
+
+#[tokio::test]
+async fn build_shipment_preview_rows_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY06693", 1)])
+        .await;
+    assert!(order
+        .items
+        .iter()
+        .all(|item| item.status == OrderItemStatus::Guaranteed));
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let items = app
+        .db
+        .get_shippable_order_items(&ids)
+        .await
+        .expect("Failed to get order items");
+    let rows = build_shipment_rows(
+        &app.db,
+        &items,
+        &[],
+        Some("en"),
+        None,
+        RoundingStrategy::HalfUp,
+    )
+    .await
+    .expect("Failed to build rows");
+    assert_eq!(rows.len(), 2);
+    let row = rows
+        .iter()
+        .find(|row| row[7].starts_with("A2121FSY009"))
+        .expect("row of A2121FSY009 should exist");
+    assert_eq!(row[1], "skirt");
+    assert_eq!(row[2], "2");
+    // the only unit is guaranteed above, so this one is back ordering.
+    let backordering = app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/export_preview"))
+        .json(&serde_json::json!({ "itemIds": [backordering.items[0].id] }))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(409, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn build_operation_rows_works() {
+    let app = spawn_app().await;
+    let related_id = mongodb::bson::Uuid::new();
+    let arrival = MongoInventoryOperation::new(
+        "A2121FSY00991",
+        related_id,
+        MongoOperationType::Arrival,
+        3,
+        InventoryLocation::JP,
+    );
+    arrival
+        .insert_self(&app.db)
+        .await
+        .expect("Failed to insert operation");
+    let ordered = MongoInventoryOperation::new(
+        "A2121FSY00991",
+        related_id,
+        MongoOperationType::Ordered,
+        -1,
+        InventoryLocation::CN,
+    );
+    ordered
+        .insert_self(&app.db)
+        .await
+        .expect("Failed to insert operation");
+    let from = Utc::now() - Duration::hours(1);
+    let to = Utc::now() + Duration::hours(1);
+    let rows = build_operation_rows(&app.db, from, to, None, None)
+        .await
+        .expect("Failed to build rows");
+    assert_eq!(rows.len(), 2);
+    assert_eq!(
+        rows[0][1..],
+        [
+            "A2121FSY00991".to_string(),
+            "arrival".to_string(),
+            "3".to_string(),
+            "日本".to_string(),
+            related_id.to_string(),
+        ]
+    );
+    assert_eq!(rows[1][2], "ordered");
+    assert_eq!(rows[1][3], "-1");
+    let rows = build_operation_rows(&app.db, from, to, Some(MongoOperationType::Ordered), None)
+        .await
+        .expect("Failed to build rows");
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][4], "中国");
+    let rows = build_operation_rows(&app.db, to, to + Duration::hours(1), None, None)
+        .await
+        .expect("Failed to build rows");
+    assert!(rows.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn build_operation_rows_filtered_by_related_kind_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let transfer_id = app
+        .create_transfer("EJ123456789JP", "A2121FSY07292", [1, 1, 0])
+        .await;
+    let from = Utc::now() - Duration::hours(1);
+    let to = Utc::now() + Duration::hours(1);
+    let all = build_operation_rows(&app.db, from, to, None, None)
+        .await
+        .expect("Failed to build rows");
+    let rows = build_operation_rows(&app.db, from, to, None, Some(RelatedKind::Transfer))
+        .await
+        .expect("Failed to build rows");
+    assert_eq!(rows.len(), 2);
+    assert!(rows.len() < all.len());
+    assert!(rows.iter().all(|row| row[5] == transfer_id.to_string()));
+    let rows = build_operation_rows(&app.db, from, to, None, Some(RelatedKind::Return))
+        .await
+        .expect("Failed to build rows");
+    assert!(rows.is_empty());
+    app.cleanup().await;
+}
+
+#[test]
+fn rows_to_csv_quotes_fields() {
+    let rows = vec![
+        vec!["A2121FSY00991".to_string(), "日本".to_string()],
+        vec!["a,b".to_string(), "say \"hi\"".to_string()],
+    ];
+    assert_eq!(
+        rows_to_csv(&rows),
+        "A2121FSY00991,日本\r\n\"a,b\",\"say \"\"hi\"\"\"\r\n"
+    );
+}
+
+#[tokio::test]
+async fn export_inventory_operations_as_csv_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let from = Utc::now() - Duration::hours(1);
+    let to = Utc::now() + Duration::hours(1);
+    let rows = build_operation_rows(&app.db, from, to, None, None)
+        .await
+        .expect("Failed to build rows");
+    assert!(!rows.is_empty());
+    let expected = rows_to_csv(&rows);
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/operations/export"))
+        .query(&[
+            ("from", from.timestamp().to_string()),
+            ("to", to.timestamp().to_string()),
+            ("format", "csv".to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let body = response.text().await.expect("Failed to read body");
+    assert_eq!(body, expected);
+    let lines = body.trim_end().split("\r\n").collect::<Vec<_>>();
+    assert_eq!(lines.len(), rows.len());
+    assert_eq!(lines[0].split(',').count(), rows[0].len());
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/operations/export"))
+        .query(&[
+            ("from", from.timestamp().to_string()),
+            ("to", to.timestamp().to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers()["content-disposition"]
+        .to_str()
+        .unwrap()
+        .ends_with(".csv"));
+    assert_eq!(
+        response.text().await.expect("Failed to read body"),
+        expected
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn daily_shipment_rows_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY06693", 1)])
+        .await;
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let shipment_id = app.create_shipment("EM123456789JP", "ems", &ids).await;
+    let today = Local::now().date_naive();
+    let (shipment_ids, rows) = daily_shipment_rows(&app.db, today)
+        .await
+        .expect("Failed to build rows");
+    assert_eq!(shipment_ids, vec![shipment_id.into()]);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][0], "A2121FSY009");
+    assert_eq!(rows[0][5], "2");
+    assert_eq!(rows[1][0], "A2121FSY066");
+    assert_eq!(rows[1][5], "1");
+    let (shipment_ids, rows) = daily_shipment_rows(&app.db, today + Duration::days(1))
+        .await
+        .expect("Failed to build rows");
+    assert!(shipment_ids.is_empty());
+    assert!(rows.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn build_vendor_sheets_partitions_rows_by_vendor() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order(
+            "customer1",
+            &[
+                ("A2121FSY00991", 2),
+                ("A2121FSY06693", 1),
+                ("A2121FSY07292", 1),
+            ],
+        )
+        .await;
+    let ems_ids = order
+        .items
+        .iter()
+        .filter(|item| item.item_code_ext != "A2121FSY06693")
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    let yy_ids = order
+        .items
+        .iter()
+        .filter(|item| item.item_code_ext == "A2121FSY06693")
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    app.create_shipment("EM123456789JP", "ems", &ems_ids).await;
+    app.create_shipment("YY0001", "yy", &yy_ids).await;
+    let now = Utc::now();
+    let (_, shipments) = app
+        .db
+        .query_shipments(
+            "",
+            now - Duration::days(1),
+            now + Duration::days(1),
+            "",
+            "",
+            None,
+        )
+        .await
+        .expect("Failed to query shipments");
+    assert_eq!(shipments.len(), 2);
+    let sheets = build_vendor_sheets(&app.db, &shipments, None, false)
+        .await
+        .expect("Failed to build sheets");
+    let partition = sheets
+        .iter()
+        .map(|sheet| {
+            let codes = sheet
+                .rows
+                .iter()
+                .map(|row| (row[0].as_str(), row[5].as_str()))
+                .collect::<Vec<_>>();
+            (sheet.name.as_str(), codes)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        partition,
+        [
+            ("ems", vec![("A2121FSY009", "2"), ("A2121FSY072", "1")]),
+            ("yy", vec![("A2121FSY066", "1")]),
+        ]
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn unconfigured_vendor_totals_are_rounded_up() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    // 1106 is 1005 without tax
+    PhItem::new_dummy("A2121FSY00991", 1106)
+        .insert_self(&app.db)
+        .await
+        .expect("Failed to insert item");
+    let order = app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_many(doc! {"id":{"$in":&ids}}, doc! {"$set":{"rate":0.83}}, None)
+        .await
+        .expect("Failed to set rate");
+    app.create_shipment("EM123456789JP", "ems", &ids).await;
+    assert_eq!(
+        SETTINGS.shipment.rounding_for(Some(&ShipmentVendor::Ems)),
+        RoundingStrategy::Legacy
+    );
+    let now = Utc::now();
+    let (_, shipments) = app
+        .db
+        .query_shipments(
+            "",
+            now - Duration::days(1),
+            now + Duration::days(1),
+            "",
+            "",
+            None,
+        )
+        .await
+        .expect("Failed to query shipments");
+    let sheets = build_vendor_sheets(&app.db, &shipments, None, false)
+        .await
+        .expect("Failed to build sheets");
+    // 2 * 1005 * 0.83 = 1668.3, rounded up as before rounding was configurable
+    assert_eq!(sheets[0].rows[0][5], "2");
+    assert_eq!(sheets[0].rows[0][7], "1669");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn build_invoice_rows_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order1 = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY06693", 1)])
+        .await;
+    let order2 = app.create_order("customer2", &[("A2121FSY07292", 2)]).await;
+    let ids = order1
+        .items
+        .iter()
+        .chain(order2.items.iter())
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    let shipment_id = app.create_shipment("EM123456789JP", "ems", &ids).await;
+    let shipment = app
+        .db
+        .get_shipment_by_id(shipment_id.into())
+        .await
+        .expect("Failed to get shipment");
+    assert_eq!(shipment.items.len(), 5);
+    let rows = build_invoice_rows(
+        &app.db,
+        &shipment.items,
+        " Customer1 ",
+        Some("en"),
+        None,
+        RoundingStrategy::HalfUp,
+    )
+    .await
+    .expect("Failed to build rows");
+    // three items and the total row
+    assert_eq!(rows.len(), 4);
+    assert!(rows[..3].iter().all(|row| row[1] != "A2121FSY072"));
+    assert_eq!(rows[0][2], "skirt");
+    assert_eq!(rows[0][6], "-");
+    let total = rows[..3]
+        .iter()
+        .map(|row| row[7].parse::<u32>().expect("price should be a number"))
+        .sum::<u32>();
+    assert_eq!(rows[3][7], total.to_string());
+    let rows = build_invoice_rows(
+        &app.db,
+        &shipment.items,
+        "customer3",
+        None,
+        None,
+        RoundingStrategy::HalfUp,
+    )
+    .await
+    .expect("Failed to build rows");
+    assert!(rows.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn export_shipment_invoice_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    for (code, price) in [("A2121FSY00991", 11000), ("A2121FSY06693", 5500)] {
+        PhItem::new_dummy(code, price)
+            .insert_self(&app.db)
+            .await
+            .expect("Failed to insert item");
+    }
+    let order1 = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY06693", 1)])
+        .await;
+    let order2 = app.create_order("customer2", &[("A2121FSY07292", 2)]).await;
+    let ids = order1
+        .items
+        .iter()
+        .chain(order2.items.iter())
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    let shipment_id = app.create_shipment("EM123456789JP", "ems", &ids).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}/invoice"))
+        .query(&[("customerId", "customer1"), ("locale", "en")])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.headers()["content-disposition"]
+        .to_str()
+        .unwrap()
+        .ends_with("_invoice.csv"));
+    let body = response.text().await.expect("Failed to read body");
+    let rows = body
+        .trim_end()
+        .split("\r\n")
+        .map(|line| line.split(',').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    // three items of customer1 and the total row
+    assert_eq!(rows.len(), 4);
+    let codes_and_prices = rows[..3]
+        .iter()
+        .map(|row| (row[0], row[1], row[5], row[7]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes_and_prices,
+        vec![
+            ("1", "A2121FSY009", "10000", "10000"),
+            ("2", "A2121FSY009", "10000", "10000"),
+            ("3", "A2121FSY066", "5000", "5000"),
+        ]
+    );
+    assert_eq!(rows[3][6], "合计");
+    assert_eq!(rows[3][7], "25000");
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}/invoice"))
+        .query(&[("customerId", "customer3")])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(404, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_item_by_code_in_bucket_works() {
+    let app = spawn_app().await;
+    for (bucket, price) in [("bucket_a", 1100), ("bucket_b", 2200)] {
+        let mut item = PhItem::new_dummy("B2121FSY00991", price);
+        item.bucket = bucket.to_string();
+        item.insert_self(&app.db)
+            .await
+            .expect("Failed to insert item");
+    }
+    let item = app
+        .db
+        .find_one_by_item_code("B2121FSY009", Some("bucket_b"))
+        .await
+        .expect("Failed to find item")
+        .expect("item should exist");
+    assert_eq!(item.bucket, "bucket_b");
+    assert_eq!(item.price, 2200);
+    let item = app
+        .db
+        .find_one_by_item_code("B2121FSY009", Some("bucket_a"))
+        .await
+        .expect("Failed to find item")
+        .expect("item should exist");
+    assert_eq!(item.price, 1100);
+    assert!(app
+        .db
+        .find_one_by_item_code("B2121FSY009", Some("bucket_c"))
+        .await
+        .expect("Failed to find item")
+        .is_none());
+    // no bucket keeps matching by code only
+    assert!(app
+        .db
+        .find_one_by_item_code("B2121FSY009", None)
+        .await
+        .expect("Failed to find item")
+        .is_some());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn invalid_rate_is_listed_and_clamped_in_export() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let shipment_id = app
+        .create_shipment("EM123456789JP", "ems", &[item_id])
+        .await;
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(doc! {"id":item_id}, doc! {"$set":{"rate":0.0}}, None)
+        .await
+        .expect("Failed to update order item");
+    let invalid: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items/invalid_rates"))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(invalid[0]["id"], item_id.to_string());
+    let shipment = app
+        .db
+        .get_shipment_by_id(shipment_id.into())
+        .await
+        .expect("Failed to get shipment");
+    let rows = build_invoice_rows(
+        &app.db,
+        &shipment.items,
+        "customer1",
+        None,
+        None,
+        RoundingStrategy::HalfUp,
+    )
+    .await
+    .expect("Failed to build rows");
+    // exported as no discount instead of a zero total.
+    assert_eq!(rows[0][6], "-");
+    assert_eq!(rows[0][7], rows[0][5]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn export_inventory_by_category_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let items = vec![
+        doc! {"code":"A2121FSY066","item_name":"top1","category":"トップス"},
+        doc! {"code":"A2121FSY009","item_name":"top2","category":"トップス"},
+        doc! {"code":"A2121FSY072","item_name":"skirt1","category":"スカート"},
+    ];
+    app.db
+        .ph_db
+        .collection::<Document>(ITEMS_COL)
+        .insert_many(items, None)
+        .await
+        .expect("Failed to insert items");
+    let private_base_uri = app.private_base_uri();
+    let export = |category: Option<&'static str>| {
+        let mut query = vec![("location", "jp"), ("format", "csv")];
+        query.extend(category.map(|category| ("category", category)));
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/inventory/export"))
+            .query(&query);
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            let body = response.text().await.expect("Failed to read body");
+            let mut codes = body
+                .trim_end()
+                .split("\r\n")
+                .map(|line| line.split(',').nth(1).unwrap_or_default().to_string())
+                .collect::<Vec<_>>();
+            codes.sort();
+            codes
+        }
+    };
+    assert_eq!(export(Some("skirt")).await, ["A2121FSY072"]);
+    assert_eq!(export(Some("top")).await, ["A2121FSY009", "A2121FSY066"]);
+    // every category is exported without the parameter.
+    assert_eq!(
+        export(None).await,
+        ["A2121FSY009", "A2121FSY066", "A2121FSY072"]
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn export_shipments_includes_concealed_items_only_if_requested() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 1), ("A2121FSY06693", 1)])
+        .await;
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    app.create_shipment("EM123456789JP", "ems", &ids).await;
+    let concealed = order
+        .items
+        .iter()
+        .find(|item| item.item_code_ext == "A2121FSY06693")
+        .expect("item not found");
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(
+            doc! {"id":concealed.id},
+            doc! {"$set":{"status":OrderItemStatus::Concealed}},
+            None,
+        )
+        .await
+        .expect("Failed to conceal order item");
+    let private_base_uri = app.private_base_uri();
+    let from = (Utc::now() - Duration::days(1)).timestamp().to_string();
+    let to = (Utc::now() + Duration::days(1)).timestamp().to_string();
+    let export = |include_concealed: &'static str| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/shipment/export"))
+            .query(&[
+                ("keyword", ""),
+                ("from", from.as_str()),
+                ("to", to.as_str()),
+                ("status", ""),
+                ("vendor", ""),
+                ("format", "csv"),
+                ("includeConcealed", include_concealed),
+            ]);
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            let body = response.text().await.expect("Failed to read body");
+            body.trim_end()
+                .split("\r\n")
+                .map(|line| line.split(',').map(String::from).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        }
+    };
+    let rows = export("false").await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][0], "A2121FSY009");
+    assert_eq!(rows[0][4], "");
+    let rows = export("true").await;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][0], "A2121FSY009");
+    assert_eq!(rows[0][4], "");
+    assert_eq!(rows[1][0], "A2121FSY066");
+    assert_eq!(rows[1][4], CONCEALED_MARK);
+    app.cleanup().await;
+}
@@ -1,7 +1,11 @@
 use chrono::{DateTime, Utc};
 use fake::Fake;
 use oism_server::{
-    db::{mongo::DbClient, RegisterItemInput, StockRegisterInput},
+    db::{
+        inventory::{InventoryLocation, Quantity},
+        mongo::DbClient,
+        Order, PhItem, RegisterItemInput, StockRegisterInput,
+    },
     telemetry::{get_subscriber, init_subscriber},
 };
 use once_cell::sync::Lazy;
@@ -106,6 +110,24 @@ impl TestApp {
         assert!(response2.cookies().any(|c| c.name() == "smt_id"));
     }
 
+    /// mint an invite by the logged in user and return its token.
+    pub async fn create_invite(&self, body: serde_json::Value) -> String {
+        let private_base_uri = self.private_base_uri();
+        let response = self
+            .request_client
+            .post(format!("{private_base_uri}/invites"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed execute request");
+        assert_eq!(201, response.status().as_u16());
+        let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+        body["token"]
+            .as_str()
+            .expect("invite token should be string")
+            .to_string()
+    }
+
     pub async fn register_inventory(&self) -> (Vec<(String, u32)>, DateTime<Utc>) {
         let item_seeds = vec![
             ("A2121FSY06693".to_string(), 1),
@@ -126,6 +148,153 @@ impl TestApp {
         (item_seeds, create_register_res.register_time)
     }
 
+    /// insert catalog items of `(item_code, price)`, looked up by exports and prices.
+    pub async fn seed_catalog(&self, items: &[(&str, u32)]) {
+        for (item_code, price) in items {
+            PhItem::new_dummy(item_code, *price)
+                .insert_self(&self.db)
+                .await
+                .expect("Failed to insert item");
+        }
+    }
+
+    /// create an order which requests `count` of each item at jp location.
+    pub async fn create_order(&self, customer_id: &str, items: &[(&str, u32)]) -> Order {
+        let taobao_order_no = format!("20230101{:020}", (0..u64::MAX).fake::<u64>());
+        let body = order_input(&taobao_order_no, customer_id, items);
+        let private_base_uri = self.private_base_uri();
+        let response1 = self
+            .request_client
+            .post(format!("{private_base_uri}/orders/"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed execute request");
+        assert_eq!(201, response1.status().as_u16());
+        let response2 = self
+            .request_client
+            .get(format!(
+                "{private_base_uri}/orders/taobao_no/{taobao_order_no}"
+            ))
+            .send()
+            .await
+            .expect("Failed execute request");
+        assert!(response2.status().is_success());
+        let mut orders: Vec<Order> = response2.json().await.expect("Failed to deserialize json");
+        orders.remove(0)
+    }
+
+    pub async fn create_shipment(
+        &self,
+        shipment_no: &str,
+        vendor: &str,
+        item_ids: &[mongodb::bson::Uuid],
+    ) -> uuid::Uuid {
+        let private_base_uri = self.private_base_uri();
+        let body = serde_json::json!({
+            "shipmentNo":shipment_no,
+            "note":"",
+            "vendor":vendor,
+            "shipmentDate":Utc::now().timestamp(),
+            "itemIds":item_ids,
+        });
+        let response1 = self
+            .request_client
+            .post(format!("{private_base_uri}/shipment/"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed execute request");
+        assert_eq!(201, response1.status().as_u16());
+        let shipments: Vec<serde_json::Value> = self
+            .request_client
+            .get(format!("{private_base_uri}/shipment/by_no/{shipment_no}"))
+            .send()
+            .await
+            .expect("Failed execute request")
+            .json()
+            .await
+            .expect("Failed to deserialize json");
+        shipments[0]["id"]
+            .as_str()
+            .expect("shipment id should be string")
+            .parse()
+            .expect("Failed to parse shipment id")
+    }
+
+    /// move the item's inventory to given quantities of jp, cn and pcn.
+    pub async fn create_transfer(
+        &self,
+        shipment_no: &str,
+        item_code_ext: &str,
+        quantity: [u32; 3],
+    ) -> uuid::Uuid {
+        let private_base_uri = self.private_base_uri();
+        let body = serde_json::json!({
+            "shipmentNo":shipment_no,
+            "note":"",
+            "transferDate":Utc::now().timestamp(),
+            "shipmentVendor":"ems",
+            "toLocation":"cn",
+            "items":[{
+                "itemCodeExt":item_code_ext,
+                "quantity":[
+                    {"location":"jp","quantity":quantity[0]},
+                    {"location":"cn","quantity":quantity[1]},
+                    {"location":"pcn","quantity":quantity[2]},
+                ],
+            }],
+        });
+        let response1 = self
+            .request_client
+            .post(format!("{private_base_uri}/transfer/"))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed execute request");
+        assert_eq!(201, response1.status().as_u16());
+        let transfers: Vec<serde_json::Value> = self
+            .request_client
+            .get(format!(
+                "{private_base_uri}/transfer/shipment_no/{shipment_no}"
+            ))
+            .send()
+            .await
+            .expect("Failed execute request")
+            .json()
+            .await
+            .expect("Failed to deserialize json");
+        transfers.last().expect("transfer should be created")["id"]
+            .as_str()
+            .expect("transfer id should be string")
+            .parse()
+            .expect("Failed to parse transfer id")
+    }
+
+    pub async fn get_inventory_quantity(
+        &self,
+        item_code_ext: &str,
+        location: InventoryLocation,
+    ) -> u32 {
+        let private_base_uri = self.private_base_uri();
+        let quantity: Vec<Quantity> = self
+            .request_client
+            .get(format!(
+                "{private_base_uri}/inventory/quantity/{item_code_ext}"
+            ))
+            .send()
+            .await
+            .expect("Failed execute request")
+            .json()
+            .await
+            .expect("Failed to deserialize json");
+        quantity
+            .into_iter()
+            .find(|q| q.location == location)
+            .map(|q| q.quantity)
+            .unwrap_or(0)
+    }
+
     pub async fn cleanup(self) {
         self.db
             .ph_db
@@ -159,3 +328,36 @@ fn create_register_input(inputs: &[(String, u32)]) -> CreateRegisterRes {
         },
     }
 }
+
+/// build an order register input which requests `count` of each item at jp location.
+pub fn order_input(
+    taobao_order_no: &str,
+    customer_id: &str,
+    items: &[(&str, u32)],
+) -> serde_json::Value {
+    let items = items
+        .iter()
+        .map(|(item_code_ext, count)| {
+            serde_json::json!({
+                "itemCodeExt":item_code_ext,
+                "rate":1.0,
+                "quantity":[
+                    {"location":"jp","quantity":count},
+                    {"location":"cn","quantity":0},
+                    {"location":"pcn","quantity":0},
+                ],
+                "price":(1000..50000).fake::<u32>(),
+                "isManual":false,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!(
+        {
+            "taobaoOrderNo":taobao_order_no,
+            "customerId":customer_id,
+            "note":"",
+            "items":items,
+            "orderDatetime":Utc::now().timestamp(),
+        }
+    )
+}
@@ -1,4 +1,17 @@
-use oism_server::db::{inventory::InventoryLocation, InventoryOutput};
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use oism_server::{
+    db::{
+        invenope::{MongoInventoryOperation, MongoOperationType},
+        inventory::{
+            GuaranteeMismatch, InventoryLocation, LocationInfo, NegativeBalance, LOCATIONS,
+        },
+        mongo::{INVENTORY_COL, ITEMS_COL, OPERATIONS_COL, REGISTERS_COL},
+        InventoryOperation, InventoryOutput, OperationType, Register, ReorderPoint, SupplySource,
+    },
+    server::inventory::OperationTypeLabel,
+};
+use strum::IntoEnumIterator;
 
 use crate::helpers::spawn_app;
 
@@ -32,3 +45,741 @@ async fn query_inventory_works() {
     }
     app.cleanup().await;
 }
+
+#[tokio::test]
+async fn query_inventory_with_envelope_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let (registered, _) = app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let query = vec![
+        ("keyword", ""),
+        ("category", ""),
+        ("showZeroQuantity", "true"),
+        ("envelope", "true"),
+    ];
+    let response1 = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response1.status().is_success());
+    let body: serde_json::Value = response1.json().await.expect("Failed to deserialize json");
+    assert_eq!(body["ok"], true);
+    let inventory: Vec<InventoryOutput> =
+        serde_json::from_value(body["data"]["data"].clone()).expect("Failed to deserialize data");
+    assert_eq!(inventory.len(), registered.len());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_operations_by_related_id_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let (registered, register_time) = app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let registers: Vec<Register> = app
+        .request_client
+        .get(format!("{private_base_uri}/registers/"))
+        .query(&[
+            ("from", register_time.timestamp() - 100),
+            ("to", register_time.timestamp() + 100),
+        ])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let register_id = registers[0].id;
+    let response1 = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/inventory/operations/by_related/{register_id}"
+        ))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response1.status().is_success());
+    let operations: Vec<InventoryOperation> =
+        response1.json().await.expect("Failed to deserialize json");
+    assert_eq!(operations.len(), registered.len());
+    for operation in operations.iter() {
+        assert_eq!(operation.related_id, register_id);
+        assert!(registered
+            .iter()
+            .any(|(code, count)| *code == operation.item_code_ext
+                && *count as i32 == operation.count));
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_inventory_with_facets_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let items = vec![
+        doc! {"code":"A2121FSY066","item_name":"top1","category":"トップス"},
+        doc! {"code":"A2121FSY009","item_name":"top2","category":"トップス"},
+        doc! {"code":"A2121FSY072","item_name":"skirt1","category":"スカート"},
+    ];
+    app.db
+        .ph_db
+        .collection::<Document>(ITEMS_COL)
+        .insert_many(items, None)
+        .await
+        .expect("Failed to insert items");
+    let private_base_uri = app.private_base_uri();
+    let query = vec![
+        ("keyword", ""),
+        ("category", "skirt"),
+        ("showZeroQuantity", "true"),
+        ("page", "0"),
+        ("withFacets", "true"),
+    ];
+    let response1 = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response1.status().is_success());
+    let body: serde_json::Value = response1.json().await.expect("Failed to deserialize json");
+    let inventory: Vec<InventoryOutput> =
+        serde_json::from_value(body["data"].clone()).expect("Failed to deserialize data");
+    assert_eq!(inventory.len(), 1);
+    assert_eq!(inventory[0].item_code_ext, "A2121FSY07292");
+    assert_eq!(body["facets"]["トップス"], 2);
+    assert_eq!(body["facets"]["スカート"], 1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn adjust_inventory_bulk_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    // the last adjustment fails, so the whole batch should be rolled back
+    let body = serde_json::json!([
+        {"itemCodeExt":"A2121FSY06693","location":"jp","delta":1,"note":"found"},
+        {"itemCodeExt":"A2121FSY99991","location":"cn","delta":3,"note":"new"},
+        {"itemCodeExt":"A2121FSY07292","location":"cn","delta":-5,"note":"lost"},
+    ]);
+    let response1 = app
+        .request_client
+        .post(format!("{private_base_uri}/inventory/adjust_bulk"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(400, response1.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::JP)
+            .await,
+        1
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY99991", InventoryLocation::CN)
+            .await,
+        0
+    );
+    let body = serde_json::json!([
+        {"itemCodeExt":"A2121FSY06693","location":"jp","delta":1,"note":"found"},
+        {"itemCodeExt":"A2121FSY99991","location":"cn","delta":3,"note":"new"},
+        {"itemCodeExt":"A2121FSY07292","location":"jp","delta":-1,"note":"lost"},
+    ]);
+    let response2 = app
+        .request_client
+        .post(format!("{private_base_uri}/inventory/adjust_bulk"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response2.status().as_u16());
+    let results: Vec<serde_json::Value> =
+        response2.json().await.expect("Failed to deserialize json");
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0]["quantity"], 2);
+    assert_eq!(results[1]["quantity"], 3);
+    assert_eq!(results[2]["quantity"], 1);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY99991", InventoryLocation::CN)
+            .await,
+        3
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        1
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_dead_stock_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let items: Vec<InventoryOutput> = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/dead_stock"))
+        .query(&[("sinceDays", "30")])
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let codes = items
+        .iter()
+        .map(|i| i.item_code_ext.as_str())
+        .collect::<Vec<_>>();
+    assert!(codes.contains(&"A2121FSY06693"));
+    assert!(!codes.contains(&"A2121FSY00991"));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_guarantee_mismatches_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let find_mismatches = || async {
+        let response = app
+            .request_client
+            .get(format!(
+                "{private_base_uri}/inventory/integrity/guarantee_mismatch"
+            ))
+            .send()
+            .await
+            .expect("Failed to request");
+        assert_eq!(200, response.status().as_u16());
+        response
+            .json::<Vec<GuaranteeMismatch>>()
+            .await
+            .expect("Failed to deserialize json")
+    };
+    assert!(find_mismatches().await.is_empty());
+    // lose the reservation of the guaranteed item.
+    app.db
+        .ph_db
+        .collection::<Document>(OPERATIONS_COL)
+        .update_many(
+            doc! {"item_code_ext":"A2121FSY00991","operation_type":"ordered"},
+            doc! {"$set":{"countered":true}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper operations");
+    let mismatches = find_mismatches().await;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].item_code_ext, "A2121FSY00991");
+    assert_eq!(mismatches[0].location, InventoryLocation::JP);
+    assert_eq!(mismatches[0].guaranteed, 1);
+    assert_eq!(mismatches[0].reserved, 0);
+    assert_eq!(mismatches[0].in_stock, 1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn stream_inventory_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/stream"))
+        .query(&[("keyword", ""), ("showZeroQuantity", "true")])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "application/x-ndjson"
+    );
+    let body = response.text().await.expect("Failed to read body");
+    let items = body
+        .lines()
+        .map(|line| serde_json::from_str::<InventoryOutput>(line).expect("invalid json line"))
+        .collect::<Vec<_>>();
+    let total = app
+        .db
+        .ph_db
+        .collection::<Document>(INVENTORY_COL)
+        .count_documents(None, None)
+        .await
+        .expect("Failed to count inventory");
+    assert_eq!(items.len() as u64, total);
+    assert_eq!(items.len(), 3);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_inventory_changes_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let since = chrono::Utc::now().timestamp();
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/changes"))
+        .query(&[("since", since)])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let items: Vec<InventoryOutput> = response.json().await.expect("Failed to deserialize");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].item_code_ext, "A2121FSY00991");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_negative_balances_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let related_id = mongodb::bson::Uuid::new();
+    let start = chrono::Utc::now() - chrono::Duration::hours(1);
+    // +1, -2 (dips to -1), +2, -1 (stays at 0); inserted out of order.
+    let history = [
+        (MongoOperationType::Ordered, -1, 3),
+        (MongoOperationType::Arrival, 1, 0),
+        (MongoOperationType::Arrival, 2, 2),
+        (MongoOperationType::Ordered, -2, 1),
+    ];
+    let mut dip_id = None;
+    for (operation_type, count, minutes) in history {
+        let mut operation = MongoInventoryOperation::new(
+            "NEGATIVE00001",
+            related_id,
+            operation_type,
+            count,
+            InventoryLocation::JP,
+        );
+        operation.time = (start + chrono::Duration::minutes(minutes)).into();
+        if minutes == 1 {
+            dip_id = Some(operation.id);
+        }
+        operation
+            .insert_self(&app.db)
+            .await
+            .expect("Failed to insert operation");
+    }
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/integrity/negatives"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let negatives = response
+        .json::<Vec<NegativeBalance>>()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(negatives.len(), 1);
+    assert_eq!(negatives[0].item_code_ext, "NEGATIVE00001");
+    assert_eq!(negatives[0].location, InventoryLocation::JP);
+    assert_eq!(Some(negatives[0].operation_id), dip_id);
+    assert_eq!(negatives[0].quantity, -1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn inventory_ledger_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    app.create_transfer("EJ123456789JP", "A2121FSY00991", [0, 1, 0])
+        .await;
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/ledger/A2121FSY00991"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let ledger: Vec<serde_json::Value> = response.json().await.expect("Failed to deserialize json");
+    assert!(ledger.len() >= 3);
+    let times = ledger
+        .iter()
+        .map(|entry| entry["time"].as_i64().expect("time should be a number"))
+        .collect::<Vec<_>>();
+    assert!(times.windows(2).all(|w| w[0] <= w[1]));
+    let last = &ledger.last().unwrap()["balance"];
+    for location in [
+        InventoryLocation::JP,
+        InventoryLocation::CN,
+        InventoryLocation::PCN,
+    ] {
+        let balance = last
+            .as_array()
+            .expect("balance should be an array")
+            .iter()
+            .find(|b| b["location"] == serde_json::to_value(location).unwrap())
+            .expect("every location should have a balance");
+        let stored = app.get_inventory_quantity("A2121FSY00991", location).await;
+        assert_eq!(balance["quantity"], stored);
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn compression_exempt_route_is_not_compressed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/inventory/quantity/A2121FSY00991"
+        ))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+    assert!(response.headers().get("content-encoding").is_none());
+    // other routes are still compressed.
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/ledger/A2121FSY00991"))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+    assert_eq!(
+        response
+            .headers()
+            .get("content-encoding")
+            .and_then(|v| v.to_str().ok()),
+        Some("gzip")
+    );
+    app.cleanup().await;
+}
+
+#[test]
+fn location_table_drives_new_inventory_quantity_docs() {
+    assert_eq!(
+        InventoryLocation::create_new_inventory_quantity_docs(3),
+        vec![
+            doc! {"location":"jp","quantity":3},
+            doc! {"location":"cn","quantity":0},
+            doc! {"location":"pcn","quantity":0},
+        ]
+    );
+    assert_eq!(InventoryLocation::PCN.kanjified(), "中国済");
+    assert!(InventoryLocation::PCN.is_paid());
+    assert_eq!(InventoryLocation::CN.to_string(), "cn");
+    let mut locations = LOCATIONS.to_vec();
+    locations.push(LocationInfo {
+        code: "kr",
+        kanji: "韓国",
+        paid: false,
+        receives_new_stock: false,
+    });
+    let docs = LocationInfo::quantity_docs(&locations, 3);
+    assert_eq!(docs.len(), 4);
+    assert_eq!(docs[3], doc! {"location":"kr","quantity":0});
+}
+
+#[test]
+fn location_table_matches_location_variants() {
+    let locations = InventoryLocation::iter().collect::<Vec<_>>();
+    assert_eq!(locations.len(), LOCATIONS.len());
+    for (i, location) in locations.into_iter().enumerate() {
+        assert_eq!(location.info(), &LOCATIONS[i]);
+        let serde_name = serde_json::to_value(location).expect("Failed to serialize location");
+        assert_eq!(serde_name, location.info().code);
+    }
+}
+
+#[tokio::test]
+async fn query_inventory_by_codes_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let now = mongodb::bson::DateTime::now();
+    for code in ["A2121FSY01111", "A2121FSY02222"] {
+        app.db
+            .ph_db
+            .collection::<Document>(INVENTORY_COL)
+            .insert_one(
+                doc! {
+                  "item_code_ext":code,
+                  "quantity":[{"location":"jp","quantity":1}],
+                  "created_at":now,
+                  "update_at":now,
+                  "operation_ids":[],
+                },
+                None,
+            )
+            .await
+            .expect("Failed to insert inventory");
+    }
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory"))
+        .query(&[
+            // the keyword matches nothing, it is ignored for codes.
+            ("keyword", "no such item"),
+            ("showZeroQuantity", "true"),
+            ("codes", "A2121FSY06693,A2121FSY07292,A2121FSY02222"),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    let inventory: Vec<InventoryOutput> =
+        serde_json::from_value(body["data"].clone()).expect("Failed to deserialize data");
+    let mut codes = inventory
+        .iter()
+        .map(|item| item.item_code_ext.as_str())
+        .collect::<Vec<_>>();
+    codes.sort();
+    assert_eq!(codes, ["A2121FSY02222", "A2121FSY06693", "A2121FSY07292"]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_operation_types_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let get = |locale: &'static str| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/inventory/operation_types"))
+            .query(&[("locale", locale)]);
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            response
+                .json::<Vec<OperationTypeLabel>>()
+                .await
+                .expect("Failed to deserialize json")
+        }
+    };
+    let labels = get("en").await;
+    let types = labels
+        .iter()
+        .map(|label| serde_json::to_value(&label.operation_type).unwrap()["type"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            "createEmpty",
+            "arrival",
+            "returned",
+            "deleteRegister",
+            "deleteOrder",
+            "deleteReturn",
+            "deleteTransfer",
+            "updateTransfer",
+            "concealOrderItem",
+            "ordered",
+            "move",
+            "expireGuarantee",
+            "holdOrderItem",
+            "adjust",
+        ]
+    );
+    assert_eq!(labels[1].label, "arrival");
+    assert_eq!(get("zh").await[1].label, "到货");
+    // unsupported locales fall back to japanese.
+    assert_eq!(get("fr").await[1].label, "入荷");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reorder_point_flags_inventory_below_it() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .put(format!(
+            "{private_base_uri}/inventory/reorder_points/A2121FSY009"
+        ))
+        .json(&serde_json::json!({"minQuantity":2}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/inventory/reorder_points/A2121"))
+        .json(&serde_json::json!({"minQuantity":2}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(400, response.status().as_u16());
+    let points = app
+        .request_client
+        .get(format!("{private_base_uri}/inventory/reorder_points"))
+        .send()
+        .await
+        .expect("Failed to request")
+        .json::<Vec<ReorderPoint>>()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].item_code, "A2121FSY009");
+    assert_eq!(points[0].min_quantity, 2);
+    let query = |code: &'static str| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/inventory"))
+            .query(&[("showZeroQuantity", "true"), ("codes", code)]);
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            let body: serde_json::Value =
+                response.json().await.expect("Failed to deserialize json");
+            let inventory: Vec<InventoryOutput> =
+                serde_json::from_value(body["data"].clone()).expect("Failed to deserialize data");
+            assert_eq!(inventory.len(), 1);
+            inventory[0].below_reorder
+        }
+    };
+    assert!(!query("A2121FSY00991").await);
+    // items without a reorder point are never flagged.
+    assert!(!query("A2121FSY06693").await);
+    app.create_order("customer", &[("A2121FSY00991", 1)]).await;
+    assert!(query("A2121FSY00991").await);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn swap_location_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let swap = |count: u32| {
+        let request = app
+            .request_client
+            .post(format!("{private_base_uri}/inventory/swap_location"))
+            .json(&serde_json::json!({
+                "itemCodeExt":"A2121FSY00991",
+                "from":"jp",
+                "to":"cn",
+                "count":count,
+                "reason":"found at cn",
+            }));
+        async move { request.send().await.expect("Failed to request") }
+    };
+    let response = swap(2).await;
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(body["reason"], "found at cn");
+    let swap_id = body["id"]
+        .as_str()
+        .expect("id should be string")
+        .to_string();
+    let operations: Vec<InventoryOperation> = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/inventory/operations/by_related/{swap_id}"
+        ))
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(operations.len(), 2);
+    assert!(operations
+        .iter()
+        .all(|o| o.operation_type == OperationType::Move));
+    assert!(operations
+        .iter()
+        .any(|o| o.location == InventoryLocation::JP && o.count == -2));
+    assert!(operations
+        .iter()
+        .any(|o| o.location == InventoryLocation::CN && o.count == 2));
+    assert_eq!(operations.iter().map(|o| o.count).sum::<i32>(), 0);
+    let jp = app
+        .get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+        .await;
+    let cn = app
+        .get_inventory_quantity("A2121FSY00991", InventoryLocation::CN)
+        .await;
+    assert_eq!((jp, cn), (0, 2));
+    // nothing left at jp to swap.
+    assert_eq!(400, swap(1).await.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::CN)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_supply_sources_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.register_inventory().await;
+    let mut register_nos = app
+        .db
+        .ph_db
+        .collection::<Document>(REGISTERS_COL)
+        .find(None, None)
+        .await
+        .expect("Failed to find registers")
+        .try_collect::<Vec<_>>()
+        .await
+        .expect("Failed to collect registers")
+        .into_iter()
+        .map(|register| register.get_str("no").unwrap().to_string())
+        .collect::<Vec<_>>();
+    register_nos.sort();
+    let private_base_uri = app.private_base_uri();
+    let sources: Vec<SupplySource> = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/inventory/supply_sources/A2121FSY00991"
+        ))
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(sources.len(), 2);
+    assert!(sources
+        .iter()
+        .all(|s| s.count == 2 && s.location == InventoryLocation::JP));
+    let mut nos = sources
+        .iter()
+        .map(|s| s.register_no.clone())
+        .collect::<Vec<_>>();
+    nos.sort();
+    assert_eq!(nos, register_nos);
+    assert_ne!(sources[0].register_id, sources[1].register_id);
+    app.cleanup().await;
+}
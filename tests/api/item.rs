@@ -0,0 +1,44 @@
+use oism_server::{db::PhItem, server::item::ItemPrice};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn get_item_price_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    PhItem::new_dummy("C2121FSY00991", 1001)
+        .insert_self(&app.db)
+        .await
+        .expect("Failed to insert item");
+    let private_base_uri = app.private_base_uri();
+    let get_price = |rate: &'static str| {
+        app.request_client
+            .get(format!("{private_base_uri}/items/C2121FSY009/price"))
+            .query(&[("rate", rate)])
+            .send()
+    };
+    let response = get_price("0.5").await.expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let price: ItemPrice = response.json().await.expect("Failed to deserialize");
+    // 500.5 is rounded up, tax exclusive prices are rounded to nearest.
+    assert_eq!(
+        price,
+        ItemPrice {
+            price: 1001,
+            discounted: 501,
+            tax_exclusive: 910,
+            tax_exclusive_discounted: 455,
+        }
+    );
+    let response = get_price("1.5").await.expect("Failed execute request");
+    assert_eq!(400, response.status().as_u16());
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/items/C2121FSY000/price"))
+        .query(&[("rate", "1")])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(404, response.status().as_u16());
+    app.cleanup().await;
+}
@@ -1,5 +1,15 @@
+mod activity;
+mod admin;
 mod auth;
+mod configuration;
+mod export;
 mod helpers;
 mod inventory;
+mod item;
+mod notifier;
 mod order;
 mod register;
+mod retrn;
+mod shipment;
+mod transfer;
+mod ws;
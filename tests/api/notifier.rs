@@ -0,0 +1,141 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use axum::async_trait;
+use oism_server::{
+    configuration::NotifierRetrySetting,
+    db::NotificationRepo,
+    error_result::{Error, Result},
+    services::notifier::{Notification, NotificationTransport, RetryingNotifier},
+};
+
+use crate::helpers::spawn_app;
+
+#[derive(Default)]
+struct FlakyTransport {
+    calls: AtomicU32,
+    recovered: AtomicBool,
+}
+
+#[async_trait]
+impl NotificationTransport for FlakyTransport {
+    async fn send(&self, _notification: &Notification) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.recovered.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(Error::HttpResponse("service unavailable".into()))
+        }
+    }
+}
+
+#[tokio::test]
+async fn failing_notification_is_queued_for_retry() {
+    let app = spawn_app().await;
+    let transport = Arc::new(FlakyTransport::default());
+    let setting = NotifierRetrySetting {
+        max_attempts: 3,
+        backoff_ms: 1,
+        failure_threshold: 10,
+        ..Default::default()
+    };
+    let notifier = RetryingNotifier::new(transport.clone(), Arc::new(app.db.clone()), &setting);
+    let notification = Notification::shipped_item_removed("A2121FSY00991", "customer1", "memo");
+    notifier
+        .deliver(notification.clone())
+        .await
+        .expect("Failed to deliver notification");
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 3);
+    let pending = app
+        .db
+        .find_pending_notifications(10)
+        .await
+        .expect("Failed to find pending notifications");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(Notification::from(pending[0].clone()), notification);
+    // once the service is back the queued notification is sent and removed.
+    transport.recovered.store(true, Ordering::SeqCst);
+    let delivered = notifier
+        .retry_pending()
+        .await
+        .expect("Failed to retry pending notifications");
+    assert_eq!(delivered, 1);
+    let pending = app
+        .db
+        .find_pending_notifications(10)
+        .await
+        .expect("Failed to find pending notifications");
+    assert!(pending.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn open_circuit_queues_without_calling() {
+    let app = spawn_app().await;
+    let transport = Arc::new(FlakyTransport::default());
+    let setting = NotifierRetrySetting {
+        max_attempts: 2,
+        backoff_ms: 1,
+        failure_threshold: 2,
+        ..Default::default()
+    };
+    let notifier = RetryingNotifier::new(transport.clone(), Arc::new(app.db.clone()), &setting);
+    let notification = Notification::shipped_item_removed("A2121FSY00991", "customer1", "memo");
+    for _ in 0..2 {
+        notifier
+            .deliver(notification.clone())
+            .await
+            .expect("Failed to deliver notification");
+    }
+    // the first delivery opened the circuit, the second did not reach the service.
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 2);
+    let pending = app
+        .db
+        .find_pending_notifications(10)
+        .await
+        .expect("Failed to find pending notifications");
+    assert_eq!(pending.len(), 2);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn pending_notification_is_dropped_after_max_attempts() {
+    let app = spawn_app().await;
+    let transport = Arc::new(FlakyTransport::default());
+    let setting = NotifierRetrySetting {
+        max_attempts: 1,
+        backoff_ms: 1,
+        failure_threshold: 10,
+        max_pending_attempts: 2,
+        ..Default::default()
+    };
+    let notifier = RetryingNotifier::new(transport.clone(), Arc::new(app.db.clone()), &setting);
+    let notification = Notification::shipped_item_removed("A2121FSY00991", "customer1", "memo");
+    notifier
+        .deliver(notification)
+        .await
+        .expect("Failed to deliver notification");
+    for expected_pending in [1, 0] {
+        let delivered = notifier
+            .retry_pending()
+            .await
+            .expect("Failed to retry pending notifications");
+        assert_eq!(delivered, 0);
+        let pending = app
+            .db
+            .find_pending_notifications(10)
+            .await
+            .expect("Failed to find pending notifications");
+        assert_eq!(pending.len(), expected_pending);
+    }
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 3);
+    // nothing is left to retry.
+    notifier
+        .retry_pending()
+        .await
+        .expect("Failed to retry pending notifications");
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 3);
+    app.cleanup().await;
+}
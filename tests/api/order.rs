@@ -1 +1,1490 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
+use axum::{
+    async_trait,
+    extract::{Path, State},
+};
+use chrono::{Duration, Utc};
+use mongodb::bson::{doc, Document};
+use oism_server::{
+    cache::{MapCache, OrderCache},
+    configuration::DeleteGraceSetting,
+    db::{
+        auth::UserRole,
+        inventory::InventoryLocation,
+        mongo::{ITEMS_COL, ORDERS_COL, ORDER_ITEMS_COL},
+        order::{OrderCursor, OrderItemPlan, OrderItemStatus},
+        slow_query::{redacted_match_filters, slow_query_message},
+        Order, OrderItem, OrderRepo, PhItem,
+    },
+    error_result::Error,
+    server::{
+        auth::{UserInfo, SETTINGS},
+        ensure_deletable_within,
+        order::{conceal_order_item, OrderImportRow},
+        ws::ControlMessage,
+        AppPrivateRoute,
+    },
+    services::{
+        guarantee_expiry::run_guarantee_expiry,
+        notifier::{Notification, Notifier},
+    },
+};
+use tokio::sync::Mutex;
+
+use crate::helpers::{order_input, spawn_app};
+
+#[tokio::test]
+async fn expire_stale_guaranteed_items_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    assert_eq!(order.items[0].status, OrderItemStatus::Guaranteed);
+    let aged: mongodb::bson::DateTime = (Utc::now() - Duration::days(40)).into();
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_many(doc! {}, doc! {"$set":{"update_at":aged}}, None)
+        .await
+        .expect("Failed to age order items");
+    let cache = MapCache::new() as Arc<dyn OrderCache>;
+    let (tx, _rx) = tokio::sync::broadcast::channel(16);
+    let expired = run_guarantee_expiry(&app.db, &cache, &Arc::new(tx), 30)
+        .await
+        .expect("Failed to run guarantee expiry");
+    assert_eq!(expired.len(), 1);
+    let private_base_uri = app.private_base_uri();
+    let item: OrderItem = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/order_items/{}",
+            order.items[0].id
+        ))
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(item.status, OrderItemStatus::BackOrdering);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_orders_bulk_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let body = serde_json::json!([
+        order_input(
+            "2023010100000000000000000001",
+            "customer1",
+            &[("A2121FSY00991", 1)]
+        ),
+        order_input("invalid", "customer2", &[("A2121FSY06693", 1)]),
+        order_input(
+            "2023010100000000000000000003",
+            "customer3",
+            &[("A2121FSY07292", 1)]
+        ),
+    ]);
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/bulk"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let results: Vec<serde_json::Value> =
+        response.json().await.expect("Failed to deserialize json");
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.iter().filter(|r| r["success"] == true).count(), 2);
+    assert_eq!(results[1]["taobaoOrderNo"], "invalid");
+    assert_eq!(results[1]["success"], false);
+    assert!(results[1]["error"].is_string());
+    for no in [
+        "2023010100000000000000000001",
+        "2023010100000000000000000003",
+    ] {
+        let response = app
+            .request_client
+            .get(format!("{private_base_uri}/orders/taobao_no/{no}"))
+            .send()
+            .await
+            .expect("Failed execute request");
+        let orders: Vec<Order> = response.json().await.expect("Failed to deserialize json");
+        assert_eq!(orders.len(), 1);
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_orders_bulk_with_oversized_body_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let body = vec![b' '; SETTINGS.max_upload_bytes + 1];
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/bulk"))
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(413, response.status().as_u16());
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains(&SETTINGS.max_upload_bytes.to_string()));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_orders_by_ids_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order1 = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    let order3 = app.create_order("customer3", &[("A2121FSY07292", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/batch"))
+        .json(&serde_json::json!({
+            "ids":[order1.id, order3.id, uuid::Uuid::new_v4()],
+        }))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let orders: Vec<Order> = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(orders.len(), 2);
+    assert_eq!(orders[0].id, order1.id);
+    assert_eq!(orders[1].id, order3.id);
+    assert_eq!(orders[1].items.len(), 1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_full_width_customer_id_with_half_width_keyword_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("ｃｕｓｔｏｍｅｒ１２３", &[("A2121FSY00991", 1)])
+        .await;
+    app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let items: Vec<OrderItem> = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items"))
+        .query(&[("keyword", " Customer123 "), ("status", "guaranteed")])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].id, order.items[0].id);
+    let now = Utc::now();
+    let response: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/orders"))
+        .query(&[
+            ("keyword", "customer123".to_string()),
+            ("status", "".to_string()),
+            ("from", (now - Duration::days(1)).timestamp().to_string()),
+            ("to", (now + Duration::days(1)).timestamp().to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let orders = response["data"].as_array().expect("data should be array");
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0]["id"], serde_json::json!(order.id));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_order_items_with_blank_keyword_matches_nothing() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    for keyword in [" ", "\u{3000}"] {
+        let items: Vec<OrderItem> = app
+            .request_client
+            .get(format!("{private_base_uri}/order_items"))
+            .query(&[("keyword", keyword), ("status", "guaranteed")])
+            .send()
+            .await
+            .expect("Failed to request")
+            .json()
+            .await
+            .expect("Failed to deserialize json");
+        assert!(items.is_empty(), "keyword {keyword:?} matched {items:?}");
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn backfill_customer_id_normalized_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("ｃｕｓｔｏｍｅｒ１２３", &[("A2121FSY00991", 1)])
+        .await;
+    for col in [ORDERS_COL, ORDER_ITEMS_COL] {
+        app.db
+            .ph_db
+            .collection::<Document>(col)
+            .update_many(doc! {}, doc! {"$unset":{"customer_id_normalized":""}}, None)
+            .await
+            .expect("Failed to unset customer_id_normalized");
+    }
+    let private_base_uri = app.private_base_uri();
+    let query_items = || {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/order_items"))
+            .query(&[("keyword", "customer123"), ("status", "guaranteed")]);
+        async move {
+            request
+                .send()
+                .await
+                .expect("Failed to request")
+                .json::<Vec<OrderItem>>()
+                .await
+                .expect("Failed to deserialize json")
+        }
+    };
+    assert!(query_items().await.is_empty());
+    let output: serde_json::Value = app
+        .request_client
+        .post(format!(
+            "{private_base_uri}/admin/orders/backfill_customer_id"
+        ))
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(output["updated"], 2);
+    let items = query_items().await;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].id, order.items[0].id);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_orders_beyond_max_page_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let now = Utc::now();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/orders"))
+        .query(&[
+            ("keyword", "".to_string()),
+            ("status", "".to_string()),
+            ("from", (now - Duration::days(1)).timestamp().to_string()),
+            ("to", (now + Duration::days(1)).timestamp().to_string()),
+            ("page", (SETTINGS.max_page + 1).to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(422, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_orders_with_tied_sort_keys_pages_stably() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    for i in 0..12 {
+        app.create_order(&format!("customer{i}"), &[("A2121FSY00991", 1)])
+            .await;
+    }
+    let now = Utc::now();
+    let tied: mongodb::bson::DateTime = now.into();
+    app.db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .update_many(
+            doc! {},
+            doc! {"$set":{"created_at":tied,"order_datetime":tied,"taobao_order_no":"tied"}},
+            None,
+        )
+        .await
+        .expect("Failed to update orders");
+    let mut ids = Vec::new();
+    for page in 0..2 {
+        let (_, orders) = app
+            .db
+            .query_orders(
+                "",
+                "",
+                now - Duration::days(1),
+                now + Duration::days(1),
+                Some(page),
+            )
+            .await
+            .expect("Failed to query orders");
+        ids.extend(orders.into_iter().map(|order| order.id));
+    }
+    assert_eq!(ids.len(), 12);
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 12);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn query_orders_rejects_unknown_status() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let now = Utc::now();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/orders"))
+        .query(&[
+            ("keyword", "".to_string()),
+            ("status", "guaranteed,shiped".to_string()),
+            ("from", (now - Duration::days(1)).timestamp().to_string()),
+            ("to", (now + Duration::days(1)).timestamp().to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(422, response.status().as_u16());
+    let body = response.text().await.expect("Failed to read body");
+    assert!(body.contains("shiped"));
+    assert_eq!(
+        OrderItemStatus::parse_list(" guaranteed, shipped ").expect("statuses should parse"),
+        vec![OrderItemStatus::Guaranteed, OrderItemStatus::Shipped]
+    );
+    assert!(OrderItemStatus::parse_list("").unwrap().is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn delete_order_releases_only_its_own_operations() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let deleted = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let kept = app.create_order("customer2", &[("A2121FSY00991", 1)]).await;
+    let kept_operations = app
+        .db
+        .get_order_operations(kept.id)
+        .await
+        .expect("Failed to get operations");
+    // link the other order's reservation to the order to delete by mistake.
+    app.db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .update_one(
+            doc! {"id":deleted.id},
+            doc! {"$push":{"operation_ids":{"$each":kept_operations.iter().map(|o| o.id).collect::<Vec<_>>()}}},
+            None,
+        )
+        .await
+        .expect("Failed to link operations");
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/orders/{}", deleted.id))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response.status().is_success());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        1
+    );
+    let operations = app
+        .db
+        .get_order_operations(kept.id)
+        .await
+        .expect("Failed to get operations");
+    assert_eq!(operations.len(), kept_operations.len());
+    assert!(operations.iter().all(|o| !o.countered));
+    let item = app
+        .db
+        .get_order_item_by_id(kept.items[0].id)
+        .await
+        .expect("Failed to get order item");
+    assert_eq!(item.status, OrderItemStatus::Guaranteed);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_order_operations_returns_ordered_operations() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/orders/{}/operations", order.id))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let operations: Vec<serde_json::Value> =
+        response.json().await.expect("Failed to deserialize json");
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0]["operationType"]["type"], "ordered");
+    assert_eq!(operations[0]["itemCodeExt"], "A2121FSY00991");
+    assert_eq!(operations[0]["relatedId"], serde_json::json!(order.id));
+    assert_eq!(operations[0]["count"], -2);
+    assert_eq!(operations[0]["location"], "jp");
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/orders/{}/operations",
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(404, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reopen_concealed_order_item_with_stock_guarantees_it() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/order_items/{item_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/order_items/{item_id}"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response.status().is_success());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        2
+    );
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/order_items/{item_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let item: OrderItem = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(item.status, OrderItemStatus::Guaranteed);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        1
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reopen_concealed_order_item_without_stock_backorders_it() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    let item_id = order.items[0].id;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/order_items/{item_id}"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response.status().is_success());
+    // another customer takes the released stock.
+    app.create_order("customer2", &[("A2121FSY06693", 1)]).await;
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::JP)
+            .await,
+        0
+    );
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/order_items/{item_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let item: OrderItem = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(item.status, OrderItemStatus::BackOrdering);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::JP)
+            .await,
+        0
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reopen_concealed_order_item_with_shipment_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/order_items/{item_id}"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response.status().is_success());
+    // as if it was concealed while still linked to its shipment
+    let shipment_id = mongodb::bson::Uuid::new();
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(
+            doc! {"id":item_id},
+            doc! {"$set":{"shipment_id":shipment_id}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper order item");
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/order_items/{item_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn count_orders_by_status_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    // one guaranteed and one backordering item.
+    app.create_order("customer1", &[("A2121FSY06693", 2)]).await;
+    let concealed = app.create_order("customer2", &[("A2121FSY00991", 1)]).await;
+    let shipped = app.create_order("customer3", &[("A2121FSY07292", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!(
+            "{private_base_uri}/order_items/{}",
+            concealed.items[0].id
+        ))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert!(response.status().is_success());
+    app.create_shipment("EJ123456789JP", "ems", &[shipped.items[0].id])
+        .await;
+    let now = Utc::now();
+    let counts: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/orders/status_counts"))
+        .query(&[
+            ("from", (now - Duration::days(1)).timestamp().to_string()),
+            ("to", (now + Duration::days(1)).timestamp().to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let counts = counts
+        .into_iter()
+        .map(|count| {
+            (
+                count["status"].as_str().unwrap().to_string(),
+                count["count"].as_u64().unwrap(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    assert_eq!(counts.len(), 5);
+    assert_eq!(counts["backordering"], 1);
+    assert_eq!(counts["held"], 0);
+    assert_eq!(counts["guaranteed"], 1);
+    assert_eq!(counts["shipped"], 1);
+    assert_eq!(counts["concealed"], 1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_order_and_shipment_with_item_details_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let mut ph_item = PhItem::new_dummy("A2121FSY00991", 22000);
+    ph_item.item_name = Some(String::from("blouse"));
+    app.db
+        .ph_db
+        .collection::<PhItem>(ITEMS_COL)
+        .insert_one(ph_item, None)
+        .await
+        .expect("Failed to insert item");
+    // A2121FSY06693 is not in the catalog.
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 1), ("A2121FSY06693", 1)])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let get_order = |with_item_details: bool| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/orders/{}", order.id))
+            .query(&[("withItemDetails", with_item_details)]);
+        async move {
+            let order: serde_json::Value = request
+                .send()
+                .await
+                .expect("Failed to request")
+                .json()
+                .await
+                .expect("Failed to deserialize json");
+            order
+        }
+    };
+    let plain = get_order(false).await;
+    assert!(plain["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|item| item.get("itemDetail").is_none()));
+    let detailed = get_order(true).await;
+    let detail_of = |body: &serde_json::Value, code: &str| {
+        body["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|item| item["itemCodeExt"] == code)
+            .expect("item should exist")["itemDetail"]
+            .clone()
+    };
+    let found = detail_of(&detailed, "A2121FSY00991");
+    assert_eq!(found["itemName"], "blouse");
+    assert_eq!(found["price"], 22000);
+    let dummy = detail_of(&detailed, "A2121FSY06693");
+    assert_eq!(dummy["code"], "A2121FSY066");
+    assert!(dummy["itemName"].is_null());
+    assert!(dummy["size"].is_null());
+    let item_id = order
+        .items
+        .iter()
+        .find(|item| item.item_code_ext == "A2121FSY00991")
+        .expect("item should exist")
+        .id;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[item_id])
+        .await;
+    let shipment: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .query(&[("withItemDetails", true)])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(shipment["items"][0]["itemDetail"]["itemName"], "blouse");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn hold_guaranteed_order_item_releases_inventory() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        1
+    );
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":true}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let item: OrderItem = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(item.status, OrderItemStatus::Held);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        2
+    );
+    // a held item is searchable by its own status.
+    let items: Vec<OrderItem> = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items/"))
+        .query(&[("keyword", "customer1"), ("status", "held")])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(items.len(), 1);
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":true}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(409, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn release_held_order_item_reserves_inventory() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":true}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":false}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(200, response.status().as_u16());
+    let item: OrderItem = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(item.status, OrderItemStatus::Guaranteed);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        1
+    );
+    // hold again then let another order take the rest of stock.
+    app.request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":true}))
+        .send()
+        .await
+        .expect("Failed to request");
+    let other = app.create_order("customer2", &[("A2121FSY00991", 2)]).await;
+    assert!(other
+        .items
+        .iter()
+        .all(|item| item.status == OrderItemStatus::Guaranteed));
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/order_items/{item_id}/hold"))
+        .json(&serde_json::json!({"hold":false}))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        0
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn locate_shipped_order_item_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let order = app.create_order("customer2", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let items: Vec<OrderItem> = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items/locate"))
+        .query(&[("itemCodeExt", "A2121FSY00991")])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].order_id, order.id);
+    assert_eq!(items[0].customer_id, "customer2");
+    assert_eq!(
+        items[0].shipment_id.map(|id| id.to_string()),
+        Some(shipment_id.to_string())
+    );
+    let items: Vec<OrderItem> = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items/locate"))
+        .query(&[("itemCodeExt", "A2121FSY00991"), ("status", "guaranteed")])
+        .send()
+        .await
+        .expect("Failed to request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].customer_id, "customer1");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_order_with_dummy_item_returns_warnings() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let mut body = order_input(
+        "2023010100000000000000000001",
+        "customer1",
+        &[("A2121FSY00991", 1)],
+    );
+    body["items"][0]["isManual"] = serde_json::json!(true);
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/"))
+        .query(&[("envelope", "true")])
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(201, response.status().as_u16());
+    let header: Vec<String> = serde_json::from_str(
+        response.headers()["x-warnings"]
+            .to_str()
+            .expect("invalid header value"),
+    )
+    .expect("Failed to deserialize warnings header");
+    let body: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(body["ok"], true);
+    let warnings = body["warnings"].as_array().expect("warnings should exist");
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].as_str().unwrap().contains("A2121FSY009"));
+    assert_eq!(header, vec![warnings[0].as_str().unwrap().to_string()]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn preview_order_with_partial_stock_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let body = order_input(
+        "2023010100000000000000000001",
+        "customer1",
+        &[
+            ("A2121FSY06693", 3),
+            ("A2121FSY07292", 1),
+            ("A2121FSY07292", 2),
+        ],
+    );
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/preview"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let plans: Vec<OrderItemPlan> = response.json().await.expect("Failed to deserialize");
+    let expected = [
+        ("A2121FSY06693", 1, 2),
+        ("A2121FSY07292", 1, 0),
+        // the former item has taken one of the two in stock.
+        ("A2121FSY07292", 1, 1),
+    ];
+    assert_eq!(plans.len(), expected.len());
+    for (plan, (code, guaranteed, backordered)) in plans.iter().zip(expected) {
+        assert_eq!(plan.item_code_ext, code);
+        assert_eq!(plan.location, InventoryLocation::JP);
+        assert_eq!(plan.guaranteed, guaranteed);
+        assert_eq!(plan.backordered, backordered);
+    }
+    // nothing is written
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::JP)
+            .await,
+        1
+    );
+    let orders = app
+        .db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .count_documents(None, None)
+        .await
+        .expect("Failed to count orders");
+    assert_eq!(orders, 0);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn validate_order_import_reports_each_order() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let mut out_of_range_rate = order_input("20230101", "customer2", &[("A2121FSY00991", 1)]);
+    out_of_range_rate["items"][0]["rate"] = serde_json::json!(1.5);
+    let orders = serde_json::json!([
+        order_input(
+            "2023010100000000000000000001",
+            "customer1",
+            &[("A2121FSY06693", 3), ("A2121FSY07292", 2)],
+        ),
+        out_of_range_rate,
+        order_input("2023010100000000000000000002", "customer\n2", &[]),
+        order_input(
+            "2023010100000000000000000003",
+            "customer3",
+            &[("A2121FSY07292", 1)],
+        ),
+        order_input(
+            "2023010100000000000000000004",
+            "customer4",
+            &[("A2121FSY0729", 1), ("A2121FSY-7292", 1)],
+        ),
+    ]);
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/import/validate"))
+        .json(&orders)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let rows: Vec<OrderImportRow> = response.json().await.expect("Failed to deserialize");
+    let indexes = rows.iter().map(|row| row.index).collect::<Vec<_>>();
+    assert_eq!(indexes, [0, 1, 2, 3, 4]);
+    let errors = rows.iter().map(|row| row.errors.len()).collect::<Vec<_>>();
+    // a short taobao no and an out of range rate, a line break in the customer id,
+    // a short item code and a non alphanumeric one
+    assert_eq!(errors, [0, 2, 1, 0, 2]);
+    assert!(rows[4].errors[0].contains("A2121FSY0729"));
+    assert!(rows[4].errors[1].contains("A2121FSY-7292"));
+    let plans = rows
+        .iter()
+        .map(|row| {
+            row.plans
+                .iter()
+                .map(|plan| {
+                    (
+                        plan.item_code_ext.as_str(),
+                        plan.guaranteed,
+                        plan.backordered,
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        plans,
+        [
+            vec![("A2121FSY06693", 1, 2), ("A2121FSY07292", 2, 0)],
+            vec![],
+            vec![],
+            // the first order has taken both in stock.
+            vec![("A2121FSY07292", 0, 1)],
+            vec![],
+        ]
+    );
+    // nothing is written
+    let orders = app
+        .db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .count_documents(None, None)
+        .await
+        .expect("Failed to count orders");
+    assert_eq!(orders, 0);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_order_with_newline_in_customer_id_is_rejected() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let body = order_input(
+        "2023010100000000000000000001",
+        "customer\n1",
+        &[("A2121FSY00991", 1)],
+    );
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(400, response.status().as_u16());
+    let orders = app
+        .db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .count_documents(None, None)
+        .await
+        .expect("Failed to count orders");
+    assert_eq!(orders, 0);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_customer_ids_by_prefix_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("Alice", &[("A2121FSY00991", 1)]).await;
+    app.create_order("Alice", &[("A2121FSY07292", 1)]).await;
+    app.create_order("alicia", &[("A2121FSY00991", 1)]).await;
+    app.create_order("bob", &[("A2121FSY07292", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let find = |prefix: &'static str| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/orders/customers"))
+            .query(&[("prefix", prefix)]);
+        async move {
+            let response = request.send().await.expect("Failed execute request");
+            assert_eq!(200, response.status().as_u16());
+            response
+                .json::<Vec<String>>()
+                .await
+                .expect("Failed to deserialize")
+        }
+    };
+    assert_eq!(find("ali").await, ["Alice", "alicia"]);
+    assert_eq!(find("ALICE").await, ["Alice"]);
+    // a prefix is not a regex.
+    assert!(find(".").await.is_empty());
+    app.cleanup().await;
+}
+
+#[test]
+fn order_cursor_round_trips() {
+    let cursor = OrderCursor {
+        created_at: mongodb::bson::DateTime::from_millis(1_672_531_200_123),
+        id: mongodb::bson::Uuid::new(),
+    };
+    assert_eq!(OrderCursor::decode(&cursor.encode()).unwrap(), cursor);
+    assert!(OrderCursor::decode("not a cursor").is_err());
+}
+
+#[tokio::test]
+async fn query_orders_by_cursor_has_no_duplicates_while_inserting() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    for index in 0..12 {
+        app.create_order(&format!("customer{index}"), &[("A2121FSY00991", 1)])
+            .await;
+    }
+    let private_base_uri = app.private_base_uri();
+    let now = Utc::now();
+    let page = |cursor: String| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/orders"))
+            .query(&[
+                ("keyword", "".to_string()),
+                ("status", "".to_string()),
+                ("from", (now - Duration::days(1)).timestamp().to_string()),
+                ("to", (now + Duration::days(1)).timestamp().to_string()),
+                ("cursor", cursor),
+            ]);
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            response
+                .json::<serde_json::Value>()
+                .await
+                .expect("Failed to deserialize json")
+        }
+    };
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let body = page(cursor).await;
+        let orders: Vec<Order> =
+            serde_json::from_value(body["data"].clone()).expect("Failed to deserialize orders");
+        seen.extend(orders.into_iter().map(|order| order.id));
+        match body["nextCursor"].as_str() {
+            Some(next) => cursor = next.to_string(),
+            None => break,
+        }
+        // orders created between pages sort before the cursor.
+        app.create_order("newcomer", &[("A2121FSY00991", 1)]).await;
+    }
+    let unique = seen.iter().collect::<HashSet<_>>();
+    assert_eq!(unique.len(), seen.len());
+    assert_eq!(seen.len(), 12);
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/orders"))
+        .query(&[
+            ("keyword", "".to_string()),
+            ("status", "".to_string()),
+            ("from", now.timestamp().to_string()),
+            ("to", now.timestamp().to_string()),
+            ("cursor", "!".to_string()),
+        ])
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(400, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[derive(Default)]
+struct RecordingNotifier(Mutex<Vec<Notification>>);
+
+#[async_trait]
+impl Notifier for RecordingNotifier {
+    async fn notify(&self, notification: Notification) {
+        self.0.lock().await.push(notification);
+    }
+}
+
+#[tokio::test]
+async fn conceal_shipped_item_notifies_once() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 1), ("A2121FSY07292", 1)])
+        .await;
+    let shipped = &order.items[0];
+    app.create_shipment("EJ123456789JP", "ems", &[shipped.id])
+        .await;
+    let notifier = Arc::new(RecordingNotifier::default());
+    let cache = MapCache::new() as Arc<dyn OrderCache>;
+    let (sender, _rx) = tokio::sync::broadcast::channel::<ControlMessage>(16);
+    let sender = Arc::new(sender);
+    for item in order.items.iter() {
+        let user_info = UserInfo {
+            user_id: uuid::Uuid::new_v4(),
+            role: UserRole::Full,
+            sub_role: HashMap::new(),
+        };
+        conceal_order_item(
+            user_info,
+            Path(item.id.into()),
+            State(Arc::new(app.db.clone())),
+            State(cache.clone()),
+            State(sender.clone()),
+            State(notifier.clone() as Arc<dyn Notifier>),
+        )
+        .await
+        .expect("Failed to conceal order item");
+    }
+    // only the shipped item is notified.
+    let notifications = notifier.0.lock().await;
+    assert_eq!(
+        *notifications,
+        vec![Notification {
+            title: shipped.item_code_ext.clone(),
+            notes: format!("顧客名:{},メモ:{}", shipped.customer_id, shipped.note),
+        }]
+    );
+    drop(notifications);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn count_awaiting_shipment_items_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let order = app
+        .create_order(
+            "customer1",
+            &[
+                ("A2121FSY00991", 2),
+                ("A2121FSY07292", 1),
+                ("A2121FSY06693", 2),
+            ],
+        )
+        .await;
+    let find = |code: &str| {
+        order
+            .items
+            .iter()
+            .filter(|item| item.item_code_ext == code)
+            .collect::<Vec<_>>()
+    };
+    // one guaranteed item at cn.
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(
+            doc! {"id":find("A2121FSY00991")[0].id},
+            doc! {"$set":{"location":"cn"}},
+            None,
+        )
+        .await
+        .expect("Failed to update order item");
+    // shipped items are not awaiting.
+    app.create_shipment("EJ123456789JP", "ems", &[find("A2121FSY07292")[0].id])
+        .await;
+    let counts: serde_json::Value = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/order_items/awaiting_shipment/counts"
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    // A2121FSY06693 has one unit, so the second one is backordered.
+    assert_eq!(
+        counts,
+        serde_json::json!([
+            {"location":"jp","count":2},
+            {"location":"cn","count":1},
+            {"location":"pcn","count":0},
+        ])
+    );
+    app.cleanup().await;
+}
+
+#[test]
+fn slow_query_message_fires_over_threshold_without_pii() {
+    let pipeline = vec![
+        doc! {"$match":{"status":"guaranteed"}},
+        doc! {"$lookup":{"from":"order_items","localField":"order_item_ids","foreignField":"id","as":"items"}},
+        doc! {"$match":{"$or":[
+          {"customer_id":"山田花子"},
+          {"items.item_code_ext":{"$regex":"山田花子","$options":"i"}},
+        ]}},
+    ];
+    let filters = redacted_match_filters(&pipeline);
+    assert_eq!(filters.len(), 2);
+    let threshold = std::time::Duration::from_millis(500);
+    assert!(slow_query_message(
+        "query orders",
+        std::time::Duration::from_millis(499),
+        threshold,
+        &filters
+    )
+    .is_none());
+    let message = slow_query_message(
+        "query orders",
+        std::time::Duration::from_millis(1200),
+        threshold,
+        &filters,
+    )
+    .expect("slow query should be reported");
+    assert!(message.contains("query orders"));
+    assert!(message.contains("1200ms"));
+    assert!(message.contains("guaranteed"));
+    assert!(message.contains("items.item_code_ext"));
+    assert!(!message.contains("山田花子"));
+}
+
+#[tokio::test]
+async fn get_order_item_lineage_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let shipped = app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    let unshipped = app.create_order("customer2", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[shipped.items[0].id])
+        .await;
+    let get = |id: mongodb::bson::Uuid| {
+        let request = app
+            .request_client
+            .get(format!("{private_base_uri}/order_items/{id}/lineage"));
+        async move {
+            let response = request.send().await.expect("Failed to request");
+            assert_eq!(200, response.status().as_u16());
+            response
+                .json::<serde_json::Value>()
+                .await
+                .expect("Failed to deserialize json")
+        }
+    };
+    let lineage = get(shipped.items[0].id).await;
+    assert_eq!(lineage["item"]["id"], shipped.items[0].id.to_string());
+    assert_eq!(lineage["order"]["id"], shipped.id.to_string());
+    assert_eq!(lineage["shipment"]["id"], shipment_id.to_string());
+    let operations = lineage["operations"]
+        .as_array()
+        .expect("operations is not an array");
+    assert!(!operations.is_empty());
+    assert!(operations
+        .iter()
+        .all(|o| o["itemCodeExt"] == "A2121FSY06693"));
+    let lineage = get(unshipped.items[0].id).await;
+    assert_eq!(lineage["order"]["id"], unshipped.id.to_string());
+    assert!(lineage["shipment"].is_null());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn delete_aged_order_without_grace_window_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    // no grace window is configured locally.
+    app.db
+        .ph_db
+        .collection::<Document>(ORDERS_COL)
+        .update_one(
+            doc! {"id":order.id},
+            doc! {"$set":{
+              "created_at":mongodb::bson::DateTime::from_chrono(Utc::now() - Duration::days(31)),
+            }},
+            None,
+        )
+        .await
+        .expect("Failed to update order");
+    let public_base_uri = app.public_base_uri();
+    let private_base_uri = app.private_base_uri();
+    let editor_client = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = editor_client
+        .post(format!("{public_base_uri}/signup"))
+        .json(&serde_json::json!({
+            "username":"editor1",
+            "password":"123456",
+            "role":"editor",
+            "secret":"eliamo_daidaidai",
+        }))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(201, response.status().as_u16());
+    let response = editor_client
+        .post(format!("{public_base_uri}/login"))
+        .json(&serde_json::json!({"username":"editor1","password":"123456"}))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    let response = editor_client
+        .delete(format!("{private_base_uri}/orders/{}", order.id))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    app.cleanup().await;
+}
+
+#[test]
+fn delete_after_grace_window_needs_override_role() {
+    let setting = DeleteGraceSetting {
+        days: Some(30),
+        ..Default::default()
+    };
+    let id = uuid::Uuid::new_v4();
+    let aged = Utc::now() - Duration::days(31);
+    let user = |role, sub_role| UserInfo {
+        user_id: uuid::Uuid::new_v4(),
+        role,
+        sub_role,
+    };
+    let editor = user(UserRole::Editor, HashMap::new());
+    assert!(matches!(
+        ensure_deletable_within(&setting, &editor, AppPrivateRoute::Orders, id, aged),
+        Err(Error::DeleteGraceExpired(_, 30))
+    ));
+    // deleting is allowed within the window.
+    assert!(ensure_deletable_within(
+        &setting,
+        &editor,
+        AppPrivateRoute::Orders,
+        id,
+        Utc::now() - Duration::days(29),
+    )
+    .is_ok());
+    // the full role, or a full sub role of the route, overrides the window.
+    let full = user(UserRole::Full, HashMap::new());
+    assert!(ensure_deletable_within(&setting, &full, AppPrivateRoute::Orders, id, aged).is_ok());
+    let sub_full = user(
+        UserRole::Editor,
+        HashMap::from([(AppPrivateRoute::Orders, UserRole::Full)]),
+    );
+    assert!(
+        ensure_deletable_within(&setting, &sub_full, AppPrivateRoute::Orders, id, aged).is_ok()
+    );
+    assert!(
+        ensure_deletable_within(&setting, &sub_full, AppPrivateRoute::Shipment, id, aged).is_err()
+    );
+}
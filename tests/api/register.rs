@@ -33,3 +33,21 @@ async fn register_inventory_works() {
     }
     app.cleanup().await;
 }
+
+#[tokio::test]
+async fn get_missing_register_returns_404() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let response1 = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/registers/{}",
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(404, response1.status().as_u16());
+    app.cleanup().await;
+}
@@ -0,0 +1,107 @@
+use oism_server::db::{inventory::InventoryLocation, Return};
+
+use crate::helpers::{order_input, spawn_app};
+
+#[tokio::test]
+async fn create_return_from_shipped_order_item_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let order_item_id = order.items[0].id;
+    app.create_shipment("EJ123456789JP", "ems", &[order_item_id])
+        .await;
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::CN)
+            .await,
+        0
+    );
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/return/from_order"))
+        .json(&serde_json::json!({ "orderItemId": order_item_id }))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(201, response.status().as_u16());
+    let retrn: Return = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(
+        retrn.order_item_id.map(|id| id.to_string()),
+        Some(order_item_id.to_string())
+    );
+    assert_eq!(retrn.return_no, "EJ123456789JP");
+    assert_eq!(retrn.items.len(), 1);
+    assert_eq!(retrn.items[0].item_code_ext, "A2121FSY00991");
+    assert_eq!(retrn.items[0].count, 1);
+    // ems is not a clearance vendor, so the unit is restored to CN.
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::CN)
+            .await,
+        1
+    );
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/return/from_order"))
+        .json(&serde_json::json!({ "orderItemId": order_item_id }))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(409, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn delete_return_of_ordered_stock_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    let order_item_id = order.items[0].id;
+    app.create_shipment("EJ123456789JP", "ems", &[order_item_id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/return/from_order"))
+        .json(&serde_json::json!({ "orderItemId": order_item_id }))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(201, response.status().as_u16());
+    let retrn: Return = response.json().await.expect("Failed to deserialize json");
+    // the returned unit is ordered out of CN again.
+    let mut body = order_input("202301010000000000000000000001", "customer2", &[]);
+    body["items"] = serde_json::json!([{
+        "itemCodeExt":"A2121FSY06693",
+        "rate":1.0,
+        "quantity":[
+            {"location":"jp","quantity":0},
+            {"location":"cn","quantity":1},
+            {"location":"pcn","quantity":0},
+        ],
+        "price":1000,
+        "isManual":false,
+    }]);
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/orders/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(201, response.status().as_u16());
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/return/{}", retrn.id))
+        .send()
+        .await
+        .expect("Failed to request");
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::CN)
+            .await,
+        0
+    );
+    app.cleanup().await;
+}
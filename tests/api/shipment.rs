@@ -0,0 +1,1045 @@
+use mongodb::bson::{doc, Document};
+use oism_server::{
+    db::{
+        inventory::InventoryLocation,
+        mongo::{ITEMS_COL, ORDER_ITEMS_COL, SHIPMENT_COL},
+        order::OrderItemStatus,
+        shipment::{DuplicateShipmentItem, PickingListRow, ShipmentInconsistency},
+        OrderRepo, PhDataBase, PhItem, ShipmentRepo,
+    },
+    server::export::DiscountBand,
+    services::consistency_check::run_consistency_check,
+};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn order_then_ship_works() {
+    let app = spawn_app().await;
+    app.seed_catalog(&[("A2121FSY00991", 22000), ("A2121FSY06693", 11000)])
+        .await;
+    let item = app
+        .db
+        .find_one_by_item_code("A2121FSY009", None)
+        .await
+        .expect("Failed to find item")
+        .expect("catalog should be seeded");
+    assert_eq!(item.price, 22000);
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    // A2121FSY06693 has only one in stock, the second one is backordered.
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY06693", 2)])
+        .await;
+    let guaranteed = order
+        .items
+        .iter()
+        .filter(|item| item.status == OrderItemStatus::Guaranteed)
+        .map(|item| item.id)
+        .collect::<Vec<_>>();
+    assert_eq!(guaranteed.len(), 3);
+    app.create_shipment("EJ123456789JP", "ems", &guaranteed)
+        .await;
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY00991", InventoryLocation::JP)
+            .await,
+        0
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY06693", InventoryLocation::JP)
+            .await,
+        0
+    );
+    let order = app
+        .db
+        .get_order_by_id(order.id)
+        .await
+        .expect("Failed to get order");
+    let shipped = order
+        .items
+        .iter()
+        .filter(|item| item.status == OrderItemStatus::Shipped)
+        .count();
+    let backordering = order
+        .items
+        .iter()
+        .filter(|item| item.status == OrderItemStatus::BackOrdering)
+        .count();
+    assert_eq!((shipped, backordering), (3, 1));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reopen_arrival_shipment_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/shipment/{shipment_id}/status"))
+        .json(&serde_json::json!({"status":"arrival"}))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/shipment/{shipment_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let shipment: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!("ongoing", shipment["status"]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn reopen_ongoing_shipment_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/shipment/{shipment_id}/reopen"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(409, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_shipment_with_invalid_no_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "shipmentNo":"12345",
+        "note":"",
+        "vendor":"ems",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+        "itemIds":[order.items[0].id],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(400, response.status().as_u16());
+    // valid ems no should pass
+    app.create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn delete_shipment_with_transfers_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let transfer_id = app
+        .create_transfer("EJ123456789JP", "A2121FSY07292", [1, 1, 0])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(409, response.status().as_u16());
+    let body = response.text().await.expect("Failed to read body");
+    assert!(body.contains(&transfer_id.to_string()));
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn delete_shipment_cascade_transfers_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    app.create_transfer("EJ123456789JP", "A2121FSY07292", [1, 1, 0])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .query(&[("cascadeTransfers", "true")])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let transfers: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/transfer/shipment_no/EJ123456789JP"
+        ))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(transfers.is_empty());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn get_missing_shipment_returns_404() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/shipment/{}",
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(404, response.status().as_u16());
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/transfer/{}",
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(404, response.status().as_u16());
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/return/{}",
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(404, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_missing_shipment_by_no_returns_empty() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_no/EJ000000000JP"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let shipments: Vec<serde_json::Value> =
+        response.json().await.expect("Failed to deserialize json");
+    assert!(shipments.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_shipment_with_backordering_item_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY06693", 2)]).await;
+    let item = order
+        .items
+        .iter()
+        .find(|item| item.status == OrderItemStatus::BackOrdering)
+        .expect("an item should be backordering");
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "shipmentNo":"EJ123456789JP",
+        "note":"",
+        "vendor":"ems",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+        "itemIds":[item.id],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(409, response.status().as_u16());
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains(&item.id.to_string()));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_shipment_with_duplicated_item_ids_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[item_id, item_id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let shipment: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(1, shipment["items"].as_array().unwrap().len());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_shipment_with_already_shipped_item_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    let shipped_id = order.items[0].id;
+    let pending_id = order.items[1].id;
+    app.create_shipment("EJ123456789JP", "ems", &[shipped_id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "shipmentNo":"EJ987654321JP",
+        "note":"",
+        "vendor":"ems",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+        "itemIds":[pending_id, pending_id, shipped_id],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(409, response.status().as_u16());
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains("already shipped"));
+    assert!(message.contains(&shipped_id.to_string()));
+    let item: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/order_items/{pending_id}"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!("guaranteed", item["status"]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn concurrent_shipments_claim_an_item_once() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let private_base_uri = app.private_base_uri();
+    let create = |shipment_no: &str| {
+        let body = serde_json::json!({
+            "shipmentNo":shipment_no,
+            "note":"",
+            "vendor":"ems",
+            "shipmentDate":chrono::Utc::now().timestamp(),
+            "itemIds":[item_id],
+        });
+        app.request_client
+            .post(format!("{private_base_uri}/shipment/"))
+            .json(&body)
+            .send()
+    };
+    let (first, second) = tokio::join!(create("EJ123456789JP"), create("EJ987654321JP"));
+    let statuses = [first, second]
+        .into_iter()
+        .map(|response| response.expect("Failed execute request").status().as_u16())
+        .collect::<Vec<_>>();
+    assert_eq!(statuses.iter().filter(|status| **status == 201).count(), 1);
+    // the other one is told the item is taken, by the check or by the conditional update.
+    assert_eq!(statuses.iter().filter(|status| **status == 409).count(), 1);
+    let claiming = app
+        .db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .count_documents(doc! {"order_item_ids":item_id}, None)
+        .await
+        .expect("Failed to count shipments");
+    assert_eq!(claiming, 1);
+    let item = app
+        .db
+        .get_order_item_by_id(item_id)
+        .await
+        .expect("Failed to get order item");
+    assert_eq!(item.status, OrderItemStatus::Shipped);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn concurrent_shipments_of_two_items_claim_them_once() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    let item_ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let private_base_uri = app.private_base_uri();
+    let create = |shipment_no: &str| {
+        let body = serde_json::json!({
+            "shipmentNo":shipment_no,
+            "note":"",
+            "vendor":"ems",
+            "shipmentDate":chrono::Utc::now().timestamp(),
+            "itemIds":&item_ids,
+        });
+        app.request_client
+            .post(format!("{private_base_uri}/shipment/"))
+            .json(&body)
+            .send()
+    };
+    let (first, second) = tokio::join!(create("EJ123456789JP"), create("EJ987654321JP"));
+    let statuses = [first, second]
+        .into_iter()
+        .map(|response| response.expect("Failed execute request").status().as_u16())
+        .collect::<Vec<_>>();
+    // a conflict on the first item must not fail the loser on the second one.
+    assert_eq!(statuses.iter().filter(|status| **status == 201).count(), 1);
+    assert_eq!(statuses.iter().filter(|status| **status == 409).count(), 1);
+    for item_id in item_ids {
+        let claiming = app
+            .db
+            .ph_db
+            .collection::<Document>(SHIPMENT_COL)
+            .count_documents(doc! {"order_item_ids":item_id}, None)
+            .await
+            .expect("Failed to count shipments");
+        assert_eq!(claiming, 1);
+        let item = app
+            .db
+            .get_order_item_by_id(item_id)
+            .await
+            .expect("Failed to get order item");
+        assert_eq!(item.status, OrderItemStatus::Shipped);
+    }
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn duplicate_shipment_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .patch(format!("{private_base_uri}/shipment/{shipment_id}/note"))
+        .json(&serde_json::json!({"note":"recurring"}))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    let body = serde_json::json!({
+        "shipmentNo":"EJ987654321JP",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+        "itemIds":[order.items[1].id],
+    });
+    let response = app
+        .request_client
+        .post(format!(
+            "{private_base_uri}/shipment/{shipment_id}/duplicate"
+        ))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(201, response.status().as_u16());
+    let shipments: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_no/EJ987654321JP"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(shipments.len(), 1);
+    assert_eq!(shipments[0]["vendor"], "ems");
+    assert_eq!(shipments[0]["note"], "recurring");
+    assert_eq!(
+        shipments[0]["orderItemIds"],
+        serde_json::json!([order.items[1].id])
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn exported_shipment_removed_from_unexported_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let query = [
+        (
+            "from",
+            (chrono::Utc::now() - chrono::Duration::days(1)).timestamp(),
+        ),
+        (
+            "to",
+            (chrono::Utc::now() + chrono::Duration::days(1)).timestamp(),
+        ),
+    ];
+    let unexported: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/unexported"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(unexported
+        .iter()
+        .any(|s| s["id"] == shipment_id.to_string()));
+    // the export handlers mark the shipments after the file is generated by the utility service.
+    app.db
+        .mark_shipments_exported(&[mongodb::bson::Uuid::from_uuid_1(shipment_id)])
+        .await
+        .expect("Failed to mark shipment exported");
+    let unexported: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/unexported"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(unexported.is_empty());
+    let shipment: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/{shipment_id}"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(shipment["exportedAt"].is_number());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn consistency_check_detects_inconsistent_shipment() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 1), ("A2121FSY07292", 1)])
+        .await;
+    let listed = order.items[0].id;
+    let unlisted = order.items[1].id;
+    let shipment_id: mongodb::bson::Uuid = app
+        .create_shipment("EJ123456789JP", "ems", &[listed])
+        .await
+        .into();
+    assert!(run_consistency_check(&app.db, false)
+        .await
+        .expect("Failed to run consistency check")
+        .is_empty());
+    // as if the publish died halfway
+    let order_items = app.db.ph_db.collection::<Document>(ORDER_ITEMS_COL);
+    order_items
+        .update_one(
+            doc! {"id":listed},
+            doc! {"$set":{"status":"guaranteed","shipment_id":null}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper order item");
+    order_items
+        .update_one(
+            doc! {"id":unlisted},
+            doc! {"$set":{"status":"shipped","shipment_id":shipment_id}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper order item");
+    let found = run_consistency_check(&app.db, false)
+        .await
+        .expect("Failed to run consistency check");
+    assert_eq!(found.len(), 2);
+    assert!(found.contains(&ShipmentInconsistency::ItemNotShipped {
+        shipment_id,
+        item_id: listed,
+        status: OrderItemStatus::Guaranteed,
+    }));
+    assert!(found.contains(&ShipmentInconsistency::ShipmentMissingItem {
+        shipment_id,
+        item_id: unlisted,
+    }));
+    // repairing reports what it has fixed
+    assert_eq!(
+        run_consistency_check(&app.db, true)
+            .await
+            .expect("Failed to run consistency check")
+            .len(),
+        2
+    );
+    assert!(run_consistency_check(&app.db, false)
+        .await
+        .expect("Failed to run consistency check")
+        .is_empty());
+    let shipment = app
+        .db
+        .get_shipment_by_id(shipment_id)
+        .await
+        .expect("Failed to get shipment");
+    assert_eq!(shipment.items.len(), 2);
+    assert!(shipment
+        .items
+        .iter()
+        .all(|item| item.status == OrderItemStatus::Shipped));
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn find_shipments_by_status_counts_concealed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY07292", 1)])
+        .await;
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    assert_eq!(ids.len(), 3);
+    let shipment_id = app.create_shipment("EJ123456789JP", "ems", &ids).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .delete(format!("{private_base_uri}/order_items/{}", ids[0]))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    let query = [
+        (
+            "from",
+            (chrono::Utc::now() - chrono::Duration::days(1)).timestamp(),
+        ),
+        (
+            "to",
+            (chrono::Utc::now() + chrono::Duration::days(1)).timestamp(),
+        ),
+    ];
+    let shipments: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_status/ongoing"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    let shipment = shipments
+        .iter()
+        .find(|s| s["id"] == shipment_id.to_string())
+        .expect("shipment should be listed");
+    assert_eq!(shipment["itemCount"], 2);
+    assert_eq!(shipment["concealedCount"], 1);
+    assert!(shipment.get("items").is_none());
+    let shipments: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_status/arrival"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(shipments.is_empty());
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_status/unknown"))
+        .query(&query)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(400, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn ship_by_customer_ships_all_guaranteed_items() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    app.create_order("customer1", &[("A2121FSY00991", 2)]).await;
+    app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    app.create_order("customer2", &[("A2121FSY07292", 1)]).await;
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "customerId":"customer1",
+        "shipmentNo":"EJ123456789JP",
+        "vendor":"ems",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/by_customer"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(201, response.status().as_u16());
+    assert!(app
+        .db
+        .find_guaranteed_items_by_customer("customer1", None, None)
+        .await
+        .expect("Failed to find order items")
+        .is_empty());
+    assert_eq!(
+        app.db
+            .find_guaranteed_items_by_customer("customer2", None, None)
+            .await
+            .expect("Failed to find order items")
+            .len(),
+        1
+    );
+    let shipments: Vec<serde_json::Value> = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/by_no/EJ123456789JP"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(shipments.len(), 1);
+    assert_eq!(shipments[0]["orderItemIds"].as_array().unwrap().len(), 3);
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/by_customer"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(404, response.status().as_u16());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn update_shipment_no_trims_surrounding_spaces() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[order.items[0].id])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .put(format!("{private_base_uri}/shipment/{shipment_id}/no"))
+        .json(&serde_json::json!({
+            "shipmentNo":"  EJ987654321JP ",
+            "updateRelatedTransfers":true,
+        }))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert!(response.status().is_success());
+    let shipments = app
+        .db
+        .find_shipment_by_no("EJ987654321JP")
+        .await
+        .expect("Failed to find shipment");
+    assert_eq!(shipments.len(), 1);
+    assert_eq!(shipments[0].shipment_no, "EJ987654321JP");
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn shipment_transfer_diff_reports_missing_codes() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 1), ("A2121FSY06693", 1)])
+        .await;
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let shipment_id = app.create_shipment("EJ123456789JP", "ems", &ids).await;
+    app.create_transfer("EJ123456789JP", "A2121FSY00991", [0, 1, 0])
+        .await;
+    app.create_transfer("EJ123456789JP", "A2121FSY07292", [1, 1, 0])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/shipment/{shipment_id}/transfer_diff"
+        ))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let diff: serde_json::Value = response.json().await.expect("Failed to deserialize json");
+    assert_eq!(
+        diff["itemsWithoutTransfer"],
+        serde_json::json!(["A2121FSY06693"])
+    );
+    assert_eq!(
+        diff["transfersWithoutItem"],
+        serde_json::json!(["A2121FSY07292"])
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn shipment_picking_list_groups_by_location() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app
+        .create_order(
+            "customer1",
+            &[
+                ("A2121FSY07292", 1),
+                ("A2121FSY06693", 1),
+                ("A2121FSY00991", 2),
+            ],
+        )
+        .await;
+    let cn_item = order
+        .items
+        .iter()
+        .find(|item| item.item_code_ext == "A2121FSY07292")
+        .expect("the order should have the item");
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(
+            doc! {"id":cn_item.id},
+            doc! {"$set":{"location":"cn"}},
+            None,
+        )
+        .await
+        .expect("Failed to move order item");
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let shipment_id = app.create_shipment("EJ123456789JP", "ems", &ids).await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/shipment/{shipment_id}/picking_list"
+        ))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let rows: Vec<PickingListRow> = response.json().await.expect("Failed to deserialize json");
+    let rows = rows
+        .iter()
+        .map(|row| (row.location, row.item_code_ext.as_str(), row.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            (InventoryLocation::JP, "A2121FSY00991", 2),
+            (InventoryLocation::JP, "A2121FSY06693", 1),
+            (InventoryLocation::CN, "A2121FSY07292", 1),
+        ]
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn discount_breakdown_sums_per_band() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    for (code, price) in [("A2121FSY00991", 22000), ("A2121FSY07292", 5500)] {
+        app.db
+            .ph_db
+            .collection::<PhItem>(ITEMS_COL)
+            .insert_one(PhItem::new_dummy(code, price), None)
+            .await
+            .expect("Failed to insert item");
+    }
+    let order = app
+        .create_order("customer1", &[("A2121FSY00991", 2), ("A2121FSY07292", 1)])
+        .await;
+    let discounted = order
+        .items
+        .iter()
+        .find(|item| item.item_code_ext == "A2121FSY00991")
+        .expect("the order should have the item");
+    app.db
+        .update_order_item_rate(discounted.id, 0.8)
+        .await
+        .expect("Failed to update rate");
+    let ids = order.items.iter().map(|item| item.id).collect::<Vec<_>>();
+    app.create_shipment("EJ123456789JP", "ems", &ids).await;
+    let private_base_uri = app.private_base_uri();
+    let now = chrono::Utc::now();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/shipment/discount_breakdown"))
+        .query(&[
+            ("from", (now - chrono::Duration::days(1)).timestamp()),
+            ("to", (now + chrono::Duration::days(1)).timestamp()),
+        ])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let bands: Vec<DiscountBand> = response.json().await.expect("Failed to deserialize json");
+    let bands = bands
+        .iter()
+        .map(|band| (band.label.as_str(), band.count, band.revenue))
+        .collect::<Vec<_>>();
+    // 22000 / 1.1 + 5500 / 1.1 and 22000 * 0.8 / 1.1
+    assert_eq!(bands, vec![("-", 2, 25000), ("20%Off", 1, 16000)]);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn item_listed_by_a_shipment_is_not_shipped_again() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let order = app.create_order("customer1", &[("A2121FSY00991", 1)]).await;
+    let item_id = order.items[0].id;
+    let shipment_id = app
+        .create_shipment("EJ123456789JP", "ems", &[item_id])
+        .await;
+    // an edit put the item back to guaranteed but left it pointing to the shipment.
+    app.db
+        .ph_db
+        .collection::<Document>(ORDER_ITEMS_COL)
+        .update_one(
+            doc! {"id":item_id},
+            doc! {"$set":{"status":OrderItemStatus::Guaranteed}},
+            None,
+        )
+        .await
+        .expect("Failed to update order item");
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "shipmentNo":"EJ987654321JP",
+        "note":"",
+        "vendor":"ems",
+        "shipmentDate":chrono::Utc::now().timestamp(),
+        "itemIds":[item_id],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/shipment/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(409, response.status().as_u16());
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains("already shipped"));
+
+    // a second shipment listing the same item is reported.
+    let mut duplicated = app
+        .db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .find_one(
+            doc! {"id":mongodb::bson::Uuid::from_uuid_1(shipment_id)},
+            None,
+        )
+        .await
+        .expect("Failed to find shipment")
+        .expect("shipment not found");
+    let duplicated_id = mongodb::bson::Uuid::new();
+    duplicated.remove("_id");
+    duplicated.insert("id", duplicated_id);
+    app.db
+        .ph_db
+        .collection::<Document>(SHIPMENT_COL)
+        .insert_one(duplicated, None)
+        .await
+        .expect("Failed to insert shipment");
+    let duplicates = app
+        .request_client
+        .get(format!(
+            "{private_base_uri}/shipment/integrity/duplicate_items"
+        ))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json::<Vec<DuplicateShipmentItem>>()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].item_id, item_id);
+    assert_eq!(duplicates[0].shipment_ids.len(), 2);
+    assert!(duplicates[0].shipment_ids.contains(&duplicated_id));
+    app.cleanup().await;
+}
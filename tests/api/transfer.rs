@@ -0,0 +1,339 @@
+use chrono::TimeZone;
+use mongodb::{
+    bson::{doc, Document},
+    options::UpdateOptions,
+};
+use oism_server::db::{
+    inventory::InventoryLocation,
+    mongo::{INVENTORY_COL, OPERATIONS_COL, ORDER_ITEMS_COL, TRANSFERS_COL},
+    shipment::ShipmentVendor,
+    transfer::TransferSummaryRow,
+    TransferRepo,
+};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn repair_tampered_transfer_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let transfer_id = app
+        .create_transfer("EJ123456789JP", "A2121FSY07292", [0, 2, 0])
+        .await;
+    // tamper the operation and the inventory as a manual edit would do.
+    app.db
+        .ph_db
+        .collection::<Document>(OPERATIONS_COL)
+        .update_one(
+            doc! {
+              "related_id":mongodb::bson::Uuid::from_uuid_1(transfer_id),
+              "location":"cn",
+            },
+            doc! {"$set":{"count":1}},
+            None,
+        )
+        .await
+        .expect("Failed to tamper operation");
+    app.db
+        .ph_db
+        .collection::<Document>(INVENTORY_COL)
+        .update_one(
+            doc! {"item_code_ext":"A2121FSY07292"},
+            doc! {"$inc":{"quantity.$[elem].quantity":-1}},
+            UpdateOptions::builder()
+                .array_filters(vec![doc! {"elem.location":"cn"}])
+                .build(),
+        )
+        .await
+        .expect("Failed to tamper inventory");
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::CN)
+            .await,
+        1
+    );
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/transfer/{transfer_id}/repair"))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let compensations: Vec<serde_json::Value> =
+        response.json().await.expect("Failed to deserialize json");
+    assert_eq!(compensations.len(), 1);
+    assert_eq!(compensations[0]["count"], 1);
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::CN)
+            .await,
+        2
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        0
+    );
+    // a repaired transfer has nothing to compensate.
+    let compensations: Vec<serde_json::Value> = app
+        .request_client
+        .post(format!("{private_base_uri}/transfer/{transfer_id}/repair"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(compensations.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn update_transfer_location_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let transfer_id = app
+        .create_transfer("EJ123456789JP", "A2121FSY07292", [0, 2, 0])
+        .await;
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .put(format!(
+            "{private_base_uri}/transfer/{transfer_id}/location"
+        ))
+        .json(&serde_json::json!({"newLocation":"pcn"}))
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::CN)
+            .await,
+        0
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::PCN)
+            .await,
+        2
+    );
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        0
+    );
+    let transfer: serde_json::Value = app
+        .request_client
+        .get(format!("{private_base_uri}/transfer/{transfer_id}"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert_eq!(transfer["shipmentVendor"], "ems");
+    let items = transfer["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["location"], "pcn");
+    // the transfer is already consistent, nothing to repair after the move.
+    let compensations: Vec<serde_json::Value> = app
+        .request_client
+        .post(format!("{private_base_uri}/transfer/{transfer_id}/repair"))
+        .send()
+        .await
+        .expect("Failed execute request")
+        .json()
+        .await
+        .expect("Failed to deserialize json");
+    assert!(compensations.is_empty());
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_transfer_with_duplicated_location_failed() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let body = serde_json::json!({
+        "shipmentNo":"EJ123456789JP",
+        "note":"",
+        "transferDate":chrono::Utc::now().timestamp(),
+        "shipmentVendor":"ems",
+        "toLocation":"cn",
+        "items":[{
+            "itemCodeExt":"A2121FSY07292",
+            "quantity":[
+                {"location":"cn","quantity":1},
+                {"location":"cn","quantity":1},
+                {"location":"pcn","quantity":0},
+            ],
+        }],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/transfer/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(422, response.status().as_u16());
+    // nothing should be moved
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn conceal_shipped_item_twice_publishes_one_transfer() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let order = app.create_order("customer1", &[("A2121FSY06693", 1)]).await;
+    let item_id = order.items[0].id;
+    let shipment_id = app
+        .create_shipment("EM123456789JP", "ems", &[item_id])
+        .await;
+    for _ in 0..2 {
+        let response = app
+            .request_client
+            .delete(format!("{private_base_uri}/order_items/{item_id}"))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert!(response.status().is_success());
+        // a retried conceal finds the item still shipped.
+        app.db
+            .ph_db
+            .collection::<Document>(ORDER_ITEMS_COL)
+            .update_one(
+                doc! {"id":item_id},
+                doc! {"$set":{"status":"shipped"}},
+                None,
+            )
+            .await
+            .expect("Failed to update order item");
+    }
+    let transfers = app
+        .db
+        .find_transfer_by_shipment_id(shipment_id.into())
+        .await
+        .expect("Failed to find transfers")
+        .expect("transfer should be published");
+    assert_eq!(transfers.len(), 1);
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn create_transfer_with_missing_inventory_reports_every_item() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let private_base_uri = app.private_base_uri();
+    let item = |item_code_ext: &str| {
+        serde_json::json!({
+            "itemCodeExt":item_code_ext,
+            "quantity":[
+                {"location":"jp","quantity":0},
+                {"location":"cn","quantity":2},
+                {"location":"pcn","quantity":0},
+            ],
+        })
+    };
+    let body = serde_json::json!({
+        "shipmentNo":"EJ123456789JP",
+        "note":"",
+        "transferDate":chrono::Utc::now().timestamp(),
+        "shipmentVendor":"ems",
+        "toLocation":"cn",
+        "items":[item("A2121FSY07292"), item("Z9999ZZZ00001"), item("Z9999ZZZ00002")],
+    });
+    let response = app
+        .request_client
+        .post(format!("{private_base_uri}/transfer/"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(422, response.status().as_u16());
+    let message = response.text().await.expect("Failed to read body");
+    assert!(message.contains("Z9999ZZZ00001"));
+    assert!(message.contains("Z9999ZZZ00002"));
+    assert!(!message.contains("A2121FSY07292"));
+    // the known item is not moved.
+    assert_eq!(
+        app.get_inventory_quantity("A2121FSY07292", InventoryLocation::JP)
+            .await,
+        2
+    );
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn summarize_transfers_by_month_and_vendor_works() {
+    let app = spawn_app().await;
+    app.signup_and_login().await;
+    app.register_inventory().await;
+    let january = app
+        .create_transfer("EJ123456789JP", "A2121FSY00991", [0, 1, 0])
+        .await;
+    let february = app
+        .create_transfer("EJ223456789JP", "A2121FSY07292", [0, 2, 0])
+        .await;
+    let february_yy = app
+        .create_transfer("EJ323456789JP", "A2121FSY00991", [0, 1, 0])
+        .await;
+    let date = |month: u32| {
+        mongodb::bson::DateTime::from_chrono(
+            chrono::Utc
+                .with_ymd_and_hms(2023, month, 15, 3, 0, 0)
+                .unwrap(),
+        )
+    };
+    for (id, month, vendor) in [
+        (january, 1, "ems"),
+        (february, 2, "ems"),
+        (february_yy, 2, "yy"),
+    ] {
+        app.db
+            .ph_db
+            .collection::<Document>(TRANSFERS_COL)
+            .update_one(
+                doc! {"id":mongodb::bson::Uuid::from_uuid_1(id)},
+                doc! {"$set":{"transfer_date":date(month),"shipment_vendor":vendor}},
+                None,
+            )
+            .await
+            .expect("Failed to update transfer");
+    }
+    let private_base_uri = app.private_base_uri();
+    let response = app
+        .request_client
+        .get(format!("{private_base_uri}/transfer/summary"))
+        .query(&[
+            ("from", date(1).timestamp_millis() / 1000 - 86400),
+            ("to", date(2).timestamp_millis() / 1000 + 86400),
+        ])
+        .send()
+        .await
+        .expect("Failed execute request");
+    assert_eq!(200, response.status().as_u16());
+    let rows: Vec<TransferSummaryRow> = response.json().await.expect("Failed to deserialize");
+    let rows = rows
+        .iter()
+        .map(|row| (row.month.as_str(), row.vendor, row.units, row.transfers))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        [
+            ("2023-01", ShipmentVendor::Ems, 1, 1),
+            ("2023-02", ShipmentVendor::Ems, 2, 1),
+            ("2023-02", ShipmentVendor::YY, 1, 1),
+        ]
+    );
+    app.cleanup().await;
+}
@@ -0,0 +1,72 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::response::IntoResponse;
+use oism_server::server::ws::{
+    acquire_ws_permit, recv_control_message, recv_control_messages_debounced, ControlMessage,
+};
+use tokio::sync::Semaphore;
+
+#[tokio::test]
+async fn lagged_subscriber_is_asked_to_resync() {
+    let (tx, mut rx) = tokio::sync::broadcast::channel::<ControlMessage>(2);
+    for _ in 0..5 {
+        tx.send(ControlMessage::RefreshOrderList)
+            .expect("Failed to send message");
+    }
+    let message = recv_control_message(&mut rx).await;
+    assert!(matches!(message, Some(ControlMessage::ResyncAll)));
+    // the subscriber keeps receiving after the resync.
+    let message = recv_control_message(&mut rx).await;
+    assert!(matches!(message, Some(ControlMessage::RefreshOrderList)));
+    drop(tx);
+    while let Some(message) = recv_control_message(&mut rx).await {
+        assert!(matches!(message, ControlMessage::RefreshOrderList));
+    }
+}
+
+#[test]
+fn control_socket_beyond_max_connections_is_refused() {
+    let max_connections = 2;
+    let ws_permits = Arc::new(Semaphore::new(max_connections));
+    let mut permits = (0..max_connections)
+        .map(|_| acquire_ws_permit(&ws_permits).expect("Failed to acquire permit"))
+        .collect::<Vec<_>>();
+    let refused = acquire_ws_permit(&ws_permits).expect_err("should be refused");
+    assert_eq!(503, refused.into_response().status().as_u16());
+    // a closed socket frees its slot.
+    permits.pop();
+    assert!(acquire_ws_permit(&ws_permits).is_ok());
+}
+
+#[tokio::test]
+async fn repeated_messages_within_window_are_sent_once() {
+    let (tx, mut rx) = tokio::sync::broadcast::channel::<ControlMessage>(64);
+    for _ in 0..10 {
+        tx.send(ControlMessage::RefreshOrderList)
+            .expect("Failed to send message");
+    }
+    tx.send(ControlMessage::RefreshValidationState)
+        .expect("Failed to send message");
+    let window = Duration::from_millis(50);
+    let messages = recv_control_messages_debounced(&mut rx, window)
+        .await
+        .expect("channel should be open");
+    assert_eq!(
+        messages,
+        vec![
+            ControlMessage::RefreshOrderList,
+            ControlMessage::RefreshValidationState
+        ]
+    );
+    // a message after the window starts a new batch.
+    tx.send(ControlMessage::RefreshOrderList)
+        .expect("Failed to send message");
+    let messages = recv_control_messages_debounced(&mut rx, window)
+        .await
+        .expect("channel should be open");
+    assert_eq!(messages, vec![ControlMessage::RefreshOrderList]);
+    drop(tx);
+    assert!(recv_control_messages_debounced(&mut rx, window)
+        .await
+        .is_none());
+}